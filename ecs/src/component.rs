@@ -1,6 +1,11 @@
-use std::{any::Any, collections::HashMap, slice::IterMut};
+use std::{
+    any::{self, Any},
+    collections::HashMap,
+    mem,
+    slice::IterMut,
+};
 
-use crate::entity::*;
+use crate::{diagnostics::ComponentStats, entity::*};
 
 pub trait Component: 'static {}
 impl<T: Any> Component for T {}
@@ -8,6 +13,10 @@ impl<T: Any> Component for T {}
 pub trait ComponentVec {
     fn as_any_ref(&self) -> &dyn Any;
     fn as_any_mut(&mut self) -> &mut dyn Any;
+    fn type_name(&self) -> &'static str;
+    fn len(&self) -> usize;
+    fn capacity(&self) -> usize;
+    fn element_size(&self) -> usize;
 }
 impl<T: Component> ComponentVec for Vec<T> {
     fn as_any_ref(&self) -> &dyn Any {
@@ -16,6 +25,18 @@ impl<T: Component> ComponentVec for Vec<T> {
     fn as_any_mut(&mut self) -> &mut dyn Any {
         self
     }
+    fn type_name(&self) -> &'static str {
+        any::type_name::<T>()
+    }
+    fn len(&self) -> usize {
+        Vec::len(self)
+    }
+    fn capacity(&self) -> usize {
+        Vec::capacity(self)
+    }
+    fn element_size(&self) -> usize {
+        mem::size_of::<T>()
+    }
 }
 
 pub struct ComponentManager {
@@ -66,6 +87,21 @@ impl ComponentManager {
         }
     }
 
+    /// Returns the number of instances stored and an estimate of the memory reserved for them
+    pub fn stats(&self) -> ComponentStats {
+        let components = self.components.capacity() * self.components.element_size();
+        let entity_ids = self.entity_ids.capacity() * mem::size_of::<u32>();
+        let instance_indices =
+            self.instance_indices.capacity() * (mem::size_of::<u32>() + mem::size_of::<usize>());
+
+        ComponentStats {
+            name: self.components.type_name(),
+            count: self.components.len(),
+            capacity: self.components.capacity(),
+            memory_usage: components + entity_ids + instance_indices,
+        }
+    }
+
     pub fn includes_entity(&self, e: &Entity) -> bool {
         self.instance_indices.contains_key(&e.id)
     }
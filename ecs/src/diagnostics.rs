@@ -0,0 +1,142 @@
+use std::{fmt, time};
+
+/// Timing information recorded for a single system each time the systems are run
+#[derive(Clone, Debug)]
+pub struct SystemTiming {
+    pub name: &'static str,
+    pub last: time::Duration,
+    pub total: time::Duration,
+    pub runs: u64,
+}
+
+impl SystemTiming {
+    pub fn new(name: &'static str) -> Self {
+        SystemTiming {
+            name,
+            last: time::Duration::ZERO,
+            total: time::Duration::ZERO,
+            runs: 0,
+        }
+    }
+
+    pub fn record(&mut self, duration: time::Duration) {
+        self.last = duration;
+        self.total += duration;
+        self.runs += 1;
+    }
+
+    pub fn average(&self) -> time::Duration {
+        if self.runs == 0 {
+            time::Duration::ZERO
+        } else {
+            time::Duration::from_secs_f64(self.total.as_secs_f64() / self.runs as f64)
+        }
+    }
+}
+
+/// Storage information for a single component type within a view
+#[derive(Clone, Debug)]
+pub struct ComponentStats {
+    pub name: &'static str,
+    pub count: usize,
+    pub capacity: usize,
+    /// Bytes reserved by the component storage, including entity bookkeeping
+    pub memory_usage: usize,
+}
+
+#[derive(Clone, Debug)]
+pub struct ViewStats {
+    pub name: String,
+    pub entity_count: usize,
+    pub components: Vec<ComponentStats>,
+}
+
+impl ViewStats {
+    pub fn memory_usage(&self) -> usize {
+        self.components.iter().map(|c| c.memory_usage).sum()
+    }
+}
+
+/// Snapshot of the world's system timings and per-view storage statistics
+#[derive(Clone, Debug)]
+pub struct Diagnostics {
+    pub systems: Vec<SystemTiming>,
+    pub views: Vec<ViewStats>,
+}
+
+impl Diagnostics {
+    pub fn total_system_time(&self) -> time::Duration {
+        self.systems.iter().map(|s| s.last).sum()
+    }
+
+    pub fn total_memory_usage(&self) -> usize {
+        self.views.iter().map(|v| v.memory_usage()).sum()
+    }
+}
+
+impl fmt::Display for Diagnostics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{:<48} {:>12} {:>12} {:>8}",
+            "System", "Last", "Average", "Runs"
+        )?;
+        for system in self.systems.iter() {
+            writeln!(
+                f,
+                "{:<48} {:>12} {:>12} {:>8}",
+                truncate(system.name, 48),
+                format!("{:.3?}", system.last),
+                format!("{:.3?}", system.average()),
+                system.runs
+            )?;
+        }
+        writeln!(
+            f,
+            "{:<48} {:>12}",
+            "Total",
+            format!("{:.3?}", self.total_system_time())
+        )?;
+
+        for view in self.views.iter() {
+            writeln!(f)?;
+            writeln!(
+                f,
+                "View '{}': {} entities, {} bytes",
+                view.name,
+                view.entity_count,
+                view.memory_usage()
+            )?;
+            writeln!(
+                f,
+                "{:<48} {:>12} {:>12} {:>12}",
+                "Component", "Count", "Capacity", "Bytes"
+            )?;
+            for component in view.components.iter() {
+                writeln!(
+                    f,
+                    "{:<48} {:>12} {:>12} {:>12}",
+                    truncate(component.name, 48),
+                    component.count,
+                    component.capacity,
+                    component.memory_usage
+                )?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Keeps the tail of long type paths, as that is the most identifying part
+fn truncate(name: &str, width: usize) -> &str {
+    if name.len() <= width {
+        return name;
+    }
+
+    let mut start = name.len() - width;
+    while !name.is_char_boundary(start) {
+        start += 1;
+    }
+    &name[start..]
+}
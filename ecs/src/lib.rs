@@ -1,11 +1,15 @@
 mod archetype;
 mod component;
+mod diagnostics;
 mod entity;
 mod system;
+#[cfg(test)]
+mod tests;
 mod world;
 
 use std::time;
 
+pub use diagnostics::*;
 pub use entity::*;
 pub use world::*;
 
@@ -1,27 +1,41 @@
-use std::time;
+use std::{any, time};
 
-use crate::world::*;
+use crate::{diagnostics::SystemTiming, world::*};
 
 pub trait System: 'static + FnMut(&mut View, time::Duration) {}
 impl<T: 'static + FnMut(&mut View, time::Duration)> System for T {}
 pub struct SystemManager {
     systems: Vec<Box<dyn System>>,
+    timings: Vec<SystemTiming>,
 }
 
 impl SystemManager {
     pub fn new() -> Self {
         SystemManager {
             systems: Vec::new(),
+            timings: Vec::new(),
         }
     }
 
-    pub fn add_system(&mut self, system: impl System) {
+    /// Adds the system, using its type name to identify it in diagnostics
+    pub fn add_system<S: System>(&mut self, system: S) {
+        self.add_named_system(any::type_name::<S>(), system)
+    }
+
+    pub fn add_named_system(&mut self, name: &'static str, system: impl System) {
         self.systems.push(Box::new(system));
+        self.timings.push(SystemTiming::new(name));
     }
 
     pub fn run_systems(&mut self, view: &mut View, dt: time::Duration) {
-        for system in self.systems.iter_mut() {
-            system(view, dt)
+        for (system, timing) in self.systems.iter_mut().zip(self.timings.iter_mut()) {
+            let start = time::Instant::now();
+            system(view, dt);
+            timing.record(start.elapsed());
         }
     }
+
+    pub fn timings(&self) -> &[SystemTiming] {
+        &self.timings
+    }
 }
@@ -0,0 +1,153 @@
+mod tests {
+    #![allow(unused_imports)]
+    use std::time;
+
+    use crate::{diagnostics::*, world::*};
+
+    struct Position {
+        coords: (f32, f32, f32),
+    }
+
+    struct Speed {
+        speed: u32,
+    }
+
+    fn world_with_entities(positions: usize, speeds: usize) -> World {
+        let mut world = World::new();
+        world.register_component::<Position>();
+        world.register_component::<Speed>();
+
+        for i in 0..positions.max(speeds) {
+            let entity = world.create_entity();
+            if i < positions {
+                world
+                    .set_component(
+                        &entity,
+                        Position {
+                            coords: (0.0, 0.0, 0.0),
+                        },
+                    )
+                    .unwrap();
+            }
+            if i < speeds {
+                world.set_component(&entity, Speed { speed: 1 }).unwrap();
+            }
+        }
+
+        world
+    }
+
+    #[test]
+    fn system_timing_test() {
+        let mut timing = SystemTiming::new("system");
+        assert_eq!(time::Duration::ZERO, timing.average());
+
+        timing.record(time::Duration::from_millis(2));
+        timing.record(time::Duration::from_millis(4));
+        assert_eq!(time::Duration::from_millis(4), timing.last);
+        assert_eq!(time::Duration::from_millis(6), timing.total);
+        assert_eq!(2, timing.runs);
+        assert_eq!(time::Duration::from_millis(3), timing.average());
+
+        // more runs than fit in a u32 must neither divide by zero nor wrap around
+        timing.runs = u32::MAX as u64 + 1;
+        timing.total = time::Duration::from_secs(u32::MAX as u64 + 1);
+        assert_eq!(time::Duration::from_secs(1), timing.average());
+    }
+
+    #[test]
+    fn system_timing_recorded_test() {
+        let mut world = World::new();
+        world.add_named_system("first", |_: &mut View, _: time::Duration| {});
+        world.add_named_system("second", |_: &mut View, _: time::Duration| {});
+
+        world.run_systems();
+        world.run_systems();
+
+        let timings = world.system_timings();
+        assert_eq!(2, timings.len());
+        assert_eq!("first", timings[0].name);
+        assert_eq!("second", timings[1].name);
+        assert!(timings.iter().all(|timing| timing.runs == 2));
+    }
+
+    #[test]
+    fn entity_count_test() {
+        let world = world_with_entities(5, 3);
+        let view = world.get_current_view_ref();
+
+        // entities with both components are only counted once
+        assert_eq!(5, view.entity_count());
+        assert_eq!(Ok(5), view.component_count::<Position>());
+        assert_eq!(Ok(3), view.component_count::<Speed>());
+        assert!(view.component_count::<u8>().is_err());
+
+        assert_eq!(0, World::new().get_current_view_ref().entity_count());
+    }
+
+    #[test]
+    fn component_iter_test() {
+        let mut world = world_with_entities(5, 3);
+        let view = world.get_current_view_mut();
+
+        let mut pairs = 0;
+        for (position, speed) in view.iter_two_components_mut::<Position, Speed>().unwrap() {
+            position.coords.0 += speed.speed as f32;
+            pairs += 1;
+        }
+        assert_eq!(3, pairs);
+
+        let moved = view
+            .iter_components_mut::<Position>()
+            .unwrap()
+            .filter(|position| position.coords.0 == 1.0)
+            .count();
+        assert_eq!(3, moved);
+    }
+
+    #[test]
+    fn memory_usage_test() {
+        let world = world_with_entities(10, 4);
+        let view = world.get_current_view_ref();
+        let stats = view.stats(String::from("main"));
+
+        assert_eq!(2, stats.components.len());
+        for component in stats.components.iter() {
+            assert!(component.capacity >= component.count);
+            // at least the components themselves are reserved
+            assert!(component.memory_usage >= component.count * std::mem::size_of::<u32>());
+        }
+        assert_eq!(view.memory_usage(), stats.memory_usage());
+        assert!(view.memory_usage() > 0);
+    }
+
+    #[test]
+    fn diagnostics_test() {
+        let mut world = world_with_entities(6, 2);
+        world.create_view(String::from("empty"));
+        world.add_named_system("system", |_: &mut View, _: time::Duration| {});
+        world.run_systems();
+
+        let diagnostics = world.diagnostics();
+        assert_eq!(1, diagnostics.systems.len());
+        assert_eq!(diagnostics.systems[0].last, diagnostics.total_system_time());
+
+        // views are sorted by name
+        let names: Vec<&str> = diagnostics
+            .views
+            .iter()
+            .map(|view| view.name.as_str())
+            .collect();
+        assert_eq!(vec!["empty", "main"], names);
+        assert_eq!(0, diagnostics.views[0].entity_count);
+        assert_eq!(6, diagnostics.views[1].entity_count);
+        assert_eq!(
+            world.get_current_view_ref().memory_usage(),
+            diagnostics.total_memory_usage()
+        );
+
+        let table = diagnostics.to_string();
+        assert!(table.contains("system"));
+        assert!(table.contains("View 'main': 6 entities"));
+    }
+}
@@ -1,12 +1,14 @@
 use std::{
     any::{self, TypeId},
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     iter::Zip,
     slice::IterMut,
     time,
 };
 
-use crate::{component::*, entity::*, system::*};
+use log::info;
+
+use crate::{component::*, diagnostics::*, entity::*, system::*};
 pub struct World {
     entity_manager: EntityManager,
     system_manager: SystemManager,
@@ -67,6 +69,12 @@ impl World {
         self.system_manager.add_system(system)
     }
 
+    /// Adds the system under the given name, which is used to identify it in diagnostics
+    #[inline]
+    pub fn add_named_system(&mut self, name: &'static str, system: impl System) {
+        self.system_manager.add_named_system(name, system)
+    }
+
     #[inline]
     pub fn run_systems(&mut self) {
         let dt = self.time - time::Instant::now();
@@ -106,6 +114,31 @@ impl World {
             .get(&name)
             .ok_or_else(|| format!("Specified view '{}' does not exist", name))
     }
+
+    /// Timings recorded for each system during the most recent, and all previous, runs
+    pub fn system_timings(&self) -> &[SystemTiming] {
+        self.system_manager.timings()
+    }
+
+    /// Collects system timings along with entity, component and memory statistics for every view
+    pub fn diagnostics(&self) -> Diagnostics {
+        let mut views: Vec<ViewStats> = self
+            .views
+            .iter()
+            .map(|(name, view)| view.stats(name.clone()))
+            .collect();
+        views.sort_by(|a, b| a.name.cmp(&b.name));
+
+        Diagnostics {
+            systems: self.system_manager.timings().to_vec(),
+            views,
+        }
+    }
+
+    /// Writes the diagnostics as a table to the log
+    pub fn log_diagnostics(&self) {
+        info!("ECS diagnostics\n{}", self.diagnostics());
+    }
 }
 
 pub struct View {
@@ -119,6 +152,50 @@ impl View {
         }
     }
 
+    /// Number of unique entities that have at least one component in this view
+    pub fn entity_count(&self) -> usize {
+        let mut entities = HashSet::new();
+        for comp_man in self.component_managers.values() {
+            entities.extend(comp_man.entity_ids.iter().copied());
+        }
+        entities.len()
+    }
+
+    /// Number of instances of the specified component in this view
+    pub fn component_count<T: Component>(&self) -> Result<usize, String> {
+        if let Some(comp_man) = self.component_managers.get(&TypeId::of::<T>()) {
+            Ok(comp_man.entity_ids.len())
+        } else {
+            Err(format!(
+                "The '{}' component must be registered before it can be used",
+                any::type_name::<T>()
+            ))
+        }
+    }
+
+    /// Estimated number of bytes reserved by all component storage in this view
+    pub fn memory_usage(&self) -> usize {
+        self.component_managers
+            .values()
+            .map(|comp_man| comp_man.stats().memory_usage)
+            .sum()
+    }
+
+    pub fn stats(&self, name: String) -> ViewStats {
+        let mut components: Vec<ComponentStats> = self
+            .component_managers
+            .values()
+            .map(|comp_man| comp_man.stats())
+            .collect();
+        components.sort_by(|a, b| a.name.cmp(b.name));
+
+        ViewStats {
+            name,
+            entity_count: self.entity_count(),
+            components,
+        }
+    }
+
     pub fn register_component<T: Component>(&mut self) {
         let comp_type = TypeId::of::<T>();
         if !self.component_managers.contains_key(&comp_type) {
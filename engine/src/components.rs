@@ -1,15 +1,49 @@
 use crate::{
+    input::camera_controller::{CameraBindings, CameraController},
     math::*,
-    resource_manager::resource_manager::{MaterialID, MeshID, ShaderProgramID},
+    renderer::{camera::Camera, pipeline_stages::RenderMask, view::ViewSettings},
+    resource_manager::resource_manager::{LodChainID, MaterialID, MeshID, ShaderProgramID},
 };
 
 #[derive(Clone)]
 pub struct Renderable {
+    /// The renderer picks a mesh from the chain each frame, from how large the renderable is on screen
+    pub lod_chain_id: LodChainID,
+    pub material_id: MaterialID,
+    pub shader_id: ShaderProgramID,
+    pub transform: Mat4f,
+    /// Flags that select the stages the renderable is drawn by, see [`RenderMask`]
+    pub pipeline_stages: RenderMask,
+    /// Render layers that the renderable is on, where it is drawn by the cameras that see any of them
+    pub layers: u32,
+}
+
+/// A camera that the scene is drawn from, in addition to the renderer's own
+#[derive(Clone)]
+pub struct CameraView {
+    pub camera: Camera,
+    pub settings: ViewSettings,
+}
+
+/// Moves the camera of the entity's [`CameraView`] each frame. Without bindings, the controller is only moved by
+/// changes to its own settings, such as the target of [`Follow`](crate::input::camera_controller::Follow)
+#[derive(Clone)]
+pub struct CameraControl {
+    pub controller: CameraController,
+    pub bindings: Option<CameraBindings>,
+}
+
+/// A renderable once the renderer has selected the level of its LOD chain that is drawn
+#[derive(Clone)]
+pub struct MeshInstance {
     pub mesh_id: MeshID,
     pub material_id: MaterialID,
     pub shader_id: ShaderProgramID,
     pub transform: Mat4f,
-    pub pipeline_stages: u16,
+    pub pipeline_stages: RenderMask,
+    /// Coverage of the instance while it cross-fades between levels, as a positive value for the level fading in
+    /// and a negative one for the level fading out, where zero means it is drawn solid
+    pub lod_fade: f32,
 }
 
 pub struct Block {}
@@ -11,12 +11,31 @@ use log::debug;
 use crate::{
     components,
     context::Context,
-    input::input::Input,
+    input::{
+        camera_controller::{CameraBindings, CameraController, FirstPerson, FreeFly, Follow, Orbit},
+        input::Input,
+    },
     math::*,
-    memory_manager::uniform_layouts::{DirectionalLight, PointLight, SpotLight},
-    renderer::{pipeline_stages, renderer::Renderer},
+    memory_manager::uniform_layouts::{
+        DirectionalLight, PointLight, ShadowFilter, ShadowSettings, SpotLight,
+    },
+    renderer::{
+        camera::Camera,
+        pipeline_stages::{
+            self,
+            anti_aliasing::{AntiAliasing, AntiAliasingStage},
+            debug::DebugStage,
+            post_process::{PostProcessStage, ToneMapping},
+            scene::SceneStage,
+            shadow::ShadowStage,
+            transparent::{TransparencyMode, TransparentStage},
+        },
+        post_effects::{PostEffect, PostParameter},
+        renderer::Renderer,
+        view::{RenderTarget, ViewSettings, ViewportRect, LAYER_DEFAULT},
+    },
     resource_manager::{
-        model::Material,
+        model::{LodChain, LodLevel, PhongMaterial},
         prefabs::{self, sphere, unit_cube_mesh},
     }, graphics::{texture::{TextureFilter, TextureConfig, TextureWrap}, self},
 };
@@ -26,6 +45,9 @@ pub struct Engine<'a> {
     renderer: Renderer<'a>,
     input: Input,
     world: World,
+    /// Moves the renderer's camera
+    camera_controller: CameraController,
+    camera_bindings: CameraBindings,
 }
 
 impl Engine<'_> {
@@ -35,13 +57,13 @@ impl Engine<'_> {
             renderer: Renderer::new(),
             input: Input::new(),
             world: World::new(),
+            camera_controller: CameraController::FreeFly(FreeFly::default()),
+            camera_bindings: CameraBindings::default(),
         }
     }
 
     fn process_input(&mut self) {
         let delta_time = self.context.last_frame_delta.as_secs_f32();
-        let sensitivity = 10.0 * delta_time;
-        let move_speed = 5.0 * delta_time;
 
         if self.input.is_key_down(VirtualKeyCode::Escape) {
             self.context
@@ -71,32 +93,6 @@ impl Engine<'_> {
 
         // only process movements if we have grabbed cursor
         if self.input.mouse.grabbed {
-            if self.input.mouse.moved {
-                let x = self.input.mouse.delta_x as f32 * sensitivity;
-                let y = self.input.mouse.delta_y as f32 * sensitivity;
-
-                self.renderer.camera.yaw += x;
-                self.renderer.camera.pitch += y;
-
-                self.renderer.camera.pitch = self.renderer.camera.pitch.clamp(-89.0, 89.0);
-
-                self.renderer.camera.direction = Vec3f::new(
-                    self.renderer.camera.yaw.to_radians().cos()
-                        * self.renderer.camera.pitch.to_radians().cos(),
-                    self.renderer.camera.pitch.to_radians().sin(),
-                    self.renderer.camera.yaw.to_radians().sin()
-                        * self.renderer.camera.pitch.to_radians().cos(),
-                )
-                .normalise();
-
-                // debug!(
-                //     "x: {}, y: {}, z: {}",
-                //     self.renderer.camera.direction.x,
-                //     self.renderer.camera.direction.y,
-                //     self.renderer.camera.direction.z
-                // )
-            }
-
             if self.input.is_key_down(VirtualKeyCode::F2) {
                 debug!("Reloading shaders");
                 for p in self
@@ -119,51 +115,247 @@ impl Engine<'_> {
                     debug!("Debug render stage: OFF");
                     self.renderer
                         .renderer_pipeline
-                        .disable_stages(pipeline_stages::STAGE_DEBUG)
+                        .disable_stage(pipeline_stages::STAGE_DEBUG)
                 } else {
                     debug!("Debug render stage: ON");
                     self.renderer
                         .renderer_pipeline
-                        .enable_stages(pipeline_stages::STAGE_DEBUG)
+                        .enable_stage(pipeline_stages::STAGE_DEBUG)
+                }
+            }
+
+            if self.input.is_key_pressed(VirtualKeyCode::F4) {
+                self.world.log_diagnostics();
+            }
+
+            if self.input.is_key_pressed(VirtualKeyCode::F5) {
+                if self
+                    .renderer
+                    .renderer_pipeline
+                    .is_enabled(pipeline_stages::STAGE_DEPTH)
+                {
+                    debug!("Depth pre-pass: OFF");
+                    self.renderer
+                        .renderer_pipeline
+                        .disable_stage(pipeline_stages::STAGE_DEPTH)
+                } else {
+                    debug!("Depth pre-pass: ON");
+                    self.renderer
+                        .renderer_pipeline
+                        .enable_stage(pipeline_stages::STAGE_DEPTH)
+                }
+            }
+
+            if self.input.is_key_pressed(VirtualKeyCode::F6) {
+                if self
+                    .renderer
+                    .renderer_pipeline
+                    .is_enabled(pipeline_stages::STAGE_AO)
+                {
+                    debug!("Ambient occlusion: OFF");
+                    self.renderer
+                        .renderer_pipeline
+                        .disable_stage(pipeline_stages::STAGE_AO)
+                } else {
+                    debug!("Ambient occlusion: ON");
+                    self.renderer
+                        .renderer_pipeline
+                        .enable_stage(pipeline_stages::STAGE_AO)
+                }
+            }
+
+            if self.input.is_key_pressed(VirtualKeyCode::F7) {
+                if let Some(shadow_stage) = self
+                    .renderer
+                    .renderer_pipeline
+                    .get_stage_mut::<ShadowStage>(pipeline_stages::STAGE_SHADOW)
+                {
+                    let debug = !shadow_stage.cascade_settings.debug;
+                    debug!(
+                        "Shadow cascade colours: {}",
+                        if debug { "ON" } else { "OFF" }
+                    );
+                    shadow_stage.cascade_settings.debug = debug;
                 }
             }
 
-            if self.input.is_key_down(VirtualKeyCode::W) {
-                self.renderer.camera.position -= self.renderer.camera.direction * move_speed;
+            if self.input.is_key_pressed(VirtualKeyCode::F8) {
+                if let Some(transparent_stage) = self
+                    .renderer
+                    .renderer_pipeline
+                    .get_stage_mut::<TransparentStage>(pipeline_stages::STAGE_TRANSPARENT)
+                {
+                    transparent_stage.mode = match transparent_stage.mode {
+                        TransparencyMode::Sorted => TransparencyMode::WeightedBlended,
+                        TransparencyMode::WeightedBlended => TransparencyMode::Sorted,
+                    };
+                    debug!("Transparency: {:?}", transparent_stage.mode);
+                    self.renderer.renderer_pipeline.invalidate();
+                }
             }
 
-            if self.input.is_key_down(VirtualKeyCode::A) {
-                self.renderer.camera.position += self
+            if self.input.is_key_pressed(VirtualKeyCode::F9) {
+                if let Some(scene_stage) = self
                     .renderer
-                    .camera
-                    .direction
-                    .cross(Vec3f::new(0.0, 1.0, 0.0))
-                    .normalise()
-                    * move_speed;
+                    .renderer_pipeline
+                    .get_stage_mut::<SceneStage>(pipeline_stages::STAGE_SCENE)
+                {
+                    scene_stage.gpu_culling = !scene_stage.gpu_culling;
+                    debug!(
+                        "GPU culling: {}",
+                        if scene_stage.gpu_culling { "ON" } else { "OFF" }
+                    );
+                }
             }
 
-            if self.input.is_key_down(VirtualKeyCode::S) {
-                self.renderer.camera.position += self.renderer.camera.direction * move_speed;
+            if self.input.is_key_pressed(VirtualKeyCode::F10) {
+                if self
+                    .renderer
+                    .renderer_pipeline
+                    .is_enabled(pipeline_stages::STAGE_HIZ)
+                {
+                    debug!("Occlusion culling: OFF");
+                    self.renderer
+                        .renderer_pipeline
+                        .disable_stage(pipeline_stages::STAGE_HIZ)
+                } else {
+                    debug!("Occlusion culling: ON");
+                    self.renderer
+                        .renderer_pipeline
+                        .enable_stage(pipeline_stages::STAGE_HIZ)
+                }
             }
 
-            if self.input.is_key_down(VirtualKeyCode::D) {
-                self.renderer.camera.position -= self
+            if self.input.is_key_pressed(VirtualKeyCode::F11) {
+                if let Some(debug_stage) = self
                     .renderer
-                    .camera
-                    .direction
-                    .cross(Vec3f::new(0.0, 1.0, 0.0))
-                    .normalise()
-                    * move_speed;
+                    .renderer_pipeline
+                    .get_stage_mut::<DebugStage>(pipeline_stages::STAGE_DEBUG)
+                {
+                    debug_stage.show_occluded = !debug_stage.show_occluded;
+                    debug!(
+                        "Occluded renderables: {}",
+                        if debug_stage.show_occluded {
+                            "SHOWN"
+                        } else {
+                            "HIDDEN"
+                        }
+                    );
+                }
+            }
+
+            if self.input.is_key_pressed(VirtualKeyCode::F12) {
+                let stats = self.renderer.lod_stats();
+                debug!(
+                    "LOD instances: {:?}, triangles: {:?}, cross-fading: {}",
+                    stats.instances, stats.triangles, stats.cross_fading
+                );
+            }
+
+            if self.input.is_key_pressed(VirtualKeyCode::Key1) {
+                if let Some(anti_aliasing_stage) = self
+                    .renderer
+                    .renderer_pipeline
+                    .get_stage_mut::<AntiAliasingStage>(pipeline_stages::STAGE_ANTI_ALIASING)
+                {
+                    anti_aliasing_stage.mode = match anti_aliasing_stage.mode {
+                        AntiAliasing::None => AntiAliasing::Fxaa,
+                        AntiAliasing::Fxaa => AntiAliasing::Taa,
+                        AntiAliasing::Taa => AntiAliasing::None,
+                    };
+                    debug!("Anti-aliasing: {:?}", anti_aliasing_stage.mode);
+                }
+            }
+
+            if self.input.is_key_pressed(VirtualKeyCode::Key2) {
+                let samples = match self.renderer.msaa_samples() {
+                    1 => 2,
+                    2 => 4,
+                    4 => 8,
+                    _ => 1,
+                };
+                match self.renderer.set_msaa_samples(samples) {
+                    Ok(()) => debug!("MSAA samples: {}", samples),
+                    Err(e) => debug!("Failed to set MSAA samples: {}", e),
+                }
             }
 
-            if self.input.is_key_down(VirtualKeyCode::Space) {
-                self.renderer.camera.position += Vec3f::new(0.0, 1.0, 0.0) * move_speed;
+            if let Some(post_process_stage) = self
+                .renderer
+                .renderer_pipeline
+                .get_stage_mut::<PostProcessStage>(pipeline_stages::STAGE_POST_PROCESS)
+            {
+                let settings = &mut post_process_stage.settings;
+
+                if self.input.is_key_pressed(VirtualKeyCode::Key3) {
+                    settings.tone_mapping = match settings.tone_mapping {
+                        ToneMapping::Reinhard => ToneMapping::Aces,
+                        ToneMapping::Aces => ToneMapping::AgX,
+                        ToneMapping::AgX => ToneMapping::Uncharted2,
+                        ToneMapping::Uncharted2 => ToneMapping::Reinhard,
+                    };
+                    debug!("Tone mapping: {:?}", settings.tone_mapping);
+                }
+
+                if self.input.is_key_pressed(VirtualKeyCode::Key4) {
+                    settings.auto_exposure = !settings.auto_exposure;
+                    debug!(
+                        "Auto exposure: {}",
+                        if settings.auto_exposure { "ON" } else { "OFF" }
+                    );
+                }
+
+                if self.input.is_key_pressed(VirtualKeyCode::Key5) {
+                    if let Some(posterise) = post_process_stage.effects.get_mut("posterise") {
+                        posterise.enabled = !posterise.enabled;
+                        debug!(
+                            "Posterise: {}",
+                            if posterise.enabled { "ON" } else { "OFF" }
+                        );
+                    }
+                }
+
+                // one EV per second while held
+                if self.input.is_key_down(VirtualKeyCode::Minus) {
+                    settings.exposure -= delta_time;
+                }
+                if self.input.is_key_down(VirtualKeyCode::Equals) {
+                    settings.exposure += delta_time;
+                }
             }
 
-            if self.input.is_key_down(VirtualKeyCode::LShift) {
-                self.renderer.camera.position -= Vec3f::new(0.0, 1.0, 0.0) * move_speed;
+            if self.input.is_key_pressed(VirtualKeyCode::Key6) {
+                let camera = &self.renderer.camera;
+                self.camera_controller = match self.camera_controller {
+                    CameraController::FreeFly(..) => {
+                        CameraController::FirstPerson(FirstPerson::from_camera(camera))
+                    }
+                    CameraController::FirstPerson(..) => CameraController::Orbit(
+                        Orbit::from_camera(camera, Vec3f::new(10.0, 3.0, -10.0)),
+                    ),
+                    _ => CameraController::FreeFly(FreeFly::default()),
+                };
+                debug!("Camera controller: {}", self.camera_controller.name());
             }
         }
+
+        let controller_input = self.camera_bindings.read(&self.input);
+        self.camera_controller
+            .update(&mut self.renderer.camera, &controller_input, delta_time);
+
+        for (control, view) in self
+            .world
+            .get_current_view_mut()
+            .iter_two_components_mut::<components::CameraControl, components::CameraView>()
+            .unwrap()
+        {
+            let controller_input = control
+                .bindings
+                .map_or(Default::default(), |bindings| bindings.read(&self.input));
+            control
+                .controller
+                .update(&mut view.camera, &controller_input, delta_time);
+        }
     }
 
     /// This runs once before rendering occurs
@@ -283,29 +475,51 @@ impl Engine<'_> {
         let basic_shader_id = self.renderer.load_shader("res/shaders/basic.glsl");
         let light_shader_id = self.renderer.load_shader("res/shaders/lighting.glsl");
         let skybox_shader_id = self.renderer.load_shader("res/shaders/skybox.glsl");
+        let posterise_shader_id = self.renderer.load_shader("res/shaders/post_posterise.glsl");
 
-        let ground_material_id = self.renderer.load_material(Material {
+        if let Some(post_process_stage) = self
+            .renderer
+            .renderer_pipeline
+            .get_stage_mut::<PostProcessStage>(pipeline_stages::STAGE_POST_PROCESS)
+        {
+            let effects = &mut post_process_stage.effects;
+            let mut posterise = PostEffect::fragment("posterise", posterise_shader_id)
+                .with_parameter("levels", PostParameter::Float(8.0));
+            posterise.enabled = false;
+
+            for effect in [
+                PostEffect::sharpen(0.2),
+                PostEffect::chromatic_aberration(0.002),
+                PostEffect::film_grain(0.03),
+                PostEffect::vignette(0.4, 0.6),
+                posterise,
+            ] {
+                effects.push(effect).unwrap();
+            }
+        }
+
+        let ground_material_id = self.renderer.load_material(PhongMaterial {
             shininess: 16.0,
             diffuse_texture_id: Some(ground_texture_id),
             specular_texture_id: None,
             normal_texture_id: Some(ground_normal_texture_id),
         });
 
-        let wood_material_id = self.renderer.load_material(Material {
+        let wood_material_id = self.renderer.load_material(PhongMaterial {
             shininess: 16.0,
             diffuse_texture_id: Some(wood_texture_id),
             specular_texture_id: Some(wood_specular_texture_id),
             normal_texture_id: Some(wood_normal_texture_id),
         });
 
-        let blank_material_id = self.renderer.load_material(Material {
+        let blank_material_id = self.renderer.load_material(PhongMaterial {
             shininess: 16.0,
             diffuse_texture_id: None,
             specular_texture_id: None,
             normal_texture_id: None,
         });
 
-        let lamp_material_id = self.renderer.load_material(Material {
+        let lamp_material_id = self.renderer.load_material(PhongMaterial {
             shininess: 128.0,
             diffuse_texture_id: Some(lamp_texture_id),
             specular_texture_id: Some(lamp_specular_texture_id),
@@ -316,7 +530,19 @@ impl Engine<'_> {
             .renderer
             .load_mesh(unit_cube_mesh(Vec4f::new(0.95, 0.85, 0.65, 0.85)));
 
-        let sphere_model_id = self.renderer.load_mesh(sphere(12));
+        let cube_lod_id = self
+            .renderer
+            .load_lod_chain(LodChain::single(cube_model_id));
+
+        let sphere_lod_id = {
+            let levels = [(32, 0.25), (12, 0.08), (6, 0.0)]
+                .map(|(resolution, screen_size)| LodLevel {
+                    mesh_id: self.renderer.load_mesh(sphere(resolution)),
+                    screen_size,
+                })
+                .to_vec();
+            self.renderer.load_lod_chain(LodChain::new(levels).unwrap())
+        };
 
         self.world
             .register_component::<components::SpotLightBlock>();
@@ -325,6 +551,8 @@ impl Engine<'_> {
         self.world.register_component::<components::DirLightBlock>();
         self.world.register_component::<components::Block>();
         self.world.register_component::<components::Renderable>();
+        self.world.register_component::<components::CameraView>();
+        self.world.register_component::<components::CameraControl>();
 
         let skybox_texture_id = self
             .renderer
@@ -347,7 +575,9 @@ impl Engine<'_> {
             )
             .unwrap();
 
-        let skybox_material_id = self.renderer.load_material(Material {
+        self.renderer.load_environment(skybox_texture_id).unwrap();
+
+        let skybox_material_id = self.renderer.load_material(PhongMaterial {
             shininess: 0.0,
             diffuse_texture_id: Some(skybox_texture_id),
             specular_texture_id: None,
@@ -356,11 +586,12 @@ impl Engine<'_> {
 
         let skybox = self.world.create_entity();
         let skybox_component = components::Renderable {
-            mesh_id: cube_model_id,
+            lod_chain_id: cube_lod_id,
             material_id: skybox_material_id,
             shader_id: skybox_shader_id,
             transform: Mat4f::identity(),
-            pipeline_stages: pipeline_stages::STAGE_SKY,
+            pipeline_stages: pipeline_stages::RENDER_SKY,
+            layers: LAYER_DEFAULT,
         };
         _ = self.world.set_component(&skybox, skybox_component);
 
@@ -382,11 +613,12 @@ impl Engine<'_> {
                 _ = self.world.set_component(
                     &block,
                     components::Renderable {
-                        mesh_id: cube_model_id,
+                        lod_chain_id: cube_lod_id,
                         material_id: ground_material_id,
                         shader_id: light_shader_id,
                         transform: Mat4f::translate(position.x, position.y, position.z),
-                        pipeline_stages: pipeline_stages::STAGE_SCENE,
+                        pipeline_stages: pipeline_stages::RENDER_SCENE,
+                        layers: LAYER_DEFAULT,
                     },
                 );
 
@@ -400,11 +632,12 @@ impl Engine<'_> {
             _ = self.world.set_component(
                 &block,
                 components::Renderable {
-                    mesh_id: cube_model_id,
+                    lod_chain_id: cube_lod_id,
                     material_id: wood_material_id,
                     shader_id: light_shader_id,
                     transform: Mat4f::translate(3.0, i as f32, -5.0),
-                    pipeline_stages: pipeline_stages::STAGE_SCENE | pipeline_stages::STAGE_SHADOW,
+                    pipeline_stages: pipeline_stages::RENDER_SCENE | pipeline_stages::RENDER_SHADOW,
+                    layers: LAYER_DEFAULT,
                 },
             );
         }
@@ -415,11 +648,12 @@ impl Engine<'_> {
             _ = self.world.set_component(
                 &block,
                 components::Renderable {
-                    mesh_id: cube_model_id,
+                    lod_chain_id: cube_lod_id,
                     material_id: wood_material_id,
                     shader_id: light_shader_id,
                     transform: Mat4f::translate(7.0, i as f32, -17.0),
-                    pipeline_stages: pipeline_stages::STAGE_SCENE | pipeline_stages::STAGE_SHADOW,
+                    pipeline_stages: pipeline_stages::RENDER_SCENE | pipeline_stages::RENDER_SHADOW,
+                    layers: LAYER_DEFAULT,
                 },
             );
         }
@@ -430,11 +664,12 @@ impl Engine<'_> {
             _ = self.world.set_component(
                 &block,
                 components::Renderable {
-                    mesh_id: cube_model_id,
+                    lod_chain_id: cube_lod_id,
                     material_id: wood_material_id,
                     shader_id: light_shader_id,
                     transform: Mat4f::translate(24.0, i as f32, -20.0),
-                    pipeline_stages: pipeline_stages::STAGE_SCENE | pipeline_stages::STAGE_SHADOW,
+                    pipeline_stages: pipeline_stages::RENDER_SCENE | pipeline_stages::RENDER_SHADOW,
+                    layers: LAYER_DEFAULT,
                 },
             );
         }
@@ -445,11 +680,12 @@ impl Engine<'_> {
             _ = self.world.set_component(
                 &block,
                 components::Renderable {
-                    mesh_id: cube_model_id,
+                    lod_chain_id: cube_lod_id,
                     material_id: wood_material_id,
                     shader_id: light_shader_id,
                     transform: Mat4f::translate(24.0, i as f32, -8.0),
-                    pipeline_stages: pipeline_stages::STAGE_SCENE | pipeline_stages::STAGE_SHADOW,
+                    pipeline_stages: pipeline_stages::RENDER_SCENE | pipeline_stages::RENDER_SHADOW,
+                    layers: LAYER_DEFAULT,
                 },
             );
         }
@@ -460,11 +696,13 @@ impl Engine<'_> {
             _ = self.world.set_component(
                 &block,
                 components::Renderable {
-                    mesh_id: cube_model_id,
+                    lod_chain_id: cube_lod_id,
                     material_id: wood_material_id,
                     shader_id: light_shader_id,
                     transform: Mat4f::translate(15.0, i as f32, 0.0),
-                    pipeline_stages: pipeline_stages::STAGE_SCENE | pipeline_stages::STAGE_SHADOW,
+                    pipeline_stages: pipeline_stages::RENDER_TRANSPARENT
+                        | pipeline_stages::RENDER_SHADOW,
+                    layers: LAYER_DEFAULT,
                 },
             );
         }
@@ -473,13 +711,14 @@ impl Engine<'_> {
         _ = self.world.set_component(
             &sphere,
             components::Renderable {
-                mesh_id: sphere_model_id,
+                lod_chain_id: sphere_lod_id,
                 material_id: ground_material_id,
                 shader_id: light_shader_id,
                 transform: Mat4f::translate(10.0, 3.0, -10.0),
-                pipeline_stages: pipeline_stages::STAGE_SCENE
-                    | pipeline_stages::STAGE_SHADOW
-                    | pipeline_stages::STAGE_DEBUG,
+                pipeline_stages: pipeline_stages::RENDER_SCENE
+                    | pipeline_stages::RENDER_SHADOW
+                    | pipeline_stages::RENDER_DEBUG,
+                layers: LAYER_DEFAULT,
             },
         );
 
@@ -496,11 +735,12 @@ impl Engine<'_> {
         _ = self.world.set_component(
             &lamp,
             components::Renderable {
-                mesh_id: cube_model_id,
+                lod_chain_id: cube_lod_id,
                 material_id: lamp_material_id,
                 shader_id: basic_shader_id,
                 transform: Mat4f::translate(0.0, 16.0, 0.0),
-                pipeline_stages: pipeline_stages::STAGE_SCENE,
+                pipeline_stages: pipeline_stages::RENDER_SCENE,
+                layers: LAYER_DEFAULT,
             },
         );
 
@@ -514,11 +754,11 @@ impl Engine<'_> {
         // _ = self.world.set_component(
         //     &lamp,
         //     components::Renderable {
-        //         mesh_id: cube_model_id,
+        //         lod_chain_id: cube_lod_id,
         //         material_id: lamp_material_id,
         //         shader_id: basic_shader_id,
         //         transform: Mat4f::translate(24.0, 6.0, -16.0),
-        //         pipeline_stages: pipeline_stages::STAGE_SCENE,
+        //         pipeline_stages: pipeline_stages::RENDER_SCENE,
         //     },
         // );
 
@@ -535,11 +775,11 @@ impl Engine<'_> {
         // _ = self.world.set_component(
         //     &lamp,
         //     components::Renderable {
-        //         mesh_id: cube_model_id,
+        //         lod_chain_id: cube_lod_id,
         //         material_id: lamp_material_id,
         //         shader_id: basic_shader_id,
         //         transform: Mat4f::translate(4.0, 4.0, -16.0),
-        //         pipeline_stages: pipeline_stages::STAGE_SCENE,
+        //         pipeline_stages: pipeline_stages::RENDER_SCENE,
         //     },
         // );
 
@@ -556,37 +796,100 @@ impl Engine<'_> {
         // _ = self.world.set_component(
         //     &lamp,
         //     components::Renderable {
-        //         mesh_id: cube_model_id,
+        //         lod_chain_id: cube_lod_id,
         //         material_id: lamp_material_id,
         //         shader_id: basic_shader_id,
         //         transform: Mat4f::translate(4.0, 4.0, -5.0),
-        //         pipeline_stages: pipeline_stages::STAGE_SCENE,
+        //         pipeline_stages: pipeline_stages::RENDER_SCENE,
         //     },
         // );
 
         let axis_mesh = prefabs::axis();
         let axis_mesh_id = self.renderer.load_mesh(axis_mesh);
+        let axis_lod_id = self.renderer.load_lod_chain(LodChain::single(axis_mesh_id));
 
         let axis = self.world.create_entity();
         _ = self.world.set_component(
             &axis,
             components::Renderable {
-                mesh_id: axis_mesh_id,
+                lod_chain_id: axis_lod_id,
                 material_id: lamp_material_id,
                 shader_id: basic_shader_id,
                 transform: Mat4f::translate(-0.5, -0.5, 0.0),
-                pipeline_stages: pipeline_stages::STAGE_DEBUG,
+                pipeline_stages: pipeline_stages::RENDER_DEBUG,
+                layers: LAYER_DEFAULT,
+            },
+        );
+
+        // a monitor that shows the scene from above, drawn before the main camera so it is up to date when seen
+        let monitor_texture = self.renderer.create_render_texture(512, 512).unwrap();
+        let monitor_material_id = self.renderer.load_material(PhongMaterial {
+            shininess: 0.0,
+            diffuse_texture_id: Some(monitor_texture.texture_id),
+            specular_texture_id: None,
+            normal_texture_id: None,
+        });
+
+        let monitor = self.world.create_entity();
+        _ = self.world.set_component(
+            &monitor,
+            components::Renderable {
+                lod_chain_id: cube_lod_id,
+                material_id: monitor_material_id,
+                shader_id: basic_shader_id,
+                transform: Mat4f::translate(-6.0, 3.0, -6.0) * Mat4f::scale(4.0, 4.0, 0.2),
+                pipeline_stages: pipeline_stages::RENDER_SCENE,
+                layers: LAYER_DEFAULT,
+            },
+        );
+
+        let mut monitor_camera = Camera::new_perspective(70.0, 0.1, 100.0);
+        monitor_camera.position = Vec3f::new(5.0, 30.0, 5.0);
+        monitor_camera.look_towards(&Vec3f::new(5.0, 0.0, 5.0));
+        _ = self.world.set_component(
+            &monitor,
+            components::CameraView {
+                camera: monitor_camera.clone(),
+                settings: ViewSettings {
+                    target: RenderTarget::Texture(monitor_texture),
+                    priority: -1,
+                    ..Default::default()
+                },
             },
-        )
+        );
+
+        // a picture in picture, in the corner of the window, that starts from the same view then swoops down to
+        // follow the sphere
+        let corner = self.world.create_entity();
+        _ = self.world.set_component(
+            &corner,
+            components::CameraView {
+                camera: monitor_camera,
+                settings: ViewSettings {
+                    viewport: ViewportRect::new(0.75, 0.75, 0.25, 0.25),
+                    priority: 1,
+                    ..Default::default()
+                },
+            },
+        );
+        _ = self.world.set_component(
+            &corner,
+            components::CameraControl {
+                controller: CameraController::Follow(Follow {
+                    target: Vec3f::new(10.0, 3.0, -10.0),
+                    offset: Vec3f::new(0.0, 4.0, 8.0),
+                    stiffness: 1.5,
+                }),
+                bindings: None,
+            },
+        );
     }
 
     /// This runs once per frame
     fn update(&mut self) {
         self.process_input();
         self.draw();
-        self.input.mouse.moved = false;
-        self.input.mouse.delta_x = 0.0;
-        self.input.mouse.delta_y = 0.0;
+        self.input.end_frame();
         self.context.frames += 1;
     }
 
@@ -615,6 +918,7 @@ impl Engine<'_> {
                     renderable.transform[(2, 3)],
                 ),
                 direction: spot_light.direction,
+                shadow: ShadowSettings::new(ShadowFilter::Pcss),
                 ..Default::default()
             })
         }
@@ -658,6 +962,7 @@ impl Engine<'_> {
                     renderable.transform[(2, 3)],
                 ),
                 direction: dir_light.direction,
+                shadow: ShadowSettings::new(ShadowFilter::Pcss),
 
                 ..Default::default()
             })
@@ -672,6 +977,15 @@ impl Engine<'_> {
             self.renderer.draw(&renderable);
         }
 
+        for camera in self
+            .world
+            .get_current_view_mut()
+            .iter_components_mut::<components::CameraView>()
+            .unwrap()
+        {
+            self.renderer.add_camera(camera);
+        }
+
         self.renderer.end();
     }
 
@@ -16,6 +16,7 @@ pub struct BufferLayout {
     pub stride: u32,
     pub buffer_size: u32,
     pub divisor: u32,
+    pub sections: u32,
 }
 
 pub struct VertexArray {
@@ -48,3 +49,13 @@ pub struct BufferLock {
 pub struct BufferLockManager {
     pub buffer_locks: Vec<BufferLock>,
 }
+
+/// Persistently mapped buffer that texture data is copied into by the GPU, to be read on the CPU once the copy has
+/// finished without stalling the pipeline
+pub struct ReadbackBuffer {
+    pub handle: ApiHandle,
+    pub buffer_base_pointer: *const u8,
+    pub size_bytes: u32,
+    /// Signalled once the last copy has finished
+    pub fence_handle: Option<FenceHandle>,
+}
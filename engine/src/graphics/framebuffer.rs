@@ -2,7 +2,7 @@
 pub use super::opengl::framebuffer::InternalFormat;
 use super::{texture::{TextureType, Texture}, ApiHandle};
 
-#[derive(Clone)]
+#[derive(Clone, PartialEq)]
 pub enum FramebufferAttachmentConfig {
     Renderbuffer {
         internal_format: InternalFormat,
@@ -23,9 +23,11 @@ pub enum FramebufferAttachment {
 }
 
 
-#[derive(Clone)]
+#[derive(Clone, PartialEq)]
 pub struct FramebufferConfig {
     pub colour: FramebufferAttachmentConfig,
+    /// Further colour attachments, after `colour`, for passes that write multiple render targets
+    pub additional_colours: Vec<FramebufferAttachmentConfig>,
     pub depth: FramebufferAttachmentConfig,
     pub stencil: FramebufferAttachmentConfig,
 
@@ -38,6 +40,7 @@ impl Default for FramebufferConfig {
     fn default() -> Self {
         Self {
             colour: FramebufferAttachmentConfig::None,
+            additional_colours: Vec::new(),
             depth: FramebufferAttachmentConfig::None,
             stencil: FramebufferAttachmentConfig::None,
             width: crate::WIDTH as u32,
@@ -50,8 +53,20 @@ impl Default for FramebufferConfig {
 pub struct Framebuffer {
     pub handle: ApiHandle,
     pub colour_handle: FramebufferAttachment,
+    pub additional_colour_handles: Vec<FramebufferAttachment>,
     pub depth_handle: FramebufferAttachment,
     pub stencil_handle: FramebufferAttachment,
 
     pub config: FramebufferConfig,
 }
+
+impl Framebuffer {
+    /// The colour attachment of a draw buffer, where zero is `colour_handle`
+    pub fn colour_attachment(&self, draw_buffer: usize) -> Option<&FramebufferAttachment> {
+        if draw_buffer == 0 {
+            Some(&self.colour_handle)
+        } else {
+            self.additional_colour_handles.get(draw_buffer - 1)
+        }
+    }
+}
@@ -2,7 +2,7 @@ use log::{error, trace};
 
 use super::shader::{shader_data_element_count, shader_data_gl_type, shader_data_size_bytes};
 use crate::{
-    graphics::{buffer::*, graphics::ApiEnum, shader::ShaderDataType},
+    graphics::{buffer::*, graphics::ApiEnum, shader::ShaderDataType, texture::Texture},
     platform::rustgl as gl,
 };
 
@@ -16,6 +16,7 @@ pub enum BufferType {
     ShaderStorage = gl::SHADER_STORAGE_BUFFER as isize,
     Texture = gl::TEXTURE_BUFFER as isize,
     Uniform = gl::UNIFORM_BUFFER as isize,
+    Parameter = gl::PARAMETER_BUFFER as isize,
     TransformFeedback = gl::TRANSFORM_FEEDBACK_BUFFER as isize,
 }
 
@@ -26,6 +27,7 @@ pub fn gl_buffer_target_to_string(target: ApiEnum) -> &'static str {
         0x8A11 => "Uniform Buffer",
         0x90D2 => "Shader Storage Buffer",
         0x8F3F => "Draw Indirect Buffer",
+        0x80EE => "Parameter Buffer",
         _ => "Unknown",
     }
 }
@@ -44,7 +46,12 @@ impl BufferElement {
 }
 
 impl BufferLayout {
-    pub fn new(mut elements: Vec<BufferElement>, buffer_size: u32, divisor: u32) -> Self {
+    pub fn new(
+        mut elements: Vec<BufferElement>,
+        buffer_size: u32,
+        divisor: u32,
+        sections: u32,
+    ) -> Self {
         let mut offset = 0;
         for element in elements.iter_mut() {
             element.offset = offset;
@@ -56,22 +63,24 @@ impl BufferLayout {
             stride: offset,
             buffer_size,
             divisor,
+            sections,
         }
     }
 }
 
 impl VertexArray {
-    pub fn new(layouts: Vec<BufferLayout>, ebo_size: u32, multiple: u32) -> Self {
+    /// Each vertex buffer is created with the number of sections given by its layout
+    pub fn new(layouts: Vec<BufferLayout>, ebo_size: u32, ebo_sections: u32) -> Self {
         let vao = unsafe { gl::create_named_vertex_array().unwrap() };
 
         let mut vbos = Vec::new();
-        let ebo = BufferStorage::new(BufferType::Index, ebo_size, multiple);
+        let ebo = BufferStorage::new(BufferType::Index, ebo_size, ebo_sections);
 
         for layout in layouts.iter() {
             vbos.push(BufferStorage::new(
                 BufferType::Vertex,
                 layout.buffer_size,
-                multiple,
+                layout.sections,
             ))
         }
 
@@ -304,6 +313,20 @@ impl BufferStorage {
         }
     }
 
+    /// Binds the whole buffer, every section included, to an indexed binding of another type, such as a vertex
+    /// buffer that is written to by a compute shader
+    pub fn bind_buffer_base_as(&self, buffer_type: BufferType, binding_index: u32) {
+        unsafe {
+            gl::bind_buffer_range(
+                buffer_type as u32,
+                binding_index,
+                Some(gl::GlBuffer(self.handle)),
+                0,
+                (self.section_size_bytes * self.sections) as i32,
+            )
+        }
+    }
+
     /// Round-robin to next section, if there are more than one
     pub fn next_section(&mut self) {
         self.current_section = (self.current_section + 1) % self.sections;
@@ -410,4 +433,90 @@ impl BufferStorage {
     pub fn unbind(&self) {
         unsafe { gl::bind_buffer(self.buffer_type as u32, None) }
     }
+
+    pub fn bind_as(&self, buffer_type: BufferType) {
+        unsafe { gl::bind_buffer(buffer_type as u32, Some(gl::GlBuffer(self.handle))) }
+    }
+}
+
+impl ReadbackBuffer {
+    pub fn new(size_bytes: u32) -> Self {
+        let handle: gl::GlBuffer;
+        let map_flags = gl::MAP_READ_BIT | gl::MAP_PERSISTENT_BIT | gl::MAP_COHERENT_BIT;
+
+        let buffer_base_pointer = unsafe {
+            handle = gl::create_named_buffer().unwrap();
+            gl::named_buffer_storage(handle, size_bytes as i32, None, map_flags);
+            gl::map_named_buffer_range(handle, 0, size_bytes as i32, map_flags)
+        };
+
+        ReadbackBuffer {
+            handle: handle.0,
+            buffer_base_pointer,
+            size_bytes,
+            fence_handle: None,
+        }
+    }
+
+    /// Starts copying a level of a single channel float texture into the buffer. <br>
+    /// Returns false, without copying, if the last copy has not been read yet or the level doesn't fit.
+    pub fn read_texture_level_f32(&mut self, texture: &Texture, level: u32) -> bool {
+        let width = (texture.width >> level).max(1);
+        let height = (texture.height >> level).max(1);
+        let size_bytes = width * height * std::mem::size_of::<f32>() as u32;
+
+        if self.fence_handle.is_some() || size_bytes > self.size_bytes {
+            return false;
+        }
+
+        unsafe {
+            gl::bind_buffer(gl::PIXEL_PACK_BUFFER, Some(gl::GlBuffer(self.handle)));
+            gl::get_texture_image(
+                gl::GlTexture(texture.handle),
+                level as i32,
+                gl::RED,
+                gl::FLOAT,
+                size_bytes as i32,
+                gl::PixelPackData::BufferOffset(0),
+            );
+            gl::bind_buffer(gl::PIXEL_PACK_BUFFER, None);
+
+            self.fence_handle = Some(gl::fence_sync(gl::SYNC_GPU_COMMANDS_COMPLETE, 0).unwrap().0);
+        }
+
+        true
+    }
+
+    /// Contents of the buffer if the last copy has finished, without waiting for it. <br>
+    /// The buffer can be copied into again afterwards, so the contents should be taken before then.
+    pub fn poll(&mut self) -> Option<&[u8]> {
+        let fence_handle = self.fence_handle?;
+
+        match unsafe { gl::client_wait_sync(gl::GlFence(fence_handle), 0, 0) } {
+            gl::ALREADY_SIGNALED | gl::CONDITION_SATISFIED => {}
+            gl::WAIT_FAILED => {
+                error!("Failed polling readback fence sync");
+                return None;
+            }
+            _ => return None,
+        }
+
+        unsafe {
+            gl::delete_sync(gl::GlFence(fence_handle));
+        }
+        self.fence_handle = None;
+
+        Some(unsafe {
+            std::slice::from_raw_parts(self.buffer_base_pointer, self.size_bytes as usize)
+        })
+    }
+
+    pub fn delete(&mut self) {
+        unsafe {
+            if let Some(fence_handle) = self.fence_handle.take() {
+                gl::delete_sync(gl::GlFence(fence_handle));
+            }
+            gl::delete_buffer(gl::GlBuffer(self.handle));
+        }
+    }
 }
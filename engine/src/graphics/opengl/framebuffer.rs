@@ -8,7 +8,7 @@ use crate::{
     platform::rustgl as gl,
 };
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, PartialEq)]
 pub enum InternalFormat {
     Depth16 = gl::DEPTH_COMPONENT16 as isize,
     Depth24 = gl::DEPTH_COMPONENT24 as isize,
@@ -90,6 +90,8 @@ impl Framebuffer {
             },
         };
 
+        let additional_colour_handles = Self::create_additional_colours(framebuffer, &config);
+
         let depth_handle = match config.depth {
             FramebufferAttachmentConfig::Renderbuffer { internal_format } => {
                 Self::create_renderbuffer(
@@ -151,6 +153,7 @@ impl Framebuffer {
         Framebuffer {
             handle: framebuffer.0,
             colour_handle,
+            additional_colour_handles,
             depth_handle,
             stencil_handle,
             config,
@@ -197,6 +200,8 @@ impl Framebuffer {
             },
         };
 
+        let additional_colour_handles = Self::create_additional_colours(framebuffer, &config);
+
         let depth_handle = match config.depth {
             FramebufferAttachmentConfig::Renderbuffer { internal_format } => {
                 Self::create_renderbuffer_multisample(
@@ -260,12 +265,76 @@ impl Framebuffer {
         Framebuffer {
             handle: framebuffer.0,
             colour_handle,
+            additional_colour_handles,
             depth_handle,
             stencil_handle,
             config,
         }
     }
 
+    /// Attaches `additional_colours` after the first colour attachment, and has fragment outputs written to all of
+    /// them
+    fn create_additional_colours(
+        framebuffer: gl::GlFramebuffer,
+        config: &FramebufferConfig,
+    ) -> Vec<FramebufferAttachment> {
+        if config.additional_colours.is_empty() {
+            return Vec::new();
+        }
+
+        let mut handles = Vec::with_capacity(config.additional_colours.len());
+        let mut draw_buffers = vec![gl::COLOR_ATTACHMENT0];
+
+        for (i, attachment) in config.additional_colours.iter().enumerate() {
+            let attachment_type = gl::COLOR_ATTACHMENT1 + i as u32;
+            draw_buffers.push(attachment_type);
+
+            handles.push(match attachment {
+                FramebufferAttachmentConfig::Renderbuffer { internal_format } => {
+                    if config.samples > 1 {
+                        Self::create_renderbuffer_multisample(
+                            framebuffer,
+                            attachment_type,
+                            *internal_format,
+                            config.width,
+                            config.height,
+                            config.samples,
+                        )
+                    } else {
+                        Self::create_renderbuffer(
+                            framebuffer,
+                            attachment_type,
+                            *internal_format,
+                            config.width,
+                            config.height,
+                        )
+                    }
+                }
+                FramebufferAttachmentConfig::Texture {
+                    target,
+                    internal_format,
+                    layers,
+                    levels,
+                } => Self::create_texture(
+                    target,
+                    framebuffer,
+                    attachment_type,
+                    *internal_format,
+                    config.width,
+                    config.height,
+                    *layers,
+                    *levels,
+                    config.samples,
+                ),
+                FramebufferAttachmentConfig::None => FramebufferAttachment::None,
+            });
+        }
+
+        unsafe { gl::named_framebuffer_draw_buffers(framebuffer, &draw_buffers) };
+
+        handles
+    }
+
     fn create_renderbuffer(
         framebuffer: gl::GlFramebuffer,
         attachment_type: ApiEnum,
@@ -381,6 +450,59 @@ impl Framebuffer {
         }
     }
 
+    /// Like [`Framebuffer::blit_to_default_framebuffer`], but into another framebuffer
+    pub fn blit_to_framebuffer(
+        &self,
+        target: &Framebuffer,
+        src_x0: u32,
+        src_y0: u32,
+        src_x1: u32,
+        src_y1: u32,
+        dst_x0: u32,
+        dst_y0: u32,
+        dst_x1: u32,
+        dst_y1: u32,
+        filter: TextureFilter,
+    ) {
+        unsafe {
+            gl::blit_named_framebuffer(
+                gl::GlFramebuffer(self.handle),
+                gl::GlFramebuffer(target.handle),
+                src_x0 as i32,
+                src_y0 as i32,
+                src_x1 as i32,
+                src_y1 as i32,
+                dst_x0 as i32,
+                dst_y0 as i32,
+                dst_x1 as i32,
+                dst_y1 as i32,
+                gl::COLOR_BUFFER_BIT,
+                filter as u32,
+            )
+        }
+    }
+
+    /// Draws into a texture that is owned elsewhere, such as by the texture manager, through the first colour
+    /// attachment. The texture isn't deleted with the framebuffer
+    pub fn attach_colour_texture(&self, texture: &Texture) {
+        unsafe {
+            gl::named_framebuffer_texture(
+                gl::GlFramebuffer(self.handle),
+                gl::COLOR_ATTACHMENT0,
+                gl::GlTexture(texture.handle),
+                0,
+            );
+            gl::named_framebuffer_draw_buffer(
+                gl::GlFramebuffer(self.handle),
+                gl::COLOR_ATTACHMENT0,
+            );
+            gl::named_framebuffer_read_buffer(
+                gl::GlFramebuffer(self.handle),
+                gl::COLOR_ATTACHMENT0,
+            );
+        }
+    }
+
     pub fn clear_color(&self, r: f32, g: f32, b: f32, a: f32) {
         unsafe {
             gl::clear_named_framebuffer_f32_slice(
@@ -392,6 +514,59 @@ impl Framebuffer {
         }
     }
 
+    /// Copies the first colour attachment into another framebuffer of the same size and colour format, resolving
+    /// it when this framebuffer is multisampled
+    pub fn blit_colour(&self, target: &Framebuffer) {
+        unsafe {
+            gl::blit_named_framebuffer(
+                gl::GlFramebuffer(self.handle),
+                gl::GlFramebuffer(target.handle),
+                0,
+                0,
+                self.config.width as i32,
+                self.config.height as i32,
+                0,
+                0,
+                target.config.width as i32,
+                target.config.height as i32,
+                gl::COLOR_BUFFER_BIT,
+                gl::NEAREST,
+            )
+        }
+    }
+
+    /// Copies the depth attachment into another framebuffer of the same size and depth format
+    pub fn blit_depth(&self, target: &Framebuffer) {
+        unsafe {
+            gl::blit_named_framebuffer(
+                gl::GlFramebuffer(self.handle),
+                gl::GlFramebuffer(target.handle),
+                0,
+                0,
+                self.config.width as i32,
+                self.config.height as i32,
+                0,
+                0,
+                target.config.width as i32,
+                target.config.height as i32,
+                gl::DEPTH_BUFFER_BIT,
+                gl::NEAREST,
+            )
+        }
+    }
+
+    /// Clears one of the colour attachments, where zero is the first
+    pub fn clear_draw_buffer(&self, draw_buffer: u32, r: f32, g: f32, b: f32, a: f32) {
+        unsafe {
+            gl::clear_named_framebuffer_f32_slice(
+                gl::GlFramebuffer(self.handle),
+                gl::COLOR,
+                draw_buffer as i32,
+                &[r, g, b, a],
+            )
+        }
+    }
+
     pub fn clear_depth(&self, value: f32) {
         unsafe {
             gl::clear_named_framebuffer_f32(gl::GlFramebuffer(self.handle), gl::DEPTH, 0, value)
@@ -406,6 +581,14 @@ impl Framebuffer {
         *self = new;
     }
 
+    /// Recreates the framebuffer with a different number of samples per pixel
+    pub fn set_samples(&mut self, samples: u32) {
+        self.config.samples = samples;
+        let new = Self::new(&self.config);
+        self.delete();
+        *self = new;
+    }
+
     pub fn bind(&self) {
         unsafe {
             gl::bind_framebuffer(gl::FRAMEBUFFER, Some(gl::GlFramebuffer(self.handle)));
@@ -430,6 +613,18 @@ impl Framebuffer {
             FramebufferAttachment::None => (),
         }
 
+        for attachment in self.additional_colour_handles.iter() {
+            match attachment {
+                FramebufferAttachment::Renderbuffer(handle) => unsafe {
+                    gl::delete_renderbuffer(gl::GlRenderbuffer(*handle))
+                },
+                FramebufferAttachment::Texture(texture) => unsafe {
+                    gl::delete_texture(gl::GlTexture(texture.handle))
+                },
+                FramebufferAttachment::None => (),
+            }
+        }
+
         match self.depth_handle {
             FramebufferAttachment::Renderbuffer(handle) => unsafe {
                 gl::delete_renderbuffer(gl::GlRenderbuffer(handle))
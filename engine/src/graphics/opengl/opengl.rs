@@ -12,6 +12,12 @@ pub enum AccessModifier {
 #[derive(Clone, Copy)]
 pub enum Barriers {
     ShaderImageAccess = gl::SHADER_IMAGE_ACCESS_BARRIER_BIT as isize,
+    TextureFetch = gl::TEXTURE_FETCH_BARRIER_BIT as isize,
+    Framebuffer = gl::FRAMEBUFFER_BARRIER_BIT as isize,
+    ShaderStorage = gl::SHADER_STORAGE_BARRIER_BIT as isize,
+    Command = gl::COMMAND_BARRIER_BIT as isize,
+    VertexAttribArray = gl::VERTEX_ATTRIB_ARRAY_BARRIER_BIT as isize,
+    TextureUpdate = gl::TEXTURE_UPDATE_BARRIER_BIT as isize,
 }
 
 #[derive(Clone, Copy)]
@@ -148,6 +154,11 @@ pub fn set_clear_color(r: f32, g: f32, b: f32, a: f32) {
     unsafe { gl::clear_color(r, g, b, a) }
 }
 
+/// The most samples per pixel that a multisampled framebuffer can have
+pub fn max_samples() -> u32 {
+    unsafe { gl::get_parameter_i32(gl::MAX_SAMPLES) as u32 }
+}
+
 pub fn memory_barrier(bitfield: u32) {
     unsafe { gl::memory_barrier(bitfield) }
 }
@@ -169,4 +180,31 @@ pub fn submit_draw_call(
     }
 }
 
+/// Like [`submit_draw_call`], but the number of commands is read from the parameter buffer, such as when they
+/// were written by a compute shader. At most `max_command_count` are drawn
+pub fn submit_draw_call_count(
+    mode: DrawMode,
+    index_type: DataType,
+    command_byte_offset: u32,
+    count_byte_offset: u32,
+    max_command_count: u32,
+) {
+    unsafe {
+        gl::multi_draw_elements_indirect_count_offset(
+            mode as u32,
+            index_type as u32,
+            command_byte_offset as i32,
+            count_byte_offset as i32,
+            max_command_count as i32,
+            0,
+        );
+    }
+}
+
+/// Draws `count` vertices without an index buffer, such as a fullscreen triangle whose vertices are made in the
+/// vertex shader
+pub fn submit_draw_arrays(mode: DrawMode, first: u32, count: u32) {
+    unsafe { gl::draw_arrays(mode as u32, first as i32, count as i32) }
+}
+
 // memory_manager.get_indirect_command_index() - command_count) * DRAW_COMMAND_SIZE
@@ -80,6 +80,15 @@ impl RasteriserState {
 
         unsafe { gl::depth_mask(self.depth_mask) }
 
+        unsafe {
+            gl::color_mask(
+                self.colour_mask,
+                self.colour_mask,
+                self.colour_mask,
+                self.colour_mask,
+            )
+        }
+
         unsafe { gl::cull_face(self.cull_face as u32) }
 
         if self.culling {
@@ -163,6 +172,18 @@ impl RasteriserState {
             unsafe { gl::depth_mask(self.depth_mask) }
         }
 
+        if self.colour_mask != state.colour_mask {
+            self.colour_mask = state.colour_mask;
+            unsafe {
+                gl::color_mask(
+                    self.colour_mask,
+                    self.colour_mask,
+                    self.colour_mask,
+                    self.colour_mask,
+                )
+            }
+        }
+
         if self.cull_face != state.cull_face {
             self.cull_face = state.cull_face;
             unsafe { gl::cull_face(self.cull_face as u32) }
@@ -213,6 +234,17 @@ impl RasteriserState {
         }
     }
 
+    /// Blends a single draw buffer with a different function to the others. This isn't tracked, so the shared blend
+    /// function must be restored with `update_all` afterwards
+    pub fn set_draw_buffer_blend_func(
+        &self,
+        draw_buffer: u32,
+        source: Blending,
+        destination: Blending,
+    ) {
+        unsafe { gl::blend_func_draw_buffer(draw_buffer, source as u32, destination as u32) }
+    }
+
     pub fn set_depth(&mut self, state: bool) {
         if self.depth != state {
             self.depth = state;
@@ -266,6 +298,14 @@ impl RasteriserState {
             unsafe { gl::depth_mask(state) }
         }
     }
+
+    pub fn set_colour_mask(&mut self, state: bool) {
+        if self.colour_mask != state {
+            self.colour_mask = state;
+            unsafe { gl::color_mask(state, state, state, state) }
+        }
+    }
+
     pub fn set_culled_face(&mut self, face: Orientation) {
         if self.cull_face != face {
             self.cull_face = face;
@@ -4,12 +4,12 @@ use super::{framebuffer::InternalFormat, AccessModifier, DataType};
 use crate::{
     graphics::{
         graphics::ApiEnum,
-        texture::{Image, Texture, TextureConfig},
+        texture::{lut_size, lut_volume, Image, Texture, TextureConfig},
     },
     platform::rustgl as gl,
 };
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, PartialEq)]
 pub enum TextureType {
     T2D = gl::TEXTURE_2D as isize,
     T2DArray = gl::TEXTURE_2D_ARRAY as isize,
@@ -323,6 +323,85 @@ impl Texture {
         }
     }
 
+    /// Creates a 3D colour grading LUT from an image of its slices laid side by side, see [`lut_size`]. The red
+    /// channel runs across each slice, green down it, and blue from one slice to the next
+    pub fn new_lut(image: Image, config: &TextureConfig) -> Result<Texture, String> {
+        let target = gl::TEXTURE_3D;
+        let config = config.clone();
+
+        if image.compressed {
+            return Err(format!("LUT '{}' must not be compressed", image.path));
+        }
+        let size = lut_size(image.width, image.height)?;
+
+        let channels = match image.format {
+            ImageFormat::RGB => 3,
+            ImageFormat::RGBA => 4,
+            _ => return Err(format!("LUT '{}' must be RGB or RGBA", image.path)),
+        };
+        let channel_bytes = match image.data_type {
+            DataType::Uint8 => 1,
+            DataType::Uint16 => 2,
+            DataType::Float32 => 4,
+            _ => return Err(format!("LUT '{}' has an unsupported data type", image.path)),
+        };
+        let bytes = lut_volume(&image.bytes, size, channels * channel_bytes);
+
+        let format = image.format;
+        let data_type = image.data_type;
+        let internal_format = derive_gl_internal_format(format, data_type, config.srgb);
+
+        let handle = unsafe {
+            let handle = gl::create_named_texture(target).unwrap();
+            gl::texture_storage_3d(
+                handle,
+                1,
+                internal_format as u32,
+                size as i32,
+                size as i32,
+                size as i32,
+            );
+
+            // rows of RGB texels aren't always a multiple of four bytes
+            gl::pixel_store_i32(gl::UNPACK_ALIGNMENT, 1);
+            gl::texture_sub_image_3d(
+                handle,
+                0,
+                0,
+                0,
+                0,
+                size as i32,
+                size as i32,
+                size as i32,
+                format as u32,
+                data_type as u32,
+                gl::PixelUnpackData::Slice(&bytes),
+            );
+            gl::pixel_store_i32(gl::UNPACK_ALIGNMENT, 4);
+
+            gl::texture_parameter_i32(handle, gl::TEXTURE_WRAP_S, config.wrap as i32);
+            gl::texture_parameter_i32(handle, gl::TEXTURE_WRAP_T, config.wrap as i32);
+            gl::texture_parameter_i32(handle, gl::TEXTURE_WRAP_R, config.wrap as i32);
+            gl::texture_parameter_i32(handle, gl::TEXTURE_MIN_FILTER, config.min_filter as i32);
+            gl::texture_parameter_i32(handle, gl::TEXTURE_MAG_FILTER, config.mag_filter as i32);
+
+            handle
+        };
+
+        Ok(Texture {
+            config,
+            handle: handle.0,
+            shader_texture_handle: None,
+            resident: false,
+            target,
+            internal_format,
+            format,
+            data_type,
+            width: size,
+            height: size,
+        })
+    }
+
     pub fn new_framebuffer_texture(
         target: TextureType,
         internal_format: InternalFormat,
@@ -420,6 +499,16 @@ impl Texture {
         }
     }
 
+    /// Handle that samples the texture using the sampler's parameters rather than its own. <br>
+    /// Unlike the texture's own handle, this is not tracked, so it is made resident straight away.
+    pub fn get_resident_sampler_handle(&self, sampler: gl::GlSampler) -> u64 {
+        unsafe {
+            let handle = gl::get_texture_sampler_handle(gl::GlTexture(self.handle), sampler);
+            gl::make_texture_handle_resident(handle);
+            handle.0.get()
+        }
+    }
+
     pub fn make_texture_resident(&mut self) {
         if !self.resident {
             let shader_texture_handle = self.get_shader_texture_handle();
@@ -465,6 +554,58 @@ impl Texture {
         }
     }
 
+    /// Binds every layer of the level, such as all six faces of a cubemap, rather than a single layer
+    pub fn bind_layered_image_unit(
+        &self,
+        unit: u32,
+        level: u32,
+        access: AccessModifier,
+        format: InternalFormat,
+    ) {
+        unsafe {
+            gl::bind_image_texture(
+                unit,
+                gl::GlTexture(self.handle),
+                level as i32,
+                true,
+                0,
+                access as u32,
+                format as u32,
+            );
+        }
+    }
+
+    /// Regenerates every level below the first from its contents, such as after it has been written by a shader
+    pub fn generate_mipmaps(&self) {
+        unsafe {
+            gl::generate_texture_mipmap(gl::GlTexture(self.handle));
+        }
+    }
+
+    /// Copies a level into the same level of another texture, of the same size and a compatible format, without
+    /// going through a framebuffer
+    pub fn copy_level_to(&self, destination: &Texture, level: u32) {
+        unsafe {
+            gl::copy_image_sub_data(
+                gl::GlTexture(self.handle),
+                self.target,
+                level as i32,
+                0,
+                0,
+                0,
+                gl::GlTexture(destination.handle),
+                destination.target,
+                level as i32,
+                0,
+                0,
+                0,
+                (self.width >> level).max(1) as i32,
+                (self.height >> level).max(1) as i32,
+                1,
+            );
+        }
+    }
+
     pub fn bind(&self) {
         unsafe {
             gl::bind_texture(self.target, Some(gl::GlTexture(self.handle)));
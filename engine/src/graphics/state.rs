@@ -9,6 +9,7 @@ pub struct RasteriserState {
     pub front_face: VertexWinding,
     pub depth: bool,
     pub depth_mask: bool,
+    pub colour_mask: bool,
     pub depth_func: Comparison,
     pub stencil: bool,
     pub stencil_func: (Comparison, i32, u32),
@@ -26,6 +27,7 @@ impl Default for RasteriserState {
             front_face: VertexWinding::Clockwise,
             depth: true,
             depth_mask: true,
+            colour_mask: true,
             depth_func: Comparison::Less,
             stencil: false,
             stencil_func: (Comparison::Always, 0, u32::max_value()),
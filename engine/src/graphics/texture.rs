@@ -61,3 +61,33 @@ impl Default for Image {
         }
     }
 }
+
+/// Size of a colour grading LUT from the image it is stored in, where its slices are laid side by side so the image
+/// is `size * size` wide and `size` high
+pub fn lut_size(width: u32, height: u32) -> Result<u32, String> {
+    if height < 2 || width != height * height {
+        return Err(format!(
+            "A LUT image must be N * N wide and N high, but was {}x{}",
+            width, height
+        ));
+    }
+
+    Ok(height)
+}
+
+/// Reorders the pixels of a LUT image, whose rows run across every slice, into the slice by slice order of a 3D
+/// texture, where the slice is the blue channel
+pub fn lut_volume(bytes: &[u8], size: u32, pixel_bytes: usize) -> Vec<u8> {
+    let size = size as usize;
+    let row_bytes = size * pixel_bytes;
+    let mut volume = Vec::with_capacity(bytes.len());
+
+    for slice in 0..size {
+        for row in 0..size {
+            let start = (row * size + slice) * row_bytes;
+            volume.extend_from_slice(&bytes[start..start + row_bytes]);
+        }
+    }
+
+    volume
+}
@@ -0,0 +1,273 @@
+use glutin::event::VirtualKeyCode;
+
+use super::input::Input;
+use crate::{math::*, renderer::camera::Camera};
+
+/// What the player asked a camera to do this frame, once the bindings have been read
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ControllerInput {
+    /// Units per second along the camera's right, up and forward axes
+    pub movement: Vec3f,
+    /// Degrees to turn right and to look down
+    pub look: Vec2f,
+    /// Positive to zoom in, negative to zoom out
+    pub zoom: f32,
+}
+
+/// The keys and sensitivities that drive a camera controller
+#[derive(Clone, Copy)]
+pub struct CameraBindings {
+    pub forward: VirtualKeyCode,
+    pub backward: VirtualKeyCode,
+    pub left: VirtualKeyCode,
+    pub right: VirtualKeyCode,
+    pub up: VirtualKeyCode,
+    pub down: VirtualKeyCode,
+    pub zoom_in: VirtualKeyCode,
+    pub zoom_out: VirtualKeyCode,
+    /// Held to move `boost_multiplier` times as fast
+    pub boost: VirtualKeyCode,
+    pub boost_multiplier: f32,
+    /// Units per second
+    pub move_speed: f32,
+    /// Degrees turned for each pixel the mouse moves
+    pub look_sensitivity: f32,
+    pub invert_y: bool,
+}
+
+impl Default for CameraBindings {
+    fn default() -> Self {
+        Self {
+            forward: VirtualKeyCode::W,
+            backward: VirtualKeyCode::S,
+            left: VirtualKeyCode::A,
+            right: VirtualKeyCode::D,
+            up: VirtualKeyCode::Space,
+            down: VirtualKeyCode::LShift,
+            zoom_in: VirtualKeyCode::E,
+            zoom_out: VirtualKeyCode::Q,
+            boost: VirtualKeyCode::LControl,
+            boost_multiplier: 4.0,
+            move_speed: 5.0,
+            look_sensitivity: 0.15,
+            invert_y: false,
+        }
+    }
+}
+
+impl CameraBindings {
+    /// Nothing is read until the cursor has been grabbed, so that the camera doesn't move while the player is
+    /// using the cursor for something else
+    pub fn read(&self, input: &Input) -> ControllerInput {
+        if !input.mouse.grabbed {
+            return ControllerInput::default();
+        }
+
+        let axis = |positive: VirtualKeyCode, negative: VirtualKeyCode| {
+            input.is_key_down(positive) as i32 as f32 - input.is_key_down(negative) as i32 as f32
+        };
+
+        let mut movement = Vec3f::new(
+            axis(self.right, self.left),
+            axis(self.up, self.down),
+            axis(self.forward, self.backward),
+        );
+        // moving diagonally is no faster than moving along an axis
+        if movement.magnitude() > 1.0 {
+            movement = movement.normalise();
+        }
+        let mut speed = self.move_speed;
+        if input.is_key_down(self.boost) {
+            speed *= self.boost_multiplier;
+        }
+
+        let look = if input.mouse.moved {
+            let y_sign = if self.invert_y { -1.0 } else { 1.0 };
+            Vec2f::new(
+                input.mouse.delta_x as f32 * self.look_sensitivity,
+                input.mouse.delta_y as f32 * self.look_sensitivity * y_sign,
+            )
+        } else {
+            Vec2f::uniform(0.0)
+        };
+
+        ControllerInput {
+            movement: movement * speed,
+            look,
+            zoom: axis(self.zoom_in, self.zoom_out),
+        }
+    }
+}
+
+/// Yaw and pitch, in degrees, of the orientation that looks along `forward` without rolling
+pub fn yaw_pitch(forward: &Vec3f) -> (f32, f32) {
+    let forward = forward.normalise();
+
+    (
+        (-forward.x).atan2(-forward.z).to_degrees(),
+        forward.y.clamp(-1.0, 1.0).asin().to_degrees(),
+    )
+}
+
+/// Forward and right, flattened onto the ground, for a camera with this yaw in degrees
+fn ground_axes(yaw: f32) -> (Vec3f, Vec3f) {
+    let (sin, cos) = yaw.to_radians().sin_cos();
+
+    (Vec3f::new(-sin, 0.0, -cos), Vec3f::new(cos, 0.0, -sin))
+}
+
+/// Keeps the camera's position at this fraction of the way to where it is heading, each frame, which approaches it
+/// at the same rate whatever the frame rate
+fn smoothing(stiffness: f32, delta_time: f32) -> f32 {
+    1.0 - (-stiffness * delta_time).exp()
+}
+
+/// Flies in any direction, turning around the world's up axis and the camera's own right axis, so that it can
+/// look straight up or down, and past them, without its view breaking down
+#[derive(Clone, Copy, Default)]
+pub struct FreeFly {}
+
+/// Walks on the ground, keeping its height, with its pitch kept short of straight up or down
+#[derive(Clone, Copy)]
+pub struct FirstPerson {
+    /// Degrees, where 0.0 looks down -Z and turning right is negative
+    pub yaw: f32,
+    /// Degrees, where looking up is positive
+    pub pitch: f32,
+    pub max_pitch: f32,
+}
+
+impl FirstPerson {
+    /// Keeps the way the camera is looking, less any roll
+    pub fn from_camera(camera: &Camera) -> Self {
+        let (yaw, pitch) = yaw_pitch(&camera.orientation.forward());
+
+        Self {
+            yaw,
+            pitch,
+            max_pitch: 89.0,
+        }
+    }
+}
+
+/// Circles around a target at a distance, which zooming changes, while moving pans the target
+#[derive(Clone, Copy)]
+pub struct Orbit {
+    pub target: Vec3f,
+    pub distance: f32,
+    pub min_distance: f32,
+    pub max_distance: f32,
+    /// Fraction of the distance zoomed per second
+    pub zoom_speed: f32,
+    /// Degrees, like [`FirstPerson::yaw`]
+    pub yaw: f32,
+    /// Degrees, like [`FirstPerson::pitch`]
+    pub pitch: f32,
+    pub max_pitch: f32,
+}
+
+impl Orbit {
+    /// Orbits the target from where the camera is
+    pub fn from_camera(camera: &Camera, target: Vec3f) -> Self {
+        let offset = camera.position - target;
+        let distance = offset.magnitude().max(0.1);
+        let (yaw, pitch) = yaw_pitch(&-offset);
+
+        Self {
+            target,
+            distance,
+            min_distance: 0.5,
+            max_distance: 100.0,
+            zoom_speed: 1.0,
+            yaw,
+            pitch,
+            max_pitch: 89.0,
+        }
+    }
+}
+
+/// Eases towards an offset from a target, which is moved by whatever the camera is following, while turning to
+/// look at it. Ignores the bindings
+#[derive(Clone, Copy)]
+pub struct Follow {
+    pub target: Vec3f,
+    pub offset: Vec3f,
+    /// How quickly the camera catches up, where higher is quicker. It covers about 63% of the way each
+    /// `1.0 / stiffness` seconds
+    pub stiffness: f32,
+}
+
+#[derive(Clone, Copy)]
+pub enum CameraController {
+    FreeFly(FreeFly),
+    FirstPerson(FirstPerson),
+    Orbit(Orbit),
+    Follow(Follow),
+}
+
+impl CameraController {
+    pub fn name(&self) -> &'static str {
+        match self {
+            CameraController::FreeFly(..) => "free-fly",
+            CameraController::FirstPerson(..) => "first-person",
+            CameraController::Orbit(..) => "orbit",
+            CameraController::Follow(..) => "follow",
+        }
+    }
+
+    /// Moves and turns the camera. Its view isn't updated until it is drawn
+    pub fn update(&mut self, camera: &mut Camera, input: &ControllerInput, delta_time: f32) {
+        let up = Vec3f::new(0.0, 1.0, 0.0);
+        let movement = input.movement * delta_time;
+
+        match self {
+            CameraController::FreeFly(..) => {
+                let yaw = Quat::from_axis_angle(&up, -input.look.x.to_radians());
+                let pitch =
+                    Quat::from_axis_angle(&Vec3f::new(1.0, 0.0, 0.0), -input.look.y.to_radians());
+                // yaw is applied in world space and pitch in the camera's own space
+                camera.orientation = (yaw * camera.orientation * pitch).normalise();
+
+                camera.position += camera.orientation.right() * movement.x
+                    + up * movement.y
+                    + camera.orientation.forward() * movement.z;
+            }
+            CameraController::FirstPerson(first_person) => {
+                first_person.yaw -= input.look.x;
+                first_person.pitch = (first_person.pitch - input.look.y)
+                    .clamp(-first_person.max_pitch, first_person.max_pitch);
+                camera.orientation = Quat::from_yaw_pitch(
+                    first_person.yaw.to_radians(),
+                    first_person.pitch.to_radians(),
+                );
+
+                let (forward, right) = ground_axes(first_person.yaw);
+                camera.position += right * movement.x + forward * movement.z;
+            }
+            CameraController::Orbit(orbit) => {
+                orbit.yaw -= input.look.x;
+                orbit.pitch = (orbit.pitch - input.look.y).clamp(-orbit.max_pitch, orbit.max_pitch);
+                orbit.distance = (orbit.distance
+                    * (1.0 - input.zoom * orbit.zoom_speed * delta_time))
+                    .clamp(orbit.min_distance, orbit.max_distance);
+
+                let (forward, right) = ground_axes(orbit.yaw);
+                orbit.target += right * movement.x + up * movement.y + forward * movement.z;
+
+                camera.orientation =
+                    Quat::from_yaw_pitch(orbit.yaw.to_radians(), orbit.pitch.to_radians());
+                camera.position = orbit.target - camera.orientation.forward() * orbit.distance;
+            }
+            CameraController::Follow(follow) => {
+                let t = smoothing(follow.stiffness, delta_time);
+                camera.position += (follow.target + follow.offset - camera.position) * t;
+
+                let to_target = follow.target - camera.position;
+                if to_target.magnitude() > 1e-4 {
+                    let look = Quat::look_rotation(&to_target, &up);
+                    camera.orientation = camera.orientation.slerp(look, t).normalise();
+                }
+            }
+        }
+    }
+}
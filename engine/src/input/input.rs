@@ -45,6 +45,17 @@ impl Input {
         }
     }
 
+    /// Called once the frame's input has been processed, so that keys and buttons are only pressed on the first
+    /// frame that they are down, and are held after that
+    pub fn end_frame(&mut self) {
+        for (action, ..) in self.key_states.values_mut() {
+            if matches!(action, KeyAction::Pressed) {
+                *action = KeyAction::Held;
+            }
+        }
+        self.mouse.end_frame();
+    }
+
     pub fn is_key_held(&self, key: VirtualKeyCode) -> bool {
         if let Some((KeyAction::Held, ..)) = self.key_states.get(&key) {
             true
@@ -95,6 +106,17 @@ impl Mouse {
         }
     }
 
+    pub fn end_frame(&mut self) {
+        for (action, ..) in self.button_states.values_mut() {
+            if matches!(action, KeyAction::Pressed) {
+                *action = KeyAction::Held;
+            }
+        }
+        self.moved = false;
+        self.delta_x = 0.0;
+        self.delta_y = 0.0;
+    }
+
     pub fn handle_input(&mut self, button: MouseButton, new_state: ElementState) {
         let new_button_action = if new_state == ElementState::Released {
             KeyAction::Released
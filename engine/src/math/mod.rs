@@ -1,11 +1,13 @@
 mod tests;
 pub mod mat4f;
+pub mod quat;
 pub mod vec2f;
 pub mod vec3f;
 pub mod vec4f;
 pub mod vec2u;
 
 pub use mat4f::Mat4f;
+pub use quat::Quat;
 pub use vec2f::Vec2f;
 pub use vec3f::Vec3f;
 pub use vec4f::Vec4f;
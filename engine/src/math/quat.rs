@@ -0,0 +1,202 @@
+use super::{Mat4f, Vec3f};
+
+/// Rotation as a unit quaternion, which, unlike Euler angles, can describe any orientation without gimbal lock
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Quat {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+    pub w: f32,
+}
+
+impl Default for Quat {
+    fn default() -> Self {
+        Quat::identity()
+    }
+}
+
+impl Quat {
+    pub fn new(x: f32, y: f32, z: f32, w: f32) -> Self {
+        Quat { x, y, z, w }
+    }
+
+    pub fn identity() -> Self {
+        Quat::new(0.0, 0.0, 0.0, 1.0)
+    }
+
+    /// Rotates anticlockwise around the axis, when looking down it towards the origin
+    pub fn from_axis_angle(axis: &Vec3f, rad: f32) -> Self {
+        let axis = axis.normalise();
+        let (sin, cos) = (rad * 0.5).sin_cos();
+
+        Quat::new(axis.x * sin, axis.y * sin, axis.z * sin, cos)
+    }
+
+    /// Rotates by pitch around the X axis, then by yaw around the Y axis, which never rolls
+    pub fn from_yaw_pitch(yaw_rad: f32, pitch_rad: f32) -> Self {
+        Quat::from_axis_angle(&Vec3f::new(0.0, 1.0, 0.0), yaw_rad)
+            * Quat::from_axis_angle(&Vec3f::new(1.0, 0.0, 0.0), pitch_rad)
+    }
+
+    /// Orientation whose -Z axis points along `forward`, with its Y axis as close to `up` as it can be. When the
+    /// two are parallel, the Z axis is used as up instead
+    pub fn look_rotation(forward: &Vec3f, up: &Vec3f) -> Self {
+        let back = -forward.normalise();
+        let mut right = up.cross(back);
+        if right.magnitude() < 1e-6 {
+            right = Vec3f::new(0.0, 0.0, 1.0).cross(back);
+        }
+        let right = right.normalise();
+        let up = back.cross(right);
+
+        Quat::from_axes(&right, &up, &back)
+    }
+
+    /// From the orthonormal basis that the X, Y and Z axes are rotated onto
+    pub fn from_axes(x_axis: &Vec3f, y_axis: &Vec3f, z_axis: &Vec3f) -> Self {
+        let (m00, m10, m20) = x_axis.as_tuple();
+        let (m01, m11, m21) = y_axis.as_tuple();
+        let (m02, m12, m22) = z_axis.as_tuple();
+
+        // uses the largest of the diagonal terms, to avoid dividing by a number near zero
+        let trace = m00 + m11 + m22;
+        let quat = if trace > 0.0 {
+            let s = (trace + 1.0).sqrt() * 2.0;
+            Quat::new((m21 - m12) / s, (m02 - m20) / s, (m10 - m01) / s, 0.25 * s)
+        } else if m00 > m11 && m00 > m22 {
+            let s = (1.0 + m00 - m11 - m22).sqrt() * 2.0;
+            Quat::new(0.25 * s, (m01 + m10) / s, (m02 + m20) / s, (m21 - m12) / s)
+        } else if m11 > m22 {
+            let s = (1.0 + m11 - m00 - m22).sqrt() * 2.0;
+            Quat::new((m01 + m10) / s, 0.25 * s, (m12 + m21) / s, (m02 - m20) / s)
+        } else {
+            let s = (1.0 + m22 - m00 - m11).sqrt() * 2.0;
+            Quat::new((m02 + m20) / s, (m12 + m21) / s, 0.25 * s, (m10 - m01) / s)
+        };
+
+        quat.normalise()
+    }
+
+    pub fn dot(&self, rhs: Self) -> f32 {
+        self.x * rhs.x + self.y * rhs.y + self.z * rhs.z + self.w * rhs.w
+    }
+
+    pub fn magnitude(&self) -> f32 {
+        self.dot(*self).sqrt()
+    }
+
+    pub fn normalise(&self) -> Self {
+        let m = self.magnitude();
+
+        Quat::new(self.x / m, self.y / m, self.z / m, self.w / m)
+    }
+
+    /// The opposite rotation, for a unit quaternion
+    pub fn conjugate(&self) -> Self {
+        Quat::new(-self.x, -self.y, -self.z, self.w)
+    }
+
+    pub fn rotate(&self, v: Vec3f) -> Vec3f {
+        let u = Vec3f::new(self.x, self.y, self.z);
+        let t = u.cross(v) * 2.0;
+
+        v + t * self.w + u.cross(t)
+    }
+
+    pub fn right(&self) -> Vec3f {
+        self.rotate(Vec3f::new(1.0, 0.0, 0.0))
+    }
+
+    pub fn up(&self) -> Vec3f {
+        self.rotate(Vec3f::new(0.0, 1.0, 0.0))
+    }
+
+    /// The -Z axis, which is the way cameras look
+    pub fn forward(&self) -> Vec3f {
+        self.rotate(Vec3f::new(0.0, 0.0, -1.0))
+    }
+
+    /// Interpolates along the shortest arc between the rotations, at a constant angular speed
+    pub fn slerp(&self, to: Self, t: f32) -> Self {
+        let mut to = to;
+        let mut cos = self.dot(to);
+        if cos < 0.0 {
+            to = -to;
+            cos = -cos;
+        }
+
+        // too close to divide by the sine of the angle between them, where a lerp is as good
+        if cos > 0.9995 {
+            return (*self * (1.0 - t) + to * t).normalise();
+        }
+
+        let angle = cos.acos();
+        let sin = angle.sin();
+        let a = ((1.0 - t) * angle).sin() / sin;
+        let b = (t * angle).sin() / sin;
+
+        *self * a + to * b
+    }
+
+    pub fn to_mat4(self) -> Mat4f {
+        let (x, y, z, w) = (self.x, self.y, self.z, self.w);
+
+        let mut matrix = Mat4f::identity();
+
+        matrix[(0, 0)] = 1.0 - 2.0 * (y * y + z * z);
+        matrix[(0, 1)] = 2.0 * (x * y - z * w);
+        matrix[(0, 2)] = 2.0 * (x * z + y * w);
+        matrix[(1, 0)] = 2.0 * (x * y + z * w);
+        matrix[(1, 1)] = 1.0 - 2.0 * (x * x + z * z);
+        matrix[(1, 2)] = 2.0 * (y * z - x * w);
+        matrix[(2, 0)] = 2.0 * (x * z - y * w);
+        matrix[(2, 1)] = 2.0 * (y * z + x * w);
+        matrix[(2, 2)] = 1.0 - 2.0 * (x * x + y * y);
+
+        matrix
+    }
+}
+
+/// Applies the rotation on the right hand side first
+impl std::ops::Mul for Quat {
+    type Output = Quat;
+
+    fn mul(self, rhs: Self) -> Self::Output {
+        Quat::new(
+            self.w * rhs.x + self.x * rhs.w + self.y * rhs.z - self.z * rhs.y,
+            self.w * rhs.y - self.x * rhs.z + self.y * rhs.w + self.z * rhs.x,
+            self.w * rhs.z + self.x * rhs.y - self.y * rhs.x + self.z * rhs.w,
+            self.w * rhs.w - self.x * rhs.x - self.y * rhs.y - self.z * rhs.z,
+        )
+    }
+}
+
+impl std::ops::Mul<f32> for Quat {
+    type Output = Quat;
+
+    fn mul(self, rhs: f32) -> Self::Output {
+        Quat::new(self.x * rhs, self.y * rhs, self.z * rhs, self.w * rhs)
+    }
+}
+
+impl std::ops::Add for Quat {
+    type Output = Quat;
+
+    fn add(self, rhs: Self) -> Self::Output {
+        Quat::new(
+            self.x + rhs.x,
+            self.y + rhs.y,
+            self.z + rhs.z,
+            self.w + rhs.w,
+        )
+    }
+}
+
+impl std::ops::Neg for Quat {
+    type Output = Quat;
+
+    fn neg(self) -> Self::Output {
+        Quat::new(-self.x, -self.y, -self.z, -self.w)
+    }
+}
@@ -1,7 +1,7 @@
 mod tests {
     #![allow(unused_imports)]
     use super::*;
-    use crate::math::{mat4f::Mat4f, vec3f::Vec3f};
+    use crate::math::{mat4f::Mat4f, quat::Quat, vec3f::Vec3f};
 
     #[test]
     fn mat4_mul_test() {
@@ -81,6 +81,34 @@ mod tests {
         assert_eq!(7.0, a[10]);
     }
 
+    #[test]
+    fn mat4_inverse_test() {
+        let a = Mat4f::translate(1.0, -2.0, 3.0)
+            * Mat4f::rotate_around_y(30f32.to_radians())
+            * Mat4f::scale(2.0, 4.0, 0.5);
+
+        let identity = a * a.inverse().unwrap();
+
+        for (value, expected) in identity.as_slice().iter().zip(Mat4f::identity().as_slice()) {
+            assert!((value - expected).abs() < 1e-5);
+        }
+
+        assert!(Mat4f::uniform(1.0).inverse().is_none());
+        assert!(Mat4f::scale(1.0, 0.0, 1.0).inverse().is_none());
+    }
+
+    #[test]
+    fn mat4_small_scale_inverse_test() {
+        // a determinant of 1e-6, well below f32::EPSILON
+        let a = Mat4f::translate(5.0, 0.0, -5.0) * Mat4f::scale(0.01, 0.01, 0.01);
+
+        let identity = a * a.inverse().unwrap();
+
+        for (value, expected) in identity.as_slice().iter().zip(Mat4f::identity().as_slice()) {
+            assert!((value - expected).abs() < 1e-5);
+        }
+    }
+
     #[test]
     fn vec3_cross_test() {
         let a = Vec3f::new(27.0, 45.0, 7.0);
@@ -96,4 +124,83 @@ mod tests {
 
         assert_eq!(1634.0, a.dot(b))
     }
+
+    #[test]
+    fn quat_rotate_test() {
+        let close = |a: Vec3f, b: Vec3f| (a - b).magnitude() < 1e-5;
+
+        let quarter = Quat::from_axis_angle(&Vec3f::new(0.0, 1.0, 0.0), 90f32.to_radians());
+        assert!(close(
+            quarter.rotate(Vec3f::new(1.0, 0.0, 0.0)),
+            Vec3f::new(0.0, 0.0, -1.0)
+        ));
+        assert!(close(quarter.forward(), Vec3f::new(-1.0, 0.0, 0.0)));
+
+        // matches the matrix that rotates around the same axis
+        let axis = Vec3f::new(1.0, 2.0, 3.0);
+        let q = Quat::from_axis_angle(&axis, 0.7);
+        let m = Mat4f::rotate(0.7, &axis);
+        let v = Vec3f::new(0.3, -1.2, 2.5);
+        let expected = Vec3f::new(
+            m[(0, 0)] * v.x + m[(0, 1)] * v.y + m[(0, 2)] * v.z,
+            m[(1, 0)] * v.x + m[(1, 1)] * v.y + m[(1, 2)] * v.z,
+            m[(2, 0)] * v.x + m[(2, 1)] * v.y + m[(2, 2)] * v.z,
+        );
+        assert!(close(q.rotate(v), expected));
+        for (a, b) in q.to_mat4().as_slice().iter().zip(m.as_slice()) {
+            assert!((a - b).abs() < 1e-5);
+        }
+
+        // the right hand side is applied first, and the conjugate undoes the rotation
+        let p = Quat::from_axis_angle(&Vec3f::new(0.0, 0.0, 1.0), 1.1);
+        assert!(close((p * q).rotate(v), p.rotate(q.rotate(v))));
+        assert!(close((q.conjugate() * q).rotate(v), v));
+    }
+
+    #[test]
+    fn quat_look_rotation_test() {
+        let close = |a: Vec3f, b: Vec3f| (a - b).magnitude() < 1e-5;
+        let up = Vec3f::new(0.0, 1.0, 0.0);
+
+        for forward in [
+            Vec3f::new(0.0, 0.0, -1.0),
+            Vec3f::new(0.0, 0.0, 1.0),
+            Vec3f::new(1.0, 0.0, 0.0),
+            Vec3f::new(-2.0, 1.0, 0.5),
+            Vec3f::new(0.3, -4.0, -0.2),
+        ] {
+            let q = Quat::look_rotation(&forward, &up);
+            assert!((q.magnitude() - 1.0).abs() < 1e-5);
+            assert!(close(q.forward(), forward.normalise()));
+            // never rolls
+            assert!(q.right().y.abs() < 1e-5);
+            assert!(q.up().y >= 0.0);
+        }
+
+        // straight up and down still give an orthonormal basis
+        for forward in [up, -up] {
+            let q = Quat::look_rotation(&forward, &up);
+            assert!(close(q.forward(), forward));
+            assert!(q.right().dot(q.up()).abs() < 1e-5);
+            assert!((q.right().magnitude() - 1.0).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn quat_slerp_test() {
+        let y = Vec3f::new(0.0, 1.0, 0.0);
+        let a = Quat::identity();
+        let b = Quat::from_axis_angle(&y, 90f32.to_radians());
+
+        assert_eq!(a, a.slerp(b, 0.0));
+        assert!((a.slerp(b, 1.0).dot(b) - 1.0).abs() < 1e-5);
+
+        let half = a.slerp(b, 0.5);
+        let expected = Quat::from_axis_angle(&y, 45f32.to_radians());
+        assert!((half.dot(expected) - 1.0).abs() < 1e-5);
+
+        // takes the shortest arc when the target is on the other side of the hypersphere
+        let half = a.slerp(-b, 0.5);
+        assert!(half.dot(expected).abs() > 1.0 - 1e-5);
+    }
 }
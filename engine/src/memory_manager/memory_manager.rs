@@ -1,25 +1,36 @@
 use std::mem::size_of;
 
 use bytemuck::Pod;
-use log::info;
+use log::{error, info};
 use memoffset::offset_of;
 
 use super::uniform_layouts::*;
 use crate::{
     graphics::{buffer::*, shader::ShaderDataType},
-    math::{Mat4f, Vec3f},
-    resource_manager::model::VERTEX_SIZE,
+    math::{Mat4f, Vec3f, Vec4f},
+    resource_manager::model::{Vertex, VERTEX_SIZE},
 };
 
-/// The number of sections in each buffer
+/// The number of frames that can be in flight at once
 pub const BUFFERS: u32 = 3;
 
-/// Not sure... <br>
-/// Thought this would end up being vertices per frame, but seems it ends up acting as vertices per mesh
-/// or MultiDrawIndirect call)?? Or it's neither - by virtue of the buffer being created as a vertex buffer, maybe
-/// the writes are DMAd straight away and so I can just overwrite the buffer willynilly because the data has already
-/// been moved?
-const MAX_VERTICES: u32 = 50_000;
+/// Views that can be drawn each frame before a view has to wait for the GPU to finish with its section
+pub const MAX_VIEWS: u32 = 4;
+
+/// The number of sections in each buffer, as every view drawn takes a section of its own
+const SECTIONS: u32 = BUFFERS * MAX_VIEWS;
+
+/// Vertices that can be resident in the geometry pool, shared by all static meshes
+const MAX_STATIC_VERTICES: u32 = 500_000;
+
+/// Indices that can be resident in the geometry pool, shared by all static meshes
+const MAX_STATIC_INDICES: u32 = MAX_STATIC_VERTICES * 3;
+
+/// Vertices per view, for dynamic meshes that are re-uploaded each time they are drawn
+const MAX_DYNAMIC_VERTICES: u32 = 50_000;
+
+/// Indices per view, for dynamic meshes that are re-uploaded each time they are drawn
+const MAX_DYNAMIC_INDICES: u32 = MAX_DYNAMIC_VERTICES * 3;
 
 /// Commands per frame (unique meshes per shader per frame)
 const MAX_COMMANDS: u32 = 1_000;
@@ -27,15 +38,39 @@ const MAX_COMMANDS: u32 = 1_000;
 /// Instances per draw call
 const MAX_INSTANCES: u32 = 100_000;
 
+/// GPU culling dispatches per frame, which each have their own header in the cull buffer
+const MAX_CULL_DISPATCHES: u32 = 16;
+
+/// Offsets of bound shader storage ranges must be a multiple of the alignment of the device, which is at most this
+/// on the hardware we care about
+const STORAGE_OFFSET_ALIGNMENT: u32 = 256;
+
 pub const DRAW_COMMAND_SIZE: u32 = size_of::<DrawElementsIndirectCommand>() as u32;
 const INSTANCE_DATA_SIZE: u32 = size_of::<InstanceData>() as u32;
 
-const VERTEX_BUFFER_SIZE: u32 = VERTEX_SIZE * MAX_VERTICES;
+const INDEX_SIZE: u32 = size_of::<u32>() as u32;
+
+// The geometry pool buffers are not multi-buffered, instead they are split into a static region followed by a
+// dynamic region per section
+const STATIC_VERTEX_REGION_SIZE: u32 = VERTEX_SIZE * MAX_STATIC_VERTICES;
+const DYNAMIC_VERTEX_REGION_SIZE: u32 = VERTEX_SIZE * MAX_DYNAMIC_VERTICES;
+const VERTEX_BUFFER_SIZE: u32 = STATIC_VERTEX_REGION_SIZE + DYNAMIC_VERTEX_REGION_SIZE * SECTIONS;
+const STATIC_INDEX_REGION_SIZE: u32 = INDEX_SIZE * MAX_STATIC_INDICES;
+const DYNAMIC_INDEX_REGION_SIZE: u32 = INDEX_SIZE * MAX_DYNAMIC_INDICES;
+const INDEX_BUFFER_SIZE: u32 = STATIC_INDEX_REGION_SIZE + DYNAMIC_INDEX_REGION_SIZE * SECTIONS;
+
 const INDIRECT_BUFFER_SIZE: u32 = DRAW_COMMAND_SIZE * MAX_COMMANDS;
 const INSTANCE_BUFFER_SIZE: u32 = INSTANCE_DATA_SIZE * MAX_INSTANCES;
 const STATIC_SHADER_STORAGE_BUFFER_SIZE: u32 = size_of::<StaticShaderStorageBuffers>() as u32;
 const FRAME_SHADER_STORAGE_BUFFER_SIZE: u32 = size_of::<FrameShaderStorageBuffers>() as u32;
 const DRAW_SHADER_STORAGE_BUFFER_SIZE: u32 = size_of::<DrawShaderStorageBuffers>() as u32;
+const LIGHT_GRID_BUFFER_SIZE: u32 = size_of::<LightGridStorageBuffers>() as u32;
+const CULL_HEADER_SIZE: u32 = size_of::<CullHeader>() as u32;
+const CULL_BATCH_SIZE: u32 = size_of::<CullBatch>() as u32;
+// every command of the frame could come from a culled batch
+const CULL_BUFFER_SIZE: u32 = (CULL_HEADER_SIZE + STORAGE_OFFSET_ALIGNMENT) * MAX_CULL_DISPATCHES
+    + CULL_BATCH_SIZE * MAX_COMMANDS;
+const DRAW_COUNT_BUFFER_SIZE: u32 = INDEX_SIZE * MAX_DRAW_COUNTS as u32;
 
 #[repr(C)]
 pub struct DrawElementsIndirectCommand {
@@ -46,19 +81,39 @@ pub struct DrawElementsIndirectCommand {
     pub base_instance: u32,
 }
 
+/// Location of a mesh's geometry within the geometry pool
+#[derive(Debug, Clone, Copy)]
+pub struct MeshAllocation {
+    pub first_index: u32,
+    pub base_vertex: u32,
+    pub index_count: u32,
+}
+
 #[repr(C)]
 pub struct InstanceData {
     pub material_index: u32,
     pub transform: Mat4f,
+    /// See `MeshInstance::lod_fade`
+    pub lod_fade: f32,
 }
 
 pub struct MemoryManager {
     vertex_array: VertexArray,
+    static_vertex_count: u32,
+    static_index_count: u32,
+    dynamic_vertex_count: u32,
+    dynamic_index_count: u32,
+
     indirect_draw_buffer: BufferStorage,
 
     static_shader_storage_buffer: BufferStorage,
     frame_shader_storage_buffer: BufferStorage,
     draw_shader_storage_buffer: BufferStorage,
+    light_grid_buffer: BufferStorage,
+
+    cull_buffer: BufferStorage,
+    /// Written by the GPU culling shader, and read as the parameter buffer when drawing
+    draw_count_buffer: BufferStorage,
 
     buffer_lock: BufferLockManager,
 }
@@ -76,6 +131,7 @@ impl MemoryManager {
                 ],
                 VERTEX_BUFFER_SIZE,
                 0,
+                1,
             ),
             BufferLayout::new(
                 vec![
@@ -84,20 +140,27 @@ impl MemoryManager {
                     BufferElement::new(ShaderDataType::Float4, "transform_col2"),
                     BufferElement::new(ShaderDataType::Float4, "transform_col3"),
                     BufferElement::new(ShaderDataType::Float4, "transform_col4"),
+                    BufferElement::new(ShaderDataType::Float1, "lodFade"),
                 ],
                 INSTANCE_BUFFER_SIZE,
                 1,
+                SECTIONS,
             ),
         ];
 
-        let vertex_array = VertexArray::new(buffer_layouts, MAX_VERTICES * 3 * 4, BUFFERS);
+        let vertex_array = VertexArray::new(buffer_layouts, INDEX_BUFFER_SIZE, 1);
 
         let mm = Self {
             vertex_array,
+            static_vertex_count: 0,
+            static_index_count: 0,
+            dynamic_vertex_count: 0,
+            dynamic_index_count: 0,
+
             indirect_draw_buffer: BufferStorage::new(
                 BufferType::DrawIndirectCommand,
                 INDIRECT_BUFFER_SIZE,
-                BUFFERS,
+                SECTIONS,
             ),
 
             static_shader_storage_buffer: BufferStorage::new(
@@ -108,39 +171,54 @@ impl MemoryManager {
             frame_shader_storage_buffer: BufferStorage::new(
                 BufferType::Uniform,
                 FRAME_SHADER_STORAGE_BUFFER_SIZE,
-                BUFFERS,
+                SECTIONS,
             ),
             draw_shader_storage_buffer: BufferStorage::new(
                 BufferType::Uniform,
                 DRAW_SHADER_STORAGE_BUFFER_SIZE,
                 1,
             ),
+            light_grid_buffer: BufferStorage::new(
+                BufferType::ShaderStorage,
+                LIGHT_GRID_BUFFER_SIZE,
+                SECTIONS,
+            ),
+            cull_buffer: BufferStorage::new(BufferType::ShaderStorage, CULL_BUFFER_SIZE, SECTIONS),
+            draw_count_buffer: BufferStorage::new(
+                BufferType::ShaderStorage,
+                DRAW_COUNT_BUFFER_SIZE,
+                SECTIONS,
+            ),
             buffer_lock: BufferLockManager::new(),
         };
 
         mm.indirect_draw_buffer.bind();
+        mm.draw_count_buffer.bind_as(BufferType::Parameter);
         mm.vertex_array.bind();
 
         mm.bind_static_shader_storage_ranges();
         mm.bind_frame_shader_storage_ranges();
         mm.bind_draw_shader_storage_ranges();
 
-        info!("Multi-buffering: {}", BUFFERS);
+        info!(
+            "Multi-buffering: {} frames of up to {} views",
+            BUFFERS, MAX_VIEWS
+        );
         info!(
             "Per Vertex Buffer Size: {:.3} MB",
-            VERTEX_BUFFER_SIZE as f32 * BUFFERS as f32 / 1_000_000.0
+            VERTEX_BUFFER_SIZE as f32 / 1_000_000.0
         );
         info!(
             "Per Instance Buffer Size: {:.3} MB",
-            INSTANCE_BUFFER_SIZE as f32 * BUFFERS as f32 / 1_000_000.0
+            INSTANCE_BUFFER_SIZE as f32 * SECTIONS as f32 / 1_000_000.0
         );
         info!(
             "Index Buffer Size: {:.3} MB",
-            MAX_VERTICES as f32 * 3.0 * 4.0 * BUFFERS as f32 / 1_000_000.0
+            INDEX_BUFFER_SIZE as f32 / 1_000_000.0
         );
         info!(
             "Indirect Draw Command Buffer Size: {:.3} MB",
-            INDIRECT_BUFFER_SIZE as f32 * BUFFERS as f32 / 1_000_000.0
+            INDIRECT_BUFFER_SIZE as f32 * SECTIONS as f32 / 1_000_000.0
         );
         info!(
             "Static Shader Storage Buffer Size: {:.3} MB",
@@ -148,12 +226,20 @@ impl MemoryManager {
         );
         info!(
             "Per Frame Shader Storage Buffer Size: {:.3} MB",
-            FRAME_SHADER_STORAGE_BUFFER_SIZE as f32 * BUFFERS as f32 / 1_000_000.0
+            FRAME_SHADER_STORAGE_BUFFER_SIZE as f32 * SECTIONS as f32 / 1_000_000.0
         );
         info!(
             "Per Draw Call Shader Storage Buffer Size: {:.3} MB",
             DRAW_SHADER_STORAGE_BUFFER_SIZE as f32 * BUFFERS as f32 / 1_000_000.0
         );
+        info!(
+            "Light Grid Buffer Size: {:.3} MB",
+            LIGHT_GRID_BUFFER_SIZE as f32 * SECTIONS as f32 / 1_000_000.0
+        );
+        info!(
+            "Cull Buffer Size: {:.3} MB",
+            CULL_BUFFER_SIZE as f32 * SECTIONS as f32 / 1_000_000.0
+        );
 
         mm
     }
@@ -167,15 +253,16 @@ impl MemoryManager {
             offset_of!(StaticShaderStorageBuffers, materials) as u32,
             size_of::<MaterialsStorageBuffer>() as u32,
         );
+    }
 
-        self.static_shader_storage_buffer.bind_buffer_range(
+    fn bind_frame_shader_storage_ranges(&self) {
+        self.frame_shader_storage_buffer.bind_buffer_range(
             1,
-            offset_of!(StaticShaderStorageBuffers, shadow_maps) as u32,
+            offset_of!(FrameShaderStorageBuffers, shadow_maps) as u32
+                + self.frame_shader_storage_buffer.buffer_section_offset,
             size_of::<ShadowMapStorageBuffer>() as u32,
         );
-    }
 
-    fn bind_frame_shader_storage_ranges(&self) {
         self.frame_shader_storage_buffer.bind_buffer_range(
             2,
             offset_of!(FrameShaderStorageBuffers, lights) as u32
@@ -189,6 +276,12 @@ impl MemoryManager {
                 + self.frame_shader_storage_buffer.buffer_section_offset,
             size_of::<MatricesStorageBuffer>() as u32,
         );
+
+        self.light_grid_buffer.bind_buffer_range(
+            5,
+            self.light_grid_buffer.buffer_section_offset,
+            LIGHT_GRID_BUFFER_SIZE,
+        );
     }
 
     fn bind_draw_shader_storage_ranges(&self) {
@@ -200,19 +293,25 @@ impl MemoryManager {
     }
 
     pub fn advance_sections(&mut self) {
-        for buffer in self.vertex_array.vertex_buffers.iter_mut() {
-            buffer.next_section();
-            buffer.reset_index();
-        }
-        self.vertex_array.index_buffer.next_section();
-        self.vertex_array.index_buffer.reset_index();
+        self.vertex_array.vertex_buffers[1].next_section();
+        self.vertex_array.vertex_buffers[1].reset_index();
+
+        self.dynamic_vertex_count = 0;
+        self.dynamic_index_count = 0;
 
         self.indirect_draw_buffer.next_section();
         self.indirect_draw_buffer.reset_index();
 
         self.frame_shader_storage_buffer.next_section();
         self.frame_shader_storage_buffer.reset_index();
+        self.light_grid_buffer.next_section();
+        self.light_grid_buffer.reset_index();
         self.bind_frame_shader_storage_ranges();
+
+        self.cull_buffer.next_section();
+        self.cull_buffer.reset_index();
+        self.draw_count_buffer.next_section();
+        self.draw_count_buffer.reset_index();
     }
 
     pub fn set_section_lock(&mut self) {
@@ -225,23 +324,101 @@ impl MemoryManager {
             .wait_for_locked_range(self.indirect_draw_buffer.current_section, 1);
     }
 
-    // Vertex Buffer 01 - per vertex
+    // Geometry Pool - Vertex Buffer 01 (per vertex) and Index Buffer
     ///////////////////////////////////////////////////////////////////////////////////////
 
-    pub fn reserve_vertex_space(&mut self, vertex_count: u32) {
-        self.vertex_array.vertex_buffers[0].reserve(vertex_count * VERTEX_SIZE);
-    }
+    /// Uploads the mesh into the static region of the geometry pool, where it stays for the lifetime of the
+    /// application. Returns an error if there is not enough space left.
+    pub fn upload_static_mesh(
+        &mut self,
+        vertices: &[Vertex],
+        indices: &[u32],
+    ) -> Result<MeshAllocation, String> {
+        let vertex_count = vertices.len() as u32;
+        let index_count = indices.len() as u32;
+
+        if self.static_vertex_count + vertex_count > MAX_STATIC_VERTICES
+            || self.static_index_count + index_count > MAX_STATIC_INDICES
+        {
+            return Err(format!(
+                "Geometry pool is full, could not fit mesh with {} vertices and {} indices",
+                vertex_count, index_count
+            ));
+        }
 
-    pub fn get_vertex_index(&mut self) -> u32 {
-        self.vertex_array.vertex_buffers[0].current_buffer_index() / VERTEX_SIZE
-    }
+        let allocation = MeshAllocation {
+            first_index: self.static_index_count,
+            base_vertex: self.static_vertex_count,
+            index_count,
+        };
 
-    pub fn push_vertex_data<T>(&mut self, data: &T) {
-        self.vertex_array.vertex_buffers[0].push_data(data)
-    }
+        self.vertex_array.vertex_buffers[0]
+            .set_data_slice(vertices, allocation.base_vertex * VERTEX_SIZE);
+        self.vertex_array
+            .index_buffer
+            .set_data_slice(indices, allocation.first_index * INDEX_SIZE);
+
+        self.static_vertex_count += vertex_count;
+        self.static_index_count += index_count;
+
+        Ok(allocation)
+    }
+
+    /// Uploads the mesh into this view's dynamic region of the geometry pool. The allocation is only valid
+    /// until the view has been drawn. Meshes that don't fit are given an empty allocation, which draws nothing.
+    pub fn push_dynamic_mesh(&mut self, vertices: &[Vertex], indices: &[u32]) -> MeshAllocation {
+        let vertex_count = vertices.len() as u32;
+        let index_count = indices.len() as u32;
+
+        if vertex_count > MAX_DYNAMIC_VERTICES || index_count > MAX_DYNAMIC_INDICES {
+            error!(
+                "Dynamic mesh with {} vertices and {} indices is too large for the geometry pool",
+                vertex_count, index_count
+            );
+            return MeshAllocation {
+                first_index: 0,
+                base_vertex: 0,
+                index_count: 0,
+            };
+        }
+
+        // earlier draws of this view still reference the rest of the region, so it can't be wrapped around to reuse
+        if self.dynamic_vertex_count + vertex_count > MAX_DYNAMIC_VERTICES
+            || self.dynamic_index_count + index_count > MAX_DYNAMIC_INDICES
+        {
+            error!(
+                "Dynamic mesh with {} vertices and {} indices doesn't fit in what is left of this view's region of \
+                 the geometry pool",
+                vertex_count, index_count
+            );
+            return MeshAllocation {
+                first_index: 0,
+                base_vertex: 0,
+                index_count: 0,
+            };
+        }
 
-    pub fn push_vertex_slice<T: Pod>(&mut self, data: &[T]) {
-        self.vertex_array.vertex_buffers[0].push_data_slice(data)
+        let section = self.indirect_draw_buffer.current_section;
+        let allocation = MeshAllocation {
+            first_index: MAX_STATIC_INDICES
+                + MAX_DYNAMIC_INDICES * section
+                + self.dynamic_index_count,
+            base_vertex: MAX_STATIC_VERTICES
+                + MAX_DYNAMIC_VERTICES * section
+                + self.dynamic_vertex_count,
+            index_count,
+        };
+
+        self.vertex_array.vertex_buffers[0]
+            .set_data_slice(vertices, allocation.base_vertex * VERTEX_SIZE);
+        self.vertex_array
+            .index_buffer
+            .set_data_slice(indices, allocation.first_index * INDEX_SIZE);
+
+        self.dynamic_vertex_count += vertex_count;
+        self.dynamic_index_count += index_count;
+
+        allocation
     }
 
     // Vertex Buffer 02 - per instance
@@ -263,23 +440,13 @@ impl MemoryManager {
         self.vertex_array.vertex_buffers[1].push_data_slice(data)
     }
 
-    // Index Buffer
-    ///////////////////////////////////////////////////////////////////////////////////////
-
-    pub fn reserve_index_space(&mut self, index_count: u32) {
-        self.vertex_array.index_buffer.reserve(index_count * 4);
-    }
+    /// Skips space for instances that are written on the GPU, returning the index of the first
+    pub fn reserve_gpu_instances(&mut self, instance_count: u32) -> u32 {
+        self.reserve_instance_space(instance_count);
+        let index = self.get_instance_index();
+        self.vertex_array.vertex_buffers[1].increase_index(instance_count * INSTANCE_DATA_SIZE);
 
-    pub fn get_index_index(&mut self) -> u32 {
-        self.vertex_array.index_buffer.current_buffer_index() / 4
-    }
-
-    pub fn push_index_data<T>(&mut self, data: &T) {
-        self.vertex_array.index_buffer.push_data(data)
-    }
-
-    pub fn push_index_slice<T: Pod>(&mut self, data: &[T]) {
-        self.vertex_array.index_buffer.push_data_slice(data)
+        index
     }
 
     // Indirect Draw Command Buffer
@@ -298,6 +465,56 @@ impl MemoryManager {
         self.indirect_draw_buffer.push_data(&command)
     }
 
+    /// Skips space for commands that are written on the GPU, returning the index of the first
+    pub fn reserve_gpu_commands(&mut self, command_count: u32) -> u32 {
+        self.reserve_indirect_command_space(command_count);
+        let index = self.get_indirect_command_index();
+        self.indirect_draw_buffer
+            .increase_index(command_count * DRAW_COMMAND_SIZE);
+
+        index
+    }
+
+    // GPU Culling
+    ///////////////////////////////////////////////////////////////////////////////////////
+
+    /// Zeroes the next draw count of the frame, returning its index within the whole parameter buffer
+    pub fn push_draw_count(&mut self) -> u32 {
+        self.draw_count_buffer.reserve(INDEX_SIZE);
+        let index = self.draw_count_buffer.current_buffer_index() / INDEX_SIZE;
+        self.draw_count_buffer.push_data(&0u32);
+
+        index
+    }
+
+    /// Writes the header and batches of one dispatch of the culling shader, where the batch count is filled in, and
+    /// binds them along with the buffers that it reads and writes
+    pub fn set_cull_batches(&mut self, header: CullHeader, batches: &[CullBatch]) {
+        let size = CULL_HEADER_SIZE + CULL_BATCH_SIZE * batches.len() as u32;
+
+        let aligned_index = self
+            .cull_buffer
+            .section_buffer_index
+            .next_multiple_of(STORAGE_OFFSET_ALIGNMENT);
+        self.cull_buffer
+            .increase_index(aligned_index - self.cull_buffer.section_buffer_index);
+        self.cull_buffer.reserve(size);
+
+        let offset = self.cull_buffer.current_buffer_index();
+        self.cull_buffer.push_data(&CullHeader {
+            batch_count: batches.len() as u32,
+            ..header
+        });
+        self.cull_buffer.push_data_slice(batches);
+
+        self.cull_buffer.bind_buffer_range(6, offset, size);
+        self.vertex_array.vertex_buffers[1].bind_buffer_base_as(BufferType::ShaderStorage, 7);
+        self.indirect_draw_buffer
+            .bind_buffer_base_as(BufferType::ShaderStorage, 8);
+        self.draw_count_buffer
+            .bind_buffer_base_as(BufferType::ShaderStorage, 9);
+    }
+
     // Static Shader Storage Buffer
     ///////////////////////////////////////////////////////////////////////////////////////
 
@@ -310,52 +527,61 @@ impl MemoryManager {
         );
     }
 
-    pub fn set_directional_shadow_map(&mut self, texture_handle: u64) {
-        self.static_shader_storage_buffer.set_data(
-            &texture_handle,
-            offset_of!(StaticShaderStorageBuffers, shadow_maps) as u32
-                + offset_of!(ShadowMapStorageBuffer, directional_shadow_map) as u32,
+    // Per Frame Shader Storage Buffer
+    ///////////////////////////////////////////////////////////////////////////////////////
+
+    //// Shadow Maps
+    ////////////////////////////
+
+    pub fn set_shadow_atlas(&mut self, shadow_map: ShadowMap) {
+        self.frame_shader_storage_buffer.set_data(
+            &shadow_map,
+            offset_of!(FrameShaderStorageBuffers, shadow_maps) as u32
+                + offset_of!(ShadowMapStorageBuffer, atlas) as u32,
         );
     }
 
-    pub fn set_spot_shadow_map(&mut self, texture_handle: u64, index: u32) {
-        self.static_shader_storage_buffer.set_data(
-            &texture_handle,
-            offset_of!(StaticShaderStorageBuffers, shadow_maps) as u32
-                + offset_of!(ShadowMapStorageBuffer, spot_shadow_map) as u32
-                + (size_of::<ShadowMap>() as u32 * index),
+    pub fn set_directional_shadow_map(&mut self, shadow_map: ShadowMap) {
+        self.frame_shader_storage_buffer.set_data(
+            &shadow_map,
+            offset_of!(FrameShaderStorageBuffers, shadow_maps) as u32
+                + offset_of!(ShadowMapStorageBuffer, directional_shadow_map) as u32,
         );
     }
 
-    pub fn set_point_shadow_map(&mut self, texture_handle: u64, index: u32) {
-        self.static_shader_storage_buffer.set_data(
-            &texture_handle,
-            offset_of!(StaticShaderStorageBuffers, shadow_maps) as u32
-                + offset_of!(ShadowMapStorageBuffer, point_shadow_map) as u32
-                + (size_of::<ShadowMap>() as u32 * index),
+    pub fn set_spot_shadow_tile(&mut self, uv_rect: Vec4f, index: u32) {
+        self.frame_shader_storage_buffer.set_data(
+            &uv_rect,
+            offset_of!(FrameShaderStorageBuffers, shadow_maps) as u32
+                + offset_of!(ShadowMapStorageBuffer, spot_tiles) as u32
+                + (size_of::<Vec4f>() as u32 * index),
         );
     }
 
-    // Per Frame Shader Storage Buffer
-    ///////////////////////////////////////////////////////////////////////////////////////
+    pub fn set_point_shadow_tiles(&mut self, uv_rects: [Vec4f; 6], index: u32) {
+        self.frame_shader_storage_buffer.set_data(
+            &uv_rects,
+            offset_of!(FrameShaderStorageBuffers, shadow_maps) as u32
+                + offset_of!(ShadowMapStorageBuffer, point_tiles) as u32
+                + (size_of::<[Vec4f; 6]>() as u32 * index),
+        );
+    }
 
     //// Point Lights
     ////////////////////////////
 
     pub fn set_point_light_data(&mut self, light: PointLight, index: u32) {
-        self.frame_shader_storage_buffer.set_data(
+        self.light_grid_buffer.set_data(
             &light,
-            offset_of!(FrameShaderStorageBuffers, lights) as u32
-                + offset_of!(LightsStorageBuffer, point_lights) as u32
+            offset_of!(LightGridStorageBuffers, point_lights) as u32
                 + (size_of::<PointLight>() as u32 * index),
         );
     }
 
     pub fn set_point_light_data_slice(&mut self, light: &[PointLight]) {
-        self.frame_shader_storage_buffer.set_data_slice(
-            light,
-            offset_of!(FrameShaderStorageBuffers, lights) as u32
-                + offset_of!(LightsStorageBuffer, point_lights) as u32,
+        self.light_grid_buffer.set_data_slice(
+            &light[..light.len().min(MAX_POINT_LIGHTS)],
+            offset_of!(LightGridStorageBuffers, point_lights) as u32,
         );
     }
 
@@ -371,19 +597,17 @@ impl MemoryManager {
     ////////////////////////////
 
     pub fn set_spot_light_data(&mut self, light: SpotLight, index: u32) {
-        self.frame_shader_storage_buffer.set_data(
+        self.light_grid_buffer.set_data(
             &light,
-            offset_of!(FrameShaderStorageBuffers, lights) as u32
-                + offset_of!(LightsStorageBuffer, spot_lights) as u32
+            offset_of!(LightGridStorageBuffers, spot_lights) as u32
                 + (size_of::<SpotLight>() as u32 * index),
         );
     }
 
     pub fn set_spot_light_data_slice(&mut self, light: &[SpotLight]) {
-        self.frame_shader_storage_buffer.set_data_slice(
-            light,
-            offset_of!(FrameShaderStorageBuffers, lights) as u32
-                + offset_of!(LightsStorageBuffer, spot_lights) as u32,
+        self.light_grid_buffer.set_data_slice(
+            &light[..light.len().min(MAX_SPOT_LIGHTS)],
+            offset_of!(LightGridStorageBuffers, spot_lights) as u32,
         );
     }
 
@@ -452,6 +676,60 @@ impl MemoryManager {
         );
     }
 
+    /// A handle of zero disables ambient occlusion in the lighting shaders
+    pub fn set_ambient_occlusion_map(&mut self, texture_handle: u64) {
+        self.frame_shader_storage_buffer.set_data(
+            &texture_handle,
+            offset_of!(FrameShaderStorageBuffers, lights) as u32
+                + offset_of!(LightsStorageBuffer, ambient_occlusion_map) as u32,
+        );
+    }
+
+    /// Handles of zero disable image based lighting in the lighting shaders
+    pub fn set_environment_maps(
+        &mut self,
+        irradiance_handle: u64,
+        prefiltered_handle: u64,
+        brdf_lut_handle: u64,
+        prefiltered_max_level: f32,
+    ) {
+        self.frame_shader_storage_buffer.set_data(
+            &[irradiance_handle, prefiltered_handle, brdf_lut_handle],
+            offset_of!(FrameShaderStorageBuffers, lights) as u32
+                + offset_of!(LightsStorageBuffer, irradiance_map) as u32,
+        );
+        self.frame_shader_storage_buffer.set_data(
+            &prefiltered_max_level,
+            offset_of!(FrameShaderStorageBuffers, lights) as u32
+                + offset_of!(LightsStorageBuffer, prefiltered_max_level) as u32,
+        );
+    }
+
+    //// Light Clusters
+    ////////////////////////////
+
+    pub fn set_light_clusters(&mut self, clusters: &[LightCluster]) {
+        self.light_grid_buffer.set_data_slice(
+            &clusters[..clusters.len().min(MAX_CLUSTERS)],
+            offset_of!(LightGridStorageBuffers, clusters) as u32,
+        );
+    }
+
+    pub fn set_cluster_light_indices(&mut self, indices: &[u32]) {
+        self.light_grid_buffer.set_data_slice(
+            &indices[..indices.len().min(MAX_CLUSTERS * MAX_LIGHTS_PER_CLUSTER)],
+            offset_of!(LightGridStorageBuffers, light_indices) as u32,
+        );
+    }
+
+    pub fn set_cluster_depth_parameters(&mut self, scale: f32, bias: f32) {
+        self.frame_shader_storage_buffer.set_data(
+            &[scale, bias],
+            offset_of!(FrameShaderStorageBuffers, lights) as u32
+                + offset_of!(LightsStorageBuffer, cluster_depth_scale) as u32,
+        );
+    }
+
     // Per Draw Call Shader Storage Buffer
     ///////////////////////////////////////////////////////////////////////////////////////
 
@@ -1,7 +1,22 @@
 use crate::math::*;
 
-pub const MAX_POINT_LIGHTS: usize = 2;
-pub const MAX_SPOT_LIGHTS: usize = 4;
+pub const MAX_POINT_LIGHTS: usize = 1024;
+pub const MAX_SPOT_LIGHTS: usize = 1024;
+/// Only the first lights of each type are given a shadow map
+pub const MAX_POINT_LIGHT_SHADOWS: usize = 2;
+pub const MAX_SPOT_LIGHT_SHADOWS: usize = 4;
+pub const MAX_CASCADES: usize = 4;
+
+/// Number of clusters that the view frustum is split into, across the screen and along the view depth
+pub const CLUSTER_GRID_X: usize = 16;
+pub const CLUSTER_GRID_Y: usize = 9;
+pub const CLUSTER_GRID_Z: usize = 24;
+pub const MAX_CLUSTERS: usize = CLUSTER_GRID_X * CLUSTER_GRID_Y * CLUSTER_GRID_Z;
+/// Point and spot lights combined, where any beyond this are left out of the cluster
+pub const MAX_LIGHTS_PER_CLUSTER: usize = 128;
+
+/// Groups of commands per frame that can be culled on the GPU, each with its own draw count
+pub const MAX_DRAW_COUNTS: usize = 64;
 
 type Padding = u32;
 
@@ -9,16 +24,27 @@ type Padding = u32;
 /// Considered static because the data will expect to persist without modification for multiple frames at least
 pub struct StaticShaderStorageBuffers {
     pub materials: MaterialsStorageBuffer,
-    pub shadow_maps: ShadowMapStorageBuffer,
 }
 
 #[repr(C)]
 /// Used as space for parameters that are expected to change each frame
 pub struct FrameShaderStorageBuffers {
+    pub shadow_maps: ShadowMapStorageBuffer,
     pub lights: LightsStorageBuffer,
     pub matrices: MatricesStorageBuffer,
 }
 
+#[repr(C)]
+/// Changes each frame, like `FrameShaderStorageBuffers`, but is too large for a uniform buffer so is bound as a
+/// shader storage buffer instead
+pub struct LightGridStorageBuffers {
+    pub point_lights: [PointLight; MAX_POINT_LIGHTS],
+    pub spot_lights: [SpotLight; MAX_SPOT_LIGHTS],
+    pub clusters: [LightCluster; MAX_CLUSTERS],
+    /// Each cluster's point light indices, followed by its spot light indices
+    pub light_indices: [u32; MAX_CLUSTERS * MAX_LIGHTS_PER_CLUSTER],
+}
+
 #[repr(C)]
 /// Used as space for parameters that are expected to change each draw call
 pub struct DrawShaderStorageBuffers {
@@ -53,25 +79,74 @@ pub struct GeneralPurposeVecStorageBuffer {
 #[repr(C)]
 #[derive(Clone, Copy)]
 pub struct ShadowMapStorageBuffer {
-    pub point_shadow_map: [ShadowMap; MAX_POINT_LIGHTS],
-    pub spot_shadow_map: [ShadowMap; MAX_SPOT_LIGHTS],
+    /// Spot lights and each face of the point lights are drawn into tiles of the atlas
+    pub atlas: ShadowMap,
     pub directional_shadow_map: ShadowMap,
+    /// UV offset (xy) and scale (zw) of each spot light's tile within the atlas, where a scale of zero means the
+    /// light has no shadow this frame
+    pub spot_tiles: [Vec4f; MAX_SPOT_LIGHT_SHADOWS],
+    /// UV rects of each face of each point light, in the same order as `PointLight::views`
+    pub point_tiles: [[Vec4f; 6]; MAX_POINT_LIGHT_SHADOWS],
 }
 
 #[repr(C)]
 #[derive(Clone, Copy)]
 pub struct ShadowMap {
+    /// Samples with a depth comparison, for hardware PCF
     pub handle: Vec2u,
-    pub _1: Padding,
-    pub _2: Padding,
+    /// Samples the stored depth directly, for the PCSS blocker search
+    pub depth_handle: Vec2u,
+}
+
+#[repr(u32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShadowFilter {
+    /// Bilinear filtered comparisons across a few neighbouring texels
+    HardwarePcf = 0,
+    /// Comparisons across a rotated Poisson disk, for a wider, fixed size penumbra
+    PoissonPcf = 1,
+    /// Percentage closer soft shadows, where the penumbra widens with the distance between caster and receiver
+    Pcss = 2,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct ShadowSettings {
+    /// A `ShadowFilter`
+    pub filter: u32,
+    /// Subtracted from the depth of the receiver before it is compared against the shadow map
+    pub depth_bias: f32,
+    /// Distance, in shadow map texels, that the receiver is moved along its normal before it is looked up.
+    /// This grows as the surface turns away from the light.
+    pub normal_bias: f32,
+    /// Scales the penumbra of `ShadowFilter::Pcss`
+    pub light_size: f32,
+}
+
+impl ShadowSettings {
+    pub fn new(filter: ShadowFilter) -> Self {
+        Self {
+            filter: filter as u32,
+            ..Default::default()
+        }
+    }
+}
+
+impl Default for ShadowSettings {
+    fn default() -> Self {
+        Self {
+            filter: ShadowFilter::HardwarePcf as u32,
+            depth_bias: 0.0001,
+            normal_bias: 1.0,
+            light_size: 40.0,
+        }
+    }
 }
 
 
 #[repr(C)]
 #[derive(Clone, Copy)]
 pub struct LightsStorageBuffer {
-    pub point_lights: [PointLight; MAX_POINT_LIGHTS],
-    pub spot_lights: [SpotLight; MAX_SPOT_LIGHTS],
     pub directional_light: DirectionalLight,
 
     pub point_light_count: u32,
@@ -83,6 +158,28 @@ pub struct LightsStorageBuffer {
     pub _2: Padding,
     pub camera_pos: Vec3f,
     pub _3: Padding,
+
+    pub ambient_occlusion_map: Vec2u,
+    /// Takes the log of the view depth to the cluster's depth slice
+    pub cluster_depth_scale: f32,
+    pub cluster_depth_bias: f32,
+
+    pub irradiance_map: Vec2u,
+    pub prefiltered_map: Vec2u,
+    pub brdf_lut: Vec2u,
+    /// The roughest level of the prefiltered map, which has a roughness of one
+    pub prefiltered_max_level: f32,
+    pub _4: Padding,
+}
+
+/// Where a cluster's lights are within `LightGridStorageBuffers::light_indices`
+#[repr(C)]
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LightCluster {
+    pub offset: u32,
+    pub point_light_count: u32,
+    pub spot_light_count: u32,
+    pub _1: Padding,
 }
 
 #[repr(C)]
@@ -103,6 +200,10 @@ pub struct PointLight {
     pub _5: Padding,
 
     pub views: [Mat4f; 6],
+
+    /// Last, as the light is read from a std430 buffer, where the settings would otherwise be packed against the
+    /// position
+    pub shadow: ShadowSettings,
 }
 
 #[repr(C)]
@@ -130,6 +231,9 @@ pub struct SpotLight {
     pub _8: Padding,
 
     pub view: Mat4f,
+
+    /// Last, for the same reason as `PointLight::shadow`
+    pub shadow: ShadowSettings,
 }
 
 #[repr(C)]
@@ -147,7 +251,16 @@ pub struct DirectionalLight {
     pub direction: Vec3f,
     pub _6: Padding,
 
-    pub view: Mat4f,
+    pub cascade_views: [Mat4f; MAX_CASCADES],
+    // view space depth that each cascade ends at, one per component
+    pub cascade_splits: Vec4f,
+    pub cascade_count: u32,
+    // fraction of each cascade, at its far end, that is blended with the next
+    pub cascade_blend: f32,
+    pub cascade_debug: u32,
+    pub _7: Padding,
+
+    pub shadow: ShadowSettings,
 }
 
 
@@ -161,11 +274,69 @@ pub struct MaterialsStorageBuffer {
 #[derive(Default, Clone, Copy)]
 pub struct Material {
     pub shininess: f32,
-    pub _1: Padding,
+    pub model: MaterialModel,
+    /// Also the base colour texture of PBR materials
     pub diffuse_texture: Vec2u,
     pub specular_texture: Vec2u,
     pub normal_texture: Vec2u,
+    /// Textures of PBR materials, which are zero when the material doesn't have them
+    pub metallic_roughness_texture: Vec2u,
+    pub occlusion_texture: Vec2u,
+    pub emissive_texture: Vec2u,
+    pub _1: [Padding; 2],
+    pub base_colour: Vec4f,
+    pub emissive: Vec3f,
+    pub metallic: f32,
+    pub roughness: f32,
+    pub occlusion_strength: f32,
+    pub _2: [Padding; 2],
+}
+
+/// Selects the lighting model that a material is shaded with
+#[repr(u32)]
+#[derive(Default, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum MaterialModel {
+    #[default]
+    Phong = 0,
+    Pbr = 1,
+}
 
+#[repr(C)]
+#[derive(Default, Clone, Copy)]
+/// Precedes the batches of a GPU culling dispatch
+pub struct CullHeader {
+    /// World space frustum planes that the instances are tested against, facing inwards
+    pub planes: [Vec4f; 6],
+    /// Projection and view of the frame the Hi-Z pyramid was built from
+    pub view_projection: Mat4f,
+    pub hiz_texture: Vec2u,
+    pub hiz_size: Vec2u,
+    /// Where zero means there is no pyramid, so instances are only frustum culled
+    pub hiz_levels: u32,
+    pub batch_count: u32,
+    pub _1: [Padding; 2],
+}
+
+#[repr(C)]
+#[derive(Default, Clone, Copy)]
+/// Instances of a mesh that are culled on the GPU, where those that are visible are drawn with a single command
+pub struct CullBatch {
+    /// Local bounds of the mesh, where a `w` of zero in the minimum means there are none, so every instance is
+    /// visible
+    pub bounds_min: Vec4f,
+    pub bounds_max: Vec4f,
+    pub index_count: u32,
+    pub first_index: u32,
+    pub base_vertex: u32,
+    /// First instance in the instance buffer that is tested
+    pub input_base: u32,
+    pub instance_count: u32,
+    /// Where the visible instances are compacted to, in the instance buffer
+    pub output_base: u32,
+    /// Counts the commands written for the batch's group, so each is drawn in one call
+    pub draw_count_index: u32,
+    /// First command of the batch's group, in the indirect command buffer
+    pub command_base: u32,
 }
 
 #[repr(C)]
@@ -175,6 +346,12 @@ pub struct MatricesStorageBuffer {
     pub view: Mat4f,
 }
 
+unsafe impl bytemuck::Pod for CullBatch {}
+unsafe impl bytemuck::Zeroable for CullBatch {}
+
+unsafe impl bytemuck::Pod for LightCluster {}
+unsafe impl bytemuck::Zeroable for LightCluster {}
+
 unsafe impl bytemuck::Pod for PointLight {}
 unsafe impl bytemuck::Zeroable for PointLight {}
 
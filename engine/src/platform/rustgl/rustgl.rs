@@ -251,6 +251,28 @@ pub unsafe fn get_tex_image(target: u32, level: i32, format: u32, ty: u32, pixel
     );
 }
 
+#[inline]
+pub unsafe fn get_texture_image(
+    texture: GlTexture,
+    level: i32,
+    format: u32,
+    ty: u32,
+    buffer_size: i32,
+    pixels: PixelPackData,
+) {
+    native_gl::glGetTextureImage(
+        texture.0.get(),
+        level,
+        format,
+        ty,
+        buffer_size,
+        match pixels {
+            PixelPackData::BufferOffset(offset) => offset as *mut std::ffi::c_void,
+            PixelPackData::Slice(data) => data.as_mut_ptr() as *mut std::ffi::c_void,
+        },
+    );
+}
+
 #[inline]
 pub unsafe fn create_program() -> Result<GlProgram, String> {
     Ok(GlProgram(
@@ -1132,6 +1154,25 @@ pub unsafe fn multi_draw_elements_indirect_offset(
     );
 }
 
+#[inline]
+pub unsafe fn multi_draw_elements_indirect_count_offset(
+    mode: u32,
+    element_type: u32,
+    offset: i32,
+    draw_count_offset: i32,
+    max_draw_count: i32,
+    stride: i32,
+) {
+    native_gl::glMultiDrawElementsIndirectCount(
+        mode,
+        element_type,
+        offset as *const std::ffi::c_void,
+        draw_count_offset as isize,
+        max_draw_count,
+        stride,
+    );
+}
+
 #[inline]
 pub unsafe fn enable(parameter: u32) {
     native_gl::glEnable(parameter);
@@ -1250,6 +1291,15 @@ pub unsafe fn named_framebuffer_draw_buffer(framebuffer: GlFramebuffer, color_bu
     native_gl::glNamedFramebufferDrawBuffer(framebuffer.0.get(), color_buffer)
 }
 
+#[inline]
+pub unsafe fn named_framebuffer_draw_buffers(framebuffer: GlFramebuffer, color_buffers: &[u32]) {
+    native_gl::glNamedFramebufferDrawBuffers(
+        framebuffer.0.get(),
+        color_buffers.len() as i32,
+        color_buffers.as_ptr(),
+    )
+}
+
 #[inline]
 pub unsafe fn named_framebuffer_read_buffer(framebuffer: GlFramebuffer, source: u32) {
     native_gl::glNamedFramebufferReadBuffer(framebuffer.0.get(), source)
@@ -1340,6 +1390,14 @@ pub unsafe fn get_texture_handle(texture: GlTexture) -> GlTextureHandle {
     )))
 }
 
+#[inline]
+pub unsafe fn get_texture_sampler_handle(texture: GlTexture, sampler: GlSampler) -> GlTextureHandle {
+    GlTextureHandle(non_zero_u64_gl_name(native_gl::glGetTextureSamplerHandleARB(
+        texture.0.get(),
+        sampler.0.get(),
+    )))
+}
+
 #[inline]
 pub unsafe fn make_texture_handle_non_resident(texture_handle: GlTextureHandle) {
     native_gl::glMakeTextureHandleNonResidentARB(texture_handle.0.get())
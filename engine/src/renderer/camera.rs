@@ -1,17 +1,28 @@
 use crate::math::*;
 
+#[derive(Clone, Copy)]
 pub enum Projection {
     Orthographic,
     Perspective,
 }
 
+#[derive(Clone)]
 pub struct Camera {
     pub position: Vec3f,
+    /// Rotates the camera from looking down -Z, with +Y up
+    pub orientation: Quat,
+    /// The camera's +Z axis, which points away from where it looks. Kept in sync with `orientation` by
+    /// `update_view`
     pub direction: Vec3f,
-    pub pitch: f32,
-    pub yaw: f32,
     pub view: Mat4f,
+    /// Includes `jitter`
     pub projection: Mat4f,
+    pub unjittered_projection: Mat4f,
+    /// Sub-pixel offset of the projection, in pixels, which temporal anti-aliasing changes each frame so that
+    /// edges are sampled at different positions within their pixels
+    pub jitter: Vec2f,
+    /// Size in pixels that the projection was last updated for
+    pub viewport: Vec2f,
     pub fov_size: f32,
     pub near_plane: f32,
     pub far_plane: f32,
@@ -21,15 +32,17 @@ pub struct Camera {
 impl Camera {
     pub fn new_perspective(fov: f32, near: f32, far: f32) -> Self {
         let position = Vec3f::new(0.0, 0.0, -3.0);
-        let direction = Vec3f::new(0.0, 0.0, 1.0);
-        let view = Self::look_at(&position, &direction, &Vec3f::new(0.0, 1.0, 0.0));
+        let orientation = Quat::identity();
+        let view = Self::view_matrix(&position, &orientation);
         Camera {
             position,
-            direction,
+            orientation,
+            direction: orientation.rotate(Vec3f::new(0.0, 0.0, 1.0)),
             view,
-            pitch: 0.0,
-            yaw: 90.0,
             projection: Mat4f::perspective(1.0, fov.to_radians(), near, far),
+            unjittered_projection: Mat4f::perspective(1.0, fov.to_radians(), near, far),
+            jitter: Vec2f::uniform(0.0),
+            viewport: Vec2f::uniform(1.0),
             fov_size: fov,
             near_plane: near,
             far_plane: far,
@@ -39,15 +52,17 @@ impl Camera {
 
     pub fn new_orthographic(size: f32, near: f32, far: f32) -> Self {
         let position = Vec3f::new(0.0, 0.0, -3.0);
-        let direction = Vec3f::new(0.0, 0.0, 1.0);
-        let view = Self::look_at(&position, &direction, &Vec3f::new(0.0, 1.0, 0.0));
+        let orientation = Quat::identity();
+        let view = Self::view_matrix(&position, &orientation);
         Camera {
             position,
-            direction,
+            orientation,
+            direction: orientation.rotate(Vec3f::new(0.0, 0.0, 1.0)),
             view,
-            pitch: 0.0,
-            yaw: 90.0,
             projection: Mat4f::orthographic(size, near, far),
+            unjittered_projection: Mat4f::orthographic(size, near, far),
+            jitter: Vec2f::uniform(0.0),
+            viewport: Vec2f::uniform(1.0),
             fov_size: size,
             near_plane: near,
             far_plane: far,
@@ -56,20 +71,26 @@ impl Camera {
     }
 
     pub fn update_view(&mut self) {
-        self.view = Self::look_at(&self.position, &self.direction, &Vec3f::new(0.0, 1.0, 0.0));
+        self.orientation = self.orientation.normalise();
+        self.direction = self.orientation.rotate(Vec3f::new(0.0, 0.0, 1.0));
+        self.view = Self::view_matrix(&self.position, &self.orientation);
+    }
+
+    /// Turns the camera to look at the point, keeping its Y axis as close to world up as it can be
+    pub fn look_towards(&mut self, target: &Vec3f) {
+        self.orientation =
+            Quat::look_rotation(&(*target - self.position), &Vec3f::new(0.0, 1.0, 0.0));
     }
 
     pub fn update_projection(&mut self, width: f32, height: f32) {
+        self.viewport = Vec2f::new(width, height);
         match self.projection_type {
             Projection::Orthographic => {
-                self.projection = Mat4f::orthographic(
-                    self.fov_size,
-                    self.near_plane,
-                    self.far_plane,
-                )
+                self.unjittered_projection =
+                    Mat4f::orthographic(self.fov_size, self.near_plane, self.far_plane)
             }
             Projection::Perspective => {
-                self.projection = Mat4f::perspective(
+                self.unjittered_projection = Mat4f::perspective(
                     width / height,
                     self.fov_size.to_radians(),
                     self.near_plane,
@@ -77,6 +98,45 @@ impl Camera {
                 )
             }
         };
+        self.set_jitter(self.jitter);
+    }
+
+    /// Offsets the projection by a fraction of a pixel, which moves everything drawn with it the same distance
+    /// across the screen whatever its depth
+    pub fn set_jitter(&mut self, jitter: Vec2f) {
+        self.jitter = jitter;
+        self.projection = Mat4f::translate(
+            2.0 * jitter.x / self.viewport.x,
+            2.0 * jitter.y / self.viewport.y,
+            0.0,
+        ) * self.unjittered_projection;
+    }
+
+    /// Transforms from world space into the space of a camera at `position` with `orientation`, which is the
+    /// inverse of its rotation followed by its translation. This works at any orientation, unlike `look_at`, which
+    /// breaks down when looking along `up`
+    pub fn view_matrix(position: &Vec3f, orientation: &Quat) -> Mat4f {
+        let (px, py, pz) = position.as_tuple();
+        let (rx, ry, rz) = orientation.right().as_tuple();
+        let (ux, uy, uz) = orientation.up().as_tuple();
+        let (bx, by, bz) = (-orientation.forward()).as_tuple();
+
+        let mut matrix = Mat4f::identity();
+
+        matrix[(0, 0)] = rx;
+        matrix[(0, 1)] = ry;
+        matrix[(0, 2)] = rz;
+        matrix[(0, 3)] = -rx * px - ry * py - rz * pz;
+        matrix[(1, 0)] = ux;
+        matrix[(1, 1)] = uy;
+        matrix[(1, 2)] = uz;
+        matrix[(1, 3)] = -ux * px - uy * py - uz * pz;
+        matrix[(2, 0)] = bx;
+        matrix[(2, 1)] = by;
+        matrix[(2, 2)] = bz;
+        matrix[(2, 3)] = -bx * px - by * py - bz * pz;
+
+        matrix
     }
 
     pub fn look_at(position: &Vec3f, direction: &Vec3f, up: &Vec3f) -> Mat4f {
@@ -0,0 +1,156 @@
+use crate::{math::*, memory_manager::uniform_layouts::MAX_CASCADES, renderer::camera::Camera};
+
+#[derive(Clone, Copy)]
+pub struct CascadeSettings {
+    /// Between 1 and `MAX_CASCADES`
+    pub count: u32,
+    /// Blends between uniform (0.0) and logarithmic (1.0) split distances
+    pub split_lambda: f32,
+    /// View space depth beyond which nothing receives a shadow, clamped to the camera's far plane
+    pub max_distance: f32,
+    /// Fraction of each cascade, at its far end, that is blended with the next
+    pub blend: f32,
+    /// Distance behind each cascade, towards the light, that shadow casters are still included from
+    pub caster_distance: f32,
+    /// Tints each cascade a different colour
+    pub debug: bool,
+}
+
+impl Default for CascadeSettings {
+    fn default() -> Self {
+        Self {
+            count: 4,
+            split_lambda: 0.75,
+            max_distance: 100.0,
+            blend: 0.1,
+            caster_distance: 50.0,
+            debug: false,
+        }
+    }
+}
+
+/// Light space transforms fitted to consecutive slices of the camera frustum
+pub struct Cascades {
+    pub views: [Mat4f; MAX_CASCADES],
+    /// View space depth that each cascade ends at
+    pub splits: [f32; MAX_CASCADES],
+    pub count: usize,
+}
+
+/// The view space depths that the range between `near` and `far` is split at, using the practical split scheme.
+/// The last split is always `far`.
+pub fn split_distances(near: f32, far: f32, count: usize, lambda: f32) -> [f32; MAX_CASCADES] {
+    let count = count.clamp(1, MAX_CASCADES);
+    let mut splits = [far; MAX_CASCADES];
+
+    for (i, split) in splits.iter_mut().enumerate().take(count - 1) {
+        let fraction = (i + 1) as f32 / count as f32;
+        let logarithmic = near * (far / near).powf(fraction);
+        let uniform = near + (far - near) * fraction;
+
+        *split = lambda * logarithmic + (1.0 - lambda) * uniform;
+    }
+
+    splits
+}
+
+/// Fits a cascade for each slice of the camera frustum. <br>
+/// Each cascade is fitted to a bounding sphere of its slice, so its size does not change as the camera rotates,
+/// and is then snapped to whole shadow map texels, so the shadow edges do not shimmer as the camera moves.
+pub fn calculate_cascades(
+    projection: &Mat4f,
+    view: &Mat4f,
+    light_direction: Vec3f,
+    resolution: u32,
+    settings: &CascadeSettings,
+) -> Cascades {
+    let count = (settings.count as usize).clamp(1, MAX_CASCADES);
+    let mut cascades = Cascades {
+        views: [Mat4f::identity(); MAX_CASCADES],
+        splits: [0.0; MAX_CASCADES],
+        count,
+    };
+
+    let (inverse_projection, inverse_view_projection) =
+        match (projection.inverse(), (*projection * *view).inverse()) {
+            (Some(a), Some(b)) => (a, b),
+            _ => return cascades,
+        };
+
+    let unproject = |matrix: &Mat4f, x: f32, y: f32, z: f32| {
+        let point = *matrix * Vec4f::new(x, y, z, 1.0);
+        Vec3f::from(point) / point.w
+    };
+
+    // view space looks down -Z
+    let near = -unproject(&inverse_projection, 0.0, 0.0, -1.0).z;
+    let far = -unproject(&inverse_projection, 0.0, 0.0, 1.0).z;
+
+    let mut near_corners = [Vec3f::uniform(0.0); 4];
+    let mut far_corners = [Vec3f::uniform(0.0); 4];
+    for (i, (x, y)) in [(-1.0, -1.0), (1.0, -1.0), (-1.0, 1.0), (1.0, 1.0)]
+        .into_iter()
+        .enumerate()
+    {
+        near_corners[i] = unproject(&inverse_view_projection, x, y, -1.0);
+        far_corners[i] = unproject(&inverse_view_projection, x, y, 1.0);
+    }
+
+    let shadow_far = settings.max_distance.clamp(near, far);
+    cascades.splits = split_distances(near, shadow_far, count, settings.split_lambda);
+
+    let light_direction = light_direction.normalise();
+    let up = if light_direction.y.abs() > 0.99 {
+        Vec3f::new(0.0, 0.0, 1.0)
+    } else {
+        Vec3f::new(0.0, 1.0, 0.0)
+    };
+
+    let mut slice_start = near;
+    for i in 0..count {
+        let slice_end = cascades.splits[i];
+
+        // points on the edges of the frustum move linearly with view space depth
+        let mut corners = [Vec3f::uniform(0.0); 8];
+        for j in 0..4 {
+            let edge = far_corners[j] - near_corners[j];
+            corners[j] = near_corners[j] + edge * ((slice_start - near) / (far - near));
+            corners[j + 4] = near_corners[j] + edge * ((slice_end - near) / (far - near));
+        }
+
+        let mut centre = Vec3f::uniform(0.0);
+        for corner in corners.iter() {
+            centre += *corner;
+        }
+        centre /= corners.len() as f32;
+
+        let mut radius: f32 = 0.0;
+        for corner in corners.iter() {
+            radius = radius.max((*corner - centre).magnitude());
+        }
+        // floating point error would otherwise change the texel size slightly between frames
+        radius = (radius * 16.0).ceil() / 16.0;
+
+        let distance = radius + settings.caster_distance;
+        let light_view = Camera::look_at(
+            &(centre + light_direction * distance),
+            &light_direction,
+            &up,
+        );
+        let mut light_projection = Mat4f::orthographic(radius * 2.0, 0.0, distance + radius);
+
+        // move the projection so that the world origin lands on a texel boundary, where clip space spans two units
+        // across the shadow map
+        let half_resolution = resolution as f32 / 2.0;
+        let origin = (light_projection * light_view) * Vec4f::new(0.0, 0.0, 0.0, 1.0);
+        let origin_x = origin.x * half_resolution;
+        let origin_y = origin.y * half_resolution;
+        light_projection[(0, 3)] += (origin_x.round() - origin_x) / half_resolution;
+        light_projection[(1, 3)] += (origin_y.round() - origin_y) / half_resolution;
+
+        cascades.views[i] = light_projection * light_view;
+        slice_start = slice_end;
+    }
+
+    cascades
+}
@@ -0,0 +1,232 @@
+use crate::{
+    math::*,
+    memory_manager::uniform_layouts::{
+        LightCluster, CLUSTER_GRID_X, CLUSTER_GRID_Y, CLUSTER_GRID_Z, MAX_CLUSTERS,
+        MAX_LIGHTS_PER_CLUSTER,
+    },
+};
+
+/// The sphere that a light has an effect within, in world space
+#[derive(Clone, Copy)]
+pub struct LightBounds {
+    pub position: Vec3f,
+    pub radius: f32,
+}
+
+/// Lights binned into the clusters of the view frustum
+pub struct LightGrid {
+    pub clusters: Vec<LightCluster>,
+    /// Each cluster's point light indices, followed by its spot light indices
+    pub light_indices: Vec<u32>,
+    /// Takes the log of the view depth to the depth slice
+    pub depth_scale: f32,
+    pub depth_bias: f32,
+}
+
+/// The clusters that a light overlaps, inclusive
+struct ClusterRange {
+    min: [usize; 3],
+    max: [usize; 3],
+}
+
+impl ClusterRange {
+    fn indices(&self) -> impl Iterator<Item = usize> + '_ {
+        (self.min[2]..=self.max[2]).flat_map(move |z| {
+            (self.min[1]..=self.max[1])
+                .flat_map(move |y| (self.min[0]..=self.max[0]).map(move |x| cluster_index(x, y, z)))
+        })
+    }
+}
+
+pub fn cluster_index(x: usize, y: usize, z: usize) -> usize {
+    x + y * CLUSTER_GRID_X + z * CLUSTER_GRID_X * CLUSTER_GRID_Y
+}
+
+/// The depth slice that a view space depth falls into. The slices get exponentially deeper, so that the clusters
+/// stay roughly cube shaped along the length of the frustum.
+pub fn depth_slice(view_depth: f32, depth_scale: f32, depth_bias: f32) -> usize {
+    let slice = view_depth.max(f32::MIN_POSITIVE).ln() * depth_scale + depth_bias;
+
+    (slice.max(0.0) as usize).min(CLUSTER_GRID_Z - 1)
+}
+
+/// Bins each light into every cluster that its bounds overlap. <br>
+/// The bounds are projected as a box, so lights are assigned conservatively, and any light that crosses the near
+/// plane covers the whole screen. Clusters keep their first `MAX_LIGHTS_PER_CLUSTER` lights, with point lights
+/// taking priority.
+pub fn assign_lights(
+    projection: &Mat4f,
+    view: &Mat4f,
+    point_lights: &[LightBounds],
+    spot_lights: &[LightBounds],
+) -> LightGrid {
+    let mut grid = LightGrid {
+        clusters: vec![LightCluster::default(); MAX_CLUSTERS],
+        light_indices: Vec::new(),
+        depth_scale: 0.0,
+        depth_bias: 0.0,
+    };
+
+    let inverse_projection = match projection.inverse() {
+        Some(inverse) => inverse,
+        None => return grid,
+    };
+
+    // view space looks down -Z
+    let unproject_depth = |z: f32| {
+        let point = inverse_projection * Vec4f::new(0.0, 0.0, z, 1.0);
+        -point.z / point.w
+    };
+    let near = unproject_depth(-1.0);
+    let far = unproject_depth(1.0);
+
+    let log_range = (far / near).ln();
+    grid.depth_scale = CLUSTER_GRID_Z as f32 / log_range;
+    grid.depth_bias = -(CLUSTER_GRID_Z as f32) * near.ln() / log_range;
+
+    let to_ranges = |lights: &[LightBounds]| -> Vec<Option<ClusterRange>> {
+        lights
+            .iter()
+            .map(|light| light_cluster_range(light, projection, view, near, far, &grid))
+            .collect()
+    };
+    let point_ranges = to_ranges(point_lights);
+    let spot_ranges = to_ranges(spot_lights);
+
+    let mut point_counts = vec![0; MAX_CLUSTERS];
+    let mut spot_counts = vec![0; MAX_CLUSTERS];
+    for (ranges, counts) in [
+        (&point_ranges, &mut point_counts),
+        (&spot_ranges, &mut spot_counts),
+    ] {
+        for range in ranges.iter().flatten() {
+            for index in range.indices() {
+                counts[index] += 1;
+            }
+        }
+    }
+
+    let mut offset = 0;
+    for (i, cluster) in grid.clusters.iter_mut().enumerate() {
+        let point_light_count = point_counts[i].min(MAX_LIGHTS_PER_CLUSTER as u32);
+        let spot_light_count =
+            spot_counts[i].min(MAX_LIGHTS_PER_CLUSTER as u32 - point_light_count);
+
+        *cluster = LightCluster {
+            offset,
+            point_light_count,
+            spot_light_count,
+            _1: 0,
+        };
+        offset += point_light_count + spot_light_count;
+    }
+
+    grid.light_indices = vec![0; offset as usize];
+
+    for (ranges, is_point) in [(&point_ranges, true), (&spot_ranges, false)] {
+        let mut filled = vec![0; MAX_CLUSTERS];
+
+        for (light_index, range) in ranges.iter().enumerate() {
+            let range = match range {
+                Some(range) => range,
+                None => continue,
+            };
+
+            for index in range.indices() {
+                let cluster = &grid.clusters[index];
+                let (start, count) = if is_point {
+                    (cluster.offset, cluster.point_light_count)
+                } else {
+                    (
+                        cluster.offset + cluster.point_light_count,
+                        cluster.spot_light_count,
+                    )
+                };
+
+                if filled[index] < count {
+                    grid.light_indices[(start + filled[index]) as usize] = light_index as u32;
+                    filled[index] += 1;
+                }
+            }
+        }
+    }
+
+    grid
+}
+
+/// The clusters that the light's bounds overlap, or `None` when it is entirely outside of the view frustum
+fn light_cluster_range(
+    light: &LightBounds,
+    projection: &Mat4f,
+    view: &Mat4f,
+    near: f32,
+    far: f32,
+    grid: &LightGrid,
+) -> Option<ClusterRange> {
+    let centre =
+        Vec3f::from(*view * Vec4f::new(light.position.x, light.position.y, light.position.z, 1.0));
+    let depth = -centre.z;
+
+    if depth + light.radius < near || depth - light.radius > far {
+        return None;
+    }
+
+    let min_z = depth_slice(
+        (depth - light.radius).max(near),
+        grid.depth_scale,
+        grid.depth_bias,
+    );
+    let max_z = depth_slice(
+        (depth + light.radius).min(far),
+        grid.depth_scale,
+        grid.depth_bias,
+    );
+
+    // corners behind the camera don't project sensibly, so the whole screen is covered instead
+    if depth - light.radius <= near {
+        return Some(ClusterRange {
+            min: [0, 0, min_z],
+            max: [CLUSTER_GRID_X - 1, CLUSTER_GRID_Y - 1, max_z],
+        });
+    }
+
+    let mut min_ndc = Vec3f::uniform(f32::MAX);
+    let mut max_ndc = Vec3f::uniform(f32::MIN);
+    for i in 0..8 {
+        let offset = Vec3f::new(
+            if i & 1 == 0 { -1.0 } else { 1.0 },
+            if i & 2 == 0 { -1.0 } else { 1.0 },
+            if i & 4 == 0 { -1.0 } else { 1.0 },
+        ) * light.radius;
+        let corner = centre + offset;
+
+        let clip = *projection * Vec4f::new(corner.x, corner.y, corner.z, 1.0);
+        let ndc = Vec3f::from(clip) / clip.w;
+
+        min_ndc.x = min_ndc.x.min(ndc.x);
+        min_ndc.y = min_ndc.y.min(ndc.y);
+        max_ndc.x = max_ndc.x.max(ndc.x);
+        max_ndc.y = max_ndc.y.max(ndc.y);
+    }
+
+    if min_ndc.x > 1.0 || min_ndc.y > 1.0 || max_ndc.x < -1.0 || max_ndc.y < -1.0 {
+        return None;
+    }
+
+    let to_tile = |ndc: f32, tiles: usize| {
+        (((ndc * 0.5 + 0.5) * tiles as f32).max(0.0) as usize).min(tiles - 1)
+    };
+
+    Some(ClusterRange {
+        min: [
+            to_tile(min_ndc.x, CLUSTER_GRID_X),
+            to_tile(min_ndc.y, CLUSTER_GRID_Y),
+            min_z,
+        ],
+        max: [
+            to_tile(max_ndc.x, CLUSTER_GRID_X),
+            to_tile(max_ndc.y, CLUSTER_GRID_Y),
+            max_z,
+        ],
+    })
+}
@@ -1,17 +1,157 @@
-use crate::components::Renderable;
+use super::pipeline_stages::RENDER_TRANSPARENT;
+use crate::{
+    components::MeshInstance,
+    math::{Mat4f, Vec4f},
+    memory_manager::memory_manager::{DrawElementsIndirectCommand, MemoryManager, MeshAllocation},
+    resource_manager::resource_manager::{MeshID, ResourceIDTrait, ResourcesManager},
+};
+
+/// View depth that the depth fields of a layout span by default, beyond which renderables share the furthest value
+pub const DEFAULT_SORT_DEPTH_RANGE: f32 = 100.0;
+
+/// Property of a renderable that can be packed into a sort key
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SortKeyField {
+    /// Orders the passes that share a queue
+    Pass,
+    /// Whether the renderable is flagged with [`RENDER_TRANSPARENT`], so opaque renderables are drawn first
+    Translucency,
+    Shader,
+    Material,
+    Mesh,
+    /// Quantised view depth, nearest first, so that hidden fragments can be rejected by early depth testing
+    Depth,
+    /// Quantised view depth, furthest first, which is the order that translucent renderables are blended in
+    ReverseDepth,
+}
+
+/// The values of a renderable that are packed into its sort key
+#[derive(Clone, Copy, Default, Debug)]
+pub struct SortKeyValues {
+    pub pass: u32,
+    pub translucent: bool,
+    pub shader: u32,
+    pub material: u32,
+    pub mesh: u32,
+    /// View space distance in front of the camera
+    pub depth: f32,
+}
+
+/// Packs fields into a 64 bit sort key, where the first field is the most significant. <br>
+/// Each field is given a number of bits, and values that don't fit are clamped to the largest that does. Renderables
+/// whose fields are not in the layout are not ordered by them, so a stage only pays for what it batches by.
+#[derive(Clone, Debug)]
+pub struct SortKeyLayout {
+    fields: Vec<(SortKeyField, u32)>,
+    depth_range: f32,
+}
+
+impl SortKeyLayout {
+    pub fn new(fields: &[(SortKeyField, u32)]) -> Result<Self, String> {
+        if let Some((field, _)) = fields.iter().find(|(_, bits)| *bits == 0) {
+            return Err(format!("Sort key field '{:?}' has no bits", field));
+        }
+
+        let bits: u32 = fields.iter().map(|(_, bits)| bits).sum();
+        if bits > 64 {
+            return Err(format!(
+                "Sort key layout needs {} bits, but keys only have 64",
+                bits
+            ));
+        }
+
+        Ok(Self {
+            fields: fields.to_vec(),
+            depth_range: DEFAULT_SORT_DEPTH_RANGE,
+        })
+    }
+
+    /// Sets the view depth that the depth fields span
+    pub fn with_depth_range(mut self, depth_range: f32) -> Self {
+        self.depth_range = depth_range;
+        self
+    }
+
+    /// Groups renderables by shader, then mesh, so each group can be drawn with one instanced command, then by
+    /// material and from front to back within each group
+    pub fn opaque() -> Self {
+        Self::new(&[
+            (SortKeyField::Pass, 4),
+            (SortKeyField::Translucency, 1),
+            (SortKeyField::Shader, 16),
+            (SortKeyField::Mesh, 22),
+            (SortKeyField::Material, 13),
+            (SortKeyField::Depth, 8),
+        ])
+        .unwrap()
+    }
+
+    /// Orders renderables from back to front before anything else, so they are blended in the right order
+    pub fn translucent() -> Self {
+        Self::new(&[
+            (SortKeyField::Pass, 4),
+            (SortKeyField::Translucency, 1),
+            (SortKeyField::ReverseDepth, 24),
+            (SortKeyField::Shader, 13),
+            (SortKeyField::Mesh, 22),
+        ])
+        .unwrap()
+    }
+
+    /// For stages that draw everything with their own shader, so only the mesh affects batching
+    pub fn mesh() -> Self {
+        Self::new(&[(SortKeyField::Mesh, 22), (SortKeyField::Depth, 16)]).unwrap()
+    }
+
+    pub fn contains(&self, field: SortKeyField) -> bool {
+        self.fields.iter().any(|(f, _)| *f == field)
+    }
+
+    pub fn build(&self, values: &SortKeyValues) -> u64 {
+        let mut key: u64 = 0;
+
+        for (field, bits) in self.fields.iter() {
+            let max = u64::MAX >> (64 - bits);
+            let value = match field {
+                SortKeyField::Pass => values.pass as u64,
+                SortKeyField::Translucency => values.translucent as u64,
+                SortKeyField::Shader => values.shader as u64,
+                SortKeyField::Material => values.material as u64,
+                SortKeyField::Mesh => values.mesh as u64,
+                SortKeyField::Depth => quantise_depth(values.depth, self.depth_range, max),
+                SortKeyField::ReverseDepth => {
+                    max - quantise_depth(values.depth, self.depth_range, max)
+                }
+            };
+
+            key = key.checked_shl(*bits).unwrap_or(0) | value.min(max);
+        }
+
+        key
+    }
+}
+
+/// Maps the depth within the range onto `0..=max`, where depths outside of the range are clamped
+fn quantise_depth(depth: f32, depth_range: f32, max: u64) -> u64 {
+    let depth = (depth / depth_range).clamp(0.0, 1.0) as f64;
+    (depth * max as f64) as u64
+}
 
 pub struct DrawCommands {
     pub indices: Vec<usize>,
-    pub renderable_keys: Vec<u32>,
-    hash_fn: fn(&Renderable) -> u32,
+    pub renderable_keys: Vec<u64>,
+    pub layout: SortKeyLayout,
+    /// Written into the pass field of every key, for layouts that have one
+    pub pass: u32,
 }
 
 impl DrawCommands {
-    pub fn new(hash_fn: fn(&Renderable) -> u32) -> Self {
+    pub fn new(layout: SortKeyLayout) -> Self {
         Self {
             indices: Vec::new(),
             renderable_keys: Vec::new(),
-            hash_fn,
+            layout,
+            pass: 0,
         }
     }
 
@@ -30,10 +170,69 @@ impl DrawCommands {
         self.indices.sort_by_key(|k| self.renderable_keys[*k])
     }
 
-    pub fn update_keys(&mut self, renderables: &[Renderable], renderable_indices: &[usize]) {
+    /// The view transform places renderables for the depth fields, where each is at the origin of its transform
+    pub fn update_keys(
+        &mut self,
+        renderables: &[MeshInstance],
+        renderable_indices: &[usize],
+        view_transform: &Mat4f,
+    ) {
+        let uses_depth = self.layout.contains(SortKeyField::Depth)
+            || self.layout.contains(SortKeyField::ReverseDepth);
+
         self.renderable_keys.clear();
         for index in renderable_indices {
-            self.renderable_keys.push((self.hash_fn)(&renderables[*index]))
+            let renderable = &renderables[*index];
+
+            let depth = if uses_depth {
+                -(*view_transform * renderable.transform * Vec4f::new(0.0, 0.0, 0.0, 1.0)).z
+            } else {
+                0.0
+            };
+
+            self.renderable_keys.push(self.layout.build(&SortKeyValues {
+                pass: self.pass,
+                translucent: renderable.pipeline_stages & RENDER_TRANSPARENT > 0,
+                shader: renderable.shader_id.index(),
+                material: renderable.material_id.index(),
+                mesh: renderable.mesh_id.index(),
+                depth,
+            }))
+        }
+    }
+}
+
+/// Pushes an indirect command for the mesh, referencing its place in the geometry pool. Dynamic meshes are
+/// uploaded for this frame first.
+pub fn upload_draw_data(
+    memory_manager: &mut MemoryManager,
+    resources_manager: &ResourcesManager,
+    mesh_id: &MeshID,
+    instance_count: u32,
+    base_instance: u32,
+) {
+    let allocation = mesh_allocation(memory_manager, resources_manager, mesh_id);
+
+    memory_manager.push_indirect_command(DrawElementsIndirectCommand {
+        count: allocation.index_count,
+        instance_count,
+        first_index: allocation.first_index,
+        base_vertex: allocation.base_vertex,
+        base_instance,
+    });
+}
+
+/// Where the mesh is in the geometry pool, where meshes that aren't resident are uploaded for this frame
+pub fn mesh_allocation(
+    memory_manager: &mut MemoryManager,
+    resources_manager: &ResourcesManager,
+    mesh_id: &MeshID,
+) -> MeshAllocation {
+    match resources_manager.borrow_mesh_allocation(mesh_id) {
+        Some(allocation) => *allocation,
+        None => {
+            let mesh = resources_manager.borrow_mesh(mesh_id).unwrap();
+            memory_manager.push_dynamic_mesh(&mesh.vertices, &mesh.indices)
         }
     }
 }
@@ -0,0 +1,104 @@
+use crate::{
+    components::MeshInstance,
+    math::*,
+    resource_manager::{
+        model::Aabb,
+        resource_manager::{MeshID, ResourceManager, ResourceManagerTrait},
+    },
+};
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Plane {
+    pub normal: Vec3f,
+    pub distance: f32,
+}
+
+impl Plane {
+    /// Creates a plane from the coefficients of `ax + by + cz + d = 0`, normalising them
+    pub fn from_coefficients(coefficients: Vec4f) -> Self {
+        let normal = Vec3f::new(coefficients.x, coefficients.y, coefficients.z);
+        let length = normal.magnitude();
+
+        Self {
+            normal: normal / length,
+            distance: coefficients.w / length,
+        }
+    }
+
+    /// Positive when the point is on the side the normal faces
+    pub fn signed_distance(&self, point: Vec3f) -> f32 {
+        self.normal.dot(point) + self.distance
+    }
+}
+
+/// Six inward facing planes, in the order: left, right, bottom, top, near, far
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Frustum {
+    pub planes: [Plane; 6],
+}
+
+impl Frustum {
+    /// Extracts the planes from a combined projection and view matrix, giving a world space frustum
+    pub fn from_matrix(m: &Mat4f) -> Self {
+        let row = |i: usize| Vec4f::new(m[(i, 0)], m[(i, 1)], m[(i, 2)], m[(i, 3)]);
+        let (x, y, z, w) = (row(0), row(1), row(2), row(3));
+
+        Self {
+            planes: [
+                Plane::from_coefficients(w + x),
+                Plane::from_coefficients(w - x),
+                Plane::from_coefficients(w + y),
+                Plane::from_coefficients(w - y),
+                Plane::from_coefficients(w + z),
+                Plane::from_coefficients(w - z),
+            ],
+        }
+    }
+
+    pub fn contains_point(&self, point: Vec3f) -> bool {
+        self.planes
+            .iter()
+            .all(|plane| plane.signed_distance(point) >= 0.0)
+    }
+
+    pub fn intersects_sphere(&self, centre: Vec3f, radius: f32) -> bool {
+        self.planes
+            .iter()
+            .all(|plane| plane.signed_distance(centre) >= -radius)
+    }
+
+    /// Conservative test, in that boxes near the corners of the frustum may be reported as visible
+    pub fn intersects_aabb(&self, aabb: &Aabb) -> bool {
+        let centre = aabb.centre();
+        let extents = aabb.extents();
+
+        self.planes.iter().all(|plane| {
+            let radius = extents.x * plane.normal.x.abs()
+                + extents.y * plane.normal.y.abs()
+                + extents.z * plane.normal.z.abs();
+            plane.signed_distance(centre) >= -radius
+        })
+    }
+}
+
+/// Removes the indices of renderables whose world space bounds are outside of every frustum. <br>
+/// Renderables without bounds are kept, as there is nothing to test them against.
+pub fn cull_renderables(
+    frustums: &[Frustum],
+    renderables: &[MeshInstance],
+    mesh_bounds: &ResourceManager<Aabb, MeshID>,
+    renderable_indices: &mut Vec<usize>,
+) {
+    renderable_indices.retain(|index| {
+        let renderable = &renderables[*index];
+
+        if let Some(bounds) = mesh_bounds.borrow(&renderable.mesh_id) {
+            let bounds = bounds.transform(&renderable.transform);
+            frustums
+                .iter()
+                .any(|frustum| frustum.intersects_aabb(&bounds))
+        } else {
+            true
+        }
+    });
+}
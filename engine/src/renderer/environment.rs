@@ -0,0 +1,315 @@
+use crate::{
+    graphics::{
+        self,
+        framebuffer::InternalFormat,
+        shader::{Program, ShaderData},
+        texture::{Texture, TextureConfig, TextureFilter, TextureType, TextureWrap},
+        AccessModifier, Barriers,
+    },
+    memory_manager::memory_manager::MemoryManager,
+    platform::rustgl,
+    renderer::state::RendererState,
+    resource_manager::resource_manager::{
+        ResourceManagerTrait, ResourcesManager, ShaderProgramID, TextureID,
+    },
+};
+
+/// Size of each face of the irradiance map, which is very blurry so can be tiny
+pub const IRRADIANCE_SIZE: u32 = 32;
+/// Size of each face of the first level of the prefiltered map, which is a perfect mirror
+pub const PREFILTERED_SIZE: u32 = 256;
+/// Each level is rougher than the last, up to a roughness of one at the last level
+pub const PREFILTERED_LEVELS: u32 = 6;
+pub const BRDF_LUT_SIZE: u32 = 512;
+
+/// Work group width of the cubemap shaders, which is smaller than the others as each invocation takes many samples
+const CUBEMAP_GROUP_WIDTH: u32 = 8;
+const GROUP_WIDTH: u32 = 16;
+
+/// The maps that light PBR materials with an environment, baked by [`EnvironmentBaker`]
+#[derive(Clone, Copy)]
+pub struct Environment {
+    /// The environment as a cubemap, which is the source itself if it already was one. This can be drawn by the
+    /// sky stage, when the source was an equirectangular texture
+    pub cubemap: TextureID,
+    pub irradiance: TextureID,
+    pub prefiltered: TextureID,
+    /// Shared by every environment
+    pub brdf_lut: TextureID,
+    /// Bindless handles of the irradiance map, prefiltered map, and BRDF LUT, which are already resident
+    handles: [u64; 3],
+}
+
+impl Environment {
+    pub fn upload(&self, memory_manager: &mut MemoryManager) {
+        memory_manager.set_environment_maps(
+            self.handles[0],
+            self.handles[1],
+            self.handles[2],
+            (PREFILTERED_LEVELS - 1) as f32,
+        );
+    }
+}
+
+/// Size of each face of the cubemap that an equirectangular texture is projected onto. <br>
+/// A quarter of the width of the texture covers the same angle as a face, so this keeps roughly the same detail,
+/// rounded up to a power of two so that every level of the cubemap halves cleanly
+pub fn equirect_face_size(width: u32, height: u32) -> u32 {
+    (width / 4)
+        .max(height / 2)
+        .next_power_of_two()
+        .clamp(64, 2048)
+}
+
+/// Roughness that the level of the prefiltered map is convolved with, from zero at the first level to one at the
+/// last
+pub fn prefiltered_roughness(level: u32, levels: u32) -> f32 {
+    if levels <= 1 {
+        return 0.0;
+    }
+
+    level.min(levels - 1) as f32 / (levels - 1) as f32
+}
+
+/// Precomputes the image based lighting of environments with compute shaders. <br>
+/// This only needs to happen once per environment, such as when it is loaded, rather than every frame.
+pub struct EnvironmentBaker {
+    equirect_shader_id: ShaderProgramID,
+    irradiance_shader_id: ShaderProgramID,
+    prefilter_shader_id: ShaderProgramID,
+    brdf_lut_shader_id: ShaderProgramID,
+    /// The BRDF integration doesn't depend on the environment, so it is baked by the first environment and shared
+    brdf_lut: Option<(TextureID, u64)>,
+}
+
+impl EnvironmentBaker {
+    pub fn new(resources_manager: &mut ResourcesManager) -> Self {
+        let equirect_shader_id =
+            resources_manager.load_shader("res/shaders/ibl_equirect_to_cubemap.glsl");
+        let irradiance_shader_id = resources_manager.load_shader("res/shaders/ibl_irradiance.glsl");
+        let prefilter_shader_id = resources_manager.load_shader("res/shaders/ibl_prefilter.glsl");
+        let brdf_lut_shader_id = resources_manager.load_shader("res/shaders/ibl_brdf_lut.glsl");
+
+        // blurry levels of the cubemaps are only a few texels wide, so filtering across the edges of faces is
+        // needed to hide the seams
+        unsafe {
+            rustgl::enable(rustgl::TEXTURE_CUBE_MAP_SEAMLESS);
+        }
+
+        Self {
+            equirect_shader_id,
+            irradiance_shader_id,
+            prefilter_shader_id,
+            brdf_lut_shader_id,
+            brdf_lut: None,
+        }
+    }
+
+    /// Bakes the maps from a loaded texture, which is either a cubemap or an equirectangular texture, such as an
+    /// HDR image loaded with `load_texture`
+    pub fn bake(
+        &mut self,
+        source_id: TextureID,
+        resources_manager: &mut ResourcesManager,
+        renderer_state: &mut RendererState,
+    ) -> Result<Environment, String> {
+        let source = resources_manager
+            .borrow_texture(&source_id)
+            .ok_or("Environment texture does not exist")?;
+        let (target, width, height) = (source.target, source.width, source.height);
+
+        let cubemap = if target == TextureType::CubeMap as u32 {
+            source_id
+        } else if target == TextureType::T2D as u32 {
+            self.project_equirect(
+                source_id,
+                equirect_face_size(width, height),
+                resources_manager,
+                renderer_state,
+            )
+        } else {
+            return Err("Environment texture must be a cubemap or a 2D texture".to_string());
+        };
+
+        let irradiance = self.convolve_irradiance(cubemap, resources_manager, renderer_state);
+        let prefiltered = self.prefilter_specular(cubemap, resources_manager, renderer_state);
+        let (brdf_lut, brdf_lut_handle) = match self.brdf_lut {
+            Some(brdf_lut) => brdf_lut,
+            None => {
+                let brdf_lut = self.integrate_brdf(resources_manager, renderer_state);
+                self.brdf_lut = Some(brdf_lut);
+                brdf_lut
+            }
+        };
+
+        unsafe {
+            rustgl::active_texture(rustgl::TEXTURE0);
+        }
+
+        Ok(Environment {
+            cubemap,
+            irradiance,
+            prefiltered,
+            brdf_lut,
+            handles: [
+                resident_handle(irradiance, resources_manager),
+                resident_handle(prefiltered, resources_manager),
+                brdf_lut_handle,
+            ],
+        })
+    }
+
+    fn project_equirect(
+        &self,
+        source_id: TextureID,
+        face_size: u32,
+        resources_manager: &mut ResourcesManager,
+        renderer_state: &mut RendererState,
+    ) -> TextureID {
+        let levels = face_size.ilog2() + 1;
+        let cubemap = Texture::new_framebuffer_texture(
+            TextureType::CubeMap,
+            InternalFormat::RGBA16F,
+            1,
+            levels,
+            1,
+            face_size,
+            face_size,
+            &map_config(true),
+        );
+
+        bind_texture(0, &source_id, resources_manager);
+        renderer_state.set_shader_program(self.equirect_shader_id, resources_manager);
+        dispatch_cubemap(&cubemap, 0, face_size, GROUP_WIDTH);
+
+        // the blurrier levels are sampled when convolving, to avoid missing small bright spots between samples
+        cubemap.generate_mipmaps();
+
+        resources_manager.texture_manager.load(cubemap)
+    }
+
+    fn convolve_irradiance(
+        &self,
+        cubemap_id: TextureID,
+        resources_manager: &mut ResourcesManager,
+        renderer_state: &mut RendererState,
+    ) -> TextureID {
+        let irradiance = Texture::new_framebuffer_texture(
+            TextureType::CubeMap,
+            InternalFormat::RGBA16F,
+            1,
+            1,
+            1,
+            IRRADIANCE_SIZE,
+            IRRADIANCE_SIZE,
+            &map_config(false),
+        );
+
+        bind_texture(0, &cubemap_id, resources_manager);
+        renderer_state.set_shader_program(self.irradiance_shader_id, resources_manager);
+        dispatch_cubemap(&irradiance, 0, IRRADIANCE_SIZE, CUBEMAP_GROUP_WIDTH);
+
+        resources_manager.texture_manager.load(irradiance)
+    }
+
+    fn prefilter_specular(
+        &self,
+        cubemap_id: TextureID,
+        resources_manager: &mut ResourcesManager,
+        renderer_state: &mut RendererState,
+    ) -> TextureID {
+        let prefiltered = Texture::new_framebuffer_texture(
+            TextureType::CubeMap,
+            InternalFormat::RGBA16F,
+            1,
+            PREFILTERED_LEVELS,
+            1,
+            PREFILTERED_SIZE,
+            PREFILTERED_SIZE,
+            &map_config(true),
+        );
+
+        bind_texture(0, &cubemap_id, resources_manager);
+        renderer_state.set_shader_program(self.prefilter_shader_id, resources_manager);
+
+        for level in 0..PREFILTERED_LEVELS {
+            resources_manager
+                .borrow_mut_shader_program(&self.prefilter_shader_id)
+                .unwrap()
+                .set_uniform(
+                    "roughness".to_string(),
+                    ShaderData::Float1(prefiltered_roughness(level, PREFILTERED_LEVELS)),
+                );
+
+            dispatch_cubemap(
+                &prefiltered,
+                level,
+                PREFILTERED_SIZE >> level,
+                CUBEMAP_GROUP_WIDTH,
+            );
+        }
+
+        resources_manager.texture_manager.load(prefiltered)
+    }
+
+    fn integrate_brdf(
+        &self,
+        resources_manager: &mut ResourcesManager,
+        renderer_state: &mut RendererState,
+    ) -> (TextureID, u64) {
+        let brdf_lut = Texture::new_framebuffer_texture(
+            TextureType::T2D,
+            InternalFormat::RG16F,
+            1,
+            1,
+            1,
+            BRDF_LUT_SIZE,
+            BRDF_LUT_SIZE,
+            &map_config(false),
+        );
+
+        renderer_state.set_shader_program(self.brdf_lut_shader_id, resources_manager);
+        brdf_lut.bind_image_unit(0, 0, 0, AccessModifier::WriteOnly, InternalFormat::RG16F);
+        Program::dispatch_compute(
+            BRDF_LUT_SIZE.div_ceil(GROUP_WIDTH),
+            BRDF_LUT_SIZE.div_ceil(GROUP_WIDTH),
+            1,
+        );
+        graphics::memory_barrier(
+            Barriers::ShaderImageAccess as u32 | Barriers::TextureFetch as u32,
+        );
+
+        let id = resources_manager.texture_manager.load(brdf_lut);
+        (id, resident_handle(id, resources_manager))
+    }
+}
+
+fn map_config(mipmap: bool) -> TextureConfig {
+    TextureConfig {
+        wrap: TextureWrap::ClampToEdge,
+        mag_filter: TextureFilter::Linear,
+        min_filter: TextureFilter::Linear,
+        mipmap,
+        srgb: false,
+    }
+}
+
+fn bind_texture(unit: u32, texture_id: &TextureID, resources_manager: &ResourcesManager) {
+    unsafe {
+        rustgl::active_texture(rustgl::TEXTURE0 + unit);
+    }
+    resources_manager.borrow_texture(texture_id).unwrap().bind();
+}
+
+/// Writes every face of one level of the cubemap with the bound shader, with a layer of work groups per face
+fn dispatch_cubemap(cubemap: &Texture, level: u32, size: u32, group_width: u32) {
+    cubemap.bind_layered_image_unit(0, level, AccessModifier::WriteOnly, InternalFormat::RGBA16F);
+    Program::dispatch_compute(size.div_ceil(group_width), size.div_ceil(group_width), 6);
+    graphics::memory_barrier(Barriers::ShaderImageAccess as u32 | Barriers::TextureFetch as u32);
+}
+
+fn resident_handle(texture_id: TextureID, resources_manager: &mut ResourcesManager) -> u64 {
+    let texture = resources_manager.borrow_mut_texture(&texture_id).unwrap();
+    texture.make_texture_resident();
+    texture.get_shader_texture_handle()
+}
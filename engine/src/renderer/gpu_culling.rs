@@ -0,0 +1,178 @@
+use std::ops::Range;
+
+use crate::{
+    graphics::{self, shader::Program, Barriers, DataType, DrawMode},
+    math::{Vec2u, Vec4f},
+    memory_manager::{
+        memory_manager::{MemoryManager, DRAW_COMMAND_SIZE},
+        uniform_layouts::{CullBatch, CullHeader},
+    },
+    renderer::{command::mesh_allocation, culling::Frustum, occlusion::HiZ, state::RendererState},
+    resource_manager::resource_manager::{MeshID, ResourcesManager, ShaderProgramID},
+};
+
+/// Batches that are drawn together, with the same shader
+struct CullGroup {
+    shader_id: ShaderProgramID,
+    batches: Range<usize>,
+    draw_count_index: u32,
+    command_base: u32,
+}
+
+/// Culls instances against the view frustum, and optionally a Hi-Z pyramid, with a compute shader, which also
+/// writes the indirect commands that draw those that are left, so the CPU never needs to know what is visible. <br>
+/// Batches are pushed in groups that share a shader, and each group is drawn with a single call that reads how
+/// many commands were written from the parameter buffer.
+pub struct GpuCuller {
+    shader_id: ShaderProgramID,
+    batches: Vec<CullBatch>,
+    groups: Vec<CullGroup>,
+    /// Start of the batches that have not been given a group yet
+    group_start: usize,
+}
+
+impl GpuCuller {
+    pub fn new(resources_manager: &mut ResourcesManager) -> Self {
+        let shader_id = resources_manager.load_shader("res/shaders/gpu_cull.glsl");
+
+        Self {
+            shader_id,
+            batches: Vec::new(),
+            groups: Vec::new(),
+            group_start: 0,
+        }
+    }
+
+    /// Adds a batch for the instances of the mesh that were just pushed to the instance buffer, starting at the
+    /// base instance. Space for the visible instances is reserved after them.
+    pub fn push_batch(
+        &mut self,
+        memory_manager: &mut MemoryManager,
+        resources_manager: &ResourcesManager,
+        mesh_id: &MeshID,
+        instance_count: u32,
+        base_instance: u32,
+    ) {
+        let allocation = mesh_allocation(memory_manager, resources_manager, mesh_id);
+
+        let (bounds_min, bounds_max) = match resources_manager.borrow_mesh_bounds(mesh_id) {
+            Some(bounds) => (
+                Vec4f::new(bounds.min.x, bounds.min.y, bounds.min.z, 1.0),
+                Vec4f::new(bounds.max.x, bounds.max.y, bounds.max.z, 1.0),
+            ),
+            None => (
+                Vec4f::new(0.0, 0.0, 0.0, 0.0),
+                Vec4f::new(0.0, 0.0, 0.0, 0.0),
+            ),
+        };
+
+        self.batches.push(CullBatch {
+            bounds_min,
+            bounds_max,
+            index_count: allocation.index_count,
+            first_index: allocation.first_index,
+            base_vertex: allocation.base_vertex,
+            input_base: base_instance,
+            instance_count,
+            output_base: memory_manager.reserve_gpu_instances(instance_count),
+            ..Default::default()
+        });
+    }
+
+    /// The batches pushed since the last group are drawn with the shader
+    pub fn end_group(&mut self, shader_id: ShaderProgramID) {
+        if self.group_start == self.batches.len() {
+            return;
+        }
+
+        self.groups.push(CullGroup {
+            shader_id,
+            batches: self.group_start..self.batches.len(),
+            draw_count_index: 0,
+            command_base: 0,
+        });
+        self.group_start = self.batches.len();
+    }
+
+    /// Culls every batch that has been given a group, where instances hidden in the Hi-Z pyramid are also culled
+    pub fn dispatch(
+        &mut self,
+        frustum: &Frustum,
+        hiz: Option<HiZ>,
+        memory_manager: &mut MemoryManager,
+        resources_manager: &mut ResourcesManager,
+        renderer_state: &mut RendererState,
+    ) {
+        if self.groups.is_empty() {
+            return;
+        }
+
+        // any batches without a group would never be drawn
+        self.batches.truncate(self.group_start);
+
+        for group in self.groups.iter_mut() {
+            group.draw_count_index = memory_manager.push_draw_count();
+            group.command_base = memory_manager.reserve_gpu_commands(group.batches.len() as u32);
+
+            for batch in self.batches[group.batches.clone()].iter_mut() {
+                batch.draw_count_index = group.draw_count_index;
+                batch.command_base = group.command_base;
+            }
+        }
+
+        let mut header = CullHeader {
+            planes: frustum_planes(frustum),
+            ..Default::default()
+        };
+        if let Some(hiz) = hiz {
+            header.view_projection = hiz.view_projection;
+            header.hiz_texture = Vec2u::new(hiz.handle as u32, (hiz.handle >> 32) as u32);
+            header.hiz_size = Vec2u::new(hiz.width, hiz.height);
+            header.hiz_levels = hiz.levels;
+        }
+
+        memory_manager.set_cull_batches(header, &self.batches);
+
+        renderer_state.set_shader_program(self.shader_id, resources_manager);
+        Program::dispatch_compute(self.batches.len() as u32, 1, 1);
+        graphics::memory_barrier(
+            Barriers::Command as u32
+                | Barriers::VertexAttribArray as u32
+                | Barriers::ShaderStorage as u32,
+        );
+    }
+
+    /// Draws each group with its shader, using the commands written by the last dispatch
+    pub fn draw(
+        &mut self,
+        resources_manager: &mut ResourcesManager,
+        renderer_state: &mut RendererState,
+    ) {
+        for group in self.groups.iter() {
+            renderer_state.set_shader_program(group.shader_id, resources_manager);
+            graphics::submit_draw_call_count(
+                DrawMode::Triangles,
+                DataType::Uint32,
+                group.command_base * DRAW_COMMAND_SIZE,
+                group.draw_count_index * std::mem::size_of::<u32>() as u32,
+                group.batches.len() as u32,
+            );
+        }
+
+        self.batches.clear();
+        self.groups.clear();
+        self.group_start = 0;
+    }
+}
+
+/// Coefficients of each plane, as the culling shader expects them
+pub fn frustum_planes(frustum: &Frustum) -> [Vec4f; 6] {
+    frustum.planes.map(|plane| {
+        Vec4f::new(
+            plane.normal.x,
+            plane.normal.y,
+            plane.normal.z,
+            plane.distance,
+        )
+    })
+}
@@ -0,0 +1,386 @@
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap, HashSet},
+};
+
+use crate::{
+    graphics::framebuffer::FramebufferConfig, resource_manager::resource_manager::FramebufferID,
+};
+
+pub type ResourceName = &'static str;
+
+#[derive(Clone, PartialEq)]
+pub struct TransientFramebuffer {
+    pub config: FramebufferConfig,
+    pub resize_with_viewport: bool,
+}
+
+#[derive(Clone)]
+pub enum GraphResource {
+    /// Owned outside of the graph, such as the main HDR framebuffer
+    Imported(FramebufferID),
+    /// Allocated by the graph, and shared with other transient resources of the same description whose
+    /// lifetimes do not overlap
+    Transient(TransientFramebuffer),
+    /// Not backed by a framebuffer the graph knows about, but still used to order the passes that touch it,
+    /// e.g. shadow maps that are owned by a stage
+    External,
+}
+
+/// Used by a pass to declare the resources it reads and writes
+#[derive(Default)]
+pub struct PassBuilder {
+    pub reads: Vec<ResourceName>,
+    pub writes: Vec<ResourceName>,
+    pub creates: Vec<(ResourceName, TransientFramebuffer)>,
+    pub render_target: Option<ResourceName>,
+    pub side_effects: bool,
+}
+
+impl PassBuilder {
+    pub fn read(&mut self, name: ResourceName) -> &mut Self {
+        if !self.reads.contains(&name) {
+            self.reads.push(name);
+        }
+        self
+    }
+
+    pub fn write(&mut self, name: ResourceName) -> &mut Self {
+        if !self.writes.contains(&name) {
+            self.writes.push(name);
+        }
+        self
+    }
+
+    /// Writes to the resource, which is bound as the framebuffer before the pass executes
+    pub fn render_target(&mut self, name: ResourceName) -> &mut Self {
+        self.render_target = Some(name);
+        self.write(name)
+    }
+
+    /// Declares a transient framebuffer that only exists while it is in use, and writes to it
+    pub fn create_framebuffer(
+        &mut self,
+        name: ResourceName,
+        config: FramebufferConfig,
+        resize_with_viewport: bool,
+    ) -> &mut Self {
+        self.creates.push((
+            name,
+            TransientFramebuffer {
+                config,
+                resize_with_viewport,
+            },
+        ));
+        self.write(name)
+    }
+
+    /// The pass is never culled, even if nothing reads what it writes
+    pub fn side_effects(&mut self) -> &mut Self {
+        self.side_effects = true;
+        self
+    }
+}
+
+pub struct GraphPass {
+    pub name: &'static str,
+    pub builder: PassBuilder,
+}
+
+/// The result of compiling a graph. Passes are referred to by the index they were added to the graph with.
+pub struct CompiledGraph {
+    /// Passes in execution order, excluding those that were culled
+    pub order: Vec<usize>,
+    pub culled: Vec<usize>,
+    /// First and last position in `order` that each transient resource is used
+    pub lifetimes: HashMap<ResourceName, (usize, usize)>,
+    /// The slot of `slots` that each transient resource has been assigned
+    pub allocations: HashMap<ResourceName, usize>,
+    pub slots: Vec<TransientFramebuffer>,
+}
+
+/// Passes declare the resources they read and write. From this, an execution order is derived where:
+/// - a pass that writes to a resource runs after any previously added pass that also writes to it
+/// - a pass that only reads a resource runs after every pass that writes to it <br>
+///
+/// Passes that do not contribute to an output resource, and have no side effects, are culled.
+pub struct RenderGraph {
+    resources: HashMap<ResourceName, GraphResource>,
+    outputs: Vec<ResourceName>,
+    passes: Vec<GraphPass>,
+}
+
+impl RenderGraph {
+    pub fn new() -> Self {
+        Self {
+            resources: HashMap::new(),
+            outputs: Vec::new(),
+            passes: Vec::new(),
+        }
+    }
+
+    pub fn import_framebuffer(&mut self, name: ResourceName, framebuffer_id: FramebufferID) {
+        self.resources
+            .insert(name, GraphResource::Imported(framebuffer_id));
+    }
+
+    pub fn create_framebuffer(
+        &mut self,
+        name: ResourceName,
+        config: FramebufferConfig,
+        resize_with_viewport: bool,
+    ) {
+        self.resources.insert(
+            name,
+            GraphResource::Transient(TransientFramebuffer {
+                config,
+                resize_with_viewport,
+            }),
+        );
+    }
+
+    pub fn declare_external(&mut self, name: ResourceName) {
+        self.resources.insert(name, GraphResource::External);
+    }
+
+    /// Output resources are consumed outside of the graph, so the passes that write them are never culled
+    pub fn mark_output(&mut self, name: ResourceName) {
+        if !self.outputs.contains(&name) {
+            self.outputs.push(name);
+        }
+    }
+
+    pub fn get_resource(&self, name: ResourceName) -> Option<&GraphResource> {
+        self.resources.get(name)
+    }
+
+    pub fn add_pass(&mut self, name: &'static str, builder: PassBuilder) -> usize {
+        self.passes.push(GraphPass { name, builder });
+        self.passes.len() - 1
+    }
+
+    pub fn get_pass(&self, index: usize) -> &GraphPass {
+        &self.passes[index]
+    }
+
+    pub fn clear_passes(&mut self) {
+        self.passes.clear();
+    }
+
+    pub fn compile(&self) -> Result<CompiledGraph, String> {
+        let transients = self.collect_transients()?;
+
+        for pass in self.passes.iter() {
+            for name in pass.builder.reads.iter().chain(pass.builder.writes.iter()) {
+                if !self.resources.contains_key(name) && !transients.contains_key(name) {
+                    return Err(format!(
+                        "Pass '{}' uses resource '{}', which has not been declared",
+                        pass.name, name
+                    ));
+                }
+            }
+        }
+
+        let order = self.sort_passes()?;
+
+        // walk backwards from the outputs, keeping passes that write something a kept pass needs
+        let mut needed: HashSet<ResourceName> = self.outputs.iter().copied().collect();
+        let mut kept = vec![false; self.passes.len()];
+
+        for &pass_index in order.iter().rev() {
+            let builder = &self.passes[pass_index].builder;
+
+            if builder.side_effects || builder.writes.iter().any(|name| needed.contains(name)) {
+                kept[pass_index] = true;
+                needed.extend(builder.reads.iter().copied());
+            }
+        }
+
+        let culled = order.iter().copied().filter(|i| !kept[*i]).collect();
+        let order: Vec<usize> = order.into_iter().filter(|i| kept[*i]).collect();
+
+        let mut lifetimes: HashMap<ResourceName, (usize, usize)> = HashMap::new();
+        for (position, &pass_index) in order.iter().enumerate() {
+            let builder = &self.passes[pass_index].builder;
+
+            for name in builder.reads.iter().chain(builder.writes.iter()) {
+                if transients.contains_key(name) {
+                    lifetimes
+                        .entry(name)
+                        .and_modify(|lifetime| lifetime.1 = position)
+                        .or_insert((position, position));
+                }
+            }
+        }
+
+        let (allocations, slots) = Self::allocate_transients(&lifetimes, &transients);
+
+        Ok(CompiledGraph {
+            order,
+            culled,
+            lifetimes,
+            allocations,
+            slots,
+        })
+    }
+
+    fn collect_transients(&self) -> Result<HashMap<ResourceName, TransientFramebuffer>, String> {
+        let mut transients = HashMap::new();
+
+        for (name, resource) in self.resources.iter() {
+            if let GraphResource::Transient(transient) = resource {
+                transients.insert(*name, transient.clone());
+            }
+        }
+
+        for pass in self.passes.iter() {
+            for (name, transient) in pass.builder.creates.iter() {
+                if self.resources.contains_key(name) || transients.contains_key(name) {
+                    return Err(format!(
+                        "Pass '{}' creates resource '{}', which has already been declared",
+                        pass.name, name
+                    ));
+                }
+                transients.insert(*name, transient.clone());
+            }
+        }
+
+        Ok(transients)
+    }
+
+    /// Topological sort, preferring the order that passes were added in when there is a choice
+    fn sort_passes(&self) -> Result<Vec<usize>, String> {
+        let pass_count = self.passes.len();
+        let mut dependants: Vec<Vec<usize>> = vec![Vec::new(); pass_count];
+        let mut dependency_counts = vec![0; pass_count];
+
+        let mut writers: HashMap<ResourceName, Vec<usize>> = HashMap::new();
+        for (pass_index, pass) in self.passes.iter().enumerate() {
+            for name in pass.builder.writes.iter() {
+                writers.entry(name).or_default().push(pass_index);
+            }
+        }
+
+        let mut add_edge = |from: usize, to: usize| {
+            if from != to && !dependants[from].contains(&to) {
+                dependants[from].push(to);
+                dependency_counts[to] += 1;
+            }
+        };
+
+        for (pass_index, pass) in self.passes.iter().enumerate() {
+            for name in pass.builder.writes.iter() {
+                let resource_writers = &writers[name];
+                let position = resource_writers
+                    .iter()
+                    .position(|i| *i == pass_index)
+                    .unwrap();
+                if position > 0 {
+                    add_edge(resource_writers[position - 1], pass_index);
+                }
+            }
+
+            for name in pass.builder.reads.iter() {
+                if pass.builder.writes.contains(name) {
+                    continue;
+                }
+                if let Some(resource_writers) = writers.get(name) {
+                    for writer in resource_writers.iter() {
+                        add_edge(*writer, pass_index);
+                    }
+                }
+            }
+        }
+
+        let mut ready: BinaryHeap<Reverse<usize>> = (0..pass_count)
+            .filter(|i| dependency_counts[*i] == 0)
+            .map(Reverse)
+            .collect();
+        let mut order = Vec::with_capacity(pass_count);
+
+        while let Some(Reverse(pass_index)) = ready.pop() {
+            order.push(pass_index);
+
+            for dependant in dependants[pass_index].iter() {
+                dependency_counts[*dependant] -= 1;
+                if dependency_counts[*dependant] == 0 {
+                    ready.push(Reverse(*dependant));
+                }
+            }
+        }
+
+        if order.len() != pass_count {
+            let cycle: Vec<&str> = (0..pass_count)
+                .filter(|i| !order.contains(i))
+                .map(|i| self.passes[i].name)
+                .collect();
+            return Err(format!(
+                "Render graph contains a cycle between passes: {}",
+                cycle.join(", ")
+            ));
+        }
+
+        Ok(order)
+    }
+
+    /// Greedily assigns each transient resource to a slot that is free for its whole lifetime and has a matching
+    /// description, creating a new slot if there isn't one
+    fn allocate_transients(
+        lifetimes: &HashMap<ResourceName, (usize, usize)>,
+        transients: &HashMap<ResourceName, TransientFramebuffer>,
+    ) -> (HashMap<ResourceName, usize>, Vec<TransientFramebuffer>) {
+        let mut by_start: Vec<(&ResourceName, &(usize, usize))> = lifetimes.iter().collect();
+        by_start.sort_by_key(|(name, lifetime)| (lifetime.0, **name));
+
+        let mut allocations = HashMap::new();
+        let mut slots: Vec<TransientFramebuffer> = Vec::new();
+        // position after which each slot is free again
+        let mut slot_free_after: Vec<usize> = Vec::new();
+
+        for (name, (start, end)) in by_start {
+            let transient = &transients[name];
+
+            let slot = (0..slots.len())
+                .find(|slot| slot_free_after[*slot] < *start && slots[*slot] == *transient);
+
+            let slot = match slot {
+                Some(slot) => slot,
+                None => {
+                    slots.push(transient.clone());
+                    slot_free_after.push(0);
+                    slots.len() - 1
+                }
+            };
+
+            slot_free_after[slot] = *end;
+            allocations.insert(*name, slot);
+        }
+
+        (allocations, slots)
+    }
+}
+
+/// Framebuffers resolved for the passes of a compiled graph
+pub struct GraphResources {
+    framebuffers: HashMap<ResourceName, FramebufferID>,
+}
+
+impl GraphResources {
+    pub fn new() -> Self {
+        Self {
+            framebuffers: HashMap::new(),
+        }
+    }
+
+    pub fn insert(&mut self, name: ResourceName, framebuffer_id: FramebufferID) {
+        self.framebuffers.insert(name, framebuffer_id);
+    }
+
+    pub fn clear(&mut self) {
+        self.framebuffers.clear();
+    }
+
+    pub fn framebuffer(&self, name: ResourceName) -> Option<FramebufferID> {
+        self.framebuffers.get(name).copied()
+    }
+}
@@ -0,0 +1,101 @@
+use crate::{
+    math::*,
+    resource_manager::model::{Aabb, LodChain, MAX_LOD_LEVELS},
+};
+
+#[derive(Debug, Clone, Copy)]
+pub struct LodSettings {
+    /// Scales the screen size that levels are selected from by `2^-bias`, so positive values switch to less
+    /// detailed levels sooner, and each whole step is equivalent to halving the renderable's size on screen
+    pub bias: f32,
+    /// Whether renderables are drawn at both levels while crossing the screen size of a level, and dithered
+    /// between them, rather than popping from one to the other
+    pub cross_fade: bool,
+    /// Width of the cross-fade, as a fraction of the screen size of the level being faded out
+    pub fade_band: f32,
+}
+
+impl Default for LodSettings {
+    fn default() -> Self {
+        Self {
+            bias: 0.0,
+            cross_fade: true,
+            fade_band: 0.2,
+        }
+    }
+}
+
+/// Which level of a chain is drawn
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LodSelection {
+    pub level: usize,
+    /// Fraction of the pixels that `level` covers, where the rest are covered by the next level. Is one when the
+    /// renderable isn't cross-fading
+    pub coverage: f32,
+}
+
+/// Counts of what was drawn at each level of detail in a frame, for tuning the screen sizes of chains and the
+/// LOD bias
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LodStats {
+    /// Renderables drawn at each level, where those that are cross-fading are counted at both of their levels
+    pub instances: [u32; MAX_LOD_LEVELS],
+    pub triangles: [u64; MAX_LOD_LEVELS],
+    /// Renderables that were drawn at two levels
+    pub cross_fading: u32,
+}
+
+impl LodStats {
+    pub fn add(&mut self, level: usize, triangles: u64) {
+        self.instances[level] += 1;
+        self.triangles[level] += triangles;
+    }
+
+    pub fn total_instances(&self) -> u32 {
+        self.instances.iter().sum()
+    }
+
+    pub fn total_triangles(&self) -> u64 {
+        self.triangles.iter().sum()
+    }
+}
+
+/// Fraction of the screen's height that the bounding sphere of the transformed bounds covers, which is unbounded
+/// when the camera is within the sphere
+pub fn screen_size(bounds: &Aabb, transform: &Mat4f, view: &Mat4f, projection: &Mat4f) -> f32 {
+    let bounds = bounds.transform(transform);
+    let radius = bounds.extents().magnitude();
+    let centre = bounds.centre();
+    let view_centre = *view * Vec4f::new(centre.x, centre.y, centre.z, 1.0);
+
+    // the clip space w, which is the view depth for perspective projections and one for orthographic ones
+    let w = (*projection * view_centre).w;
+    if w <= radius && projection[(3, 2)] != 0.0 {
+        return f32::MAX;
+    }
+
+    radius * projection[(1, 1)] / w
+}
+
+/// The first level whose screen size the renderable covers, or the last level when it is smaller than all of them.
+/// When cross-fading, a renderable that is only just larger than its level's screen size is partly covered by the
+/// next level, which takes over entirely as the renderable shrinks to it.
+pub fn select_lod(chain: &LodChain, screen_size: f32, settings: &LodSettings) -> LodSelection {
+    let size = screen_size * (-settings.bias).exp2();
+    let level = chain
+        .levels()
+        .iter()
+        .position(|level| size >= level.screen_size)
+        .unwrap_or(chain.levels().len() - 1);
+
+    let mut coverage = 1.0;
+    if settings.cross_fade && settings.fade_band > 0.0 && level + 1 < chain.levels().len() {
+        let threshold = chain.levels()[level].screen_size;
+        let fade = (size / threshold - 1.0) / settings.fade_band;
+        if fade > 0.0 && fade < 1.0 {
+            coverage = fade;
+        }
+    }
+
+    LodSelection { level, coverage }
+}
@@ -1,6 +1,18 @@
+mod tests;
 pub mod camera;
+pub mod cascades;
+pub mod clusters;
 mod command;
+pub mod culling;
+pub mod environment;
+pub mod gpu_culling;
+pub mod graph;
+pub mod lod;
+pub mod occlusion;
 mod pipeline;
 pub mod pipeline_stages;
+pub mod post_effects;
 pub mod renderer;
+pub mod shadow_atlas;
 mod state;
+pub mod view;
@@ -0,0 +1,231 @@
+use crate::{
+    components::MeshInstance,
+    math::*,
+    resource_manager::{
+        model::Aabb,
+        resource_manager::{MeshID, ResourceManager, ResourceManagerTrait},
+    },
+};
+
+/// Frames that a depth pyramid read back to the CPU is trusted for, as the further the camera has moved since it
+/// was built, the more it will wrongly hide
+pub const MAX_DEPTH_PYRAMID_AGE: u64 = 4;
+/// Widest level of the Hi-Z pyramid, along either axis, that is read back to the CPU
+pub const MAX_READBACK_SIZE: u32 = 256;
+
+/// Levels in a full mip chain of a texture of this size
+pub fn mip_level_count(width: u32, height: u32) -> u32 {
+    32 - width.max(height).max(1).leading_zeros()
+}
+
+/// The finest level of the pyramid that fits within `MAX_READBACK_SIZE`
+pub fn readback_level(width: u32, height: u32) -> u32 {
+    let mut level = 0;
+    while (width >> level).max(1) > MAX_READBACK_SIZE
+        || (height >> level).max(1) > MAX_READBACK_SIZE
+    {
+        level += 1;
+    }
+    level
+}
+
+/// Hi-Z pyramid on the GPU, built from the depth buffer at the end of a frame
+#[derive(Debug, Clone, Copy)]
+pub struct HiZ {
+    /// Bindless handle, sampled without filtering
+    pub handle: u64,
+    pub width: u32,
+    pub height: u32,
+    pub levels: u32,
+    /// Projection and view of the frame the depth buffer was drawn in
+    pub view_projection: Mat4f,
+    pub frame: u64,
+}
+
+impl HiZ {
+    /// Whether the pyramid was built from the frame before, so is what the current frame should be tested against
+    pub fn is_previous_frame(&self, frame: u64) -> bool {
+        self.frame + 1 == frame
+    }
+}
+
+/// A level of `DepthPyramid`, with rows from the bottom of the screen to the top
+pub struct DepthLevel {
+    pub width: u32,
+    pub height: u32,
+    pub depths: Vec<f32>,
+}
+
+impl DepthLevel {
+    pub fn depth(&self, x: u32, y: u32) -> f32 {
+        self.depths[(y * self.width + x) as usize]
+    }
+
+    /// Same reduction as the Hi-Z shader, where each texel keeps the furthest of the texels it covers, and the last
+    /// texel of a row or column also covers the one left over when the size is odd
+    pub fn downsample(&self) -> Self {
+        let width = (self.width / 2).max(1);
+        let height = (self.height / 2).max(1);
+        let mut depths = Vec::with_capacity((width * height) as usize);
+
+        for y in 0..height {
+            for x in 0..width {
+                let last_x = 2 * x + 1 + if x == width - 1 { self.width & 1 } else { 0 };
+                let last_y = 2 * y + 1 + if y == height - 1 { self.height & 1 } else { 0 };
+
+                let mut depth = 0.0f32;
+                for read_y in (2 * y)..=last_y.min(self.height - 1) {
+                    for read_x in (2 * x)..=last_x.min(self.width - 1) {
+                        depth = depth.max(self.depth(read_x, read_y));
+                    }
+                }
+                depths.push(depth);
+            }
+        }
+
+        Self {
+            width,
+            height,
+            depths,
+        }
+    }
+}
+
+/// A level of the Hi-Z pyramid that has been read back to the CPU, along with the coarser levels below it, so
+/// renderables can be occlusion tested before any draw data is uploaded for them
+pub struct DepthPyramid {
+    pub levels: Vec<DepthLevel>,
+    /// Size of the depth buffer the pyramid was built from
+    pub screen_width: u32,
+    pub screen_height: u32,
+    /// Level of the Hi-Z pyramid that the first level was read back from
+    pub base_level: u32,
+    pub view_projection: Mat4f,
+    pub frame: u64,
+}
+
+impl DepthPyramid {
+    pub fn new(
+        base: DepthLevel,
+        screen_width: u32,
+        screen_height: u32,
+        base_level: u32,
+        view_projection: Mat4f,
+        frame: u64,
+    ) -> Self {
+        let mut levels = vec![base];
+        while levels
+            .last()
+            .is_some_and(|level| level.width > 1 || level.height > 1)
+        {
+            let next = levels.last().unwrap().downsample();
+            levels.push(next);
+        }
+
+        Self {
+            levels,
+            screen_width,
+            screen_height,
+            base_level,
+            view_projection,
+            frame,
+        }
+    }
+
+    /// Whether the pyramid is recent enough to be tested against
+    pub fn is_fresh(&self, frame: u64) -> bool {
+        frame.saturating_sub(self.frame) <= MAX_DEPTH_PYRAMID_AGE
+    }
+
+    /// Conservative test of whether world space bounds were entirely hidden behind what was drawn. <br>
+    /// Bounds that cross the camera's plane, or are offscreen, are never occluded, as that is for frustum culling to
+    /// decide.
+    pub fn is_occluded(&self, bounds: &Aabb) -> bool {
+        let (mut min_x, mut min_y) = (f32::MAX, f32::MAX);
+        let (mut max_x, mut max_y) = (f32::MIN, f32::MIN);
+        let mut nearest = f32::MAX;
+
+        for i in 0..8 {
+            let pick = |bit: u32, min: f32, max: f32| if i & bit == 0 { min } else { max };
+            let corner = Vec4f::new(
+                pick(1, bounds.min.x, bounds.max.x),
+                pick(2, bounds.min.y, bounds.max.y),
+                pick(4, bounds.min.z, bounds.max.z),
+                1.0,
+            );
+            let clip = self.view_projection * corner;
+
+            if clip.w <= 0.0 {
+                return false;
+            }
+
+            let (x, y) = (clip.x / clip.w * 0.5 + 0.5, clip.y / clip.w * 0.5 + 0.5);
+            min_x = min_x.min(x);
+            min_y = min_y.min(y);
+            max_x = max_x.max(x);
+            max_y = max_y.max(y);
+            nearest = nearest.min(clip.z / clip.w * 0.5 + 0.5);
+        }
+
+        if max_x < 0.0 || max_y < 0.0 || min_x > 1.0 || min_y > 1.0 {
+            return false;
+        }
+
+        // screen texels that the bounds cover, which are mapped to each level by the same shift that the pyramid
+        // was reduced by
+        let to_texel =
+            |uv: f32, size: u32| ((uv.clamp(0.0, 1.0) * size as f32) as u32).min(size - 1);
+        let (first_x, last_x) = (
+            to_texel(min_x, self.screen_width),
+            to_texel(max_x, self.screen_width),
+        );
+        let (first_y, last_y) = (
+            to_texel(min_y, self.screen_height),
+            to_texel(max_y, self.screen_height),
+        );
+
+        // the finest level where the bounds cover no more than two texels along each axis
+        let mut level = 0;
+        while level + 1 < self.levels.len() {
+            let shift = self.base_level + level as u32;
+            if (last_x >> shift) - (first_x >> shift) <= 1
+                && (last_y >> shift) - (first_y >> shift) <= 1
+            {
+                break;
+            }
+            level += 1;
+        }
+
+        let shift = self.base_level + level as u32;
+        let depths = &self.levels[level];
+        let to_level = |texel: u32, size: u32| (texel >> shift).min(size - 1);
+        let mut furthest = 0.0f32;
+
+        for y in to_level(first_y, depths.height)..=to_level(last_y, depths.height) {
+            for x in to_level(first_x, depths.width)..=to_level(last_x, depths.width) {
+                furthest = furthest.max(depths.depth(x, y));
+            }
+        }
+
+        nearest > furthest
+    }
+}
+
+/// Removes the indices of renderables whose world space bounds are hidden in the depth pyramid. <br>
+/// Renderables without bounds are kept, as there is nothing to test them against.
+pub fn cull_occluded(
+    pyramid: &DepthPyramid,
+    renderables: &[MeshInstance],
+    mesh_bounds: &ResourceManager<Aabb, MeshID>,
+    renderable_indices: &mut Vec<usize>,
+) {
+    renderable_indices.retain(|index| {
+        let renderable = &renderables[*index];
+
+        if let Some(bounds) = mesh_bounds.borrow(&renderable.mesh_id) {
+            !pyramid.is_occluded(&bounds.transform(&renderable.transform))
+        } else {
+            true
+        }
+    });
+}
@@ -1,79 +1,378 @@
-use std::collections::HashMap;
+use log::error;
 
-use nohash_hasher::BuildNoHashHasher;
-
-use super::{pipeline_stages::*, state::RendererState};
+use super::{
+    graph::{
+        CompiledGraph, GraphResource, GraphResources, PassBuilder, RenderGraph,
+        TransientFramebuffer,
+    },
+    pipeline_stages::*,
+    state::RendererState,
+};
 use crate::{
-    components::Renderable, graphics::state::RasteriserState,
+    components::MeshInstance,
+    graphics::{framebuffer::FramebufferConfig, state::RasteriserState},
     memory_manager::memory_manager::MemoryManager,
-    resource_manager::resource_manager::ResourcesManager,
+    resource_manager::resource_manager::{FramebufferID, ResourcesManager},
 };
 
 pub struct RendererPipeline<'a> {
-    stages: HashMap<u16, Box<dyn PipelineStage + 'a>, BuildNoHashHasher<u16>>,
-    enabled: u16,
+    /// Stages in the order they were added, which is used to break ties in the graph's execution order
+    stages: Vec<Box<dyn PipelineStage + 'a>>,
+    enabled: Vec<StageName>,
+
+    pub graph: RenderGraph,
+    compiled: Option<CompiledGraph>,
+    /// Index into `stages` for each pass in the graph
+    pass_stages: Vec<usize>,
+    /// Whether each stage's pass survived compilation
+    active: Vec<bool>,
+    dirty: bool,
+
+    /// The first is for views at the resolution of the window, followed by any others drawn last frame
+    resolutions: Vec<ResolutionResources>,
+    /// Index into `resolutions` of the view being drawn
+    resolution: usize,
+}
+
+/// Framebuffers that the graph's resources are realised as, for views drawn at one resolution
+struct ResolutionResources {
+    /// None for the window's resolution, which uses the imported framebuffers as they are
+    size: Option<(u32, u32)>,
+    graph_resources: GraphResources,
+    realised: bool,
+    /// Framebuffers allocated for transient resources, kept between compilations so they can be reused
+    transient_pool: Vec<(TransientFramebuffer, FramebufferID)>,
+    /// Framebuffers drawn to in place of imported framebuffers that follow the window's size, keyed by the one
+    /// they stand in for
+    imported: Vec<(FramebufferID, FramebufferID)>,
+    last_used: u64,
+}
+
+impl ResolutionResources {
+    fn new(size: Option<(u32, u32)>) -> Self {
+        Self {
+            size,
+            graph_resources: GraphResources::new(),
+            realised: false,
+            transient_pool: Vec::new(),
+            imported: Vec::new(),
+            last_used: 0,
+        }
+    }
+
+    fn delete(self, resources_manager: &mut ResourcesManager) {
+        for (_, framebuffer_id) in self.transient_pool {
+            resources_manager.remove_framebuffer(framebuffer_id);
+        }
+        for (_, framebuffer_id) in self.imported {
+            resources_manager.remove_framebuffer(framebuffer_id);
+        }
+    }
+
+    /// The config of the framebuffer as it is drawn at this resolution, which keeps its own size unless it follows
+    /// the window's
+    fn config(&self, config: &FramebufferConfig, resize_with_viewport: bool) -> FramebufferConfig {
+        let mut config = config.clone();
+        if let (Some((width, height)), true) = (self.size, resize_with_viewport) {
+            config.width = width;
+            config.height = height;
+        }
+        config
+    }
+
+    /// The framebuffer drawn to in place of an imported one, which is recreated if the imported framebuffer has
+    /// changed since
+    fn imported_framebuffer(
+        &mut self,
+        resources_manager: &mut ResourcesManager,
+        framebuffer_id: FramebufferID,
+    ) -> FramebufferID {
+        if self.size.is_none()
+            || !resources_manager
+                .resize_framebuffers
+                .contains(&framebuffer_id)
+        {
+            return framebuffer_id;
+        }
+        let config = match resources_manager.borrow_framebuffer(&framebuffer_id) {
+            Some(framebuffer) => self.config(&framebuffer.config, true),
+            None => return framebuffer_id,
+        };
+
+        let existing = self
+            .imported
+            .iter()
+            .position(|(imported_id, _)| *imported_id == framebuffer_id);
+        if let Some(i) = existing {
+            let copy_id = self.imported[i].1;
+            if resources_manager
+                .borrow_framebuffer(&copy_id)
+                .is_some_and(|copy| copy.config == config)
+            {
+                return copy_id;
+            }
+            resources_manager.remove_framebuffer(copy_id);
+            self.imported.swap_remove(i);
+        }
+
+        let copy_id = resources_manager.load_framebuffer(&config, false);
+        self.imported.push((framebuffer_id, copy_id));
+        copy_id
+    }
 }
 
 impl<'a> RendererPipeline<'a> {
     pub fn new() -> Self {
         Self {
-            stages: HashMap::with_hasher(BuildNoHashHasher::default()),
-            enabled: 0,
+            stages: Vec::new(),
+            enabled: Vec::new(),
+
+            graph: RenderGraph::new(),
+            compiled: None,
+            pass_stages: Vec::new(),
+            active: Vec::new(),
+            dirty: true,
+
+            resolutions: vec![ResolutionResources::new(None)],
+            resolution: 0,
         }
     }
 
-    pub fn add_stage(&mut self, stage: impl PipelineStage + 'a, id: u16) {
-        self.stages.insert(id, Box::new(stage));
-        self.enable_stages(id);
+    /// Replaces any stage of the same name
+    pub fn add_stage(&mut self, stage: impl PipelineStage + 'a) {
+        let name = stage.name();
+        self.stages.retain(|stage| stage.name() != name);
+        self.stages.push(Box::new(stage));
+        self.enable_stage(name);
     }
 
-    pub fn remove_stage(&mut self, id: u16) {
-        self.disable_stages(id);
-        self.stages.remove(&id);
+    pub fn remove_stage(&mut self, name: StageName) {
+        self.disable_stage(name);
+        self.stages.retain(|stage| stage.name() != name);
     }
 
-    pub fn enable_stages(&mut self, mask: u16) {
-        self.enabled |= mask;
+    pub fn get_stage_mut<T: PipelineStage + 'static>(&mut self, name: StageName) -> Option<&mut T> {
+        self.stages
+            .iter_mut()
+            .find(|stage| stage.name() == name)
+            .and_then(|stage| AsAny::as_any_mut(stage.as_mut()).downcast_mut::<T>())
     }
 
-    pub fn disable_stages(&mut self, mask: u16) {
-        self.enabled &= !mask;
+    pub fn enable_stage(&mut self, name: StageName) {
+        if !self.enabled.contains(&name) {
+            self.enabled.push(name);
+        }
+        self.dirty = true;
     }
 
-    pub fn is_enabled(&mut self, mask: u16) -> bool {
-        self.enabled & mask == mask
+    pub fn disable_stage(&mut self, name: StageName) {
+        self.enabled.retain(|enabled| *enabled != name);
+        self.dirty = true;
+    }
+
+    pub fn is_enabled(&self, name: StageName) -> bool {
+        self.enabled.contains(&name)
+    }
+
+    /// Forces the graph to be rebuilt before the next frame, such as after changing graph resources
+    pub fn invalidate(&mut self) {
+        self.dirty = true;
+    }
+
+    /// Selects the framebuffers that the next view is drawn to, where None is the resolution of the window and
+    /// other sizes have framebuffers of their own
+    pub fn set_resolution(&mut self, size: Option<(u32, u32)>, frame: u64) {
+        self.resolution = match self
+            .resolutions
+            .iter()
+            .position(|resolution| resolution.size == size)
+        {
+            Some(i) => i,
+            None => {
+                self.resolutions.push(ResolutionResources::new(size));
+                self.resolutions.len() - 1
+            }
+        };
+        self.resolutions[self.resolution].last_used = frame;
+    }
+
+    /// Deletes the framebuffers of resolutions that no view was drawn at this frame
+    pub fn release_unused_resolutions(
+        &mut self,
+        resources_manager: &mut ResourcesManager,
+        frame: u64,
+    ) {
+        let mut i = 1;
+        while i < self.resolutions.len() {
+            if self.resolutions[i].last_used < frame {
+                self.resolutions.swap_remove(i).delete(resources_manager);
+            } else {
+                i += 1;
+            }
+        }
+        self.resolution = 0;
     }
 
-    pub fn submit(&mut self, renderable_index: usize, pipeline_stages: u16) {
-        for (id, stage) in self.stages.iter_mut() {
-            if *id & self.enabled > 0 && *id & pipeline_stages == *id {
+    pub fn submit(&mut self, renderable_index: usize, pipeline_stages: RenderMask) {
+        self.compile();
+
+        for (i, stage) in self.stages.iter_mut().enumerate() {
+            if self.active[i] && stage.accepts(pipeline_stages) {
                 stage.submit(renderable_index)
             }
         }
     }
 
+    /// Rebuilds the graph from the enabled stages, if anything has changed since it was last compiled
+    fn compile(&mut self) {
+        if !self.dirty {
+            return;
+        }
+        self.dirty = false;
+        for resolution in self.resolutions.iter_mut() {
+            resolution.realised = false;
+        }
+
+        self.graph.clear_passes();
+        self.pass_stages.clear();
+
+        for (i, stage) in self.stages.iter_mut().enumerate() {
+            if self.enabled.contains(&stage.name()) {
+                let mut pass = PassBuilder::default();
+                stage.setup(&mut pass, &self.enabled);
+                self.graph.add_pass(stage.name(), pass);
+                self.pass_stages.push(i);
+            }
+        }
+
+        self.active = vec![false; self.stages.len()];
+
+        match self.graph.compile() {
+            Ok(compiled) => {
+                for pass_index in compiled.order.iter() {
+                    self.active[self.pass_stages[*pass_index]] = true;
+                }
+                self.compiled = Some(compiled);
+            }
+            Err(e) => {
+                error!("Failed to compile render graph: {}", e);
+                self.compiled = None;
+            }
+        }
+    }
+
+    /// Creates framebuffers for the transient resources of the compiled graph, at the selected resolution, reusing
+    /// those from previous compilations where the descriptions match
+    fn realise_resources(&mut self, resources_manager: &mut ResourcesManager) {
+        let resolution = &mut self.resolutions[self.resolution];
+        resolution.graph_resources.clear();
+        resolution.realised = true;
+
+        let compiled = match &self.compiled {
+            Some(compiled) => compiled,
+            None => return,
+        };
+
+        let mut claimed = vec![false; resolution.transient_pool.len()];
+        let mut slot_framebuffers = Vec::with_capacity(compiled.slots.len());
+
+        for slot in compiled.slots.iter() {
+            let existing = resolution
+                .transient_pool
+                .iter()
+                .enumerate()
+                .position(|(i, (transient, _))| !claimed[i] && transient == slot);
+
+            let framebuffer_id = match existing {
+                Some(i) => {
+                    claimed[i] = true;
+                    resolution.transient_pool[i].1
+                }
+                None => {
+                    // framebuffers at other resolutions keep their size when the window is resized
+                    let framebuffer_id = resources_manager.load_framebuffer(
+                        &resolution.config(&slot.config, slot.resize_with_viewport),
+                        slot.resize_with_viewport && resolution.size.is_none(),
+                    );
+                    resolution
+                        .transient_pool
+                        .push((slot.clone(), framebuffer_id));
+                    claimed.push(true);
+                    framebuffer_id
+                }
+            };
+
+            slot_framebuffers.push(framebuffer_id);
+        }
+
+        // framebuffers that no slot needs any more would otherwise be kept until the resolution is dropped
+        let mut claimed = claimed.into_iter();
+        resolution.transient_pool.retain(|(_, framebuffer_id)| {
+            let keep = claimed.next().unwrap_or(true);
+            if !keep {
+                resources_manager.remove_framebuffer(*framebuffer_id);
+            }
+            keep
+        });
+
+        for (name, slot) in compiled.allocations.iter() {
+            resolution
+                .graph_resources
+                .insert(name, slot_framebuffers[*slot]);
+        }
+
+        for pass_index in compiled.order.iter() {
+            let pass = self.graph.get_pass(*pass_index);
+
+            for name in pass.builder.reads.iter().chain(pass.builder.writes.iter()) {
+                if let Some(GraphResource::Imported(framebuffer_id)) = self.graph.get_resource(name)
+                {
+                    let framebuffer_id =
+                        resolution.imported_framebuffer(resources_manager, *framebuffer_id);
+                    resolution.graph_resources.insert(name, framebuffer_id);
+                }
+            }
+        }
+    }
+
     pub fn execute(
         &mut self,
         memory_manager: &mut MemoryManager,
         resources_manager: &mut ResourcesManager,
         renderer_state: &mut RendererState,
         rasteriser_state: &mut RasteriserState,
-        renderables: &[Renderable],
+        renderables: &[MeshInstance],
     ) {
-        for stage_id in STAGES {
-            if stage_id & self.enabled > 0 {
-                if let Some(stage) = self.stages.get_mut(stage_id) {
-                    renderer_state.set_framebuffer(Some(&stage.get_target()), resources_manager);
-                    stage.execute(
-                        memory_manager,
-                        resources_manager,
-                        renderer_state,
-                        rasteriser_state,
-                        renderables,
-                    );
-                    rasteriser_state.set(Default::default());
-                }
+        self.compile();
+        if !self.resolutions[self.resolution].realised {
+            self.realise_resources(resources_manager);
+        }
+        let graph_resources = &self.resolutions[self.resolution].graph_resources;
+
+        let order = match &self.compiled {
+            Some(compiled) => compiled.order.clone(),
+            None => return,
+        };
+
+        for pass_index in order {
+            let stage_index = self.pass_stages[pass_index];
+            let render_target = self.graph.get_pass(pass_index).builder.render_target;
+
+            if let Some(framebuffer_id) =
+                render_target.and_then(|name| graph_resources.framebuffer(name))
+            {
+                renderer_state.set_framebuffer(Some(&framebuffer_id), resources_manager);
             }
+
+            self.stages[stage_index].execute(
+                memory_manager,
+                resources_manager,
+                renderer_state,
+                rasteriser_state,
+                graph_resources,
+                renderables,
+            );
+            rasteriser_state.set(Default::default());
         }
 
         renderer_state.set_framebuffer(None, resources_manager);
@@ -0,0 +1,455 @@
+use super::*;
+use crate::{
+    components::MeshInstance,
+    graphics::{
+        self,
+        framebuffer::{FramebufferAttachment, InternalFormat},
+        shader::{Program, ShaderData},
+        state::RasteriserState,
+        texture::{Texture, TextureConfig, TextureFilter, TextureType, TextureWrap},
+        AccessModifier, Barriers,
+    },
+    math::{Mat4f, Vec2f},
+    memory_manager::memory_manager::MemoryManager,
+    platform::rustgl,
+    renderer::state::RendererState,
+    resource_manager::resource_manager::{FramebufferID, ResourcesManager, ShaderProgramID},
+};
+
+const GROUP_WIDTH: u32 = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AntiAliasing {
+    None,
+    /// Blurs along the edges it finds in the image, which is cheap but softens the image slightly
+    Fxaa,
+    /// Jitters the camera by a fraction of a pixel each frame, and blends each frame with the last ones, reprojected
+    /// to where they are now
+    Taa,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct FxaaSettings {
+    /// Contrast, relative to the brightest of the neighbouring pixels, below which a pixel isn't treated as an edge
+    pub edge_threshold: f32,
+    /// Contrast below which a pixel is never treated as an edge, so dark areas aren't blurred
+    pub edge_threshold_min: f32,
+    /// Furthest, in pixels, that an edge is blurred along
+    pub span_max: f32,
+}
+
+impl Default for FxaaSettings {
+    fn default() -> Self {
+        Self {
+            edge_threshold: 0.125,
+            edge_threshold_min: 0.0312,
+            span_max: 8.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct TaaSettings {
+    /// Weight of the current frame, where the rest comes from the history. Lower values are smoother, but take
+    /// longer to settle after the camera moves
+    pub blend: f32,
+    /// Length of the jitter sequence before it repeats
+    pub jitter_samples: u32,
+}
+
+impl Default for TaaSettings {
+    fn default() -> Self {
+        Self {
+            blend: 0.1,
+            jitter_samples: 8,
+        }
+    }
+}
+
+/// Element of the Halton sequence with the base, which is in [0, 1)
+pub fn halton(mut index: u32, base: u32) -> f32 {
+    let mut fraction = 1.0;
+    let mut result = 0.0;
+
+    while index > 0 {
+        fraction /= base as f32;
+        result += fraction * (index % base) as f32;
+        index /= base;
+    }
+
+    result
+}
+
+/// Offset of the camera for the frame, in pixels, which is within half a pixel of the centre
+pub fn taa_jitter(frame: u64, samples: u32) -> Vec2f {
+    // the sequence starts from one, as every base's first element is zero
+    let index = (frame % samples.max(1) as u64) as u32 + 1;
+
+    Vec2f::new(halton(index, 2) - 0.5, halton(index, 3) - 0.5)
+}
+
+/// Textures that are the size of the HDR buffer
+struct AntiAliasingTargets {
+    /// Resolved frames, where one is read as the last frame's while the other is written, and swapped each frame.
+    /// FXAA writes to the first, as it only needs somewhere to write before copying back to the HDR buffer
+    history: [Texture; 2],
+    /// How far each pixel has moved across the screen since the last frame, in UV
+    motion: Texture,
+}
+
+impl AntiAliasingTargets {
+    fn new(width: u32, height: u32) -> Self {
+        let config = TextureConfig {
+            wrap: TextureWrap::ClampToEdge,
+            min_filter: TextureFilter::Linear,
+            mag_filter: TextureFilter::Linear,
+            mipmap: false,
+            srgb: false,
+        };
+        let texture = |internal_format| {
+            Texture::new_framebuffer_texture(
+                TextureType::T2D,
+                internal_format,
+                1,
+                1,
+                1,
+                width,
+                height,
+                &config,
+            )
+        };
+
+        Self {
+            history: [
+                texture(InternalFormat::RGBA16F),
+                texture(InternalFormat::RGBA16F),
+            ],
+            motion: texture(InternalFormat::RG16F),
+        }
+    }
+
+    fn delete(&self) {
+        for texture in self.history.iter() {
+            texture.delete();
+        }
+        self.motion.delete();
+    }
+}
+
+/// Anti-aliases [`RESOURCE_HDR`] once the scene has been drawn into it, and before it is post processed
+pub struct AntiAliasingStage {
+    pub mode: AntiAliasing,
+    pub fxaa: FxaaSettings,
+    pub taa: TaaSettings,
+
+    fxaa_shader_id: ShaderProgramID,
+    motion_shader_id: ShaderProgramID,
+    resolve_shader_id: ShaderProgramID,
+    /// Depth textures compare against a reference by default, which we don't want when reading the values directly
+    depth_sampler: rustgl::GlSampler,
+    /// Recreated whenever the HDR buffer changes size
+    targets: Option<AntiAliasingTargets>,
+    /// Which of the history textures was written last
+    current_history: usize,
+    /// Whether the history holds a resolved frame, which it doesn't when it has just been created, or FXAA wrote
+    /// to it
+    history_valid: bool,
+    /// Frame that the history was resolved in, as it is only reprojected from the frame before
+    history_frame: u64,
+    /// Projection and view of the last frame, without jitter
+    previous_view_projection: Mat4f,
+}
+
+impl AntiAliasingStage {
+    pub fn new(resources_manager: &mut ResourcesManager) -> Self {
+        let fxaa_shader_id = resources_manager.load_shader("res/shaders/fxaa.glsl");
+        let motion_shader_id = resources_manager.load_shader("res/shaders/taa_motion.glsl");
+        let resolve_shader_id = resources_manager.load_shader("res/shaders/taa_resolve.glsl");
+
+        let depth_sampler = unsafe {
+            let sampler = rustgl::create_sampler().unwrap();
+            rustgl::sampler_parameter_i32(
+                sampler,
+                rustgl::TEXTURE_COMPARE_MODE,
+                rustgl::NONE as i32,
+            );
+            rustgl::sampler_parameter_i32(
+                sampler,
+                rustgl::TEXTURE_MIN_FILTER,
+                rustgl::NEAREST as i32,
+            );
+            rustgl::sampler_parameter_i32(
+                sampler,
+                rustgl::TEXTURE_MAG_FILTER,
+                rustgl::NEAREST as i32,
+            );
+            sampler
+        };
+
+        Self {
+            mode: AntiAliasing::Taa,
+            fxaa: FxaaSettings::default(),
+            taa: TaaSettings::default(),
+            fxaa_shader_id,
+            motion_shader_id,
+            resolve_shader_id,
+            depth_sampler,
+            targets: None,
+            current_history: 0,
+            history_valid: false,
+            history_frame: 0,
+            previous_view_projection: Mat4f::identity(),
+        }
+    }
+
+    /// Offset that the camera should be given for the frame, which is zero unless TAA is used
+    pub fn jitter(&self, frame: u64) -> Vec2f {
+        match self.mode {
+            AntiAliasing::Taa => taa_jitter(frame, self.taa.jitter_samples),
+            _ => Vec2f::uniform(0.0),
+        }
+    }
+
+    fn resize(&mut self, width: u32, height: u32) {
+        if let Some(targets) = self.targets.take() {
+            targets.delete();
+        }
+
+        self.targets = Some(AntiAliasingTargets::new(width, height));
+        self.history_valid = false;
+    }
+
+    fn fxaa(
+        &mut self,
+        resources_manager: &mut ResourcesManager,
+        renderer_state: &mut RendererState,
+        target: &FramebufferID,
+    ) {
+        renderer_state.set_shader_program(self.fxaa_shader_id, resources_manager);
+        let program = resources_manager
+            .borrow_mut_shader_program(&self.fxaa_shader_id)
+            .unwrap();
+        program.set_uniform(
+            "edgeThreshold".to_string(),
+            ShaderData::Float1(self.fxaa.edge_threshold),
+        );
+        program.set_uniform(
+            "edgeThresholdMin".to_string(),
+            ShaderData::Float1(self.fxaa.edge_threshold_min),
+        );
+        program.set_uniform(
+            "spanMax".to_string(),
+            ShaderData::Float1(self.fxaa.span_max),
+        );
+
+        let targets = self.targets.as_ref().unwrap();
+        let (hdr, _) = hdr_textures(resources_manager, target).unwrap();
+
+        unsafe {
+            rustgl::active_texture(rustgl::TEXTURE0);
+        }
+        hdr.bind();
+        targets.history[0].bind_image_unit(
+            0,
+            0,
+            0,
+            AccessModifier::WriteOnly,
+            InternalFormat::RGBA16F,
+        );
+
+        Program::dispatch_compute(
+            hdr.width.div_ceil(GROUP_WIDTH),
+            hdr.height.div_ceil(GROUP_WIDTH),
+            1,
+        );
+        graphics::memory_barrier(
+            Barriers::ShaderImageAccess as u32 | Barriers::TextureUpdate as u32,
+        );
+
+        targets.history[0].copy_level_to(hdr, 0);
+        // TAA can't use what FXAA wrote as its history
+        self.history_valid = false;
+    }
+
+    fn taa(
+        &mut self,
+        resources_manager: &mut ResourcesManager,
+        renderer_state: &mut RendererState,
+        target: &FramebufferID,
+    ) {
+        let (width, height) = {
+            let (hdr, _) = hdr_textures(resources_manager, target).unwrap();
+            (hdr.width, hdr.height)
+        };
+        let groups = (width.div_ceil(GROUP_WIDTH), height.div_ceil(GROUP_WIDTH));
+
+        let view_projection =
+            renderer_state.unjittered_projection_transform * renderer_state.view_transform;
+        let inverse_view_projection = (renderer_state.projection_transform
+            * renderer_state.view_transform)
+            .inverse()
+            .unwrap_or(Mat4f::identity());
+        let reprojection = self.previous_view_projection * inverse_view_projection;
+        let jitter = Vec2f::new(
+            renderer_state.jitter.x / width as f32,
+            renderer_state.jitter.y / height as f32,
+        );
+
+        // motion vectors, from the depth of each pixel and how the camera has moved
+        renderer_state.set_shader_program(self.motion_shader_id, resources_manager);
+        let program = resources_manager
+            .borrow_mut_shader_program(&self.motion_shader_id)
+            .unwrap();
+        program.set_uniform("reprojection".to_string(), ShaderData::Mat4f(&reprojection));
+        program.set_uniform("jitter".to_string(), ShaderData::Float2(jitter.x, jitter.y));
+
+        let targets = self.targets.as_ref().unwrap();
+        let (_, depth) = hdr_textures(resources_manager, target).unwrap();
+
+        unsafe {
+            rustgl::active_texture(rustgl::TEXTURE0);
+            rustgl::bind_sampler(0, Some(self.depth_sampler));
+        }
+        depth.bind();
+        targets
+            .motion
+            .bind_image_unit(0, 0, 0, AccessModifier::WriteOnly, InternalFormat::RG16F);
+
+        Program::dispatch_compute(groups.0, groups.1, 1);
+        graphics::memory_barrier(Barriers::ShaderImageAccess as u32);
+
+        unsafe {
+            rustgl::bind_sampler(0, None);
+        }
+
+        // blends the frame with the history, after clamping the history to the colours around each pixel
+        let previous = self.current_history;
+        let current = 1 - previous;
+
+        renderer_state.set_shader_program(self.resolve_shader_id, resources_manager);
+        let program = resources_manager
+            .borrow_mut_shader_program(&self.resolve_shader_id)
+            .unwrap();
+        program.set_uniform("blend".to_string(), ShaderData::Float1(self.taa.blend));
+        let history_valid = self.history_valid && self.history_frame + 1 == renderer_state.frame;
+        program.set_uniform(
+            "historyValid".to_string(),
+            ShaderData::Int1(history_valid as i32),
+        );
+
+        let targets = self.targets.as_ref().unwrap();
+        let (hdr, _) = hdr_textures(resources_manager, target).unwrap();
+
+        unsafe {
+            rustgl::active_texture(rustgl::TEXTURE0);
+        }
+        targets.history[previous].bind();
+        hdr.bind_image_unit(0, 0, 0, AccessModifier::ReadOnly, InternalFormat::RGBA16F);
+        targets
+            .motion
+            .bind_image_unit(1, 0, 0, AccessModifier::ReadOnly, InternalFormat::RG16F);
+        targets.history[current].bind_image_unit(
+            2,
+            0,
+            0,
+            AccessModifier::WriteOnly,
+            InternalFormat::RGBA16F,
+        );
+
+        Program::dispatch_compute(groups.0, groups.1, 1);
+        graphics::memory_barrier(
+            Barriers::ShaderImageAccess as u32
+                | Barriers::TextureUpdate as u32
+                | Barriers::TextureFetch as u32,
+        );
+
+        targets.history[current].copy_level_to(hdr, 0);
+
+        self.current_history = current;
+        self.history_valid = true;
+        self.history_frame = renderer_state.frame;
+        self.previous_view_projection = view_projection;
+    }
+}
+
+/// Colour and depth textures of the HDR framebuffer
+fn hdr_textures<'a>(
+    resources_manager: &'a ResourcesManager,
+    target: &FramebufferID,
+) -> Option<(&'a Texture, &'a Texture)> {
+    let fb = resources_manager.borrow_framebuffer(target)?;
+
+    match (&fb.colour_handle, &fb.depth_handle) {
+        (FramebufferAttachment::Texture(colour), FramebufferAttachment::Texture(depth)) => {
+            Some((colour, depth))
+        }
+        _ => None,
+    }
+}
+
+impl PipelineStage for AntiAliasingStage {
+    fn name(&self) -> StageName {
+        STAGE_ANTI_ALIASING
+    }
+
+    fn setup(&mut self, pass: &mut PassBuilder, enabled_stages: &[StageName]) {
+        pass.read(RESOURCE_HDR)
+            .read(RESOURCE_DEPTH)
+            .write(RESOURCE_HDR);
+    }
+
+    /// Only needs the HDR and depth buffers, so takes no renderables
+    fn init(
+        &mut self,
+        memory_manager: &mut MemoryManager,
+        resource_manager: &mut ResourcesManager,
+        renderer_state: &mut RendererState,
+    ) {
+    }
+
+    fn submit(&mut self, renderable_index: usize) {}
+
+    fn execute(
+        &mut self,
+        memory_manager: &mut MemoryManager,
+        resources_manager: &mut ResourcesManager,
+        renderer_state: &mut RendererState,
+        rasteriser_state: &mut RasteriserState,
+        graph_resources: &GraphResources,
+        renderables: &[MeshInstance],
+    ) {
+        if self.mode == AntiAliasing::None {
+            self.history_valid = false;
+            return;
+        }
+
+        let target = graph_resources.framebuffer(RESOURCE_HDR).unwrap();
+        let (width, height) = {
+            let fb = resources_manager.borrow_framebuffer(&target).unwrap();
+            (fb.config.width, fb.config.height)
+        };
+
+        if self
+            .targets
+            .as_ref()
+            .is_none_or(|targets| targets.motion.width != width || targets.motion.height != height)
+        {
+            self.resize(width, height);
+        }
+
+        if hdr_textures(resources_manager, &target).is_none() {
+            return;
+        }
+
+        // the history belongs to the primary view, so the other views fall back to FXAA rather than blending with
+        // another camera's frames
+        match self.mode {
+            AntiAliasing::Fxaa => self.fxaa(resources_manager, renderer_state, &target),
+            AntiAliasing::Taa if !renderer_state.primary_view => {
+                self.fxaa(resources_manager, renderer_state, &target)
+            }
+            AntiAliasing::Taa => self.taa(resources_manager, renderer_state, &target),
+            AntiAliasing::None => {}
+        }
+    }
+}
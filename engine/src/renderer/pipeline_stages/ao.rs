@@ -1,28 +1,129 @@
-use super::PipelineStage;
+use super::*;
 use crate::{
-    components::Renderable,
-    graphics::state::RasteriserState,
-    memory_manager::memory_manager::MemoryManager,
+    components::MeshInstance,
+    graphics::{
+        self,
+        framebuffer::{
+            FramebufferAttachment, FramebufferAttachmentConfig, FramebufferConfig, InternalFormat,
+        },
+        shader::Program,
+        state::RasteriserState,
+        texture::TextureType,
+        AccessModifier, Barriers,
+    },
+    math::Vec4f,
+    memory_manager::{
+        memory_manager::MemoryManager,
+        uniform_layouts::{
+            GeneralPurposeIndexStorageBuffer, GeneralPurposeStorageBuffer,
+            GeneralPurposeVecStorageBuffer,
+        },
+    },
+    platform::rustgl,
     renderer::state::RendererState,
     resource_manager::resource_manager::{FramebufferID, ResourcesManager, ShaderProgramID},
 };
 
+/// Intermediate target for the first direction of the separable blur
+const RESOURCE_AO_BLUR: ResourceName = "ao_blur";
+
+#[derive(Clone, Copy)]
+pub struct AmbientOcclusionSettings {
+    /// View space distance that occluders are searched for within
+    pub radius: f32,
+    pub sample_count: u32,
+    /// Exponent applied to the result, where values above one darken occluded areas
+    pub intensity: f32,
+    /// View space depth that an occluder must be in front of a sample by, to avoid self occlusion
+    pub bias: f32,
+    /// Radius of the blur in texels, where zero disables the blur
+    pub blur_radius: u32,
+}
+
+impl Default for AmbientOcclusionSettings {
+    fn default() -> Self {
+        Self {
+            radius: 0.5,
+            sample_count: 16,
+            intensity: 1.5,
+            bias: 0.025,
+            blur_radius: 4,
+        }
+    }
+}
+
+/// Screen space ambient occlusion, calculated from the depth buffer. <br>
+/// This relies on the depth stage, as the depth buffer must be filled before the scene stage samples the result. When
+/// the depth stage is disabled, nothing reads this stage's output, so the graph culls it. The deferred path fills the
+/// depth buffer in its G-buffer stage instead.
 pub struct AOStage {
-    target: FramebufferID,
+    pub settings: AmbientOcclusionSettings,
     shader_id: ShaderProgramID,
+    blur_shader_id: ShaderProgramID,
+    /// Depth textures compare against a reference by default, which we don't want when reading the values directly
+    depth_sampler: rustgl::GlSampler,
+}
+
+/// Declares the depth that ambient occlusion is calculated from, and the single channel targets of the occlusion and
+/// its blur, which are sized with the viewport
+pub fn ao_pass(pass: &mut PassBuilder) {
+    let config = FramebufferConfig {
+        colour: FramebufferAttachmentConfig::Texture {
+            target: TextureType::T2D,
+            internal_format: InternalFormat::R8,
+            layers: 1,
+            levels: 1,
+        },
+        ..Default::default()
+    };
+
+    pass.read(RESOURCE_DEPTH)
+        .create_framebuffer(RESOURCE_AO, config.clone(), true)
+        .create_framebuffer(RESOURCE_AO_BLUR, config, true);
 }
 
 impl AOStage {
-    pub fn new(target: FramebufferID, resources_manager: &mut ResourcesManager) -> Self {
+    pub fn new(resources_manager: &mut ResourcesManager) -> Self {
         let shader_id = resources_manager.load_shader("res/shaders/ambient_occlusion.glsl");
+        let blur_shader_id =
+            resources_manager.load_shader("res/shaders/ambient_occlusion_blur.glsl");
+
+        let depth_sampler = unsafe {
+            let sampler = rustgl::create_sampler().unwrap();
+            rustgl::sampler_parameter_i32(
+                sampler,
+                rustgl::TEXTURE_COMPARE_MODE,
+                rustgl::NONE as i32,
+            );
+            rustgl::sampler_parameter_i32(
+                sampler,
+                rustgl::TEXTURE_MIN_FILTER,
+                rustgl::NEAREST as i32,
+            );
+            rustgl::sampler_parameter_i32(
+                sampler,
+                rustgl::TEXTURE_MAG_FILTER,
+                rustgl::NEAREST as i32,
+            );
+            sampler
+        };
 
-        Self { target, shader_id }
+        Self {
+            settings: AmbientOcclusionSettings::default(),
+            shader_id,
+            blur_shader_id,
+            depth_sampler,
+        }
     }
 }
 
 impl PipelineStage for AOStage {
-    fn get_target(&self) -> FramebufferID {
-        self.target
+    fn name(&self) -> StageName {
+        STAGE_AO
+    }
+
+    fn setup(&mut self, pass: &mut PassBuilder, enabled_stages: &[StageName]) {
+        ao_pass(pass);
     }
 
     fn init(
@@ -41,7 +142,129 @@ impl PipelineStage for AOStage {
         resources_manager: &mut ResourcesManager,
         renderer_state: &mut RendererState,
         rasteriser_state: &mut RasteriserState,
-        renderables: &[Renderable],
+        graph_resources: &GraphResources,
+        renderables: &[MeshInstance],
     ) {
+        let depth_target = graph_resources.framebuffer(RESOURCE_DEPTH).unwrap();
+        let ao_target = graph_resources.framebuffer(RESOURCE_AO).unwrap();
+        let blur_target = graph_resources.framebuffer(RESOURCE_AO_BLUR).unwrap();
+
+        let depth_fb = resources_manager.borrow_framebuffer(&depth_target).unwrap();
+        let width = depth_fb.config.width;
+        let height = depth_fb.config.height;
+
+        unsafe {
+            rustgl::active_texture(rustgl::TEXTURE0);
+            rustgl::bind_sampler(0, Some(self.depth_sampler));
+        }
+        if let FramebufferAttachment::Texture(texture) = &depth_fb.depth_handle {
+            texture.bind();
+        }
+
+        renderer_state.set_shader_program(self.shader_id, resources_manager);
+        self.dispatch(
+            memory_manager,
+            resources_manager,
+            &ao_target,
+            1,
+            GeneralPurposeStorageBuffer {
+                indices: GeneralPurposeIndexStorageBuffer {
+                    index_1: self.settings.sample_count.max(1),
+                    ..Default::default()
+                },
+                vecs: GeneralPurposeVecStorageBuffer {
+                    vec_1: Vec4f::new(
+                        width as f32,
+                        height as f32,
+                        self.settings.radius,
+                        self.settings.intensity,
+                    ),
+                    vec_2: Vec4f::new(self.settings.bias, 0.0, 0.0, 0.0),
+                    ..Default::default()
+                },
+            },
+        );
+
+        if self.settings.blur_radius > 0 {
+            renderer_state.set_shader_program(self.blur_shader_id, resources_manager);
+
+            for (read, write, direction) in [
+                (ao_target, blur_target, (1.0, 0.0)),
+                (blur_target, ao_target, (0.0, 1.0)),
+            ] {
+                let read_fb = resources_manager.borrow_framebuffer(&read).unwrap();
+                if let FramebufferAttachment::Texture(texture) = &read_fb.colour_handle {
+                    unsafe {
+                        rustgl::active_texture(rustgl::TEXTURE1);
+                    }
+                    texture.bind();
+                }
+
+                self.dispatch(
+                    memory_manager,
+                    resources_manager,
+                    &write,
+                    2,
+                    GeneralPurposeStorageBuffer {
+                        indices: GeneralPurposeIndexStorageBuffer {
+                            index_1: self.settings.blur_radius,
+                            ..Default::default()
+                        },
+                        vecs: GeneralPurposeVecStorageBuffer {
+                            vec_1: Vec4f::new(
+                                width as f32,
+                                height as f32,
+                                direction.0,
+                                direction.1,
+                            ),
+                            ..Default::default()
+                        },
+                    },
+                );
+            }
+
+            unsafe {
+                rustgl::active_texture(rustgl::TEXTURE0);
+            }
+        }
+
+        unsafe {
+            rustgl::bind_sampler(0, None);
+        }
+
+        // the lighting shaders read the result through a bindless handle, which changes when the viewport is resized
+        let ao_fb = resources_manager
+            .borrow_mut_framebuffer(&ao_target)
+            .unwrap();
+        if let FramebufferAttachment::Texture(texture) = &mut ao_fb.colour_handle {
+            texture.make_texture_resident();
+            memory_manager.set_ambient_occlusion_map(texture.get_shader_texture_handle());
+        }
+    }
+}
+
+impl AOStage {
+    fn dispatch(
+        &self,
+        memory_manager: &mut MemoryManager,
+        resources_manager: &ResourcesManager,
+        write_target: &FramebufferID,
+        image_unit: u32,
+        parameters: GeneralPurposeStorageBuffer,
+    ) {
+        memory_manager.reserve_per_draw_shader_data(1);
+        memory_manager.set_general(parameters);
+
+        let write_fb = resources_manager.borrow_framebuffer(write_target).unwrap();
+        if let FramebufferAttachment::Texture(texture) = &write_fb.colour_handle {
+            texture.bind_image_unit(image_unit, 0, 0, AccessModifier::WriteOnly, InternalFormat::R8);
+        }
+
+        Program::dispatch_compute(
+            write_fb.config.width.div_ceil(16),
+            write_fb.config.height.div_ceil(16),
+            1,
+        );
+        graphics::memory_barrier(Barriers::ShaderImageAccess as u32 | Barriers::TextureFetch as u32);
     }
 }
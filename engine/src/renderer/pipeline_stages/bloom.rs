@@ -1,11 +1,12 @@
-use super::PipelineStage;
+use super::*;
 use crate::{
-    components::Renderable,
+    components::MeshInstance,
     graphics::{
         self,
-        framebuffer::{FramebufferAttachment, FramebufferAttachmentConfig, InternalFormat},
-        shader::Program,
+        framebuffer::{FramebufferAttachment, InternalFormat},
+        shader::{Program, ShaderData},
         state::RasteriserState,
+        texture::{Texture, TextureConfig, TextureFilter, TextureType, TextureWrap},
         AccessModifier, Barriers,
     },
     math::Vec4f,
@@ -18,32 +19,144 @@ use crate::{
     },
     platform::rustgl,
     renderer::state::RendererState,
-    resource_manager::resource_manager::{FramebufferID, ResourcesManager, ShaderProgramID},
+    resource_manager::resource_manager::{
+        FramebufferID, ResourcesManager, ShaderProgramID, TextureID,
+    },
 };
 
+const GROUP_WIDTH: u32 = 16;
+/// Size, in pixels, that the shorter side of the coarsest level of the chain is kept above
+pub const MIN_BLOOM_SIZE: u32 = 8;
+
+#[derive(Clone, Copy)]
+pub struct BloomSettings {
+    /// Brightness, as the largest channel of the HDR colour, above which pixels bloom
+    pub threshold: f32,
+    /// Fraction of the threshold, below it, over which the bloom fades in rather than cutting off sharply
+    pub soft_knee: f32,
+    /// Strength of the bloom when it is added to the scene
+    pub intensity: f32,
+    /// Weight of each wider level as it is added to the one above, so larger values spread the bloom further
+    pub radius: f32,
+    /// Times that the scene is halved, where `None` derives it from the resolution. It is always limited by
+    /// [`MIN_BLOOM_SIZE`]
+    pub mip_count: Option<u32>,
+    /// Scales the bloom across the screen, such as with dirt on a lens
+    pub dirt_texture: Option<TextureID>,
+    pub dirt_intensity: f32,
+}
+
+impl Default for BloomSettings {
+    fn default() -> Self {
+        Self {
+            threshold: 0.35,
+            soft_knee: 0.5,
+            intensity: 1.33,
+            radius: 1.0,
+            mip_count: None,
+            dirt_texture: None,
+            dirt_intensity: 1.0,
+        }
+    }
+}
+
+/// Levels in the bloom's chain for a scene of this size, where the first is half its size, and each is half the
+/// size of the one before, until the shorter side would fall below [`MIN_BLOOM_SIZE`]
+pub fn bloom_mip_count(width: u32, height: u32, mip_count: Option<u32>) -> u32 {
+    let max_mip_count = (width.min(height) / MIN_BLOOM_SIZE).max(1).ilog2().max(1);
+
+    match mip_count {
+        Some(mip_count) => mip_count.clamp(1, max_mip_count),
+        None => max_mip_count,
+    }
+}
+
+/// Size of a level of the bloom's chain
+fn level_size(width: u32, height: u32, level: u32) -> (u32, u32) {
+    ((width >> level).max(1), (height >> level).max(1))
+}
+
+/// Downsamples [`RESOURCE_HDR`] through a chain of mip levels, keeping only what is brighter than the threshold,
+/// then upsamples back through them, accumulating the wider blurs, and adds the result to the scene. <br>
+/// The chains are owned by the stage, one for each resolution and mip count drawn at, and deleted once a frame
+/// passes without them being used.
 pub struct BloomStage {
-    target: FramebufferID,
     upsample_shader_id: ShaderProgramID,
     downsample_shader_id: ShaderProgramID,
+    chains: Vec<BloomChain>,
+    pub settings: BloomSettings,
+}
+
+struct BloomChain {
+    texture: Texture,
+    levels: u32,
+    last_used: u64,
 }
 
 impl BloomStage {
-    pub fn new(target: FramebufferID, resources_manager: &mut ResourcesManager) -> Self {
+    pub fn new(resources_manager: &mut ResourcesManager) -> Self {
         let downsample_shader_id =
             resources_manager.load_shader("res/shaders/bloom_downsample.glsl");
         let upsample_shader_id = resources_manager.load_shader("res/shaders/bloom_upsample.glsl");
 
         Self {
-            target,
             upsample_shader_id,
             downsample_shader_id,
+            chains: Vec::new(),
+            settings: BloomSettings::default(),
+        }
+    }
+
+    /// Index of the chain of this size and mip count, which is created if it doesn't exist. Chains that weren't
+    /// used this frame or the last are deleted
+    fn chain(&mut self, width: u32, height: u32, levels: u32, frame: u64) -> usize {
+        self.chains.retain(|chain| {
+            let keep = chain.last_used + 1 >= frame;
+            if !keep {
+                chain.texture.delete();
+            }
+            keep
+        });
+
+        if let Some(i) = self.chains.iter().position(|chain| {
+            chain.texture.width == width && chain.texture.height == height && chain.levels == levels
+        }) {
+            self.chains[i].last_used = frame;
+            return i;
         }
+
+        let texture = Texture::new_framebuffer_texture(
+            TextureType::T2D,
+            InternalFormat::RGBA16F,
+            1,
+            levels,
+            1,
+            width,
+            height,
+            &TextureConfig {
+                wrap: TextureWrap::ClampToEdge,
+                min_filter: TextureFilter::Linear,
+                mag_filter: TextureFilter::Linear,
+                mipmap: true,
+                srgb: false,
+            },
+        );
+        self.chains.push(BloomChain {
+            texture,
+            levels,
+            last_used: frame,
+        });
+        self.chains.len() - 1
     }
 }
 
 impl PipelineStage for BloomStage {
-    fn get_target(&self) -> FramebufferID {
-        self.target
+    fn name(&self) -> StageName {
+        STAGE_BLOOM
+    }
+
+    fn setup(&mut self, pass: &mut PassBuilder, enabled_stages: &[StageName]) {
+        pass.read(RESOURCE_HDR).write(RESOURCE_HDR);
     }
 
     fn init(
@@ -62,97 +175,155 @@ impl PipelineStage for BloomStage {
         resources_manager: &mut ResourcesManager,
         renderer_state: &mut RendererState,
         rasteriser_state: &mut RasteriserState,
-        renderables: &[Renderable],
+        graph_resources: &GraphResources,
+        renderables: &[MeshInstance],
     ) {
-        let fb = resources_manager.borrow_framebuffer(&self.target).unwrap();
-
-        if let FramebufferAttachment::Texture(texture) = &fb.colour_handle {
-            unsafe {
-                rustgl::active_texture(rustgl::TEXTURE0);
-            }
-            texture.bind()
-        }
+        let target = graph_resources.framebuffer(RESOURCE_HDR).unwrap();
+        let (width, height) = {
+            let fb = resources_manager.borrow_framebuffer(&target).unwrap();
+            (fb.config.width, fb.config.height)
+        };
 
-        if let FramebufferAttachmentConfig::Texture { levels, .. } = fb.config.colour {
-            renderer_state.set_shader_program(self.downsample_shader_id, resources_manager);
-            for level in 0..(levels - 1) {
-                self.downsample(memory_manager, resources_manager, level);
-            }
+        let (chain_width, chain_height) = level_size(width, height, 1);
+        let levels = bloom_mip_count(width, height, self.settings.mip_count);
+        let chain_index = self.chain(chain_width, chain_height, levels, renderer_state.frame);
+        let chain = &self.chains[chain_index].texture;
+        let settings = self.settings;
 
-            renderer_state.set_shader_program(self.upsample_shader_id, resources_manager);
-            for level in (1..levels).rev() {
-                self.upsample(memory_manager, resources_manager, level);
-            }
+        unsafe {
+            rustgl::active_texture(rustgl::TEXTURE0);
         }
-    }
-}
-
-impl BloomStage {
-    fn downsample(
-        &mut self,
-        memory_manager: &mut MemoryManager,
-        resources_manager: &mut ResourcesManager,
-        read_mip: u32,
-    ) {
-        let fb = resources_manager.borrow_framebuffer(&self.target).unwrap();
-
-        // 64 threads per subgroup, one per written pixel, so calculate number of subgroups based on resolution of
-        // write mip level which is one higher
-
-        let write_w = fb.config.width / 2u32.pow(read_mip + 1);
-        let write_h = fb.config.height / 2u32.pow(read_mip + 1);
 
-        let read_w = write_w * 2;
-        let read_h = write_h * 2;
+        // the scene is filtered by brightness into the first level, then each level is halved into the next
+        renderer_state.set_shader_program(self.downsample_shader_id, resources_manager);
+        let program = resources_manager
+            .borrow_mut_shader_program(&self.downsample_shader_id)
+            .unwrap();
+        program.set_uniform(
+            "threshold".to_string(),
+            ShaderData::Float1(settings.threshold),
+        );
+        program.set_uniform(
+            "knee".to_string(),
+            ShaderData::Float1(settings.threshold * settings.soft_knee.max(0.0)),
+        );
 
-        let blocks_w = (write_w + 15) / 16;
-        let blocks_h = (write_h + 15) / 16;
-
-        memory_manager.reserve_per_draw_shader_data(1);
-        memory_manager.set_general(GeneralPurposeStorageBuffer {
-            indices: GeneralPurposeIndexStorageBuffer {
-                index_1: read_mip,
-                ..Default::default()
-            },
-            vecs: GeneralPurposeVecStorageBuffer {
-                vec_1: Vec4f::new(write_w as f32, write_h as f32, 0.0, 0.0),
-                ..Default::default()
-            },
-        });
+        for level in 0..levels {
+            let program = resources_manager
+                .borrow_mut_shader_program(&self.downsample_shader_id)
+                .unwrap();
+            program.set_uniform(
+                "prefilter".to_string(),
+                ShaderData::Int1((level == 0) as i32),
+            );
 
-        if let FramebufferAttachment::Texture(ref texture) = fb.colour_handle {
-            texture.bind_image_unit(
+            let read_mip = if level == 0 {
+                if let Some(scene) = Self::scene(resources_manager, &target) {
+                    scene.bind();
+                }
+                0
+            } else {
+                chain.bind();
+                level - 1
+            };
+            chain.bind_image_unit(
                 1,
-                read_mip + 1,
+                level,
                 0,
                 AccessModifier::WriteOnly,
                 InternalFormat::RGBA16F,
-            )
+            );
+
+            Self::dispatch(
+                memory_manager,
+                read_mip,
+                level_size(chain_width, chain_height, level),
+            );
         }
 
-        Program::dispatch_compute(blocks_w, blocks_h, 1);
-        graphics::memory_barrier(Barriers::ShaderImageAccess as u32);
-    }
+        // each level is added to the one above it, until the first is added to the scene
+        renderer_state.set_shader_program(self.upsample_shader_id, resources_manager);
+        let dirt_texture = settings
+            .dirt_texture
+            .and_then(|texture_id| resources_manager.borrow_texture(&texture_id));
+        if let Some(dirt_texture) = dirt_texture {
+            unsafe {
+                rustgl::active_texture(rustgl::TEXTURE2);
+            }
+            dirt_texture.bind();
+            unsafe {
+                rustgl::active_texture(rustgl::TEXTURE0);
+            }
+        }
+        let use_dirt = dirt_texture.is_some();
 
-    fn upsample(
-        &mut self,
-        memory_manager: &mut MemoryManager,
-        resources_manager: &mut ResourcesManager,
-        read_mip: u32,
-    ) {
-        let fb = resources_manager.borrow_framebuffer(&self.target).unwrap();
+        let program = resources_manager
+            .borrow_mut_shader_program(&self.upsample_shader_id)
+            .unwrap();
+        program.set_uniform("radius".to_string(), ShaderData::Float1(settings.radius));
+        program.set_uniform(
+            "intensity".to_string(),
+            ShaderData::Float1(settings.intensity),
+        );
+        program.set_uniform("useDirt".to_string(), ShaderData::Int1(use_dirt as i32));
+        program.set_uniform(
+            "dirtIntensity".to_string(),
+            ShaderData::Float1(settings.dirt_intensity),
+        );
 
-        // 64 threads per subgroup, one per written pixel, so calculate number of subgroups based on resolution of
-        // write mip level which is one lower
+        chain.bind();
+        for level in (0..levels).rev() {
+            let composite = level == 0;
+            let program = resources_manager
+                .borrow_mut_shader_program(&self.upsample_shader_id)
+                .unwrap();
+            program.set_uniform("composite".to_string(), ShaderData::Int1(composite as i32));
 
-        let read_w = fb.config.width / 2u32.pow(read_mip);
-        let read_h = fb.config.height / 2u32.pow(read_mip);
+            let write_size = if composite {
+                if let Some(scene) = Self::scene(resources_manager, &target) {
+                    scene.bind_image_unit(
+                        1,
+                        0,
+                        0,
+                        AccessModifier::ReadWrite,
+                        InternalFormat::RGBA16F,
+                    );
+                }
+                (width, height)
+            } else {
+                chain.bind_image_unit(
+                    1,
+                    level - 1,
+                    0,
+                    AccessModifier::ReadWrite,
+                    InternalFormat::RGBA16F,
+                );
+                level_size(chain_width, chain_height, level - 1)
+            };
+
+            Self::dispatch(memory_manager, level, write_size);
+        }
+    }
+}
 
-        let write_w = read_w * 2;
-        let write_h = read_h * 2;
+impl BloomStage {
+    fn scene<'a>(
+        resources_manager: &'a ResourcesManager,
+        target: &FramebufferID,
+    ) -> Option<&'a Texture> {
+        match &resources_manager
+            .borrow_framebuffer(target)
+            .unwrap()
+            .colour_handle
+        {
+            FramebufferAttachment::Texture(texture) => Some(texture),
+            _ => None,
+        }
+    }
 
-        let blocks_w = (write_w + 15) / 16;
-        let blocks_h = (write_h + 15) / 16;
+    /// Runs the bound shader with a thread for each pixel of the level being written
+    fn dispatch(memory_manager: &mut MemoryManager, read_mip: u32, write_size: (u32, u32)) {
+        let (write_width, write_height) = write_size;
 
         memory_manager.reserve_per_draw_shader_data(1);
         memory_manager.set_general(GeneralPurposeStorageBuffer {
@@ -161,22 +332,18 @@ impl BloomStage {
                 ..Default::default()
             },
             vecs: GeneralPurposeVecStorageBuffer {
-                vec_1: Vec4f::new(write_w as f32, write_h as f32, 0.0, 0.0),
+                vec_1: Vec4f::new(write_width as f32, write_height as f32, 0.0, 0.0),
                 ..Default::default()
             },
         });
 
-        if let FramebufferAttachment::Texture(ref texture) = fb.colour_handle {
-            texture.bind_image_unit(
-                1,
-                read_mip - 1,
-                0,
-                AccessModifier::ReadWrite,
-                InternalFormat::RGBA16F,
-            )
-        }
-
-        Program::dispatch_compute(blocks_w, blocks_h, 1);
-        graphics::memory_barrier(Barriers::ShaderImageAccess as u32);
+        Program::dispatch_compute(
+            write_width.div_ceil(GROUP_WIDTH),
+            write_height.div_ceil(GROUP_WIDTH),
+            1,
+        );
+        graphics::memory_barrier(
+            Barriers::ShaderImageAccess as u32 | Barriers::TextureFetch as u32,
+        );
     }
 }
@@ -1,39 +1,50 @@
-use super::PipelineStage;
+use super::*;
 use crate::{
-    components::Renderable,
+    components::MeshInstance,
     graphics::{
         self,
+        shader::ShaderData,
         state::{Orientation, RasteriserState},
     },
     math::Mat4f,
     memory_manager::memory_manager::{
-        DrawElementsIndirectCommand, InstanceData, MemoryManager, DRAW_COMMAND_SIZE,
+        InstanceData, MemoryManager, DRAW_COMMAND_SIZE,
+    },
+    renderer::{
+        command::{upload_draw_data, DrawCommands, SortKeyField, SortKeyLayout},
+        culling::{cull_renderables, Frustum},
+        occlusion::DepthPyramid,
+        state::RendererState,
     },
-    renderer::{command::DrawCommands, state::RendererState},
     resource_manager::resource_manager::{
-        FramebufferID, MaterialID, MeshID, ResourceIDTrait, ResourcesManager, ShaderProgramID,
+        MaterialID, MeshID, ResourceIDTrait, ResourcesManager, ShaderProgramID,
     },
 };
 
 pub struct DebugStage {
-    target: FramebufferID,
     wireframe_shader_id: ShaderProgramID,
     vertices_shader_id: ShaderProgramID,
     renderable_indices: Vec<usize>,
     command_queue: DrawCommands,
-    pending_indirect_command_count: u32,
+    /// Scene renderables that are hidden in the read back depth pyramid are drawn as red wireframes, over the top of
+    /// everything else. These are what the CPU culls, which the GPU culling path closely follows.
+    pub show_occluded: bool,
+    occluded_indices: Vec<usize>,
 }
 
 impl DebugStage {
-    pub fn new(target: FramebufferID, resources_manager: &mut ResourcesManager) -> Self {
+    pub fn new(resources_manager: &mut ResourcesManager) -> Self {
         let wireframe_shader_id = resources_manager.load_shader("res/shaders/debug_wireframe.glsl");
         let vertices_shader_id = resources_manager.load_shader("res/shaders/debug_vertices.glsl");
 
         Self {
-            target,
             renderable_indices: Vec::new(),
-            command_queue: DrawCommands::new(hash),
-            pending_indirect_command_count: 0,
+            // wireframes are only for inspecting the scene, so only batching matters
+            command_queue: DrawCommands::new(
+                SortKeyLayout::new(&[(SortKeyField::Mesh, 22)]).unwrap(),
+            ),
+            show_occluded: false,
+            occluded_indices: Vec::new(),
             wireframe_shader_id,
             vertices_shader_id,
         }
@@ -41,8 +52,18 @@ impl DebugStage {
 }
 
 impl PipelineStage for DebugStage {
-    fn get_target(&self) -> FramebufferID {
-        self.target
+    fn name(&self) -> StageName {
+        STAGE_DEBUG
+    }
+
+    fn setup(&mut self, pass: &mut PassBuilder, enabled_stages: &[StageName]) {
+        pass.read(RESOURCE_DEPTH).render_target(RESOURCE_HDR);
+    }
+
+    /// Also takes scene renderables while occluded renderables are shown, to test them against the depth pyramid
+    fn accepts(&self, pipeline_stages: RenderMask) -> bool {
+        pipeline_stages & RENDER_DEBUG == RENDER_DEBUG
+            || (self.show_occluded && pipeline_stages & RENDER_SCENE == RENDER_SCENE)
     }
 
     fn init(
@@ -63,57 +84,38 @@ impl PipelineStage for DebugStage {
         resources_manager: &mut ResourcesManager,
         renderer_state: &mut RendererState,
         rasteriser_state: &mut RasteriserState,
-        renderables: &[Renderable],
+        graph_resources: &GraphResources,
+        renderables: &[MeshInstance],
     ) {
-        self.command_queue
-            .update_keys(renderables, &self.renderable_indices);
-        self.command_queue.sort_indices();
-
-        let mut instance_count = 0;
-
-        let r = Renderable {
-            mesh_id: MeshID::new(0xFFFF),
-            material_id: MaterialID::new(0xFFFF),
-            shader_id: ShaderProgramID::new(0xFFFF),
-            transform: Mat4f::identity(),
-            pipeline_stages: 0,
-        };
+        let frustum = Frustum::from_matrix(
+            &(renderer_state.projection_transform * renderer_state.view_transform),
+        );
+        cull_renderables(
+            &[frustum],
+            renderables,
+            &resources_manager.mesh_bounds_manager,
+            &mut self.renderable_indices,
+        );
 
-        for i in 0..self.command_queue.indices.len() {
-            let renderable = &renderables[self.renderable_indices[self.command_queue.indices[i]]];
-            let next_renderable = if i == self.command_queue.indices.len() - 1 {
-                &r
-            } else {
-                &renderables[self.renderable_indices[self.command_queue.indices[i + 1]]]
-            };
-
-            instance_count += 1;
-
-            if renderable.mesh_id != next_renderable.mesh_id {
-                memory_manager.reserve_instance_space(instance_count);
-                upload_draw_data(
-                    memory_manager,
-                    resources_manager,
-                    renderable,
-                    instance_count,
-                );
-                self.pending_indirect_command_count += 1;
-
-                for instance_index in
-                    self.command_queue.indices[(i - (instance_count - 1) as usize)..=i].iter()
-                {
-                    let renderable = &renderables[self.renderable_indices[*instance_index]];
-
-                    memory_manager.push_instance_data(&InstanceData {
-                        material_index: renderable.material_id.index(),
-                        transform: renderable.transform,
-                    });
-                }
-
-                instance_count = 0;
-            }
+        if self.show_occluded {
+            split_occluded(
+                renderer_state.fresh_depth_pyramid(),
+                renderables,
+                resources_manager,
+                &mut self.renderable_indices,
+                &mut self.occluded_indices,
+            );
         }
 
+        let command_count = upload_commands(
+            &mut self.command_queue,
+            &self.renderable_indices,
+            memory_manager,
+            resources_manager,
+            renderables,
+            &renderer_state.view_transform,
+        );
+
         rasteriser_state.set_polygon_mode(Orientation::FrontAndBack, false);
 
         renderer_state.set_shader_program(self.wireframe_shader_id, resources_manager);
@@ -121,9 +123,8 @@ impl PipelineStage for DebugStage {
         graphics::submit_draw_call(
             graphics::DrawMode::Triangles,
             graphics::DataType::Uint32,
-            (memory_manager.get_indirect_command_index() - self.pending_indirect_command_count)
-                * DRAW_COMMAND_SIZE,
-            self.pending_indirect_command_count,
+            (memory_manager.get_indirect_command_index() - command_count) * DRAW_COMMAND_SIZE,
+            command_count,
         );
 
         rasteriser_state.set_polygon_mode(Orientation::FrontAndBack, true);
@@ -133,38 +134,163 @@ impl PipelineStage for DebugStage {
         graphics::submit_draw_call(
             graphics::DrawMode::Points,
             graphics::DataType::Uint32,
-            (memory_manager.get_indirect_command_index() - self.pending_indirect_command_count)
-                * DRAW_COMMAND_SIZE,
-            self.pending_indirect_command_count,
+            (memory_manager.get_indirect_command_index() - command_count) * DRAW_COMMAND_SIZE,
+            command_count,
         );
 
-        self.pending_indirect_command_count = 0;
+        if !self.occluded_indices.is_empty() {
+            self.draw_occluded(
+                memory_manager,
+                resources_manager,
+                renderer_state,
+                rasteriser_state,
+                renderables,
+            );
+        }
+
         self.renderable_indices.clear();
+        self.occluded_indices.clear();
     }
 }
 
-fn hash(r: &Renderable) -> u32 {
-    r.mesh_id.index()
+impl DebugStage {
+    fn draw_occluded(
+        &mut self,
+        memory_manager: &mut MemoryManager,
+        resources_manager: &mut ResourcesManager,
+        renderer_state: &mut RendererState,
+        rasteriser_state: &mut RasteriserState,
+        renderables: &[MeshInstance],
+    ) {
+        let command_count = upload_commands(
+            &mut self.command_queue,
+            &self.occluded_indices,
+            memory_manager,
+            resources_manager,
+            renderables,
+            &renderer_state.view_transform,
+        );
+
+        // they are hidden by definition, so would never pass the depth test
+        let depth = rasteriser_state.depth;
+        rasteriser_state.set_depth(false);
+        rasteriser_state.set_polygon_mode(Orientation::FrontAndBack, false);
+
+        renderer_state.set_shader_program(self.wireframe_shader_id, resources_manager);
+        set_occluded(self.wireframe_shader_id, true, resources_manager);
+
+        graphics::submit_draw_call(
+            graphics::DrawMode::Triangles,
+            graphics::DataType::Uint32,
+            (memory_manager.get_indirect_command_index() - command_count) * DRAW_COMMAND_SIZE,
+            command_count,
+        );
+
+        set_occluded(self.wireframe_shader_id, false, resources_manager);
+        rasteriser_state.set_polygon_mode(Orientation::FrontAndBack, true);
+        rasteriser_state.set_depth(depth);
+    }
 }
 
-fn upload_draw_data(
+/// Moves the scene renderables that are hidden in the depth pyramid into `occluded_indices`, and drops the rest,
+/// leaving only debug renderables in `renderable_indices`
+fn split_occluded(
+    pyramid: Option<&DepthPyramid>,
+    renderables: &[MeshInstance],
+    resources_manager: &ResourcesManager,
+    renderable_indices: &mut Vec<usize>,
+    occluded_indices: &mut Vec<usize>,
+) {
+    renderable_indices.retain(|index| {
+        let renderable = &renderables[*index];
+
+        if renderable.pipeline_stages & RENDER_DEBUG == RENDER_DEBUG {
+            return true;
+        }
+
+        let bounds = resources_manager.borrow_mesh_bounds(&renderable.mesh_id);
+        if let (Some(pyramid), Some(bounds)) = (pyramid, bounds) {
+            if pyramid.is_occluded(&bounds.transform(&renderable.transform)) {
+                occluded_indices.push(*index);
+            }
+        }
+
+        false
+    });
+}
+
+/// Uploads the draw data of the renderables, batched by mesh, returning the number of indirect commands written
+fn upload_commands(
+    command_queue: &mut DrawCommands,
+    renderable_indices: &[usize],
     memory_manager: &mut MemoryManager,
+    resources_manager: &ResourcesManager,
+    renderables: &[MeshInstance],
+    view_transform: &Mat4f,
+) -> u32 {
+    command_queue.update_keys(renderables, renderable_indices, view_transform);
+    command_queue.sort_indices();
+
+    let mut command_count = 0;
+    let mut instance_count = 0;
+
+    let r = MeshInstance {
+        mesh_id: MeshID::new(0xFFFF),
+        material_id: MaterialID::new(0xFFFF),
+        shader_id: ShaderProgramID::new(0xFFFF),
+        transform: Mat4f::identity(),
+        pipeline_stages: 0,
+        lod_fade: 0.0,
+    };
+
+    for i in 0..command_queue.indices.len() {
+        let renderable = &renderables[renderable_indices[command_queue.indices[i]]];
+        let next_renderable = if i == command_queue.indices.len() - 1 {
+            &r
+        } else {
+            &renderables[renderable_indices[command_queue.indices[i + 1]]]
+        };
+
+        instance_count += 1;
+
+        if renderable.mesh_id != next_renderable.mesh_id {
+            memory_manager.reserve_instance_space(instance_count);
+            let base_instance = memory_manager.get_instance_index();
+            upload_draw_data(
+                memory_manager,
+                resources_manager,
+                &renderable.mesh_id,
+                instance_count,
+                base_instance,
+            );
+            command_count += 1;
+
+            for instance_index in
+                command_queue.indices[(i - (instance_count - 1) as usize)..=i].iter()
+            {
+                let renderable = &renderables[renderable_indices[*instance_index]];
+
+                memory_manager.push_instance_data(&InstanceData {
+                    material_index: renderable.material_id.index(),
+                    transform: renderable.transform,
+                    lod_fade: renderable.lod_fade,
+                });
+            }
+
+            instance_count = 0;
+        }
+    }
+
+    command_count
+}
+
+fn set_occluded(
+    shader_id: ShaderProgramID,
+    occluded: bool,
     resources_manager: &mut ResourcesManager,
-    renderable: &Renderable,
-    instance_count: u32,
 ) {
-    let mesh = resources_manager.borrow_mesh(&renderable.mesh_id).unwrap();
-
-    memory_manager.reserve_vertex_space(mesh.vertices.len() as u32);
-    memory_manager.reserve_index_space(mesh.indices.len() as u32);
-    let indirect_command = DrawElementsIndirectCommand {
-        count: mesh.indices.len() as u32,
-        instance_count,
-        first_index: memory_manager.get_index_index(),
-        base_vertex: memory_manager.get_vertex_index(),
-        base_instance: memory_manager.get_instance_index(),
-    };
-    memory_manager.push_indirect_command(indirect_command);
-    memory_manager.push_vertex_slice(&mesh.vertices);
-    memory_manager.push_index_slice(&mesh.indices);
+    resources_manager
+        .borrow_mut_shader_program(&shader_id)
+        .unwrap()
+        .set_uniform("occluded".to_string(), ShaderData::Int1(occluded as i32));
 }
@@ -1,33 +1,62 @@
-use super::PipelineStage;
+use super::*;
 use crate::{
-    components::Renderable,
-    memory_manager::memory_manager::MemoryManager,
-    renderer::state::RendererState,
-    resource_manager::resource_manager::{FramebufferID, ResourcesManager, ShaderProgramID}, graphics::state::RasteriserState,
+    components::MeshInstance,
+    graphics::{self, state::RasteriserState, DataType, DrawMode},
+    math::Mat4f,
+    memory_manager::memory_manager::{InstanceData, MemoryManager, DRAW_COMMAND_SIZE},
+    renderer::{
+        command::{upload_draw_data, DrawCommands, SortKeyLayout},
+        culling::{cull_renderables, Frustum},
+        graph::ResourceName,
+        state::RendererState,
+    },
+    resource_manager::resource_manager::{
+        MaterialID, MeshID, ResourceIDTrait, ResourcesManager, ShaderProgramID,
+    },
 };
 
+/// Fills the depth buffer with the opaque scene geometry ahead of the scene stage, so that the scene stage only
+/// shades the visible fragment of each pixel
 pub struct DepthStage {
-    target: FramebufferID,
     shader_id: ShaderProgramID,
+    renderable_indices: Vec<usize>,
+    command_queue: DrawCommands,
+    pending_indirect_command_count: u32,
+    /// Either [`RESOURCE_DEPTH`], or the multisampled depth when MSAA is enabled, which is then resolved into
+    /// [`RESOURCE_DEPTH`]
+    depth_target: ResourceName,
 }
 
 impl DepthStage {
-    pub fn new(
-        target: FramebufferID,
-        resources_manager: &mut ResourcesManager,
-    ) -> Self {
+    pub fn new(resources_manager: &mut ResourcesManager) -> Self {
         let shader_id = resources_manager.load_shader("res/shaders/depth_only.glsl");
 
         Self {
-            target,
             shader_id,
+            renderable_indices: Vec::new(),
+            command_queue: DrawCommands::new(SortKeyLayout::mesh()),
+            pending_indirect_command_count: 0,
+            depth_target: RESOURCE_DEPTH,
         }
     }
 }
 
 impl PipelineStage for DepthStage {
-    fn get_target(&self) -> FramebufferID {
-        self.target
+    fn name(&self) -> StageName {
+        STAGE_DEPTH
+    }
+
+    fn setup(&mut self, pass: &mut PassBuilder, enabled_stages: &[StageName]) {
+        self.depth_target = msaa::scene_targets(enabled_stages).1;
+
+        // the ambient occlusion stage samples the depth before the scene is drawn
+        pass.render_target(self.depth_target).write(RESOURCE_DEPTH);
+    }
+
+    /// Everything drawn by the scene stage is opaque, so it must also be drawn here, otherwise it would fail the
+    /// scene stage's equal depth test
+    fn accepts(&self, pipeline_stages: RenderMask) -> bool {
+        pipeline_stages & RENDER_SCENE == RENDER_SCENE
     }
 
     fn init(
@@ -38,7 +67,9 @@ impl PipelineStage for DepthStage {
     ) {
     }
 
-    fn submit(&mut self, renderable_index: usize) {}
+    fn submit(&mut self, renderable_index: usize) {
+        self.renderable_indices.push(renderable_index);
+    }
 
     fn execute(
         &mut self,
@@ -46,8 +77,102 @@ impl PipelineStage for DepthStage {
         resources_manager: &mut ResourcesManager,
         renderer_state: &mut RendererState,
         rasteriser_state: &mut RasteriserState,
-        renderables: &[Renderable]
+        graph_resources: &GraphResources,
+        renderables: &[MeshInstance],
     ) {
+        rasteriser_state.set(RasteriserState {
+            colour_mask: false,
+            ..Default::default()
+        });
+
+        let frustum = Frustum::from_matrix(
+            &(renderer_state.projection_transform * renderer_state.view_transform),
+        );
+        cull_renderables(
+            &[frustum],
+            renderables,
+            &resources_manager.mesh_bounds_manager,
+            &mut self.renderable_indices,
+        );
+
+        let target = graph_resources.framebuffer(self.depth_target).unwrap();
+        let fb = resources_manager.borrow_framebuffer(&target).unwrap();
+        fb.clear_depth(1.0);
+
+        self.command_queue.update_keys(
+            renderables,
+            &self.renderable_indices,
+            &renderer_state.view_transform,
+        );
+        self.command_queue.sort_indices();
+
+        renderer_state.set_shader_program(self.shader_id, resources_manager);
+
+        let mut instance_count = 0;
+
+        let r = MeshInstance {
+            mesh_id: MeshID::new(0xFFFF),
+            material_id: MaterialID::new(0xFFFF),
+            shader_id: ShaderProgramID::new(0xFFFF),
+            transform: Mat4f::identity(),
+            pipeline_stages: 0,
+            lod_fade: 0.0,
+        };
+
+        for i in 0..self.command_queue.indices.len() {
+            let renderable = &renderables[self.renderable_indices[self.command_queue.indices[i]]];
+            let next_renderable = if i == self.command_queue.indices.len() - 1 {
+                &r
+            } else {
+                &renderables[self.renderable_indices[self.command_queue.indices[i + 1]]]
+            };
+
+            instance_count += 1;
+
+            if renderable.mesh_id != next_renderable.mesh_id {
+                memory_manager.reserve_instance_space(instance_count);
+                let base_instance = memory_manager.get_instance_index();
+                upload_draw_data(
+                    memory_manager,
+                    resources_manager,
+                    &renderable.mesh_id,
+                    instance_count,
+                    base_instance,
+                );
+                self.pending_indirect_command_count += 1;
+
+                for instance_index in
+                    self.command_queue.indices[(i - (instance_count - 1) as usize)..=i].iter()
+                {
+                    let renderable = &renderables[self.renderable_indices[*instance_index]];
+
+                    memory_manager.push_instance_data(&InstanceData {
+                        material_index: renderable.material_id.index(),
+                        transform: renderable.transform,
+                        lod_fade: renderable.lod_fade,
+                    });
+                }
+
+                instance_count = 0;
+            }
+        }
+
+        // the shader is the same for every renderable, so everything can be drawn with a single call
+        if self.pending_indirect_command_count > 0 {
+            graphics::submit_draw_call(
+                DrawMode::Triangles,
+                DataType::Uint32,
+                (memory_manager.get_indirect_command_index() - self.pending_indirect_command_count)
+                    * DRAW_COMMAND_SIZE,
+                self.pending_indirect_command_count,
+            );
+            self.pending_indirect_command_count = 0;
+        }
+
+        if self.depth_target != RESOURCE_DEPTH {
+            msaa::resolve_depth(resources_manager, graph_resources);
+        }
 
+        self.renderable_indices.clear();
     }
 }
@@ -0,0 +1,230 @@
+use super::*;
+use crate::{
+    components::MeshInstance,
+    graphics::{
+        self,
+        framebuffer::{FramebufferAttachmentConfig, FramebufferConfig, InternalFormat},
+        state::RasteriserState,
+        texture::TextureType,
+        DataType, DrawMode,
+    },
+    math::Mat4f,
+    memory_manager::memory_manager::{InstanceData, MemoryManager, DRAW_COMMAND_SIZE},
+    renderer::{
+        command::{upload_draw_data, DrawCommands, SortKeyLayout},
+        culling::{cull_renderables, Frustum},
+        occlusion::cull_occluded,
+        state::RendererState,
+    },
+    resource_manager::resource_manager::{
+        MaterialID, MeshID, ResourceIDTrait, ResourcesManager, ShaderProgramID,
+    },
+};
+
+/// Draw buffer of each attachment of [`RESOURCE_GBUFFER`]
+pub const GBUFFER_ALBEDO: usize = 0;
+/// World space normal, with and without the normal map, each octahedron encoded
+pub const GBUFFER_NORMAL: usize = 1;
+/// Gloss of Phong materials, or roughness of PBR materials, then metallic, occlusion, and whether the material
+/// is PBR
+pub const GBUFFER_MATERIAL: usize = 2;
+pub const GBUFFER_EMISSIVE: usize = 3;
+
+/// Writes the surface attributes of the opaque scene geometry into the G-buffer, to be lit by the lighting stage
+/// of the deferred path. <br>
+/// Every renderable is drawn with the G-buffer shader, so the shaders of the renderables are not used. Once drawn,
+/// the depth is copied into [`RESOURCE_DEPTH`], so the stages that follow can depth test against the scene.
+pub struct GBufferStage {
+    shader_id: ShaderProgramID,
+    renderable_indices: Vec<usize>,
+    command_queue: DrawCommands,
+    pending_indirect_command_count: u32,
+    /// Renderables hidden in the last read back depth pyramid are culled, when the Hi-Z stage is enabled
+    occlusion_culling: bool,
+}
+
+impl GBufferStage {
+    pub fn new(resources_manager: &mut ResourcesManager) -> Self {
+        let shader_id = resources_manager.load_shader("res/shaders/gbuffer.glsl");
+
+        Self {
+            shader_id,
+            renderable_indices: Vec::new(),
+            command_queue: DrawCommands::new(SortKeyLayout::mesh()),
+            pending_indirect_command_count: 0,
+            occlusion_culling: false,
+        }
+    }
+
+    fn config() -> FramebufferConfig {
+        let attachment = |internal_format| FramebufferAttachmentConfig::Texture {
+            target: TextureType::T2D,
+            internal_format,
+            layers: 1,
+            levels: 1,
+        };
+
+        FramebufferConfig {
+            colour: attachment(InternalFormat::RGBA8),
+            additional_colours: vec![
+                attachment(InternalFormat::RGBA16F),
+                attachment(InternalFormat::RGBA8),
+                attachment(InternalFormat::RGBA16F),
+            ],
+            // must match the format of the scene depth, for it to be copied across
+            depth: attachment(InternalFormat::Depth32F),
+            ..Default::default()
+        }
+    }
+}
+
+impl PipelineStage for GBufferStage {
+    fn name(&self) -> StageName {
+        STAGE_GBUFFER
+    }
+
+    fn setup(&mut self, pass: &mut PassBuilder, enabled_stages: &[StageName]) {
+        self.occlusion_culling = enabled_stages.contains(&STAGE_HIZ);
+
+        pass.create_framebuffer(RESOURCE_GBUFFER, Self::config(), true)
+            .render_target(RESOURCE_GBUFFER)
+            .write(RESOURCE_DEPTH);
+    }
+
+    /// Takes the place of the scene stage, so draws everything that would have been submitted to it
+    fn accepts(&self, pipeline_stages: RenderMask) -> bool {
+        pipeline_stages & RENDER_SCENE == RENDER_SCENE
+    }
+
+    fn init(
+        &mut self,
+        memory_manager: &mut MemoryManager,
+        resources_manager: &mut ResourcesManager,
+        renderer_state: &mut RendererState,
+    ) {
+    }
+
+    fn submit(&mut self, renderable_index: usize) {
+        self.renderable_indices.push(renderable_index);
+    }
+
+    fn execute(
+        &mut self,
+        memory_manager: &mut MemoryManager,
+        resources_manager: &mut ResourcesManager,
+        renderer_state: &mut RendererState,
+        rasteriser_state: &mut RasteriserState,
+        graph_resources: &GraphResources,
+        renderables: &[MeshInstance],
+    ) {
+        let frustum = Frustum::from_matrix(
+            &(renderer_state.projection_transform * renderer_state.view_transform),
+        );
+        cull_renderables(
+            &[frustum],
+            renderables,
+            &resources_manager.mesh_bounds_manager,
+            &mut self.renderable_indices,
+        );
+        if let Some(pyramid) = renderer_state
+            .fresh_depth_pyramid()
+            .filter(|_| self.occlusion_culling)
+        {
+            cull_occluded(
+                pyramid,
+                renderables,
+                &resources_manager.mesh_bounds_manager,
+                &mut self.renderable_indices,
+            );
+        }
+
+        let target = graph_resources.framebuffer(RESOURCE_GBUFFER).unwrap();
+        let fb = resources_manager.borrow_framebuffer(&target).unwrap();
+        for draw_buffer in [
+            GBUFFER_ALBEDO,
+            GBUFFER_NORMAL,
+            GBUFFER_MATERIAL,
+            GBUFFER_EMISSIVE,
+        ] {
+            fb.clear_draw_buffer(draw_buffer as u32, 0.0, 0.0, 0.0, 0.0);
+        }
+        fb.clear_depth(1.0);
+
+        self.command_queue.update_keys(
+            renderables,
+            &self.renderable_indices,
+            &renderer_state.view_transform,
+        );
+        self.command_queue.sort_indices();
+
+        renderer_state.set_shader_program(self.shader_id, resources_manager);
+
+        let mut instance_count = 0;
+
+        let r = MeshInstance {
+            mesh_id: MeshID::new(0xFFFF),
+            material_id: MaterialID::new(0xFFFF),
+            shader_id: ShaderProgramID::new(0xFFFF),
+            transform: Mat4f::identity(),
+            pipeline_stages: 0,
+            lod_fade: 0.0,
+        };
+
+        for i in 0..self.command_queue.indices.len() {
+            let renderable = &renderables[self.renderable_indices[self.command_queue.indices[i]]];
+            let next_renderable = if i == self.command_queue.indices.len() - 1 {
+                &r
+            } else {
+                &renderables[self.renderable_indices[self.command_queue.indices[i + 1]]]
+            };
+
+            instance_count += 1;
+
+            if renderable.mesh_id != next_renderable.mesh_id {
+                memory_manager.reserve_instance_space(instance_count);
+                let base_instance = memory_manager.get_instance_index();
+                upload_draw_data(
+                    memory_manager,
+                    resources_manager,
+                    &renderable.mesh_id,
+                    instance_count,
+                    base_instance,
+                );
+                self.pending_indirect_command_count += 1;
+
+                for instance_index in
+                    self.command_queue.indices[(i - (instance_count - 1) as usize)..=i].iter()
+                {
+                    let renderable = &renderables[self.renderable_indices[*instance_index]];
+
+                    memory_manager.push_instance_data(&InstanceData {
+                        material_index: renderable.material_id.index(),
+                        transform: renderable.transform,
+                        lod_fade: renderable.lod_fade,
+                    });
+                }
+
+                instance_count = 0;
+            }
+        }
+
+        // the shader is the same for every renderable, so everything can be drawn with a single call
+        if self.pending_indirect_command_count > 0 {
+            graphics::submit_draw_call(
+                DrawMode::Triangles,
+                DataType::Uint32,
+                (memory_manager.get_indirect_command_index() - self.pending_indirect_command_count)
+                    * DRAW_COMMAND_SIZE,
+                self.pending_indirect_command_count,
+            );
+            self.pending_indirect_command_count = 0;
+        }
+
+        let depth_target = graph_resources.framebuffer(RESOURCE_DEPTH).unwrap();
+        let fb = resources_manager.borrow_framebuffer(&target).unwrap();
+        let depth_fb = resources_manager.borrow_framebuffer(&depth_target).unwrap();
+        fb.blit_depth(depth_fb);
+
+        self.renderable_indices.clear();
+    }
+}
@@ -0,0 +1,251 @@
+use super::*;
+use crate::{
+    components::MeshInstance,
+    graphics::{
+        self,
+        buffer::ReadbackBuffer,
+        framebuffer::{FramebufferAttachment, InternalFormat},
+        shader::{Program, ShaderData},
+        state::RasteriserState,
+        texture::{Texture, TextureConfig, TextureFilter, TextureType, TextureWrap},
+        AccessModifier, Barriers,
+    },
+    math::Mat4f,
+    memory_manager::memory_manager::MemoryManager,
+    platform::rustgl,
+    renderer::{
+        occlusion::{
+            mip_level_count, readback_level, DepthLevel, DepthPyramid, HiZ, MAX_READBACK_SIZE,
+        },
+        state::RendererState,
+    },
+    resource_manager::resource_manager::{ResourcesManager, ShaderProgramID},
+};
+
+const GROUP_WIDTH: u32 = 16;
+
+/// What a readback that is still in flight was copied from
+struct PendingReadback {
+    screen_width: u32,
+    screen_height: u32,
+    level: u32,
+    view_projection: Mat4f,
+    frame: u64,
+}
+
+/// Builds a Hi-Z pyramid from [`RESOURCE_DEPTH`] once the frame's depth is complete, which the next frame's
+/// renderables are occlusion tested against. <br>
+/// A coarse level is also read back to the CPU whenever the last one has arrived, for stages that cull renderables
+/// before uploading their draw data.
+pub struct HiZStage {
+    shader_id: ShaderProgramID,
+    /// Depth textures compare against a reference by default, which we don't want when reading the values directly
+    depth_sampler: rustgl::GlSampler,
+    /// Recreated whenever the depth buffer changes size
+    pyramid: Option<Texture>,
+    readback: ReadbackBuffer,
+    pending_readback: Option<PendingReadback>,
+}
+
+impl HiZStage {
+    pub fn new(resources_manager: &mut ResourcesManager) -> Self {
+        let shader_id = resources_manager.load_shader("res/shaders/hiz_downsample.glsl");
+
+        let depth_sampler = unsafe {
+            let sampler = rustgl::create_sampler().unwrap();
+            rustgl::sampler_parameter_i32(
+                sampler,
+                rustgl::TEXTURE_COMPARE_MODE,
+                rustgl::NONE as i32,
+            );
+            rustgl::sampler_parameter_i32(
+                sampler,
+                rustgl::TEXTURE_MIN_FILTER,
+                rustgl::NEAREST as i32,
+            );
+            rustgl::sampler_parameter_i32(
+                sampler,
+                rustgl::TEXTURE_MAG_FILTER,
+                rustgl::NEAREST as i32,
+            );
+            sampler
+        };
+
+        Self {
+            shader_id,
+            depth_sampler,
+            pyramid: None,
+            readback: ReadbackBuffer::new(
+                MAX_READBACK_SIZE * MAX_READBACK_SIZE * std::mem::size_of::<f32>() as u32,
+            ),
+            pending_readback: None,
+        }
+    }
+
+    fn resize(&mut self, width: u32, height: u32) {
+        if let Some(mut pyramid) = self.pyramid.take() {
+            pyramid.make_texture_non_resident();
+            pyramid.delete();
+        }
+
+        let mut pyramid = Texture::new_framebuffer_texture(
+            TextureType::T2D,
+            InternalFormat::R32F,
+            1,
+            mip_level_count(width, height),
+            1,
+            width,
+            height,
+            &TextureConfig {
+                wrap: TextureWrap::ClampToEdge,
+                min_filter: TextureFilter::Nearest,
+                mag_filter: TextureFilter::Nearest,
+                mipmap: true,
+                srgb: false,
+            },
+        );
+        pyramid.make_texture_resident();
+
+        self.pyramid = Some(pyramid);
+    }
+}
+
+impl PipelineStage for HiZStage {
+    fn name(&self) -> StageName {
+        STAGE_HIZ
+    }
+
+    fn setup(&mut self, pass: &mut PassBuilder, enabled_stages: &[StageName]) {
+        // nothing in the graph reads the pyramid, as it is for the next frame
+        pass.read(RESOURCE_DEPTH).side_effects();
+    }
+
+    /// Only needs the depth buffer, so takes no renderables
+    fn init(
+        &mut self,
+        memory_manager: &mut MemoryManager,
+        resource_manager: &mut ResourcesManager,
+        renderer_state: &mut RendererState,
+    ) {
+    }
+
+    fn submit(&mut self, renderable_index: usize) {}
+
+    fn execute(
+        &mut self,
+        memory_manager: &mut MemoryManager,
+        resources_manager: &mut ResourcesManager,
+        renderer_state: &mut RendererState,
+        rasteriser_state: &mut RasteriserState,
+        graph_resources: &GraphResources,
+        renderables: &[MeshInstance],
+    ) {
+        // the pyramid is kept for the primary view, which the other views' depth would replace
+        if !renderer_state.primary_view {
+            return;
+        }
+
+        let target = graph_resources.framebuffer(RESOURCE_DEPTH).unwrap();
+        let (width, height) = {
+            let fb = resources_manager.borrow_framebuffer(&target).unwrap();
+            (fb.config.width, fb.config.height)
+        };
+
+        if self
+            .pyramid
+            .as_ref()
+            .is_none_or(|pyramid| pyramid.width != width || pyramid.height != height)
+        {
+            self.resize(width, height);
+        }
+
+        let levels = mip_level_count(width, height);
+        renderer_state.set_shader_program(self.shader_id, resources_manager);
+
+        unsafe {
+            rustgl::active_texture(rustgl::TEXTURE0);
+            rustgl::bind_sampler(0, Some(self.depth_sampler));
+        }
+        let fb = resources_manager.borrow_framebuffer(&target).unwrap();
+        if let FramebufferAttachment::Texture(texture) = &fb.depth_handle {
+            texture.bind();
+        }
+
+        let pyramid = self.pyramid.as_mut().unwrap();
+        for level in 0..levels {
+            resources_manager
+                .borrow_mut_shader_program(&self.shader_id)
+                .unwrap()
+                .set_uniform("level".to_string(), ShaderData::Int1(level as i32));
+
+            if level > 0 {
+                pyramid.bind_image_unit(
+                    0,
+                    level - 1,
+                    0,
+                    AccessModifier::ReadOnly,
+                    InternalFormat::R32F,
+                );
+            }
+            pyramid.bind_image_unit(1, level, 0, AccessModifier::WriteOnly, InternalFormat::R32F);
+
+            Program::dispatch_compute(
+                (width >> level).max(1).div_ceil(GROUP_WIDTH),
+                (height >> level).max(1).div_ceil(GROUP_WIDTH),
+                1,
+            );
+            graphics::memory_barrier(Barriers::ShaderImageAccess as u32);
+        }
+        graphics::memory_barrier(Barriers::TextureFetch as u32 | Barriers::TextureUpdate as u32);
+
+        unsafe {
+            rustgl::bind_sampler(0, None);
+        }
+
+        let view_projection = renderer_state.projection_transform * renderer_state.view_transform;
+        renderer_state.hiz = Some(HiZ {
+            handle: pyramid.get_shader_texture_handle(),
+            width,
+            height,
+            levels,
+            view_projection,
+            frame: renderer_state.frame,
+        });
+
+        if let Some(bytes) = self.readback.poll() {
+            if let Some(pending) = self.pending_readback.take() {
+                let level_width = (pending.screen_width >> pending.level).max(1);
+                let level_height = (pending.screen_height >> pending.level).max(1);
+                let depths = bytes
+                    [..(level_width * level_height) as usize * std::mem::size_of::<f32>()]
+                    .chunks_exact(std::mem::size_of::<f32>())
+                    .map(|depth| f32::from_ne_bytes(depth.try_into().unwrap()))
+                    .collect();
+
+                renderer_state.depth_pyramid = Some(DepthPyramid::new(
+                    DepthLevel {
+                        width: level_width,
+                        height: level_height,
+                        depths,
+                    },
+                    pending.screen_width,
+                    pending.screen_height,
+                    pending.level,
+                    pending.view_projection,
+                    pending.frame,
+                ));
+            }
+        }
+
+        let level = readback_level(width, height);
+        if self.readback.read_texture_level_f32(pyramid, level) {
+            self.pending_readback = Some(PendingReadback {
+                screen_width: width,
+                screen_height: height,
+                level,
+                view_projection,
+                frame: renderer_state.frame,
+            });
+        }
+    }
+}
@@ -0,0 +1,158 @@
+use super::{
+    gbuffer::{GBUFFER_ALBEDO, GBUFFER_EMISSIVE, GBUFFER_MATERIAL, GBUFFER_NORMAL},
+    *,
+};
+use crate::{
+    components::MeshInstance,
+    graphics::{
+        self,
+        framebuffer::{FramebufferAttachment, InternalFormat},
+        shader::Program,
+        state::RasteriserState,
+        AccessModifier, Barriers,
+    },
+    memory_manager::memory_manager::MemoryManager,
+    platform::rustgl,
+    renderer::state::RendererState,
+    resource_manager::resource_manager::{ResourcesManager, ShaderProgramID},
+};
+
+/// Texture unit that the G-buffer depth is bound to, after each of its colour attachments
+const DEPTH_TEXTURE_UNIT: u32 = 4;
+
+/// Lights the G-buffer into [`RESOURCE_HDR`] with a compute shader, as the second half of the deferred path. <br>
+/// Each pixel is lit by the lights of its cluster, the same as the forward path, but only once, however much
+/// geometry was drawn over it.
+pub struct LightingStage {
+    shader_id: ShaderProgramID,
+    /// Depth textures compare against a reference by default, which we don't want when reading the values directly
+    depth_sampler: rustgl::GlSampler,
+}
+
+impl LightingStage {
+    pub fn new(resources_manager: &mut ResourcesManager) -> Self {
+        let shader_id = resources_manager.load_shader("res/shaders/deferred_lighting.glsl");
+
+        let depth_sampler = unsafe {
+            let sampler = rustgl::create_sampler().unwrap();
+            rustgl::sampler_parameter_i32(
+                sampler,
+                rustgl::TEXTURE_COMPARE_MODE,
+                rustgl::NONE as i32,
+            );
+            rustgl::sampler_parameter_i32(
+                sampler,
+                rustgl::TEXTURE_MIN_FILTER,
+                rustgl::NEAREST as i32,
+            );
+            rustgl::sampler_parameter_i32(
+                sampler,
+                rustgl::TEXTURE_MAG_FILTER,
+                rustgl::NEAREST as i32,
+            );
+            sampler
+        };
+
+        Self {
+            shader_id,
+            depth_sampler,
+        }
+    }
+}
+
+impl PipelineStage for LightingStage {
+    fn name(&self) -> StageName {
+        STAGE_LIGHTING
+    }
+
+    fn setup(&mut self, pass: &mut PassBuilder, enabled_stages: &[StageName]) {
+        pass.read(RESOURCE_GBUFFER).write(RESOURCE_HDR);
+
+        if enabled_stages.contains(&STAGE_SHADOW) {
+            pass.read(RESOURCE_SHADOW_MAPS);
+        }
+
+        // the G-buffer stage fills the depth buffer, so ambient occlusion doesn't need the depth pre-pass here
+        if enabled_stages.contains(&STAGE_AO) {
+            pass.read(RESOURCE_AO);
+        }
+    }
+
+    /// Renderables are drawn by the G-buffer stage, so nothing is submitted here
+    fn init(
+        &mut self,
+        memory_manager: &mut MemoryManager,
+        resources_manager: &mut ResourcesManager,
+        renderer_state: &mut RendererState,
+    ) {
+    }
+
+    fn submit(&mut self, renderable_index: usize) {}
+
+    fn execute(
+        &mut self,
+        memory_manager: &mut MemoryManager,
+        resources_manager: &mut ResourcesManager,
+        renderer_state: &mut RendererState,
+        rasteriser_state: &mut RasteriserState,
+        graph_resources: &GraphResources,
+        renderables: &[MeshInstance],
+    ) {
+        let gbuffer_target = graph_resources.framebuffer(RESOURCE_GBUFFER).unwrap();
+        let hdr_target = graph_resources.framebuffer(RESOURCE_HDR).unwrap();
+
+        let gbuffer_fb = resources_manager
+            .borrow_framebuffer(&gbuffer_target)
+            .unwrap();
+
+        for (unit, draw_buffer) in [
+            GBUFFER_ALBEDO,
+            GBUFFER_NORMAL,
+            GBUFFER_MATERIAL,
+            GBUFFER_EMISSIVE,
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            if let Some(FramebufferAttachment::Texture(texture)) =
+                gbuffer_fb.colour_attachment(draw_buffer)
+            {
+                unsafe {
+                    rustgl::active_texture(rustgl::TEXTURE0 + unit as u32);
+                }
+                texture.bind();
+            }
+        }
+
+        unsafe {
+            rustgl::active_texture(rustgl::TEXTURE0 + DEPTH_TEXTURE_UNIT);
+            rustgl::bind_sampler(DEPTH_TEXTURE_UNIT, Some(self.depth_sampler));
+        }
+        if let FramebufferAttachment::Texture(texture) = &gbuffer_fb.depth_handle {
+            texture.bind();
+        }
+
+        renderer_state.set_shader_program(self.shader_id, resources_manager);
+
+        let hdr_fb = resources_manager.borrow_framebuffer(&hdr_target).unwrap();
+        if let FramebufferAttachment::Texture(texture) = &hdr_fb.colour_handle {
+            texture.bind_image_unit(0, 0, 0, AccessModifier::WriteOnly, InternalFormat::RGBA16F);
+        }
+
+        Program::dispatch_compute(
+            hdr_fb.config.width.div_ceil(16),
+            hdr_fb.config.height.div_ceil(16),
+            1,
+        );
+        graphics::memory_barrier(
+            Barriers::ShaderImageAccess as u32
+                | Barriers::TextureFetch as u32
+                | Barriers::Framebuffer as u32,
+        );
+
+        unsafe {
+            rustgl::bind_sampler(DEPTH_TEXTURE_UNIT, None);
+            rustgl::active_texture(rustgl::TEXTURE0);
+        }
+    }
+}
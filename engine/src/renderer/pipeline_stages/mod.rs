@@ -1,44 +1,105 @@
-use super::state::RendererState;
+use std::any::Any;
+
+use super::{
+    graph::{GraphResources, PassBuilder, ResourceName},
+    state::RendererState,
+};
 use crate::{
-    components::Renderable,
-    graphics::state::RasteriserState,
+    components::MeshInstance, graphics::state::RasteriserState,
     memory_manager::memory_manager::MemoryManager,
-    resource_manager::resource_manager::{FramebufferID, ResourcesManager},
+    resource_manager::resource_manager::ResourcesManager,
 };
 
+pub mod anti_aliasing;
 pub mod ao;
 pub mod bloom;
 pub mod debug;
 pub mod depth;
+pub mod gbuffer;
+pub mod hiz;
+pub mod lighting;
+pub mod msaa;
 pub mod post_process;
 pub mod scene;
 pub mod shadow;
 pub mod sky;
+pub mod transparent;
+
+/// Identifies a stage in the pipeline, and is also the name of its pass in the render graph
+pub type StageName = &'static str;
+
+pub const STAGE_SCENE: StageName = "scene";
+pub const STAGE_SKY: StageName = "sky";
+pub const STAGE_POST_PROCESS: StageName = "post_process";
+pub const STAGE_SHADOW: StageName = "shadow";
+pub const STAGE_BLOOM: StageName = "bloom";
+pub const STAGE_AO: StageName = "ao";
+pub const STAGE_DEPTH: StageName = "depth";
+pub const STAGE_GBUFFER: StageName = "gbuffer";
+pub const STAGE_LIGHTING: StageName = "lighting";
+pub const STAGE_TRANSPARENT: StageName = "transparent";
+/// Builds the Hi-Z pyramid that the next frame is occlusion culled against
+pub const STAGE_HIZ: StageName = "hiz";
+/// Anti-aliases the lit scene before post processing
+pub const STAGE_ANTI_ALIASING: StageName = "anti_aliasing";
+/// Resolves the multisampled scene into [`RESOURCE_HDR`], which is enabled by the renderer's MSAA sample count
+/// rather than directly
+pub const STAGE_MSAA_RESOLVE: StageName = "msaa_resolve";
+pub const STAGE_DEBUG: StageName = "debug";
+
+/// Flags of a renderable, which select the stages it is submitted to. Each stage decides which flags it accepts in
+/// [`PipelineStage::accepts`]
+pub type RenderMask = u32;
+
+/// Opaque renderables, which are drawn by the scene stages of either path
+pub const RENDER_SCENE: RenderMask = 1 << 0;
+pub const RENDER_SKY: RenderMask = 1 << 1;
+pub const RENDER_SHADOW: RenderMask = 1 << 2;
+/// Renderables that are blended over the scene, which shouldn't also be flagged with [`RENDER_SCENE`]
+pub const RENDER_TRANSPARENT: RenderMask = 1 << 3;
+pub const RENDER_DEBUG: RenderMask = 1 << 4;
+
+/// Colour target that the scene is lit into, before post processing
+pub const RESOURCE_HDR: ResourceName = "hdr";
+/// Scene depth, which shares a framebuffer with [`RESOURCE_HDR`]
+pub const RESOURCE_DEPTH: ResourceName = "depth";
+/// Multisampled colour that the opaque scene is drawn into while MSAA is enabled, before being resolved into
+/// [`RESOURCE_HDR`]
+pub const RESOURCE_MSAA: ResourceName = "msaa";
+/// Multisampled depth, which shares a framebuffer with [`RESOURCE_MSAA`]
+pub const RESOURCE_MSAA_DEPTH: ResourceName = "msaa_depth";
+/// Shadow maps owned by the shadow stage
+pub const RESOURCE_SHADOW_MAPS: ResourceName = "shadow_maps";
+/// The default framebuffer, which is the output of the graph
+pub const RESOURCE_BACKBUFFER: ResourceName = "backbuffer";
+/// Blurred ambient occlusion, created by the AO stage
+pub const RESOURCE_AO: ResourceName = "ao";
+/// Surface attributes of the scene, created by the G-buffer stage of the deferred path
+pub const RESOURCE_GBUFFER: ResourceName = "gbuffer";
+
+/// Allows a stage to be downcast back to its concrete type, such as to change its settings
+pub trait AsAny {
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+impl<T: Any> AsAny for T {
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
 
-pub const STAGE_SCENE: u16 = 0b1000_0000_0000_0000;
-pub const STAGE_SKY: u16 = 0b0100_0000_0000_0000;
-pub const STAGE_POST_PROCESS: u16 = 0b0010_0000_0000_0000;
-pub const STAGE_SHADOW: u16 = 0b0001_0000_0000_0000;
-pub const STAGE_BLOOM: u16 = 0b0000_1000_0000_0000;
-pub const STAGE_AO: u16 = 0b0000_0100_0000_0000;
-pub const STAGE_DEPTH: u16 = 0b0000_0010_0000_0000;
-
-pub const STAGE_DEBUG: u16 = 0b0000_0000_0000_0001;
-
-/// Pipeline executes stages in this order
-pub const STAGES: &[u16] = &[
-    STAGE_SHADOW,
-    STAGE_DEPTH,
-    STAGE_AO,
-    STAGE_SCENE,
-    STAGE_SKY,
-    STAGE_BLOOM,
-    STAGE_DEBUG,
-    STAGE_POST_PROCESS,
-];
-
-pub trait PipelineStage {
-    fn get_target(&self) -> FramebufferID;
+/// Stages are passes of the render graph, so the order they execute in is derived from the resources they declare
+/// in `setup`, rather than being fixed
+pub trait PipelineStage: AsAny {
+    fn name(&self) -> StageName;
+    /// Declares the resources of the stage's pass. `enabled_stages` allows a stage to adapt to the other stages it
+    /// runs alongside
+    fn setup(&mut self, pass: &mut PassBuilder, enabled_stages: &[StageName]);
+    /// Whether a renderable with the given `pipeline_stages` should be submitted to this stage, which by default is
+    /// never
+    fn accepts(&self, pipeline_stages: RenderMask) -> bool {
+        false
+    }
     fn submit(&mut self, renderable_index: usize);
     fn init(
         &mut self,
@@ -52,6 +113,7 @@ pub trait PipelineStage {
         resource_manager: &mut ResourcesManager,
         renderer_state: &mut RendererState,
         rasteriser_state: &mut RasteriserState,
-        renderables: &[Renderable],
+        graph_resources: &GraphResources,
+        renderables: &[MeshInstance],
     );
 }
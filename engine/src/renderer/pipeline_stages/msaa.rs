@@ -0,0 +1,97 @@
+use super::*;
+use crate::{
+    components::MeshInstance,
+    graphics::state::RasteriserState,
+    memory_manager::memory_manager::MemoryManager,
+    renderer::state::RendererState,
+    resource_manager::resource_manager::ResourcesManager,
+};
+
+/// Checks that a multisampled framebuffer can be created with `samples` per pixel, where one disables MSAA
+pub fn validate_samples(samples: u32, max_samples: u32) -> Result<u32, String> {
+    if !samples.is_power_of_two() {
+        return Err(format!(
+            "MSAA sample count must be a power of two, but was {}",
+            samples
+        ));
+    }
+    if samples > max_samples.max(1) {
+        return Err(format!(
+            "MSAA sample count of {} exceeds the maximum of {}",
+            samples, max_samples
+        ));
+    }
+
+    Ok(samples)
+}
+
+/// The colour and depth resources that the opaque scene is drawn into, which are multisampled while the MSAA
+/// resolve stage is enabled
+pub fn scene_targets(enabled_stages: &[StageName]) -> (ResourceName, ResourceName) {
+    if enabled_stages.contains(&STAGE_MSAA_RESOLVE) {
+        (RESOURCE_MSAA, RESOURCE_MSAA_DEPTH)
+    } else {
+        (RESOURCE_HDR, RESOURCE_DEPTH)
+    }
+}
+
+/// Copies the multisampled depth into [`RESOURCE_DEPTH`], for the stages that sample it as a texture. The stage
+/// that finishes writing the scene's depth does this, since the passes that read it can run before the colour is
+/// resolved
+pub fn resolve_depth(resources_manager: &ResourcesManager, graph_resources: &GraphResources) {
+    let msaa_target = graph_resources.framebuffer(RESOURCE_MSAA_DEPTH).unwrap();
+    let depth_target = graph_resources.framebuffer(RESOURCE_DEPTH).unwrap();
+    let msaa_fb = resources_manager.borrow_framebuffer(&msaa_target).unwrap();
+    let depth_fb = resources_manager.borrow_framebuffer(&depth_target).unwrap();
+
+    msaa_fb.blit_depth(depth_fb);
+}
+
+/// Resolves the multisampled scene colour into [`RESOURCE_HDR`] once the opaque scene and sky have been drawn, so
+/// the stages that follow, including the compute passes that bind it as an image, only see a single sample per
+/// pixel
+pub struct MsaaResolveStage {}
+
+impl MsaaResolveStage {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl PipelineStage for MsaaResolveStage {
+    fn name(&self) -> StageName {
+        STAGE_MSAA_RESOLVE
+    }
+
+    fn setup(&mut self, pass: &mut PassBuilder, enabled_stages: &[StageName]) {
+        pass.read(RESOURCE_MSAA).write(RESOURCE_HDR);
+    }
+
+    /// Only needs the multisampled scene, so takes no renderables
+    fn init(
+        &mut self,
+        memory_manager: &mut MemoryManager,
+        resources_manager: &mut ResourcesManager,
+        renderer_state: &mut RendererState,
+    ) {
+    }
+
+    fn submit(&mut self, renderable_index: usize) {}
+
+    fn execute(
+        &mut self,
+        memory_manager: &mut MemoryManager,
+        resources_manager: &mut ResourcesManager,
+        renderer_state: &mut RendererState,
+        rasteriser_state: &mut RasteriserState,
+        graph_resources: &GraphResources,
+        renderables: &[MeshInstance],
+    ) {
+        let msaa_target = graph_resources.framebuffer(RESOURCE_MSAA).unwrap();
+        let hdr_target = graph_resources.framebuffer(RESOURCE_HDR).unwrap();
+        let msaa_fb = resources_manager.borrow_framebuffer(&msaa_target).unwrap();
+        let hdr_fb = resources_manager.borrow_framebuffer(&hdr_target).unwrap();
+
+        msaa_fb.blit_colour(hdr_fb);
+    }
+}
@@ -1,35 +1,365 @@
-use super::PipelineStage;
+use super::*;
 use crate::{
-    components::Renderable,
+    components::MeshInstance,
     graphics::{
         self,
-        framebuffer::{Framebuffer, FramebufferAttachment, InternalFormat},
-        shader::Program,
+        buffer::{BufferStorage, BufferType},
+        framebuffer::{
+            FramebufferAttachment, FramebufferAttachmentConfig, FramebufferConfig, InternalFormat,
+        },
+        shader::{Program, ShaderData},
         state::RasteriserState,
-        texture::TextureFilter,
-        AccessModifier, Barriers,
+        texture::{TextureFilter, TextureType},
+        AccessModifier, Barriers, DrawMode,
     },
     memory_manager::memory_manager::MemoryManager,
-    renderer::state::RendererState,
+    platform::rustgl,
+    renderer::{
+        post_effects::{
+            BuiltInEffect, PostEffect, PostEffectStack, PostParameter, PostPassKind, PostShader,
+        },
+        state::RendererState,
+    },
     resource_manager::resource_manager::{FramebufferID, ResourcesManager, ShaderProgramID},
 };
 
+const GROUP_WIDTH: u32 = 16;
+/// Bins of the luminance histogram, which the averaging shader runs a thread for each of
+const HISTOGRAM_BINS: u32 = 256;
+/// The histogram, followed by the adapted luminance
+const LUMINANCE_BUFFER_SIZE: u32 = (HISTOGRAM_BINS + 1) * 4;
+const LUMINANCE_BUFFER_BINDING: u32 = 10;
+/// Target that the post effects alternate with [`RESOURCE_HDR`], each reading the result of the one before
+const RESOURCE_POST: ResourceName = "post";
+
+/// Curve that maps the exposed HDR colour into the displayable range, in the order of the shader's
+/// `TONE_MAPPING_*` defines
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ToneMapping {
+    /// Compresses each channel by `x / (1 + x)`, which never reaches white and washes out bright colours
+    Reinhard,
+    /// Fit of the ACES filmic curve, which has more contrast and saturation
+    Aces,
+    /// Desaturates bright colours towards white, rather than skewing their hue, so is the most neutral
+    AgX,
+    /// Hable's filmic curve from Uncharted 2, with a softer shoulder than ACES
+    Uncharted2,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct AutoExposureSettings {
+    /// Darkest luminance that the histogram covers, in log2, where darker pixels are clamped to it
+    pub min_log_luminance: f32,
+    /// Brightest luminance that the histogram covers, in log2, where brighter pixels are clamped to it
+    pub max_log_luminance: f32,
+    /// How quickly the exposure adapts, per second, when the scene becomes brighter
+    pub speed_up: f32,
+    /// How quickly the exposure adapts, per second, when the scene becomes darker, which is usually slower, as with
+    /// eyes adjusting to the dark
+    pub speed_down: f32,
+}
+
+impl Default for AutoExposureSettings {
+    fn default() -> Self {
+        Self {
+            min_log_luminance: -10.0,
+            max_log_luminance: 4.0,
+            speed_up: 3.0,
+            speed_down: 1.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct PostProcessSettings {
+    pub tone_mapping: ToneMapping,
+    /// Exposure in EVs, where each step doubles the brightness. When the exposure is automatic, this compensates
+    /// for what it adapts to
+    pub exposure: f32,
+    /// Exposes the scene so that its average luminance, from a histogram of the frame, becomes middle grey
+    pub auto_exposure: bool,
+    pub auto_exposure_settings: AutoExposureSettings,
+}
+
+impl Default for PostProcessSettings {
+    fn default() -> Self {
+        Self {
+            tone_mapping: ToneMapping::Aces,
+            exposure: 0.0,
+            auto_exposure: false,
+            auto_exposure_settings: AutoExposureSettings::default(),
+        }
+    }
+}
+
+/// Scale that colours are multiplied by for an exposure in EVs
+pub fn exposure_scale(exposure: f32) -> f32 {
+    exposure.exp2()
+}
+
+/// Fraction of the remaining distance that a value adapting exponentially, at `speed` per second, covers in
+/// `delta_time` seconds, so adaptation takes the same time whatever the frame rate
+pub fn adaptation_rate(speed: f32, delta_time: f32) -> f32 {
+    1.0 - (-speed.max(0.0) * delta_time.max(0.0)).exp()
+}
+
+/// Exposes and tone maps [`RESOURCE_HDR`], applies the enabled post effects in order, then blits the result to the
+/// view's output. <br>
+/// With auto exposure, a histogram of the frame's luminance is built and averaged on the GPU, and the adapted
+/// luminance is kept there between frames, so it never needs to be read back.
 pub struct PostProcessStage {
-    target: FramebufferID,
     shader_id: ShaderProgramID,
+    histogram_shader_id: ShaderProgramID,
+    average_shader_id: ShaderProgramID,
+    luminance_buffer: BufferStorage,
+    /// Frame that the luminance was last adapted in, where it starts again from the frame's average if that wasn't
+    /// the last frame
+    adapted_frame: Option<u64>,
+    /// Indexed by [`BuiltInEffect`]
+    built_in_shader_ids: [ShaderProgramID; BuiltInEffect::ALL.len()],
+    pub settings: PostProcessSettings,
+    pub effects: PostEffectStack,
 }
 
 impl PostProcessStage {
-    pub fn new(target: FramebufferID, resources_manager: &mut ResourcesManager) -> Self {
+    pub fn new(resources_manager: &mut ResourcesManager) -> Self {
         let shader_id = resources_manager.load_shader("res/shaders/post_process_comp.glsl");
+        let histogram_shader_id =
+            resources_manager.load_shader("res/shaders/luminance_histogram.glsl");
+        let average_shader_id = resources_manager.load_shader("res/shaders/luminance_average.glsl");
+
+        // the averaging shader clears the histogram as it reads it, so it only needs clearing once
+        let mut luminance_buffer =
+            BufferStorage::new(BufferType::ShaderStorage, LUMINANCE_BUFFER_SIZE, 1);
+        luminance_buffer.set_data_slice(&[0u32; HISTOGRAM_BINS as usize + 1], 0);
+
+        let built_in_shader_ids =
+            BuiltInEffect::ALL.map(|effect| resources_manager.load_shader(effect.shader_path()));
+
+        Self {
+            shader_id,
+            histogram_shader_id,
+            average_shader_id,
+            luminance_buffer,
+            adapted_frame: None,
+            built_in_shader_ids,
+            settings: PostProcessSettings::default(),
+            effects: PostEffectStack::new(),
+        }
+    }
+
+    fn adapt_luminance(
+        &mut self,
+        resources_manager: &mut ResourcesManager,
+        renderer_state: &mut RendererState,
+        target: &FramebufferID,
+    ) {
+        let auto_exposure = self.settings.auto_exposure_settings;
+        let log_luminance_range =
+            (auto_exposure.max_log_luminance - auto_exposure.min_log_luminance).max(0.0001);
+
+        self.luminance_buffer
+            .bind_buffer_range(LUMINANCE_BUFFER_BINDING, 0, LUMINANCE_BUFFER_SIZE);
+
+        // histogram of the frame's luminance
+        renderer_state.set_shader_program(self.histogram_shader_id, resources_manager);
+        let program = resources_manager
+            .borrow_mut_shader_program(&self.histogram_shader_id)
+            .unwrap();
+        program.set_uniform(
+            "minLogLuminance".to_string(),
+            ShaderData::Float1(auto_exposure.min_log_luminance),
+        );
+        program.set_uniform(
+            "inverseLogLuminanceRange".to_string(),
+            ShaderData::Float1(1.0 / log_luminance_range),
+        );
+
+        let fb = resources_manager.borrow_framebuffer(target).unwrap();
+        let (width, height) = (fb.config.width, fb.config.height);
+        if let FramebufferAttachment::Texture(texture) = &fb.colour_handle {
+            texture.bind_image_unit(0, 0, 0, AccessModifier::ReadOnly, InternalFormat::RGBA16F)
+        }
+
+        Program::dispatch_compute(width.div_ceil(GROUP_WIDTH), height.div_ceil(GROUP_WIDTH), 1);
+        graphics::memory_barrier(Barriers::ShaderStorage as u32);
+
+        // averaged, then the adapted luminance moves towards it
+        let frame = renderer_state.frame;
+        let reset = !self
+            .adapted_frame
+            .is_some_and(|adapted| adapted + 1 == frame);
+        let delta_time = renderer_state.delta_time;
+
+        renderer_state.set_shader_program(self.average_shader_id, resources_manager);
+        let program = resources_manager
+            .borrow_mut_shader_program(&self.average_shader_id)
+            .unwrap();
+        program.set_uniform(
+            "minLogLuminance".to_string(),
+            ShaderData::Float1(auto_exposure.min_log_luminance),
+        );
+        program.set_uniform(
+            "logLuminanceRange".to_string(),
+            ShaderData::Float1(log_luminance_range),
+        );
+        program.set_uniform("pixelCount".to_string(), ShaderData::Uint1(width * height));
+        program.set_uniform(
+            "adaptation".to_string(),
+            ShaderData::Float2(
+                adaptation_rate(auto_exposure.speed_up, delta_time),
+                adaptation_rate(auto_exposure.speed_down, delta_time),
+            ),
+        );
+        program.set_uniform(
+            "resetAdaptation".to_string(),
+            ShaderData::Int1(reset as i32),
+        );
 
-        Self { target, shader_id }
+        Program::dispatch_compute(1, 1, 1);
+        graphics::memory_barrier(Barriers::ShaderStorage as u32);
+
+        self.adapted_frame = Some(frame);
+    }
+
+    /// Applies each enabled effect, alternating between the two targets, and returns the one that holds the result
+    fn apply_effects(
+        &self,
+        resources_manager: &mut ResourcesManager,
+        renderer_state: &mut RendererState,
+        rasteriser_state: &mut RasteriserState,
+        hdr_target: FramebufferID,
+        post_target: FramebufferID,
+    ) -> FramebufferID {
+        let (mut source, mut destination) = (hdr_target, post_target);
+
+        for effect in self.effects.enabled() {
+            let (shader_id, kind) = match effect.shader() {
+                PostShader::BuiltIn(built_in) => (
+                    self.built_in_shader_ids[built_in as usize],
+                    PostPassKind::Compute,
+                ),
+                PostShader::Custom { shader_id, kind } => (shader_id, kind),
+            };
+
+            renderer_state.set_shader_program(shader_id, resources_manager);
+            Self::set_parameters(effect, resources_manager, &shader_id, renderer_state.frame);
+
+            let source_fb = resources_manager.borrow_framebuffer(&source).unwrap();
+            if let FramebufferAttachment::Texture(texture) = &source_fb.colour_handle {
+                unsafe {
+                    rustgl::active_texture(rustgl::TEXTURE0);
+                }
+                texture.bind();
+            }
+
+            match kind {
+                PostPassKind::Compute => {
+                    let fb = resources_manager.borrow_framebuffer(&destination).unwrap();
+                    if let FramebufferAttachment::Texture(texture) = &fb.colour_handle {
+                        texture.bind_image_unit(
+                            0,
+                            0,
+                            0,
+                            AccessModifier::WriteOnly,
+                            InternalFormat::RGBA16F,
+                        );
+                    }
+
+                    Program::dispatch_compute(
+                        fb.config.width.div_ceil(GROUP_WIDTH),
+                        fb.config.height.div_ceil(GROUP_WIDTH),
+                        1,
+                    );
+                    graphics::memory_barrier(
+                        Barriers::ShaderImageAccess as u32 | Barriers::TextureFetch as u32,
+                    );
+                }
+                PostPassKind::Fragment => {
+                    renderer_state.set_framebuffer(Some(&destination), resources_manager);
+                    rasteriser_state.set(RasteriserState {
+                        blend: false,
+                        culling: false,
+                        depth: false,
+                        depth_mask: false,
+                        ..Default::default()
+                    });
+                    graphics::submit_draw_arrays(DrawMode::Triangles, 0, 3);
+                }
+            }
+
+            std::mem::swap(&mut source, &mut destination);
+        }
+
+        unsafe {
+            rustgl::active_texture(rustgl::TEXTURE0);
+        }
+
+        source
+    }
+
+    /// Sets the effect's parameters, and the frame, as uniforms of its shader, binding its textures to the units
+    /// after the previous result
+    fn set_parameters(
+        effect: &PostEffect,
+        resources_manager: &mut ResourcesManager,
+        shader_id: &ShaderProgramID,
+        frame: u64,
+    ) {
+        let mut texture_unit = 1;
+        let mut uniforms = Vec::with_capacity(effect.parameters().len() + 1);
+
+        for (name, parameter) in effect.parameters() {
+            let data = match *parameter {
+                PostParameter::Float(x) => ShaderData::Float1(x),
+                PostParameter::Vec2(v) => ShaderData::Float2(v.x, v.y),
+                PostParameter::Vec3(v) => ShaderData::Float3(v.x, v.y, v.z),
+                PostParameter::Vec4(v) => ShaderData::Float4(v.x, v.y, v.z, v.w),
+                PostParameter::Int(x) => ShaderData::Int1(x),
+                PostParameter::Bool(b) => ShaderData::Int1(b as i32),
+                PostParameter::Texture(texture_id) => {
+                    if let Some(texture) = resources_manager.borrow_texture(&texture_id) {
+                        unsafe {
+                            rustgl::active_texture(rustgl::TEXTURE0 + texture_unit);
+                        }
+                        texture.bind();
+                    }
+                    texture_unit += 1;
+                    ShaderData::Int1(texture_unit as i32 - 1)
+                }
+            };
+            uniforms.push((name.to_string(), data));
+        }
+        uniforms.push(("frame".to_string(), ShaderData::Uint1(frame as u32)));
+
+        let program = resources_manager
+            .borrow_mut_shader_program(shader_id)
+            .unwrap();
+        for (name, data) in uniforms {
+            program.set_uniform(name, data);
+        }
     }
 }
 
 impl PipelineStage for PostProcessStage {
-    fn get_target(&self) -> FramebufferID {
-        self.target
+    fn name(&self) -> StageName {
+        STAGE_POST_PROCESS
+    }
+
+    fn setup(&mut self, pass: &mut PassBuilder, enabled_stages: &[StageName]) {
+        let config = FramebufferConfig {
+            colour: FramebufferAttachmentConfig::Texture {
+                target: TextureType::T2D,
+                internal_format: InternalFormat::RGBA16F,
+                layers: 1,
+                levels: 1,
+            },
+            ..Default::default()
+        };
+
+        pass.read(RESOURCE_HDR)
+            .create_framebuffer(RESOURCE_POST, config, true)
+            .write(RESOURCE_BACKBUFFER);
     }
 
     fn init(
@@ -48,31 +378,79 @@ impl PipelineStage for PostProcessStage {
         resources_manager: &mut ResourcesManager,
         renderer_state: &mut RendererState,
         rasteriser_state: &mut RasteriserState,
-        renderables: &[Renderable],
+        graph_resources: &GraphResources,
+        renderables: &[MeshInstance],
     ) {
-        let fb = resources_manager.borrow_framebuffer(&self.target).unwrap();
+        let target = graph_resources.framebuffer(RESOURCE_HDR).unwrap();
 
-        let blocks_w = (fb.config.width + 15) / 16;
-        let blocks_h = (fb.config.height + 15) / 16;
+        // the other views are exposed with what the primary view adapted to
+        if self.settings.auto_exposure && renderer_state.primary_view {
+            self.adapt_luminance(resources_manager, renderer_state, &target);
+        }
 
         renderer_state.set_shader_program(self.shader_id, resources_manager);
+        let program = resources_manager
+            .borrow_mut_shader_program(&self.shader_id)
+            .unwrap();
+        program.set_uniform(
+            "toneMapping".to_string(),
+            ShaderData::Int1(self.settings.tone_mapping as i32),
+        );
+        program.set_uniform(
+            "exposure".to_string(),
+            ShaderData::Float1(exposure_scale(self.settings.exposure)),
+        );
+        program.set_uniform(
+            "autoExposure".to_string(),
+            ShaderData::Int1(self.settings.auto_exposure as i32),
+        );
+        self.luminance_buffer
+            .bind_buffer_range(LUMINANCE_BUFFER_BINDING, 0, LUMINANCE_BUFFER_SIZE);
+
+        let fb = resources_manager.borrow_framebuffer(&target).unwrap();
 
         if let FramebufferAttachment::Texture(texture) = &fb.colour_handle {
             texture.bind_image_unit(0, 0, 0, AccessModifier::ReadWrite, InternalFormat::RGBA16F)
         }
 
-        Program::dispatch_compute(blocks_w + 1, blocks_h + 1, 1);
-        graphics::memory_barrier(Barriers::ShaderImageAccess as u32);
-        fb.blit_to_default_framebuffer(
-            0,
-            0,
-            fb.config.width,
-            fb.config.height,
-            0,
-            0,
-            fb.config.width,
-            fb.config.height,
-            TextureFilter::Nearest,
+        Program::dispatch_compute(
+            fb.config.width.div_ceil(GROUP_WIDTH),
+            fb.config.height.div_ceil(GROUP_WIDTH),
+            1,
+        );
+        graphics::memory_barrier(
+            Barriers::ShaderImageAccess as u32 | Barriers::TextureFetch as u32,
+        );
+
+        let post_target = graph_resources.framebuffer(RESOURCE_POST).unwrap();
+        let output = self.apply_effects(
+            resources_manager,
+            renderer_state,
+            rasteriser_state,
+            target,
+            post_target,
         );
+
+        let fb = resources_manager.borrow_framebuffer(&output).unwrap();
+        let view = renderer_state.output;
+        let (x1, y1) = (view.x + view.width, view.y + view.height);
+        let (width, height) = (fb.config.width, fb.config.height);
+        let filter = if (width, height) == (view.width, view.height) {
+            TextureFilter::Nearest
+        } else {
+            TextureFilter::Linear
+        };
+
+        match view
+            .framebuffer
+            .and_then(|target| resources_manager.borrow_framebuffer(&target))
+        {
+            Some(target) => fb.blit_to_framebuffer(
+                target, 0, 0, width, height, view.x, view.y, x1, y1, filter,
+            ),
+            None => {
+                fb.blit_to_default_framebuffer(0, 0, width, height, view.x, view.y, x1, y1, filter)
+            }
+        }
     }
 }
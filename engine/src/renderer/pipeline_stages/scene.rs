@@ -1,38 +1,109 @@
-use super::PipelineStage;
+use super::*;
 use crate::{
-    components::Renderable,
-    graphics::{self, state::RasteriserState, DataType, DrawMode},
+    components::MeshInstance,
+    graphics::{
+        self,
+        state::{Comparison, RasteriserState},
+        DataType, DrawMode,
+    },
     math::Mat4f,
     memory_manager::memory_manager::{
-        DrawElementsIndirectCommand, InstanceData, MemoryManager, DRAW_COMMAND_SIZE,
+        InstanceData, MemoryManager, DRAW_COMMAND_SIZE,
+    },
+    renderer::{
+        command::{upload_draw_data, DrawCommands, SortKeyLayout},
+        culling::{cull_renderables, Frustum},
+        gpu_culling::GpuCuller,
+        graph::ResourceName,
+        occlusion::cull_occluded,
+        state::RendererState,
     },
-    renderer::{command::DrawCommands, state::RendererState},
     resource_manager::resource_manager::{
-        FramebufferID, MaterialID, MeshID, ResourceIDTrait, ResourcesManager, ShaderProgramID,
+        MaterialID, MeshID, ResourceIDTrait, ResourcesManager, ShaderProgramID,
     },
 };
 
 pub struct SceneStage {
-    target: FramebufferID,
     renderable_indices: Vec<usize>,
     command_queue: DrawCommands,
     pending_indirect_command_count: u32,
+    /// When the depth stage has already filled the depth buffer, only fragments that match it are shaded
+    depth_prepass: bool,
+    /// Renderables are culled by a compute shader, which also writes the draw commands, rather than on the CPU
+    pub gpu_culling: bool,
+    gpu_culler: GpuCuller,
+    /// Renderables hidden in the previous frames' depth are culled, when the Hi-Z stage is enabled
+    occlusion_culling: bool,
+    /// Either [`RESOURCE_DEPTH`], or the multisampled depth when MSAA is enabled
+    depth_target: ResourceName,
+}
+
+/// Declares what the opaque scene reads and writes, returning the depth resource that it is drawn against. When the
+/// depth stage is enabled the depth buffer has already been filled, so it is only read
+pub fn scene_pass(pass: &mut PassBuilder, enabled_stages: &[StageName]) -> ResourceName {
+    let (colour_target, depth_target) = msaa::scene_targets(enabled_stages);
+
+    if enabled_stages.contains(&STAGE_SHADOW) {
+        pass.read(RESOURCE_SHADOW_MAPS);
+    }
+    pass.render_target(colour_target);
+
+    if enabled_stages.contains(&STAGE_DEPTH) {
+        pass.read(depth_target);
+
+        // ambient occlusion needs the depth buffer before the scene is lit, so relies on the pre-pass
+        if enabled_stages.contains(&STAGE_AO) {
+            pass.read(RESOURCE_AO);
+        }
+    } else {
+        pass.write(depth_target).write(RESOURCE_DEPTH);
+    }
+
+    depth_target
+}
+
+/// Translucent geometry is blended by the transparent stage, so opaque geometry overwrites what is behind it. After
+/// the depth pre-pass, only the nearest fragments match the depth buffer, which is left as it is
+pub fn scene_rasteriser_state(depth_prepass: bool) -> RasteriserState {
+    if depth_prepass {
+        RasteriserState {
+            depth_func: Comparison::Equal,
+            depth_mask: false,
+            blend: false,
+            ..Default::default()
+        }
+    } else {
+        RasteriserState {
+            blend: false,
+            ..Default::default()
+        }
+    }
 }
 
 impl SceneStage {
-    pub fn new(target: FramebufferID) -> Self {
+    pub fn new(resources_manager: &mut ResourcesManager) -> Self {
         Self {
-            target,
             renderable_indices: Vec::new(),
-            command_queue: DrawCommands::new(hash),
+            command_queue: DrawCommands::new(SortKeyLayout::opaque()),
             pending_indirect_command_count: 0,
+            depth_prepass: false,
+            gpu_culling: false,
+            gpu_culler: GpuCuller::new(resources_manager),
+            occlusion_culling: false,
+            depth_target: RESOURCE_DEPTH,
         }
     }
 }
 
 impl PipelineStage for SceneStage {
-    fn get_target(&self) -> FramebufferID {
-        self.target
+    fn name(&self) -> StageName {
+        STAGE_SCENE
+    }
+
+    fn setup(&mut self, pass: &mut PassBuilder, enabled_stages: &[StageName]) {
+        self.depth_prepass = enabled_stages.contains(&STAGE_DEPTH);
+        self.occlusion_culling = enabled_stages.contains(&STAGE_HIZ);
+        self.depth_target = scene_pass(pass, enabled_stages);
     }
 
     fn init(
@@ -43,6 +114,10 @@ impl PipelineStage for SceneStage {
     ) {
     }
 
+    fn accepts(&self, pipeline_stages: RenderMask) -> bool {
+        pipeline_stages & RENDER_SCENE == RENDER_SCENE
+    }
+
     fn submit(&mut self, renderable_index: usize) {
         self.renderable_indices.push(renderable_index);
     }
@@ -53,25 +128,58 @@ impl PipelineStage for SceneStage {
         resources_manager: &mut ResourcesManager,
         renderer_state: &mut RendererState,
         rasteriser_state: &mut RasteriserState,
-        renderables: &[Renderable],
+        graph_resources: &GraphResources,
+        renderables: &[MeshInstance],
     ) {
-        let fb = resources_manager.borrow_framebuffer(&self.target).unwrap();
+        let frustum = Frustum::from_matrix(
+            &(renderer_state.projection_transform * renderer_state.view_transform),
+        );
+        if !self.gpu_culling {
+            cull_renderables(
+                &[frustum],
+                renderables,
+                &resources_manager.mesh_bounds_manager,
+                &mut self.renderable_indices,
+            );
 
-        // fb.clear_color(0.4, 0.5, 0.9, 1.0);
-        fb.clear_depth(1.0);
+            if let Some(pyramid) = renderer_state
+                .fresh_depth_pyramid()
+                .filter(|_| self.occlusion_culling)
+            {
+                cull_occluded(
+                    pyramid,
+                    renderables,
+                    &resources_manager.mesh_bounds_manager,
+                    &mut self.renderable_indices,
+                );
+            }
+        }
+
+        rasteriser_state.set(scene_rasteriser_state(self.depth_prepass));
+        if !self.depth_prepass {
+            let target = graph_resources.framebuffer(self.depth_target).unwrap();
+            let fb = resources_manager.borrow_framebuffer(&target).unwrap();
+
+            // fb.clear_color(0.4, 0.5, 0.9, 1.0);
+            fb.clear_depth(1.0);
+        }
 
-        self.command_queue
-            .update_keys(renderables, &self.renderable_indices);
+        self.command_queue.update_keys(
+            renderables,
+            &self.renderable_indices,
+            &renderer_state.view_transform,
+        );
         self.command_queue.sort_indices();
 
         let mut instance_count = 0;
 
-        let r = Renderable {
+        let r = MeshInstance {
             mesh_id: MeshID::new(0xFFFF),
             material_id: MaterialID::new(0xFFFF),
             shader_id: ShaderProgramID::new(0xFFFF),
             transform: Mat4f::identity(),
             pipeline_stages: 0,
+            lod_fade: 0.0,
         };
 
         for i in 0..self.command_queue.indices.len() {
@@ -88,13 +196,7 @@ impl PipelineStage for SceneStage {
                 || renderable.mesh_id != next_renderable.mesh_id
             {
                 memory_manager.reserve_instance_space(instance_count);
-                upload_draw_data(
-                    memory_manager,
-                    resources_manager,
-                    renderable,
-                    instance_count,
-                );
-                self.pending_indirect_command_count += 1;
+                let base_instance = memory_manager.get_instance_index();
 
                 for instance_index in
                     self.command_queue.indices[(i - (instance_count - 1) as usize)..=i].iter()
@@ -104,54 +206,69 @@ impl PipelineStage for SceneStage {
                     memory_manager.push_instance_data(&InstanceData {
                         material_index: renderable.material_id.index(),
                         transform: renderable.transform,
+                        lod_fade: renderable.lod_fade,
                     });
                 }
 
+                if self.gpu_culling {
+                    self.gpu_culler.push_batch(
+                        memory_manager,
+                        resources_manager,
+                        &renderable.mesh_id,
+                        instance_count,
+                        base_instance,
+                    );
+                } else {
+                    upload_draw_data(
+                        memory_manager,
+                        resources_manager,
+                        &renderable.mesh_id,
+                        instance_count,
+                        base_instance,
+                    );
+                    self.pending_indirect_command_count += 1;
+                }
+
                 instance_count = 0;
             }
             if renderable.shader_id != next_renderable.shader_id {
-                renderer_state.set_shader_program(renderable.shader_id, resources_manager);
-                graphics::submit_draw_call(
-                    DrawMode::Triangles,
-                    DataType::Uint32,
-                    (memory_manager.get_indirect_command_index()
-                        - self.pending_indirect_command_count)
-                        * DRAW_COMMAND_SIZE,
-                    self.pending_indirect_command_count,
-                );
-                self.pending_indirect_command_count = 0;
+                if self.gpu_culling {
+                    // drawn once every group has been culled
+                    self.gpu_culler.end_group(renderable.shader_id);
+                } else {
+                    renderer_state.set_shader_program(renderable.shader_id, resources_manager);
+                    graphics::submit_draw_call(
+                        DrawMode::Triangles,
+                        DataType::Uint32,
+                        (memory_manager.get_indirect_command_index()
+                            - self.pending_indirect_command_count)
+                            * DRAW_COMMAND_SIZE,
+                        self.pending_indirect_command_count,
+                    );
+                    self.pending_indirect_command_count = 0;
+                }
             }
         }
 
-        self.renderable_indices.clear();
-    }
-}
-
-fn hash(r: &Renderable) -> u32 {
-    let shader_index = r.shader_id.index();
-    let mesh_index = r.mesh_id.index();
+        if self.gpu_culling {
+            let hiz = renderer_state
+                .previous_hiz()
+                .filter(|_| self.occlusion_culling);
+            self.gpu_culler.dispatch(
+                &frustum,
+                hiz,
+                memory_manager,
+                resources_manager,
+                renderer_state,
+            );
+            self.gpu_culler.draw(resources_manager, renderer_state);
+        }
 
-    (shader_index << 16) | (mesh_index & 0x00FF)
-}
+        // without the pre-pass, the multisampled depth is only complete once the scene has been drawn
+        if !self.depth_prepass && self.depth_target != RESOURCE_DEPTH {
+            msaa::resolve_depth(resources_manager, graph_resources);
+        }
 
-fn upload_draw_data(
-    memory_manager: &mut MemoryManager,
-    resources_manager: &mut ResourcesManager,
-    renderable: &Renderable,
-    instance_count: u32,
-) {
-    let mesh = resources_manager.borrow_mesh(&renderable.mesh_id).unwrap();
-
-    memory_manager.reserve_vertex_space(mesh.vertices.len() as u32);
-    memory_manager.reserve_index_space(mesh.indices.len() as u32);
-    let indirect_command = DrawElementsIndirectCommand {
-        count: mesh.indices.len() as u32,
-        instance_count,
-        first_index: memory_manager.get_index_index(),
-        base_vertex: memory_manager.get_vertex_index(),
-        base_instance: memory_manager.get_instance_index(),
-    };
-    memory_manager.push_indirect_command(indirect_command);
-    memory_manager.push_vertex_slice(&mesh.vertices);
-    memory_manager.push_index_slice(&mesh.indices);
+        self.renderable_indices.clear();
+    }
 }
@@ -1,36 +1,64 @@
-use super::PipelineStage;
+use super::*;
 use crate::{
-    components::Renderable,
+    components::MeshInstance,
     graphics::{
         self,
         framebuffer::{
             FramebufferAttachment, FramebufferAttachmentConfig, FramebufferConfig, InternalFormat,
         },
         state::{Orientation, RasteriserState},
-        texture::TextureType,
+        texture::{Texture, TextureType},
         DataType, DrawMode,
     },
-    math::Mat4f,
+    math::{Mat4f, Vec2u, Vec4f},
     memory_manager::{
         memory_manager::{
-            DrawElementsIndirectCommand, InstanceData, MemoryManager, DRAW_COMMAND_SIZE,
+            InstanceData, MemoryManager, DRAW_COMMAND_SIZE,
         },
-        uniform_layouts::{GeneralPurposeIndexStorageBuffer, MAX_POINT_LIGHTS, MAX_SPOT_LIGHTS},
+        uniform_layouts::{
+            GeneralPurposeIndexStorageBuffer, ShadowMap, MAX_CASCADES, MAX_POINT_LIGHT_SHADOWS,
+            MAX_SPOT_LIGHT_SHADOWS,
+        },
+    },
+    platform::rustgl,
+    renderer::{
+        cascades::{calculate_cascades, CascadeSettings},
+        command::{upload_draw_data, DrawCommands, SortKeyField, SortKeyLayout},
+        culling::{cull_renderables, Frustum},
+        shadow_atlas::{
+            light_range, screen_coverage, tile_size_for_coverage, AtlasTile, ShadowAtlasAllocator,
+            ShadowAtlasSettings,
+        },
+        state::RendererState,
     },
-    renderer::{command::DrawCommands, state::RendererState},
     resource_manager::resource_manager::{
         FramebufferID, ResourceIDTrait, ResourcesManager, ShaderProgramID, MeshID, MaterialID,
     },
 };
 
+/// Width and height of each of the directional light's cascades
+const CASCADE_RESOLUTION: u32 = 2048;
+
+/// Width and height of the atlas that spot and point lights are given tiles of
+const ATLAS_RESOLUTION: u32 = 4096;
+
+/// Smallest tile that a light falls back to when the atlas is too full for the size it asked for
+const ATLAS_MIN_TILE_SIZE: u32 = 64;
+
 pub struct ShadowStage {
-    target: FramebufferID,
-    dir_shadow_shader_id: ShaderProgramID,
-    omni_shadow_shader_id: ShaderProgramID,
+    pub cascade_settings: CascadeSettings,
+    pub atlas_settings: ShadowAtlasSettings,
+
+    atlas_shadow_shader_id: ShaderProgramID,
+    cascade_shadow_shader_id: ShaderProgramID,
 
-    point_shadows: [FramebufferID; MAX_POINT_LIGHTS],
-    spot_shadows: [FramebufferID; MAX_SPOT_LIGHTS],
+    atlas: FramebufferID,
+    atlas_allocator: ShadowAtlasAllocator,
+    atlas_shadow_map: ShadowMap,
     directional_shadow: FramebufferID,
+    directional_shadow_map: ShadowMap,
+    /// The shader index and tile of each spot light and point light face that is drawn this frame
+    atlas_tiles: Vec<(u32, AtlasTile)>,
 
     renderable_indices: Vec<usize>,
     command_queue: DrawCommands,
@@ -39,106 +67,123 @@ pub struct ShadowStage {
 
 impl ShadowStage {
     pub fn new(
-        target: FramebufferID,
         memory_manager: &mut MemoryManager,
         resources_manager: &mut ResourcesManager,
         renderer_state: &mut RendererState,
     ) -> Self {
-        let dir_config = FramebufferConfig {
+        // an array of a single layer, so that the atlas can share the filtering code of the cascades
+        let atlas_config = FramebufferConfig {
             depth: FramebufferAttachmentConfig::Texture {
-                target: TextureType::T2D,
+                target: TextureType::T2DArray,
                 internal_format: InternalFormat::Depth32F,
                 layers: 1,
                 levels: 1,
             },
-            width: 4096,
-            height: 4096,
+            width: ATLAS_RESOLUTION,
+            height: ATLAS_RESOLUTION,
             ..Default::default()
         };
 
-        let omni_config = FramebufferConfig {
+        let cascade_config = FramebufferConfig {
             depth: FramebufferAttachmentConfig::Texture {
                 target: TextureType::T2DArray,
                 internal_format: InternalFormat::Depth32F,
-                layers: 6,
+                layers: MAX_CASCADES as u32,
                 levels: 1,
             },
-            width: 512,
-            height: 512,
+            width: CASCADE_RESOLUTION,
+            height: CASCADE_RESOLUTION,
             ..Default::default()
         };
 
+        let depth_sampler = unsafe {
+            let sampler = rustgl::create_sampler().unwrap();
+            rustgl::sampler_parameter_i32(
+                sampler,
+                rustgl::TEXTURE_COMPARE_MODE,
+                rustgl::NONE as i32,
+            );
+            rustgl::sampler_parameter_i32(
+                sampler,
+                rustgl::TEXTURE_MIN_FILTER,
+                rustgl::NEAREST as i32,
+            );
+            rustgl::sampler_parameter_i32(
+                sampler,
+                rustgl::TEXTURE_MAG_FILTER,
+                rustgl::NEAREST as i32,
+            );
+            for wrap in [
+                rustgl::TEXTURE_WRAP_S,
+                rustgl::TEXTURE_WRAP_T,
+                rustgl::TEXTURE_WRAP_R,
+            ] {
+                rustgl::sampler_parameter_i32(sampler, wrap, rustgl::CLAMP_TO_EDGE as i32);
+            }
+            sampler
+        };
+
         // set light projections
-        renderer_state.light_ortho_projection = Mat4f::orthographic(80.0, 1.0, 80.0);
         renderer_state.light_persp_projection =
             Mat4f::perspective(1.0, 90f32.to_radians(), 1.0, 20.0);
 
-        // init and upload point light shadow maps
-        let mut point_shadows = [FramebufferID::new(0); MAX_POINT_LIGHTS];
-
-        for i in 0..point_shadows.len() {
-            point_shadows[i] = resources_manager.load_framebuffer(&omni_config, false);
-        }
-
-        for (i, shadow_map_id) in point_shadows.iter().enumerate() {
-            let shadow_map = resources_manager.borrow_mut_framebuffer(shadow_map_id).unwrap();
-
-            if let FramebufferAttachment::Texture(texture) = &mut shadow_map.depth_handle {
-                texture.make_texture_resident();
-                memory_manager.set_point_shadow_map(texture.get_shader_texture_handle(), i as u32);
-            };
-        }
-
-        // init and upload spot light shadow maps
-        let mut spot_shadows = [FramebufferID::new(0); MAX_SPOT_LIGHTS];
-
-        for i in 0..spot_shadows.len() {
-            spot_shadows[i] = resources_manager.load_framebuffer(&dir_config, false);
-        }
-
-        for (i, shadow_map_id) in spot_shadows.iter().enumerate() {
-            let shadow_map = resources_manager.borrow_mut_framebuffer(shadow_map_id).unwrap();
-
-            if let FramebufferAttachment::Texture(texture) = &mut shadow_map.depth_handle {
-                texture.make_texture_resident();
-                memory_manager.set_spot_shadow_map(texture.get_shader_texture_handle(), i as u32);
-            };
-        }
+        // the handles don't change, but are uploaded each frame alongside the tiles, as they share a buffer
+        let atlas = resources_manager.load_framebuffer(&atlas_config, false);
+        let atlas_shadow_map = match &mut resources_manager
+            .borrow_mut_framebuffer(&atlas)
+            .unwrap()
+            .depth_handle
+        {
+            FramebufferAttachment::Texture(texture) => shadow_map_handles(texture, depth_sampler),
+            _ => unreachable!(),
+        };
 
-        // init and upload directional light shadow map
-        let directional_shadow = resources_manager.load_framebuffer(&dir_config, false);
-        let shadow_map = resources_manager
+        let directional_shadow = resources_manager.load_framebuffer(&cascade_config, false);
+        let directional_shadow_map = match &mut resources_manager
             .borrow_mut_framebuffer(&directional_shadow)
-            .unwrap();
-
-        if let FramebufferAttachment::Texture(texture) = &mut shadow_map.depth_handle {
-            texture.make_texture_resident();
-            memory_manager.set_directional_shadow_map(texture.get_shader_texture_handle());
+            .unwrap()
+            .depth_handle
+        {
+            FramebufferAttachment::Texture(texture) => shadow_map_handles(texture, depth_sampler),
+            _ => unreachable!(),
         };
 
-        let dir_shadow_shader_id = resources_manager.load_shader("res/shaders/dir_shadow_map.glsl");
-        let omni_shadow_shader_id =
-            resources_manager.load_shader("res/shaders/cube_shadow_map.glsl");
+        let atlas_shadow_shader_id =
+            resources_manager.load_shader("res/shaders/atlas_shadow_map.glsl");
+        let cascade_shadow_shader_id =
+            resources_manager.load_shader("res/shaders/cascade_shadow_map.glsl");
 
         Self {
-            target,
-            dir_shadow_shader_id,
-            omni_shadow_shader_id,
+            cascade_settings: CascadeSettings::default(),
+            atlas_settings: ShadowAtlasSettings::default(),
+
+            atlas_shadow_shader_id,
+            cascade_shadow_shader_id,
 
-            point_shadows,
-            spot_shadows,
+            atlas,
+            atlas_allocator: ShadowAtlasAllocator::new(ATLAS_RESOLUTION, ATLAS_MIN_TILE_SIZE),
+            atlas_shadow_map,
             directional_shadow,
+            directional_shadow_map,
+            atlas_tiles: Vec::new(),
 
             renderable_indices: Vec::new(),
-            command_queue: DrawCommands::new(hash),
+            // rendered from each light rather than the camera, so the camera depth would say nothing about the order
+            command_queue: DrawCommands::new(
+                SortKeyLayout::new(&[(SortKeyField::Mesh, 22)]).unwrap(),
+            ),
             pending_indirect_command_count: 0,
         }
     }
 }
 
 impl PipelineStage for ShadowStage {
-    fn get_target(&self) -> FramebufferID {
-        self.target
+    fn name(&self) -> StageName {
+        STAGE_SHADOW
+    }
+
+    fn setup(&mut self, pass: &mut PassBuilder, enabled_stages: &[StageName]) {
+        pass.write(RESOURCE_SHADOW_MAPS);
     }
 
     fn init(
@@ -149,6 +194,10 @@ impl PipelineStage for ShadowStage {
     ) {
     }
 
+    fn accepts(&self, pipeline_stages: RenderMask) -> bool {
+        pipeline_stages & RENDER_SHADOW == RENDER_SHADOW
+    }
+
     fn submit(&mut self, renderable_index: usize) {
         self.renderable_indices.push(renderable_index);
     }
@@ -159,25 +208,74 @@ impl PipelineStage for ShadowStage {
         resources_manager: &mut ResourcesManager,
         renderer_state: &mut RendererState,
         rasteriser_state: &mut RasteriserState,
-        renderables: &[Renderable],
+        graph_resources: &GraphResources,
+        renderables: &[MeshInstance],
     ) {
         rasteriser_state.set(RasteriserState {
             cull_face: Orientation::Front,
             ..Default::default()
         });
 
-        self.command_queue
-            .update_keys(renderables, &self.renderable_indices);
+        // the cascades follow the camera, so they are refitted every frame, though not to its jitter, which would
+        // make them shimmer while the camera is still
+        if let Some(light) = renderer_state.directional_light.as_mut() {
+            let cascades = calculate_cascades(
+                &renderer_state.unjittered_projection_transform,
+                &renderer_state.view_transform,
+                light.direction,
+                CASCADE_RESOLUTION,
+                &self.cascade_settings,
+            );
+
+            light.cascade_views = cascades.views;
+            light.cascade_splits = Vec4f::from_array(cascades.splits);
+            light.cascade_count = cascades.count as u32;
+            light.cascade_blend = self.cascade_settings.blend;
+            light.cascade_debug = self.cascade_settings.debug as u32;
+
+            memory_manager.set_directional_light_data(*light);
+        }
+
+        self.allocate_atlas_tiles(memory_manager, renderer_state);
+        memory_manager.set_shadow_atlas(self.atlas_shadow_map);
+        memory_manager.set_directional_shadow_map(self.directional_shadow_map);
+
+        // keep anything that could cast a shadow into the view of at least one light
+        let mut light_frustums = Vec::new();
+        for (shader_index, _) in self.atlas_tiles.iter() {
+            let view = light_view(renderer_state, *shader_index);
+            light_frustums.push(Frustum::from_matrix(&view));
+        }
+        if let Some(light) = renderer_state.directional_light {
+            light_frustums.extend(
+                light.cascade_views[..light.cascade_count as usize]
+                    .iter()
+                    .map(Frustum::from_matrix),
+            );
+        }
+        cull_renderables(
+            &light_frustums,
+            renderables,
+            &resources_manager.mesh_bounds_manager,
+            &mut self.renderable_indices,
+        );
+
+        self.command_queue.update_keys(
+            renderables,
+            &self.renderable_indices,
+            &renderer_state.view_transform,
+        );
         self.command_queue.sort_indices();
 
         let mut instance_count = 0;
 
-        let r = Renderable {
+        let r = MeshInstance {
             mesh_id: MeshID::new(0xFFFF),
             material_id: MaterialID::new(0xFFFF),
             shader_id: ShaderProgramID::new(0xFFFF),
             transform: Mat4f::identity(),
             pipeline_stages: 0,
+            lod_fade: 0.0,
         };
 
         for i in 0..self.command_queue.indices.len() {
@@ -192,11 +290,13 @@ impl PipelineStage for ShadowStage {
 
             if renderable.mesh_id != next_renderable.mesh_id {
                 memory_manager.reserve_instance_space(instance_count);
+                let base_instance = memory_manager.get_instance_index();
                 upload_draw_data(
                     memory_manager,
                     resources_manager,
-                    renderable,
+                    &renderable.mesh_id,
                     instance_count,
+                    base_instance,
                 );
                 self.pending_indirect_command_count += 1;
 
@@ -208,6 +308,7 @@ impl PipelineStage for ShadowStage {
                     memory_manager.push_instance_data(&InstanceData {
                         material_index: renderable.material_id.index(),
                         transform: renderable.transform,
+                        lod_fade: renderable.lod_fade,
                     });
                 }
 
@@ -215,51 +316,25 @@ impl PipelineStage for ShadowStage {
             }
         }
 
-        if renderer_state.point_lights.len() > 0 {
-            renderer_state.set_shader_program(self.omni_shadow_shader_id, resources_manager);
-        }
-
-        for i in 0..MAX_POINT_LIGHTS.min(renderer_state.point_lights.len()) {
-            renderer_state.set_framebuffer(Some(&self.point_shadows[i]), resources_manager);
-
-            memory_manager.reserve_per_draw_shader_data(1);
-            memory_manager.set_general_index(GeneralPurposeIndexStorageBuffer {
-                index_1: i as u32,
-                ..Default::default()
-            });
+        if !self.atlas_tiles.is_empty() {
+            renderer_state.set_shader_program(self.atlas_shadow_shader_id, resources_manager);
+            renderer_state.set_framebuffer(Some(&self.atlas), resources_manager);
 
             resources_manager
-                .borrow_framebuffer(&self.point_shadows[i])
+                .borrow_framebuffer(&self.atlas)
                 .unwrap()
                 .clear_depth(1.0);
-
-            graphics::submit_draw_call(
-                DrawMode::Triangles,
-                DataType::Uint32,
-                (memory_manager.get_indirect_command_index() - self.pending_indirect_command_count)
-                    * DRAW_COMMAND_SIZE,
-                self.pending_indirect_command_count,
-            );
         }
 
-        if renderer_state.spot_lights.len() > 0 || renderer_state.directional_light.is_some() {
-            renderer_state.set_shader_program(self.dir_shadow_shader_id, resources_manager);
-        }
-
-        for i in 0..MAX_SPOT_LIGHTS.min(renderer_state.spot_lights.len()) {
-            renderer_state.set_framebuffer(Some(&self.spot_shadows[i]), resources_manager);
+        for (shader_index, tile) in self.atlas_tiles.iter() {
+            graphics::set_viewport(tile.x, tile.y, tile.size, tile.size);
 
             memory_manager.reserve_per_draw_shader_data(1);
             memory_manager.set_general_index(GeneralPurposeIndexStorageBuffer {
-                index_1: 1 + i as u32,
+                index_1: *shader_index,
                 ..Default::default()
             });
 
-            resources_manager
-                .borrow_framebuffer(&self.spot_shadows[i])
-                .unwrap()
-                .clear_depth(1.0);
-
             graphics::submit_draw_call(
                 DrawMode::Triangles,
                 DataType::Uint32,
@@ -269,15 +344,11 @@ impl PipelineStage for ShadowStage {
             );
         }
 
+        // every cascade is drawn at once, with the geometry shader choosing the layer
         if let Some(_directional_light) = renderer_state.directional_light {
+            renderer_state.set_shader_program(self.cascade_shadow_shader_id, resources_manager);
             renderer_state.set_framebuffer(Some(&self.directional_shadow), resources_manager);
 
-            memory_manager.reserve_per_draw_shader_data(1);
-            memory_manager.set_general_index(GeneralPurposeIndexStorageBuffer {
-                index_1: 0 as u32,
-                ..Default::default()
-            });
-
             resources_manager
                 .borrow_framebuffer(&self.directional_shadow)
                 .unwrap()
@@ -297,28 +368,101 @@ impl PipelineStage for ShadowStage {
     }
 }
 
-fn hash(r: &Renderable) -> u32 {
-    r.mesh_id.index()
+impl ShadowStage {
+    /// Gives each spot light, and each face of each point light, a tile of the atlas sized by how much of the
+    /// screen the light could affect, then uploads where the tiles are
+    fn allocate_atlas_tiles(
+        &mut self,
+        memory_manager: &mut MemoryManager,
+        renderer_state: &RendererState,
+    ) {
+        let coverage = |position, attenuation| {
+            screen_coverage(
+                &renderer_state.projection_transform,
+                renderer_state.camera_position,
+                position,
+                light_range(attenuation),
+            )
+        };
+
+        // only the first lights of each type cast shadows
+        let spot_count = MAX_SPOT_LIGHT_SHADOWS.min(renderer_state.spot_lights.len());
+        let point_count = MAX_POINT_LIGHT_SHADOWS.min(renderer_state.point_lights.len());
+        let spot_lights = &renderer_state.spot_lights[..spot_count];
+        let point_lights = &renderer_state.point_lights[..point_count];
+
+        let mut sizes = Vec::with_capacity(spot_lights.len() + point_lights.len() * 6);
+        for light in spot_lights {
+            let coverage = coverage(light.position, light.attenuation);
+            sizes.push(tile_size_for_coverage(coverage, &self.atlas_settings));
+        }
+        for light in point_lights {
+            let coverage = coverage(light.position, light.attenuation);
+            let size = tile_size_for_coverage(coverage, &self.atlas_settings);
+            sizes.extend([size; 6]);
+        }
+
+        let tiles = self.atlas_allocator.allocate_all(&sizes);
+        let atlas_size = self.atlas_allocator.size();
+        let uv_rect = |tile: &Option<AtlasTile>| {
+            tile.map_or(Vec4f::uniform(0.0), |tile| tile.uv_rect(atlas_size))
+        };
+
+        self.atlas_tiles.clear();
+
+        // the shader expects the spot lights first, followed by the faces of the point lights
+        let mut spot_tiles = [None; MAX_SPOT_LIGHT_SHADOWS];
+        spot_tiles[..spot_lights.len()].copy_from_slice(&tiles[..spot_lights.len()]);
+
+        for (i, tile) in spot_tiles.iter().enumerate() {
+            memory_manager.set_spot_shadow_tile(uv_rect(tile), i as u32);
+
+            if let Some(tile) = tile {
+                self.atlas_tiles.push((i as u32, *tile));
+            }
+        }
+
+        for i in 0..MAX_POINT_LIGHT_SHADOWS {
+            let mut uv_rects = [Vec4f::uniform(0.0); 6];
+
+            if i < point_lights.len() {
+                let faces = &tiles[spot_lights.len() + i * 6..][..6];
+
+                for (face, tile) in faces.iter().enumerate() {
+                    uv_rects[face] = uv_rect(tile);
+
+                    if let Some(tile) = tile {
+                        self.atlas_tiles
+                            .push(((MAX_SPOT_LIGHT_SHADOWS + i * 6 + face) as u32, *tile));
+                    }
+                }
+            }
+
+            memory_manager.set_point_shadow_tiles(uv_rects, i as u32);
+        }
+    }
+}
+
+/// The view of the spot light or point light face that the shader index refers to
+fn light_view(renderer_state: &RendererState, shader_index: u32) -> Mat4f {
+    let index = shader_index as usize;
+
+    if index < MAX_SPOT_LIGHT_SHADOWS {
+        renderer_state.spot_lights[index].view
+    } else {
+        let face = index - MAX_SPOT_LIGHT_SHADOWS;
+        renderer_state.point_lights[face / 6].views[face % 6]
+    }
 }
 
-fn upload_draw_data(
-    memory_manager: &mut MemoryManager,
-    resources_manager: &mut ResourcesManager,
-    renderable: &Renderable,
-    instance_count: u32,
-) {
-    let mesh = resources_manager.borrow_mesh(&renderable.mesh_id).unwrap();
-
-    memory_manager.reserve_vertex_space(mesh.vertices.len() as u32);
-    memory_manager.reserve_index_space(mesh.indices.len() as u32);
-    let indirect_command = DrawElementsIndirectCommand {
-        count: mesh.indices.len() as u32,
-        instance_count,
-        first_index: memory_manager.get_index_index(),
-        base_vertex: memory_manager.get_vertex_index(),
-        base_instance: memory_manager.get_instance_index(),
-    };
-    memory_manager.push_indirect_command(indirect_command);
-    memory_manager.push_vertex_slice(&mesh.vertices);
-    memory_manager.push_index_slice(&mesh.indices);
+/// Handles for sampling the shadow map both with and without a depth comparison
+fn shadow_map_handles(texture: &mut Texture, depth_sampler: rustgl::GlSampler) -> ShadowMap {
+    texture.make_texture_resident();
+    let handle = texture.get_shader_texture_handle();
+    let depth_handle = texture.get_resident_sampler_handle(depth_sampler);
+
+    ShadowMap {
+        handle: Vec2u::new(handle as u32, (handle >> 32) as u32),
+        depth_handle: Vec2u::new(depth_handle as u32, (depth_handle >> 32) as u32),
+    }
 }
@@ -1,34 +1,35 @@
-use super::PipelineStage;
+use super::*;
 use crate::{
-    components::Renderable,
+    components::MeshInstance,
     graphics::{
         self,
         state::{Comparison, Orientation, RasteriserState},
     },
-    memory_manager::memory_manager::{
-        DrawElementsIndirectCommand, InstanceData, MemoryManager, DRAW_COMMAND_SIZE,
-    },
-    renderer::state::RendererState,
-    resource_manager::resource_manager::{FramebufferID, ResourceIDTrait, ResourcesManager},
+    memory_manager::memory_manager::{InstanceData, MemoryManager, DRAW_COMMAND_SIZE},
+    renderer::{command::upload_draw_data, state::RendererState},
+    resource_manager::resource_manager::{ResourceIDTrait, ResourcesManager},
 };
 
 pub struct SkyStage {
-    target: FramebufferID,
     skybox: Option<usize>,
 }
 
 impl SkyStage {
-    pub fn new(target: FramebufferID) -> Self {
+    pub fn new() -> Self {
         Self {
-            target,
             skybox: None,
         }
     }
 }
 
 impl PipelineStage for SkyStage {
-    fn get_target(&self) -> FramebufferID {
-        self.target
+    fn name(&self) -> StageName {
+        STAGE_SKY
+    }
+
+    fn setup(&mut self, pass: &mut PassBuilder, enabled_stages: &[StageName]) {
+        let (colour_target, depth_target) = msaa::scene_targets(enabled_stages);
+        pass.read(depth_target).render_target(colour_target);
     }
 
     fn init(
@@ -39,6 +40,10 @@ impl PipelineStage for SkyStage {
     ) {
     }
 
+    fn accepts(&self, pipeline_stages: RenderMask) -> bool {
+        pipeline_stages & RENDER_SKY == RENDER_SKY
+    }
+
     fn submit(&mut self, renderable_index: usize) {
         self.skybox = Some(renderable_index)
     }
@@ -49,7 +54,8 @@ impl PipelineStage for SkyStage {
         resources_manager: &mut ResourcesManager,
         renderer_state: &mut RendererState,
         rasteriser_state: &mut RasteriserState,
-        renderables: &[Renderable],
+        graph_resources: &GraphResources,
+        renderables: &[MeshInstance],
     ) {
         if let Some(skybox_index) = &self.skybox {
             let skybox = &renderables[*skybox_index];
@@ -67,12 +73,19 @@ impl PipelineStage for SkyStage {
             let instance = InstanceData {
                 material_index: skybox.material_id.index(),
                 transform: skybox.transform,
+                lod_fade: 0.0,
             };
 
             let base_instance = memory_manager.get_instance_index();
             memory_manager.push_instance_data(&instance);
 
-            upload_draw_data(memory_manager, resources_manager, skybox, 1, base_instance);
+            upload_draw_data(
+                memory_manager,
+                resources_manager,
+                &skybox.mesh_id,
+                1,
+                base_instance,
+            );
 
             graphics::submit_draw_call(
                 graphics::DrawMode::Triangles,
@@ -83,26 +96,3 @@ impl PipelineStage for SkyStage {
         }
     }
 }
-
-fn upload_draw_data(
-    memory_manager: &mut MemoryManager,
-    resources_manager: &mut ResourcesManager,
-    renderable: &Renderable,
-    instance_count: u32,
-    base_instance: u32,
-) {
-    let mesh = resources_manager.borrow_mesh(&renderable.mesh_id).unwrap();
-
-    memory_manager.reserve_vertex_space(mesh.vertices.len() as u32);
-    memory_manager.reserve_index_space(mesh.indices.len() as u32);
-    let indirect_command = DrawElementsIndirectCommand {
-        count: mesh.indices.len() as u32,
-        instance_count,
-        first_index: memory_manager.get_index_index(),
-        base_vertex: memory_manager.get_vertex_index(),
-        base_instance,
-    };
-    memory_manager.push_indirect_command(indirect_command);
-    memory_manager.push_vertex_slice(&mesh.vertices);
-    memory_manager.push_index_slice(&mesh.indices);
-}
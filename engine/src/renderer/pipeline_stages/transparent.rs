@@ -0,0 +1,394 @@
+use super::*;
+use crate::{
+    components::MeshInstance,
+    graphics::{
+        self,
+        framebuffer::{
+            FramebufferAttachment, FramebufferAttachmentConfig, FramebufferConfig, InternalFormat,
+        },
+        shader::{Program, ShaderData},
+        state::{Blending, Comparison, RasteriserState},
+        texture::TextureType,
+        AccessModifier, Barriers, DataType, DrawMode,
+    },
+    math::{Mat4f, Vec3f, Vec4f},
+    memory_manager::memory_manager::{InstanceData, MemoryManager, DRAW_COMMAND_SIZE},
+    platform::rustgl,
+    renderer::{
+        command::{upload_draw_data, DrawCommands, SortKeyLayout},
+        culling::{cull_renderables, Frustum},
+        state::RendererState,
+    },
+    resource_manager::resource_manager::{
+        MaterialID, MeshID, ResourceIDTrait, ResourcesManager, ShaderProgramID,
+    },
+};
+
+/// Weighted colour and revealage of the translucent geometry, when it is order independent
+const RESOURCE_OIT: ResourceName = "oit";
+
+/// Draw buffer of the revealage within [`RESOURCE_OIT`], after the accumulated colour
+const OIT_REVEALAGE: usize = 1;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TransparencyMode {
+    /// Renderables are drawn from back to front, and blended over each other. Sorting is by the centre of each
+    /// renderable, so intersecting or surrounding geometry may still be blended in the wrong order
+    Sorted,
+    /// Weighted blended order independent transparency. Translucent fragments are accumulated as a weighted average,
+    /// which favours nearer fragments, then composited over the scene, so the order they are drawn in doesn't matter
+    WeightedBlended,
+}
+
+/// Draws renderables flagged with [`RENDER_TRANSPARENT`] after the opaque scene and sky, blending them over it. <br>
+/// Depth is tested against the opaque scene but not written, so translucent geometry doesn't hide what is behind
+/// it. The shaders of the renderables are used, which are expected to output alpha, and to support the
+/// `weightedBlended` uniform for [`TransparencyMode::WeightedBlended`]. <br>
+/// The mode is read when the graph is built, so the pipeline must be invalidated after changing it.
+pub struct TransparentStage {
+    pub mode: TransparencyMode,
+    composite_shader_id: ShaderProgramID,
+    renderable_indices: Vec<usize>,
+    command_queue: DrawCommands,
+    /// The order to draw `renderable_indices` in
+    draw_order: Vec<usize>,
+    pending_indirect_command_count: u32,
+    /// Each shader that `weightedBlended` was set for this frame, so it can be cleared again for the opaque scene
+    weighted_shaders: Vec<ShaderProgramID>,
+}
+
+impl TransparentStage {
+    pub fn new(resources_manager: &mut ResourcesManager) -> Self {
+        let composite_shader_id = resources_manager.load_shader("res/shaders/oit_composite.glsl");
+
+        Self {
+            mode: TransparencyMode::Sorted,
+            composite_shader_id,
+            renderable_indices: Vec::new(),
+            command_queue: DrawCommands::new(SortKeyLayout::opaque()),
+            draw_order: Vec::new(),
+            pending_indirect_command_count: 0,
+            weighted_shaders: Vec::new(),
+        }
+    }
+
+    fn oit_config() -> FramebufferConfig {
+        let attachment = |internal_format| FramebufferAttachmentConfig::Texture {
+            target: TextureType::T2D,
+            internal_format,
+            layers: 1,
+            levels: 1,
+        };
+
+        FramebufferConfig {
+            colour: attachment(InternalFormat::RGBA16F),
+            additional_colours: vec![attachment(InternalFormat::R8)],
+            // must match the format of the scene depth, for it to be copied across
+            depth: attachment(InternalFormat::Depth32F),
+            ..Default::default()
+        }
+    }
+
+    /// Submits the renderables in draw order, batching neighbours that share a mesh and shader into a single
+    /// instanced command, so that the order is kept
+    fn draw(
+        &mut self,
+        memory_manager: &mut MemoryManager,
+        resources_manager: &mut ResourcesManager,
+        renderer_state: &mut RendererState,
+        renderables: &[MeshInstance],
+    ) {
+        let mut instance_count = 0;
+
+        let r = MeshInstance {
+            mesh_id: MeshID::new(0xFFFF),
+            material_id: MaterialID::new(0xFFFF),
+            shader_id: ShaderProgramID::new(0xFFFF),
+            transform: Mat4f::identity(),
+            pipeline_stages: 0,
+            lod_fade: 0.0,
+        };
+
+        for i in 0..self.draw_order.len() {
+            let renderable = &renderables[self.renderable_indices[self.draw_order[i]]];
+            let next_renderable = if i == self.draw_order.len() - 1 {
+                &r
+            } else {
+                &renderables[self.renderable_indices[self.draw_order[i + 1]]]
+            };
+
+            instance_count += 1;
+
+            if renderable.shader_id != next_renderable.shader_id
+                || renderable.mesh_id != next_renderable.mesh_id
+            {
+                memory_manager.reserve_instance_space(instance_count);
+                let base_instance = memory_manager.get_instance_index();
+                upload_draw_data(
+                    memory_manager,
+                    resources_manager,
+                    &renderable.mesh_id,
+                    instance_count,
+                    base_instance,
+                );
+                self.pending_indirect_command_count += 1;
+
+                for instance_index in
+                    self.draw_order[(i - (instance_count - 1) as usize)..=i].iter()
+                {
+                    let renderable = &renderables[self.renderable_indices[*instance_index]];
+
+                    memory_manager.push_instance_data(&InstanceData {
+                        material_index: renderable.material_id.index(),
+                        transform: renderable.transform,
+                        lod_fade: renderable.lod_fade,
+                    });
+                }
+
+                instance_count = 0;
+            }
+            if renderable.shader_id != next_renderable.shader_id {
+                renderer_state.set_shader_program(renderable.shader_id, resources_manager);
+
+                if self.mode == TransparencyMode::WeightedBlended {
+                    set_weighted_blended(renderable.shader_id, true, resources_manager);
+                    if !self.weighted_shaders.contains(&renderable.shader_id) {
+                        self.weighted_shaders.push(renderable.shader_id);
+                    }
+                }
+
+                graphics::submit_draw_call(
+                    DrawMode::Triangles,
+                    DataType::Uint32,
+                    (memory_manager.get_indirect_command_index()
+                        - self.pending_indirect_command_count)
+                        * DRAW_COMMAND_SIZE,
+                    self.pending_indirect_command_count,
+                );
+                self.pending_indirect_command_count = 0;
+            }
+        }
+    }
+
+    fn composite(
+        &mut self,
+        resources_manager: &mut ResourcesManager,
+        renderer_state: &mut RendererState,
+        graph_resources: &GraphResources,
+    ) {
+        let oit_target = graph_resources.framebuffer(RESOURCE_OIT).unwrap();
+        let hdr_target = graph_resources.framebuffer(RESOURCE_HDR).unwrap();
+
+        let oit_fb = resources_manager.borrow_framebuffer(&oit_target).unwrap();
+        for (unit, draw_buffer) in [0, OIT_REVEALAGE].into_iter().enumerate() {
+            if let Some(FramebufferAttachment::Texture(texture)) =
+                oit_fb.colour_attachment(draw_buffer)
+            {
+                unsafe {
+                    rustgl::active_texture(rustgl::TEXTURE0 + unit as u32);
+                }
+                texture.bind();
+            }
+        }
+
+        renderer_state.set_shader_program(self.composite_shader_id, resources_manager);
+
+        let hdr_fb = resources_manager.borrow_framebuffer(&hdr_target).unwrap();
+        if let FramebufferAttachment::Texture(texture) = &hdr_fb.colour_handle {
+            texture.bind_image_unit(0, 0, 0, AccessModifier::ReadWrite, InternalFormat::RGBA16F);
+        }
+
+        Program::dispatch_compute(
+            hdr_fb.config.width.div_ceil(16),
+            hdr_fb.config.height.div_ceil(16),
+            1,
+        );
+        graphics::memory_barrier(
+            Barriers::ShaderImageAccess as u32
+                | Barriers::TextureFetch as u32
+                | Barriers::Framebuffer as u32,
+        );
+
+        unsafe {
+            rustgl::active_texture(rustgl::TEXTURE0);
+        }
+    }
+}
+
+impl PipelineStage for TransparentStage {
+    fn name(&self) -> StageName {
+        STAGE_TRANSPARENT
+    }
+
+    fn setup(&mut self, pass: &mut PassBuilder, enabled_stages: &[StageName]) {
+        // nothing writes the shadow maps while the shadow stage is disabled
+        if enabled_stages.contains(&STAGE_SHADOW) {
+            pass.read(RESOURCE_SHADOW_MAPS);
+        }
+        pass.read(RESOURCE_DEPTH);
+
+        match self.mode {
+            TransparencyMode::Sorted => {
+                pass.render_target(RESOURCE_HDR);
+            }
+            TransparencyMode::WeightedBlended => {
+                pass.create_framebuffer(RESOURCE_OIT, Self::oit_config(), true)
+                    .render_target(RESOURCE_OIT)
+                    .write(RESOURCE_HDR);
+            }
+        }
+    }
+
+    fn init(
+        &mut self,
+        memory_manager: &mut MemoryManager,
+        resource_manager: &mut ResourcesManager,
+        renderer_state: &mut RendererState,
+    ) {
+    }
+
+    fn accepts(&self, pipeline_stages: RenderMask) -> bool {
+        pipeline_stages & RENDER_TRANSPARENT == RENDER_TRANSPARENT
+    }
+
+    fn submit(&mut self, renderable_index: usize) {
+        self.renderable_indices.push(renderable_index);
+    }
+
+    fn execute(
+        &mut self,
+        memory_manager: &mut MemoryManager,
+        resources_manager: &mut ResourcesManager,
+        renderer_state: &mut RendererState,
+        rasteriser_state: &mut RasteriserState,
+        graph_resources: &GraphResources,
+        renderables: &[MeshInstance],
+    ) {
+        let frustum = Frustum::from_matrix(
+            &(renderer_state.projection_transform * renderer_state.view_transform),
+        );
+        cull_renderables(
+            &[frustum],
+            renderables,
+            &resources_manager.mesh_bounds_manager,
+            &mut self.renderable_indices,
+        );
+
+        if self.renderable_indices.is_empty() {
+            return;
+        }
+
+        rasteriser_state.set(RasteriserState {
+            depth_func: Comparison::Less,
+            depth_mask: false,
+            blend: true,
+            blend_func: (Blending::SourceAlpha, Blending::OneMinusSourceAlpha),
+            ..Default::default()
+        });
+
+        match self.mode {
+            TransparencyMode::Sorted => {
+                let centres: Vec<Vec3f> = self
+                    .renderable_indices
+                    .iter()
+                    .map(|index| {
+                        let renderable = &renderables[*index];
+                        match resources_manager.borrow_mesh_bounds(&renderable.mesh_id) {
+                            Some(bounds) => bounds.transform(&renderable.transform).centre(),
+                            None => {
+                                Vec3f::from(renderable.transform * Vec4f::new(0.0, 0.0, 0.0, 1.0))
+                            }
+                        }
+                    })
+                    .collect();
+
+                back_to_front(
+                    &renderer_state.view_transform,
+                    &centres,
+                    &mut self.draw_order,
+                );
+
+                self.draw(
+                    memory_manager,
+                    resources_manager,
+                    renderer_state,
+                    renderables,
+                );
+            }
+            TransparencyMode::WeightedBlended => {
+                let oit_target = graph_resources.framebuffer(RESOURCE_OIT).unwrap();
+                let depth_target = graph_resources.framebuffer(RESOURCE_DEPTH).unwrap();
+
+                let oit_fb = resources_manager.borrow_framebuffer(&oit_target).unwrap();
+                let depth_fb = resources_manager.borrow_framebuffer(&depth_target).unwrap();
+                depth_fb.blit_depth(oit_fb);
+                oit_fb.clear_draw_buffer(0, 0.0, 0.0, 0.0, 0.0);
+                oit_fb.clear_draw_buffer(OIT_REVEALAGE as u32, 1.0, 1.0, 1.0, 1.0);
+
+                // the weighted colours are summed, and the revealage is multiplied by how transparent each
+                // fragment is
+                rasteriser_state.set_draw_buffer_blend_func(0, Blending::One, Blending::One);
+                rasteriser_state.set_draw_buffer_blend_func(
+                    OIT_REVEALAGE as u32,
+                    Blending::Zero,
+                    Blending::OneMinusSourceColor,
+                );
+
+                // the order doesn't matter, so renderables are batched as much as they can be
+                self.command_queue.update_keys(
+                    renderables,
+                    &self.renderable_indices,
+                    &renderer_state.view_transform,
+                );
+                self.command_queue.sort_indices();
+                self.draw_order.clear();
+                self.draw_order
+                    .extend_from_slice(&self.command_queue.indices);
+
+                self.draw(
+                    memory_manager,
+                    resources_manager,
+                    renderer_state,
+                    renderables,
+                );
+
+                rasteriser_state.update_all();
+
+                for shader_id in self.weighted_shaders.drain(..) {
+                    renderer_state.set_shader_program(shader_id, resources_manager);
+                    set_weighted_blended(shader_id, false, resources_manager);
+                }
+
+                self.composite(resources_manager, renderer_state, graph_resources);
+            }
+        }
+
+        self.renderable_indices.clear();
+    }
+}
+
+/// Orders the renderables from the furthest from the camera to the nearest, by the view depth of their centres
+pub fn back_to_front(view_transform: &Mat4f, centres: &[Vec3f], order: &mut Vec<usize>) {
+    let depths: Vec<f32> = centres
+        .iter()
+        .map(|centre| -(*view_transform * Vec4f::new(centre.x, centre.y, centre.z, 1.0)).z)
+        .collect();
+
+    order.clear();
+    order.extend(0..centres.len());
+    order.sort_by(|a, b| depths[*b].total_cmp(&depths[*a]));
+}
+
+fn set_weighted_blended(
+    shader_id: ShaderProgramID,
+    enabled: bool,
+    resources_manager: &mut ResourcesManager,
+) {
+    resources_manager
+        .borrow_mut_shader_program(&shader_id)
+        .unwrap()
+        .set_uniform(
+            "weightedBlended".to_string(),
+            ShaderData::Int1(enabled as i32),
+        );
+}
@@ -0,0 +1,251 @@
+use std::mem::discriminant;
+
+use crate::{
+    math::*,
+    resource_manager::resource_manager::{ShaderProgramID, TextureID},
+};
+
+/// Value of a parameter of a post effect, which is set as the uniform of the same name before the effect runs.
+/// Textures are bound to the texture units after the previous result, in the order they were declared, and the
+/// uniform is set to the unit
+#[derive(Clone, Copy)]
+pub enum PostParameter {
+    Float(f32),
+    Vec2(Vec2f),
+    Vec3(Vec3f),
+    Vec4(Vec4f),
+    Int(i32),
+    Bool(bool),
+    Texture(TextureID),
+}
+
+/// How a user supplied post effect is run
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PostPassKind {
+    /// Dispatched in groups of 16x16 threads, writing the result to `output_image`, an RGBA16F image in unit zero
+    Compute,
+    /// Drawn as a fullscreen triangle, writing the result to its first output, where the vertex shader can include
+    /// `res/shaders/common/fullscreen.glsl`
+    Fragment,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BuiltInEffect {
+    ColourGrading,
+    Vignette,
+    ChromaticAberration,
+    FilmGrain,
+    Sharpen,
+}
+
+impl BuiltInEffect {
+    pub const ALL: [BuiltInEffect; 5] = [
+        BuiltInEffect::ColourGrading,
+        BuiltInEffect::Vignette,
+        BuiltInEffect::ChromaticAberration,
+        BuiltInEffect::FilmGrain,
+        BuiltInEffect::Sharpen,
+    ];
+
+    /// The compute shader that applies the effect
+    pub fn shader_path(&self) -> &'static str {
+        match self {
+            BuiltInEffect::ColourGrading => "res/shaders/post_colour_grading.glsl",
+            BuiltInEffect::Vignette => "res/shaders/post_vignette.glsl",
+            BuiltInEffect::ChromaticAberration => "res/shaders/post_chromatic_aberration.glsl",
+            BuiltInEffect::FilmGrain => "res/shaders/post_film_grain.glsl",
+            BuiltInEffect::Sharpen => "res/shaders/post_sharpen.glsl",
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub enum PostShader {
+    BuiltIn(BuiltInEffect),
+    Custom {
+        shader_id: ShaderProgramID,
+        kind: PostPassKind,
+    },
+}
+
+/// A pass of the post effect stack, which reads the result of the pass before it from `previous_texture`, in
+/// texture unit zero, once the scene has been exposed and tone mapped. The `frame` uniform is also set, for effects
+/// that animate
+#[derive(Clone)]
+pub struct PostEffect {
+    /// Identifies the effect within its stack
+    pub name: &'static str,
+    pub enabled: bool,
+    shader: PostShader,
+    parameters: Vec<(&'static str, PostParameter)>,
+}
+
+impl PostEffect {
+    fn built_in(name: &'static str, effect: BuiltInEffect) -> Self {
+        Self {
+            name,
+            enabled: true,
+            shader: PostShader::BuiltIn(effect),
+            parameters: Vec::new(),
+        }
+    }
+
+    /// Remaps colours through a 3D LUT, see [`crate::graphics::texture::lut_size`], blended with the original
+    /// colours by `strength`
+    pub fn colour_grading(lut: TextureID, strength: f32) -> Self {
+        Self::built_in("colour_grading", BuiltInEffect::ColourGrading)
+            .with_parameter("lut_texture", PostParameter::Texture(lut))
+            .with_parameter("strength", PostParameter::Float(strength))
+    }
+
+    /// Darkens the edges of the screen, where `smoothness` is the width of the falloff
+    pub fn vignette(intensity: f32, smoothness: f32) -> Self {
+        Self::built_in("vignette", BuiltInEffect::Vignette)
+            .with_parameter("intensity", PostParameter::Float(intensity))
+            .with_parameter("smoothness", PostParameter::Float(smoothness))
+    }
+
+    /// Offsets the red and blue channels away from the centre of the screen, by up to `intensity` of its width
+    pub fn chromatic_aberration(intensity: f32) -> Self {
+        Self::built_in("chromatic_aberration", BuiltInEffect::ChromaticAberration)
+            .with_parameter("intensity", PostParameter::Float(intensity))
+    }
+
+    /// Adds noise that changes every frame, which is strongest in the mid-tones
+    pub fn film_grain(intensity: f32) -> Self {
+        Self::built_in("film_grain", BuiltInEffect::FilmGrain)
+            .with_parameter("intensity", PostParameter::Float(intensity))
+    }
+
+    /// Unsharp mask, which exaggerates the difference between each pixel and its neighbours by `strength`
+    pub fn sharpen(strength: f32) -> Self {
+        Self::built_in("sharpen", BuiltInEffect::Sharpen)
+            .with_parameter("strength", PostParameter::Float(strength))
+    }
+
+    /// A user supplied compute shader, see [`PostPassKind::Compute`]
+    pub fn compute(name: &'static str, shader_id: ShaderProgramID) -> Self {
+        Self {
+            name,
+            enabled: true,
+            shader: PostShader::Custom {
+                shader_id,
+                kind: PostPassKind::Compute,
+            },
+            parameters: Vec::new(),
+        }
+    }
+
+    /// A user supplied vertex and fragment shader, see [`PostPassKind::Fragment`]
+    pub fn fragment(name: &'static str, shader_id: ShaderProgramID) -> Self {
+        Self {
+            name,
+            enabled: true,
+            shader: PostShader::Custom {
+                shader_id,
+                kind: PostPassKind::Fragment,
+            },
+            parameters: Vec::new(),
+        }
+    }
+
+    /// Declares a parameter with its initial value, replacing it if it was already declared
+    pub fn with_parameter(mut self, name: &'static str, value: PostParameter) -> Self {
+        match self.parameters.iter_mut().find(|(n, _)| *n == name) {
+            Some(parameter) => parameter.1 = value,
+            None => self.parameters.push((name, value)),
+        }
+        self
+    }
+
+    pub fn parameter(&self, name: &str) -> Option<PostParameter> {
+        self.parameters
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|(_, value)| *value)
+    }
+
+    /// Changes a declared parameter, which must keep the type it was declared with
+    pub fn set_parameter(&mut self, name: &str, value: PostParameter) -> Result<(), String> {
+        let effect_name = self.name;
+        let parameter = self
+            .parameters
+            .iter_mut()
+            .find(|(n, _)| *n == name)
+            .ok_or_else(|| format!("Post effect '{}' has no parameter '{}'", effect_name, name))?;
+
+        if discriminant(&parameter.1) != discriminant(&value) {
+            return Err(format!(
+                "Parameter '{}' of post effect '{}' was declared with a different type",
+                name, effect_name
+            ));
+        }
+
+        parameter.1 = value;
+        Ok(())
+    }
+
+    pub fn parameters(&self) -> &[(&'static str, PostParameter)] {
+        &self.parameters
+    }
+
+    pub fn shader(&self) -> PostShader {
+        self.shader
+    }
+}
+
+/// Post effects in the order they are applied, where each has a unique name
+#[derive(Clone, Default)]
+pub struct PostEffectStack {
+    effects: Vec<PostEffect>,
+}
+
+impl PostEffectStack {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the effect after the others
+    pub fn push(&mut self, effect: PostEffect) -> Result<(), String> {
+        self.insert(self.effects.len(), effect)
+    }
+
+    /// Adds the effect before the one at `index`, or after the others if `index` is past the end
+    pub fn insert(&mut self, index: usize, effect: PostEffect) -> Result<(), String> {
+        if self.position(effect.name).is_some() {
+            return Err(format!(
+                "A post effect named '{}' is already in the stack",
+                effect.name
+            ));
+        }
+
+        self.effects.insert(index.min(self.effects.len()), effect);
+        Ok(())
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<PostEffect> {
+        self.position(name).map(|index| self.effects.remove(index))
+    }
+
+    pub fn position(&self, name: &str) -> Option<usize> {
+        self.effects.iter().position(|effect| effect.name == name)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&PostEffect> {
+        self.effects.iter().find(|effect| effect.name == name)
+    }
+
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut PostEffect> {
+        self.effects.iter_mut().find(|effect| effect.name == name)
+    }
+
+    /// Effects in the order they are applied, including those that are disabled
+    pub fn effects(&self) -> &[PostEffect] {
+        &self.effects
+    }
+
+    /// The effects that are applied
+    pub fn enabled(&self) -> impl Iterator<Item = &PostEffect> {
+        self.effects.iter().filter(|effect| effect.enabled)
+    }
+}
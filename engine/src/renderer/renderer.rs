@@ -1,14 +1,37 @@
+use std::time::Instant;
+
+use log::{error, warn};
+
 use super::{
     camera::Camera,
+    environment::{Environment, EnvironmentBaker},
+    graph::GraphResource,
+    lod::{screen_size, select_lod, LodSettings, LodStats},
     pipeline::RendererPipeline,
     pipeline_stages::{
-        bloom::BloomStage, debug::DebugStage, post_process::PostProcessStage, scene::SceneStage,
-        shadow::ShadowStage, sky::SkyStage, *,
+        anti_aliasing::AntiAliasingStage,
+        ao::AOStage,
+        bloom::BloomStage,
+        debug::DebugStage,
+        depth::DepthStage,
+        gbuffer::GBufferStage,
+        hiz::HiZStage,
+        lighting::LightingStage,
+        msaa::{validate_samples, MsaaResolveStage},
+        post_process::PostProcessStage,
+        scene::SceneStage,
+        shadow::ShadowStage,
+        sky::SkyStage,
+        transparent::TransparentStage,
+        *,
     },
     state::RendererState,
+    view::{
+        draw_order, is_visible, primary_view, RenderTarget, RenderTexture, ViewOutput, ViewSettings,
+    },
 };
 use crate::{
-    components::Renderable,
+    components::{CameraView, MeshInstance, Renderable},
     graphics::{
         self,
         framebuffer::{FramebufferAttachmentConfig, FramebufferConfig, InternalFormat},
@@ -16,38 +39,86 @@ use crate::{
     },
     math::*,
     memory_manager::{
-        memory_manager::MemoryManager,
-        uniform_layouts::{self, DirectionalLight, PointLight, SpotLight},
+        memory_manager::{MemoryManager, MAX_VIEWS},
+        uniform_layouts::{self, DirectionalLight, MaterialModel, PointLight, SpotLight},
     },
     resource_manager::{
         model,
         resource_manager::{
-            MaterialID, MeshID, ResourceIDTrait, ResourcesManager, ShaderProgramID, TextureID,
+            FramebufferID, LodChainID, MaterialID, MeshID, ResourceIDTrait, ResourcesManager,
+            ShaderProgramID, TextureID,
         },
     },
 };
 
+/// How the opaque scene is lit, which is fixed once the renderer has been created
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ShadingPath {
+    /// Each renderable is lit as it is drawn, by its own shader, after a depth pre-pass
+    Forward,
+    /// Renderables are drawn into a G-buffer, which is then lit once per pixel. Renderables are all drawn with the
+    /// G-buffer shader, rather than their own
+    Deferred,
+}
+
 pub struct Renderer<'a> {
     pub renderer_state: RendererState,
     pub rasteriser_state: RasteriserState,
     pub renderer_pipeline: RendererPipeline<'a>,
     pub resources_manager: ResourcesManager,
     pub memory_manager: MemoryManager,
+    /// The main camera, which is drawn along with those added each frame
     pub camera: Camera,
+    pub camera_settings: ViewSettings,
+    pub lod_settings: LodSettings,
 
-    renderables: Vec<Renderable>,
+    shading_path: ShadingPath,
+    /// Samples per pixel of the opaque scene, where one disables MSAA
+    msaa_samples: u32,
+    /// Created once MSAA is first enabled, then kept and recreated whenever the sample count changes
+    msaa_framebuffer: Option<FramebufferID>,
+    environment_baker: EnvironmentBaker,
+    /// Cameras added for the frame, besides the main camera
+    cameras: Vec<CameraView>,
+    /// Drawn by each camera that sees them, once the frame ends
+    frame_renderables: Vec<Renderable>,
+    /// The instances of `frame_renderables` for the view being drawn, with their levels of detail selected
+    renderables: Vec<MeshInstance>,
+    /// Counted as renderables are drawn, across every view, then kept in `lod_stats` once the frame ends
+    frame_lod_stats: LodStats,
+    lod_stats: LodStats,
+    /// When the last frame began, which the delta time is measured from
+    frame_start: Option<Instant>,
 }
 
 impl Renderer<'_> {
     pub fn new() -> Self {
+        Self::with_shading_path(ShadingPath::Forward)
+    }
+
+    pub fn with_shading_path(shading_path: ShadingPath) -> Self {
+        let mut resources_manager = ResourcesManager::new();
+        let environment_baker = EnvironmentBaker::new(&mut resources_manager);
+
         let mut r = Renderer {
             renderer_state: RendererState::new(),
             rasteriser_state: RasteriserState::default(),
             renderer_pipeline: RendererPipeline::new(),
-            resources_manager: ResourcesManager::new(),
+            resources_manager,
             memory_manager: MemoryManager::new(),
             camera: Camera::new_perspective(70.0, 0.1, 100.0),
+            camera_settings: ViewSettings::default(),
+            lod_settings: LodSettings::default(),
+            shading_path,
+            msaa_samples: 1,
+            msaa_framebuffer: None,
+            environment_baker,
+            cameras: Vec::new(),
+            frame_renderables: Vec::new(),
             renderables: Vec::new(),
+            frame_lod_stats: LodStats::default(),
+            lod_stats: LodStats::default(),
+            frame_start: None,
         };
         r.init();
         r
@@ -61,8 +132,9 @@ impl Renderer<'_> {
                 target: TextureType::T2D,
                 internal_format: InternalFormat::RGBA16F,
                 layers: 1,
-                levels: 5,
+                levels: 1,
             },
+            additional_colours: Vec::new(),
             depth: FramebufferAttachmentConfig::Texture {
                 target: TextureType::T2D,
                 internal_format: InternalFormat::Depth32F,
@@ -72,36 +144,131 @@ impl Renderer<'_> {
             stencil: FramebufferAttachmentConfig::None,
             width: crate::WIDTH,
             height: crate::HEIGHT,
-            samples: crate::SAMPLES,
+            samples: 1,
         };
 
         let fb_id = self.resources_manager.load_framebuffer(&config, true);
 
-        self.renderer_pipeline.add_stage(
-            ShadowStage::new(
-                fb_id,
-                &mut self.memory_manager,
-                &mut self.resources_manager,
-                &mut self.renderer_state,
-            ),
-            STAGE_SHADOW,
-        );
+        let graph = &mut self.renderer_pipeline.graph;
+        graph.import_framebuffer(RESOURCE_HDR, fb_id);
+        graph.import_framebuffer(RESOURCE_DEPTH, fb_id);
+        graph.declare_external(RESOURCE_SHADOW_MAPS);
+        graph.declare_external(RESOURCE_BACKBUFFER);
+        graph.mark_output(RESOURCE_BACKBUFFER);
+
+        self.renderer_pipeline.add_stage(ShadowStage::new(
+            &mut self.memory_manager,
+            &mut self.resources_manager,
+            &mut self.renderer_state,
+        ));
+        match self.shading_path {
+            ShadingPath::Forward => {
+                self.renderer_pipeline
+                    .add_stage(DepthStage::new(&mut self.resources_manager));
+                self.renderer_pipeline
+                    .add_stage(AOStage::new(&mut self.resources_manager));
+                self.renderer_pipeline
+                    .add_stage(SceneStage::new(&mut self.resources_manager));
+                self.renderer_pipeline.add_stage(MsaaResolveStage::new());
+                self.renderer_pipeline.disable_stage(STAGE_MSAA_RESOLVE);
+            }
+            ShadingPath::Deferred => {
+                self.renderer_pipeline
+                    .add_stage(GBufferStage::new(&mut self.resources_manager));
+                self.renderer_pipeline
+                    .add_stage(AOStage::new(&mut self.resources_manager));
+                self.renderer_pipeline
+                    .add_stage(LightingStage::new(&mut self.resources_manager));
+            }
+        }
+        self.renderer_pipeline.add_stage(SkyStage::new());
         self.renderer_pipeline
-            .add_stage(SceneStage::new(fb_id), STAGE_SCENE);
+            .add_stage(TransparentStage::new(&mut self.resources_manager));
         self.renderer_pipeline
-            .add_stage(SkyStage::new(fb_id), STAGE_SKY);
-        self.renderer_pipeline.add_stage(
-            BloomStage::new(fb_id, &mut self.resources_manager),
-            STAGE_BLOOM,
-        );
-        self.renderer_pipeline.add_stage(
-            DebugStage::new(fb_id, &mut self.resources_manager),
-            STAGE_DEBUG,
-        );
-        self.renderer_pipeline.add_stage(
-            PostProcessStage::new(fb_id, &mut self.resources_manager),
-            STAGE_POST_PROCESS,
-        );
+            .add_stage(HiZStage::new(&mut self.resources_manager));
+        self.renderer_pipeline
+            .add_stage(AntiAliasingStage::new(&mut self.resources_manager));
+        self.renderer_pipeline
+            .add_stage(BloomStage::new(&mut self.resources_manager));
+        self.renderer_pipeline
+            .add_stage(DebugStage::new(&mut self.resources_manager));
+        self.renderer_pipeline
+            .add_stage(PostProcessStage::new(&mut self.resources_manager));
+
+        if let Err(e) = self.set_msaa_samples(crate::SAMPLES) {
+            error!("Failed to enable MSAA: {}", e);
+        }
+    }
+
+    pub fn shading_path(&self) -> ShadingPath {
+        self.shading_path
+    }
+
+    pub fn msaa_samples(&self) -> u32 {
+        self.msaa_samples
+    }
+
+    /// Sets how many samples per pixel the opaque scene is drawn with, where one disables MSAA. <br>
+    /// The scene is drawn into a multisampled framebuffer, which is resolved into [`RESOURCE_HDR`] before the
+    /// stages that read it as a texture. Only the forward path supports this, as the G-buffer isn't multisampled.
+    pub fn set_msaa_samples(&mut self, samples: u32) -> Result<(), String> {
+        let samples = validate_samples(samples, graphics::max_samples())?;
+        if samples > 1 && self.shading_path != ShadingPath::Forward {
+            return Err("MSAA is only supported by the forward shading path".to_string());
+        }
+        if samples == self.msaa_samples {
+            return Ok(());
+        }
+        self.msaa_samples = samples;
+
+        if samples == 1 {
+            self.renderer_pipeline.disable_stage(STAGE_MSAA_RESOLVE);
+            return Ok(());
+        }
+
+        match self.msaa_framebuffer {
+            Some(fb_id) => self
+                .resources_manager
+                .borrow_mut_framebuffer(&fb_id)
+                .unwrap()
+                .set_samples(samples),
+            None => {
+                let hdr_id = match self.renderer_pipeline.graph.get_resource(RESOURCE_HDR) {
+                    Some(GraphResource::Imported(hdr_id)) => *hdr_id,
+                    _ => return Err("The HDR framebuffer has not been created".to_string()),
+                };
+                let hdr_config = &self
+                    .resources_manager
+                    .borrow_framebuffer(&hdr_id)
+                    .unwrap()
+                    .config;
+
+                // only ever resolved, never sampled, so renderbuffers are enough
+                let config = FramebufferConfig {
+                    colour: FramebufferAttachmentConfig::Renderbuffer {
+                        internal_format: InternalFormat::RGBA16F,
+                    },
+                    depth: FramebufferAttachmentConfig::Renderbuffer {
+                        internal_format: InternalFormat::Depth32F,
+                    },
+                    width: hdr_config.width,
+                    height: hdr_config.height,
+                    samples,
+                    ..Default::default()
+                };
+
+                let fb_id = self.resources_manager.load_framebuffer(&config, true);
+                let graph = &mut self.renderer_pipeline.graph;
+                graph.import_framebuffer(RESOURCE_MSAA, fb_id);
+                graph.import_framebuffer(RESOURCE_MSAA_DEPTH, fb_id);
+                self.msaa_framebuffer = Some(fb_id);
+            }
+        }
+
+        self.renderer_pipeline.enable_stage(STAGE_MSAA_RESOLVE);
+        self.renderer_pipeline.invalidate();
+
+        Ok(())
     }
 
     pub fn set_viewport(&mut self, width: u32, height: u32) {
@@ -171,25 +338,142 @@ impl Renderer<'_> {
         self.renderer_state.spot_lights.push(light);
     }
 
-    pub fn set_directional_light(&mut self, mut light: DirectionalLight) {
-        light.view = self.renderer_state.light_ortho_projection
-            * Camera::look_at(
-                &light.position,
-                &light.direction,
-                &Vec3f::new(0.0, 1.0, 0.0),
-            );
+    /// The shadow cascades are fitted to the camera by the shadow stage
+    pub fn set_directional_light(&mut self, light: DirectionalLight) {
         self.renderer_state.directional_light = Some(light);
     }
 
     pub fn begin(&mut self) {
-        self.camera.update_view();
-        self.renderer_state.view_transform = self.camera.view.clone();
-        self.renderer_state.projection_transform = self.camera.projection.clone();
-        self.renderer_state.camera_position = self.camera.position;
-        self.renderer_state.camera_direction = self.camera.direction;
+        let now = Instant::now();
+        self.renderer_state.delta_time = self
+            .frame_start
+            .map_or(0.0, |frame_start| (now - frame_start).as_secs_f32());
+        self.frame_start = Some(now);
+    }
+
+    /// Draws the scene from another camera this frame, as well as from the main camera
+    pub fn add_camera(&mut self, camera: &CameraView) {
+        self.cameras.push(camera.clone());
     }
 
+    /// Draws each camera, in order of priority, with the lights and renderables given since the frame began
     pub fn end(&mut self) {
+        let main_camera = CameraView {
+            camera: self.camera.clone(),
+            settings: self.camera_settings,
+        };
+        let mut cameras = std::mem::take(&mut self.cameras);
+        cameras.insert(0, main_camera);
+
+        let settings = cameras
+            .iter()
+            .map(|camera| camera.settings)
+            .collect::<Vec<_>>();
+        let order = draw_order(&settings);
+        let primary = primary_view(&settings, &order);
+
+        if cameras.len() > MAX_VIEWS as usize {
+            warn!(
+                "Drawing {} views, where more than {} will wait for the GPU to finish earlier frames",
+                cameras.len(),
+                MAX_VIEWS
+            );
+        }
+
+        for &index in order.iter() {
+            self.draw_view(&mut cameras[index], primary == Some(index));
+        }
+        self.renderer_pipeline
+            .release_unused_resolutions(&mut self.resources_manager, self.renderer_state.frame);
+
+        // keeps the main camera's projection, which is updated for its viewport as it is drawn
+        self.camera = cameras.swap_remove(0).camera;
+
+        self.renderer_state.frame += 1;
+        self.renderer_state.reset_lights();
+        self.frame_renderables.clear();
+        self.lod_stats = std::mem::take(&mut self.frame_lod_stats);
+    }
+
+    /// Size of the HDR framebuffer, which follows the window
+    fn window_size(&self) -> (u32, u32) {
+        let hdr = match self.renderer_pipeline.graph.get_resource(RESOURCE_HDR) {
+            Some(GraphResource::Imported(hdr_id)) => {
+                self.resources_manager.borrow_framebuffer(hdr_id)
+            }
+            _ => None,
+        };
+        hdr.map_or((crate::WIDTH, crate::HEIGHT), |hdr| {
+            (hdr.config.width, hdr.config.height)
+        })
+    }
+
+    /// Where the view's final image goes, and the size of its target
+    fn view_output(&self, settings: &ViewSettings) -> ViewOutput {
+        let (framebuffer, width, height) = match settings.target {
+            RenderTarget::Window => {
+                let (width, height) = self.window_size();
+                (None, width, height)
+            }
+            RenderTarget::Texture(texture) => {
+                (Some(texture.framebuffer_id), texture.width, texture.height)
+            }
+        };
+
+        let (x, y, width, height) = settings.viewport.pixels(width, height);
+        ViewOutput {
+            framebuffer,
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    /// Draws the renderables that the camera sees, at the size of its viewport, then copies the image into it.
+    /// Only the primary view keeps the history of temporal effects and occlusion culling
+    fn draw_view(&mut self, view: &mut CameraView, primary: bool) {
+        let output = self.view_output(&view.settings);
+        if output.width == 0 || output.height == 0 {
+            return;
+        }
+
+        let frame = self.renderer_state.frame;
+        let size = (output.width, output.height);
+        self.renderer_pipeline
+            .set_resolution((size != self.window_size()).then_some(size), frame);
+        let jitter = if primary && self.renderer_pipeline.is_enabled(STAGE_ANTI_ALIASING) {
+            self.renderer_pipeline
+                .get_stage_mut::<AntiAliasingStage>(STAGE_ANTI_ALIASING)
+                .map_or(Vec2f::uniform(0.0), |stage| stage.jitter(frame))
+        } else {
+            Vec2f::uniform(0.0)
+        };
+
+        let camera = &mut view.camera;
+        camera.update_projection(output.width as f32, output.height as f32);
+        camera.set_jitter(jitter);
+        camera.update_view();
+        self.renderer_state.view_transform = camera.view;
+        self.renderer_state.projection_transform = camera.projection;
+        self.renderer_state.unjittered_projection_transform = camera.unjittered_projection;
+        self.renderer_state.jitter = jitter;
+        self.renderer_state.camera_position = camera.position;
+        self.renderer_state.camera_direction = camera.direction;
+        self.renderer_state.primary_view = primary;
+        self.renderer_state.output = output;
+
+        let renderables = std::mem::take(&mut self.frame_renderables);
+        for renderable in renderables.iter() {
+            if is_visible(renderable.layers, view.settings.layers) {
+                self.submit(renderable);
+            }
+        }
+        self.frame_renderables = renderables;
+
+        // each view has its own section of the per-frame buffers, as the GPU may not have drawn the last view by
+        // the time the next one is written, and there are enough sections for `MAX_VIEWS` views of each frame in
+        // flight
         self.memory_manager.wait_for_section_lock();
         self.renderer_state
             .upload_camera_data(&mut self.memory_manager);
@@ -203,16 +487,80 @@ impl Renderer<'_> {
             &self.renderables,
         );
         self.memory_manager.set_section_lock();
-
         self.memory_manager.advance_sections();
-        self.renderer_state.reset_lights();
+
         self.renderables.clear();
     }
 
+    /// Draws the renderable from each camera that sees it, once the frame ends
     pub fn draw(&mut self, renderable: &Renderable) {
-        self.renderables.push(renderable.clone());
-        self.renderer_pipeline
-            .submit(self.renderables.len() - 1, renderable.pipeline_stages);
+        self.frame_renderables.push(renderable.clone());
+    }
+
+    /// Selects the level of the renderable's LOD chain from its size on screen, as seen by the view being drawn,
+    /// and submits it to the stages. Renderables whose chain is dead are not drawn
+    fn submit(&mut self, renderable: &Renderable) {
+        let Some(chain) = self
+            .resources_manager
+            .borrow_lod_chain(&renderable.lod_chain_id)
+        else {
+            return;
+        };
+
+        let size = match self
+            .resources_manager
+            .borrow_mesh_bounds(&chain.levels()[0].mesh_id)
+        {
+            Some(bounds) => screen_size(
+                bounds,
+                &renderable.transform,
+                &self.renderer_state.view_transform,
+                &self.renderer_state.projection_transform,
+            ),
+            None => f32::MAX,
+        };
+        let selection = select_lod(chain, size, &self.lod_settings);
+
+        let mut instances = [(selection.level, 0.0, renderable.pipeline_stages); 2];
+        let mut instance_count = 1;
+        if selection.coverage < 1.0 {
+            instances[0].1 = selection.coverage;
+            instances[1] = (
+                selection.level + 1,
+                -selection.coverage,
+                renderable.pipeline_stages,
+            );
+            // shadows aren't dithered, so only the level that covers the most pixels casts one
+            let faded = if selection.coverage >= 0.5 { 1 } else { 0 };
+            instances[faded].2 &= !RENDER_SHADOW;
+            instance_count = 2;
+            self.frame_lod_stats.cross_fading += 1;
+        }
+
+        for &(level, lod_fade, pipeline_stages) in &instances[..instance_count] {
+            let mesh_id = chain.levels()[level].mesh_id;
+            let triangles = self
+                .resources_manager
+                .borrow_mesh(&mesh_id)
+                .map_or(0, |mesh| mesh.indices.len() as u64 / 3);
+            self.frame_lod_stats.add(level, triangles);
+
+            self.renderables.push(MeshInstance {
+                mesh_id,
+                material_id: renderable.material_id,
+                shader_id: renderable.shader_id,
+                transform: renderable.transform,
+                pipeline_stages,
+                lod_fade,
+            });
+            self.renderer_pipeline
+                .submit(self.renderables.len() - 1, pipeline_stages);
+        }
+    }
+
+    /// What was drawn at each level of detail in the last frame
+    pub fn lod_stats(&self) -> &LodStats {
+        &self.lod_stats
     }
 
     pub fn load_shader(&mut self, path: &'static str) -> ShaderProgramID {
@@ -220,81 +568,64 @@ impl Renderer<'_> {
     }
 
     pub fn load_mesh(&mut self, mesh: model::Mesh) -> MeshID {
-        self.resources_manager.load_mesh(mesh)
+        self.resources_manager
+            .load_mesh(mesh, &mut self.memory_manager)
     }
 
-    pub fn load_material(&mut self, material: model::Material) -> MaterialID {
+    pub fn load_dynamic_mesh(&mut self, mesh: model::Mesh) -> MeshID {
+        self.resources_manager.load_dynamic_mesh(mesh)
+    }
+
+    pub fn load_lod_chain(&mut self, lod_chain: model::LodChain) -> LodChainID {
+        self.resources_manager.load_lod_chain(lod_chain)
+    }
+
+    pub fn load_material(&mut self, material: impl Into<model::Material>) -> MaterialID {
         let id = self.resources_manager.load_material(material);
         let index = id.index();
         let material = self.resources_manager.material_manager.resources[index as usize];
 
-        let diff_texture_handle;
-        let spec_texture_handle;
-        let norm_texture_handle;
-
-        if let Some(texture_id) = material.diffuse_texture_id {
-            let texture = self
-                .resources_manager
-                .borrow_mut_texture(&texture_id)
-                .unwrap();
-            texture.make_texture_resident();
-            diff_texture_handle = texture.shader_texture_handle.unwrap();
-        } else {
-            diff_texture_handle = self
-                .resources_manager
-                .borrow_texture(&self.resources_manager.placeholder_diffuse_texture)
-                .unwrap()
-                .shader_texture_handle
-                .unwrap();
-        }
-
-        if let Some(texture_id) = material.specular_texture_id {
-            let texture = self
-                .resources_manager
-                .borrow_mut_texture(&texture_id)
-                .unwrap();
-            texture.make_texture_resident();
-            spec_texture_handle = texture.shader_texture_handle.unwrap();
-        } else {
-            spec_texture_handle = self
-                .resources_manager
-                .borrow_texture(&self.resources_manager.placeholder_specular_texture)
-                .unwrap()
-                .shader_texture_handle
-                .unwrap();
-        }
-
-        if let Some(texture_id) = material.normal_texture_id {
-            let texture = self
-                .resources_manager
-                .borrow_mut_texture(&texture_id)
-                .unwrap();
-            texture.make_texture_resident();
-            norm_texture_handle = texture.shader_texture_handle.unwrap();
-        } else {
-            norm_texture_handle = self
-                .resources_manager
-                .borrow_texture(&self.resources_manager.placeholder_normal_texture)
-                .unwrap()
-                .shader_texture_handle
-                .unwrap();
-        }
+        let material_uniform = match material {
+            model::Material::Phong(material) => {
+                let diffuse_texture = self.resident_texture_handle(
+                    material.diffuse_texture_id,
+                    Some(self.resources_manager.placeholder_diffuse_texture),
+                );
+                let specular_texture = self.resident_texture_handle(
+                    material.specular_texture_id,
+                    Some(self.resources_manager.placeholder_specular_texture),
+                );
+                let normal_texture = self.resident_texture_handle(
+                    material.normal_texture_id,
+                    Some(self.resources_manager.placeholder_normal_texture),
+                );
 
-        let material_uniform = uniform_layouts::Material {
-            shininess: material.shininess,
-            diffuse_texture: Vec2u::new(
-                diff_texture_handle as u32,
-                (diff_texture_handle >> 32) as u32,
-            ),
-            specular_texture: Vec2u::new(
-                spec_texture_handle as u32,
-                (spec_texture_handle >> 32) as u32,
-            ),
-            normal_texture: Vec2u::new(
-                norm_texture_handle as u32,
-                (norm_texture_handle >> 32) as u32,
-            ),
-            ..Default::default()
+                uniform_layouts::Material {
+                    shininess: material.shininess,
+                    model: MaterialModel::Phong,
+                    diffuse_texture,
+                    specular_texture,
+                    normal_texture,
+                    ..Default::default()
+                }
+            }
+            model::Material::Pbr(material) => uniform_layouts::Material {
+                model: MaterialModel::Pbr,
+                diffuse_texture: self
+                    .resident_texture_handle(material.base_colour_texture_id, None),
+                normal_texture: self.resident_texture_handle(material.normal_texture_id, None),
+                metallic_roughness_texture: self
+                    .resident_texture_handle(material.metallic_roughness_texture_id, None),
+                occlusion_texture: self
+                    .resident_texture_handle(material.occlusion_texture_id, None),
+                emissive_texture: self.resident_texture_handle(material.emissive_texture_id, None),
+                base_colour: material.base_colour,
+                emissive: material.emissive,
+                metallic: material.metallic,
+                roughness: material.roughness,
+                occlusion_strength: material.occlusion_strength,
+                ..Default::default()
+            },
         };
 
         self.memory_manager
@@ -303,6 +634,28 @@ impl Renderer<'_> {
         id
     }
 
+    /// Makes the texture resident, falling back to the placeholder when there is no texture, and returns its
+    /// bindless handle split into two halves. The handle is zero when there is neither
+    fn resident_texture_handle(
+        &mut self,
+        texture_id: Option<TextureID>,
+        placeholder_id: Option<TextureID>,
+    ) -> Vec2u {
+        let handle = match texture_id.or(placeholder_id) {
+            Some(texture_id) => {
+                let texture = self
+                    .resources_manager
+                    .borrow_mut_texture(&texture_id)
+                    .unwrap();
+                texture.make_texture_resident();
+                texture.shader_texture_handle.unwrap()
+            }
+            None => 0,
+        };
+
+        Vec2u::new(handle as u32, (handle >> 32) as u32)
+    }
+
     pub fn load_texture(
         &mut self,
         path: &'static str,
@@ -311,6 +664,32 @@ impl Renderer<'_> {
         self.resources_manager.load_texture(path, config)
     }
 
+    /// Creates a texture that cameras can draw into, by targeting it, and that materials can sample
+    pub fn create_render_texture(
+        &mut self,
+        width: u32,
+        height: u32,
+    ) -> Result<RenderTexture, String> {
+        let (texture_id, framebuffer_id) =
+            self.resources_manager.load_render_texture(width, height)?;
+
+        Ok(RenderTexture {
+            texture_id,
+            framebuffer_id,
+            width,
+            height,
+        })
+    }
+
+    /// Loads a 3D LUT for [`PostEffect::colour_grading`](super::post_effects::PostEffect::colour_grading)
+    pub fn load_lut(
+        &mut self,
+        path: &'static str,
+        config: &TextureConfig,
+    ) -> Result<TextureID, String> {
+        self.resources_manager.load_lut(path, config)
+    }
+
     pub fn load_skybox_textures(
         &mut self,
         paths: [&'static str; 6],
@@ -318,4 +697,22 @@ impl Renderer<'_> {
     ) -> Result<TextureID, String> {
         self.resources_manager.load_skybox_textures(paths, config)
     }
+
+    /// Bakes image based lighting from a loaded texture, which is either a cubemap or an equirectangular texture,
+    /// and lights the scene with it from then on. The returned environment can be switched back to later
+    pub fn load_environment(&mut self, texture_id: TextureID) -> Result<Environment, String> {
+        let environment = self.environment_baker.bake(
+            texture_id,
+            &mut self.resources_manager,
+            &mut self.renderer_state,
+        )?;
+        self.renderer_state.environment = Some(environment);
+
+        Ok(environment)
+    }
+
+    /// Switches to a previously loaded environment, where None turns image based lighting off
+    pub fn set_environment(&mut self, environment: Option<Environment>) {
+        self.renderer_state.environment = environment;
+    }
 }
@@ -0,0 +1,223 @@
+use crate::math::*;
+
+#[derive(Clone, Copy)]
+pub struct ShadowAtlasSettings {
+    /// Size of the tile given to a light that covers the whole screen
+    pub max_tile_size: u32,
+    /// Lights are given at least this size, however far away they are, unless the atlas is too full
+    pub min_tile_size: u32,
+    /// Scales the screen coverage of every light before it is turned into a tile size
+    pub importance_scale: f32,
+}
+
+impl Default for ShadowAtlasSettings {
+    fn default() -> Self {
+        Self {
+            max_tile_size: 2048,
+            min_tile_size: 128,
+            importance_scale: 1.0,
+        }
+    }
+}
+
+/// A square region of the atlas, in texels
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AtlasTile {
+    pub x: u32,
+    pub y: u32,
+    pub size: u32,
+}
+
+impl AtlasTile {
+    /// The offset (xy) and scale (zw) that take UVs within the tile to UVs within the atlas
+    pub fn uv_rect(&self, atlas_size: u32) -> Vec4f {
+        let atlas_size = atlas_size as f32;
+
+        Vec4f::new(
+            self.x as f32 / atlas_size,
+            self.y as f32 / atlas_size,
+            self.size as f32 / atlas_size,
+            self.size as f32 / atlas_size,
+        )
+    }
+
+    pub fn overlaps(&self, other: &AtlasTile) -> bool {
+        self.x < other.x + other.size
+            && other.x < self.x + self.size
+            && self.y < other.y + other.size
+            && other.y < self.y + self.size
+    }
+}
+
+/// Hands out power of two tiles of a square, power of two atlas, by splitting larger free tiles into quarters
+/// until they are the requested size. <br>
+/// Tiles are never freed individually, instead the whole atlas is cleared when the tiles are reallocated.
+pub struct ShadowAtlasAllocator {
+    size: u32,
+    min_tile_size: u32,
+    /// Free tiles of each size, from the whole atlas at index 0 down to `min_tile_size`
+    free_tiles: Vec<Vec<AtlasTile>>,
+}
+
+impl ShadowAtlasAllocator {
+    pub fn new(size: u32, min_tile_size: u32) -> Self {
+        let size = size.next_power_of_two();
+        let min_tile_size = min_tile_size.next_power_of_two().clamp(1, size);
+        let levels = (size / min_tile_size).trailing_zeros() as usize + 1;
+
+        let mut allocator = Self {
+            size,
+            min_tile_size,
+            free_tiles: vec![Vec::new(); levels],
+        };
+        allocator.clear();
+
+        allocator
+    }
+
+    pub fn size(&self) -> u32 {
+        self.size
+    }
+
+    pub fn min_tile_size(&self) -> u32 {
+        self.min_tile_size
+    }
+
+    /// Frees every tile, leaving the whole atlas available
+    pub fn clear(&mut self) {
+        for tiles in self.free_tiles.iter_mut() {
+            tiles.clear();
+        }
+
+        self.free_tiles[0].push(AtlasTile {
+            x: 0,
+            y: 0,
+            size: self.size,
+        });
+    }
+
+    /// Allocates a tile of at least the requested size, rounded up to a power of two and clamped to the limits
+    /// of the atlas. Returns `None` when there is no space left for a tile of that size.
+    pub fn allocate(&mut self, size: u32) -> Option<AtlasTile> {
+        let size = size
+            .clamp(self.min_tile_size, self.size)
+            .next_power_of_two();
+        let level = self.level(size);
+
+        // the smallest free tile that the request fits in
+        let free_level = (0..=level)
+            .rev()
+            .find(|&free_level| !self.free_tiles[free_level].is_empty())?;
+        let mut tile = self.free_tiles[free_level].pop().unwrap();
+
+        for split_level in free_level + 1..=level {
+            let half = tile.size / 2;
+
+            // keep the first quarter, so tiles are handed out from the top left of each split
+            for (x, y) in [(half, half), (0, half), (half, 0)] {
+                self.free_tiles[split_level].push(AtlasTile {
+                    x: tile.x + x,
+                    y: tile.y + y,
+                    size: half,
+                });
+            }
+
+            tile.size = half;
+        }
+
+        Some(tile)
+    }
+
+    /// Clears the atlas, then allocates a tile for each of the requested sizes. <br>
+    /// When the requests add up to more than the atlas, the largest of them are halved until they fit, so that lights
+    /// lose resolution before any of them lose their shadow. Only once every request is at the minimum tile size
+    /// are the last of them left without a tile.
+    pub fn allocate_all(&mut self, sizes: &[u32]) -> Vec<Option<AtlasTile>> {
+        self.clear();
+
+        let mut sizes: Vec<u32> = sizes
+            .iter()
+            .map(|&size| {
+                size.clamp(self.min_tile_size, self.size)
+                    .next_power_of_two()
+            })
+            .collect();
+
+        let capacity = self.size as u64 * self.size as u64;
+        let area = |sizes: &[u32]| {
+            sizes
+                .iter()
+                .map(|size| *size as u64 * *size as u64)
+                .sum::<u64>()
+        };
+
+        while area(&sizes) > capacity {
+            // the last of the largest, so earlier requests keep their size when they are equal
+            let largest = sizes
+                .iter()
+                .enumerate()
+                .filter(|(_, size)| **size > self.min_tile_size)
+                .max_by_key(|(index, size)| (**size, *index))
+                .map(|(index, _)| index);
+
+            match largest {
+                Some(index) => sizes[index] /= 2,
+                None => break,
+            }
+        }
+
+        // larger tiles first, so that the free tiles are never smaller than the next request and the atlas packs
+        // without gaps. The sort is stable, so requests of equal size keep their order
+        let mut order: Vec<usize> = (0..sizes.len()).collect();
+        order.sort_by(|a, b| sizes[*b].cmp(&sizes[*a]));
+
+        let mut tiles = vec![None; sizes.len()];
+        for index in order {
+            tiles[index] = self.allocate(sizes[index]);
+        }
+
+        tiles
+    }
+
+    fn level(&self, size: u32) -> usize {
+        (self.size / size).trailing_zeros() as usize
+    }
+}
+
+/// Distance at which the light's attenuation, `1 / (Ad^2 + Bd + C)`, falls below 1/256th of its full intensity
+pub fn light_range(attenuation: Vec3f) -> f32 {
+    let (a, b, c) = (attenuation.x, attenuation.y, attenuation.z - 256.0);
+
+    if a > 0.0 {
+        (-b + (b * b - 4.0 * a * c).sqrt()) / (2.0 * a)
+    } else if b > 0.0 {
+        -c / b
+    } else {
+        f32::INFINITY
+    }
+}
+
+/// How much of the screen's height the bounding sphere of a light covers, between 0.0 and 1.0
+pub fn screen_coverage(
+    projection: &Mat4f,
+    camera_position: Vec3f,
+    position: Vec3f,
+    radius: f32,
+) -> f32 {
+    let distance = (position - camera_position).magnitude();
+    if distance <= radius {
+        return 1.0;
+    }
+
+    // the projection's Y scale is the cotangent of half the vertical field of view
+    (radius * projection[(1, 1)] / distance).min(1.0)
+}
+
+/// The tile size that a light covering the given fraction of the screen should be given
+pub fn tile_size_for_coverage(coverage: f32, settings: &ShadowAtlasSettings) -> u32 {
+    let size =
+        (coverage * settings.importance_scale * settings.max_tile_size as f32).max(1.0) as u32;
+
+    size.next_power_of_two()
+        .clamp(settings.min_tile_size, settings.max_tile_size)
+}
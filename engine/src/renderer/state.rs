@@ -2,7 +2,16 @@ use crate::{
     math::*,
     memory_manager::{
         memory_manager::MemoryManager,
-        uniform_layouts::{DirectionalLight, PointLight, SpotLight},
+        uniform_layouts::{
+            DirectionalLight, PointLight, SpotLight, MAX_POINT_LIGHTS, MAX_SPOT_LIGHTS,
+        },
+    },
+    renderer::{
+        clusters::{assign_lights, LightBounds},
+        environment::Environment,
+        occlusion::{DepthPyramid, HiZ},
+        shadow_atlas::light_range,
+        view::ViewOutput,
     },
     resource_manager::resource_manager::{
         FramebufferID, ResourceIDTrait, ResourceManagerTrait, ResourcesManager, ShaderProgramID,
@@ -17,16 +26,34 @@ pub struct RendererState {
     // pub vertex_buffer: ,
     // pub indirect_draw_buffer: ,
     pub view_transform: Mat4f,
+    /// Includes the camera's jitter
     pub projection_transform: Mat4f,
+    pub unjittered_projection_transform: Mat4f,
+    /// Sub-pixel offset of `projection_transform`, in pixels
+    pub jitter: Vec2f,
     pub camera_position: Vec3f,
     pub camera_direction: Vec3f,
 
     pub point_lights: Vec<PointLight>,
     pub spot_lights: Vec<SpotLight>,
     pub directional_light: Option<DirectionalLight>,
+    /// Lights PBR materials with image based lighting, which is kept between frames unlike the other lights
+    pub environment: Option<Environment>,
 
-    pub light_ortho_projection: Mat4f,
     pub light_persp_projection: Mat4f,
+
+    /// Counts the frames that have been drawn
+    pub frame: u64,
+    /// Seconds between the start of the last frame and this one, for effects that change over time
+    pub delta_time: f32,
+    /// Hi-Z pyramid of the last frame that was drawn, which renderables are occlusion tested against on the GPU
+    pub hiz: Option<HiZ>,
+    /// Coarser levels of a Hi-Z pyramid that have been read back to the CPU, so are a few frames behind
+    pub depth_pyramid: Option<DepthPyramid>,
+    /// Whether the view being drawn keeps the history of temporal effects and occlusion culling, see
+    /// [`crate::renderer::view::primary_view`]
+    pub primary_view: bool,
+    pub output: ViewOutput,
 }
 
 impl RendererState {
@@ -37,15 +64,30 @@ impl RendererState {
 
             view_transform: Mat4f::identity(),
             projection_transform: Mat4f::identity(),
+            unjittered_projection_transform: Mat4f::identity(),
+            jitter: Vec2f::uniform(0.0),
             camera_position: Vec3f::new(0.0, 0.0, 0.0),
             camera_direction: Vec3f::new(0.0, 0.0, 0.0),
 
             point_lights: Vec::new(),
             spot_lights: Vec::new(),
             directional_light: None,
+            environment: None,
 
-            light_ortho_projection: Mat4f::identity(),
             light_persp_projection: Mat4f::identity(),
+
+            frame: 0,
+            delta_time: 0.0,
+            hiz: None,
+            depth_pyramid: None,
+            primary_view: true,
+            output: ViewOutput {
+                framebuffer: None,
+                x: 0,
+                y: 0,
+                width: crate::WIDTH,
+                height: crate::HEIGHT,
+            },
         }
     }
 
@@ -57,11 +99,40 @@ impl RendererState {
     }
 
     pub fn upload_light_data(&self, memory_manager: &mut MemoryManager) {
-        memory_manager.set_point_light_data_slice(&self.point_lights);
-        memory_manager.set_point_light_count(self.point_lights.len() as u32);
-
-        memory_manager.set_spot_light_data_slice(&self.spot_lights);
-        memory_manager.set_spot_light_count(self.spot_lights.len() as u32);
+        let point_lights = &self.point_lights[..self.point_lights.len().min(MAX_POINT_LIGHTS)];
+        let spot_lights = &self.spot_lights[..self.spot_lights.len().min(MAX_SPOT_LIGHTS)];
+
+        memory_manager.set_point_light_data_slice(point_lights);
+        memory_manager.set_point_light_count(point_lights.len() as u32);
+
+        memory_manager.set_spot_light_data_slice(spot_lights);
+        memory_manager.set_spot_light_count(spot_lights.len() as u32);
+
+        // the lighting shaders only loop over the lights in the fragment's cluster, rather than every light
+        let point_bounds: Vec<LightBounds> = point_lights
+            .iter()
+            .map(|light| LightBounds {
+                position: light.position,
+                radius: light_range(light.attenuation),
+            })
+            .collect();
+        let spot_bounds: Vec<LightBounds> = spot_lights
+            .iter()
+            .map(|light| LightBounds {
+                position: light.position,
+                radius: light_range(light.attenuation),
+            })
+            .collect();
+
+        let grid = assign_lights(
+            &self.projection_transform,
+            &self.view_transform,
+            &point_bounds,
+            &spot_bounds,
+        );
+        memory_manager.set_light_clusters(&grid.clusters);
+        memory_manager.set_cluster_light_indices(&grid.light_indices);
+        memory_manager.set_cluster_depth_parameters(grid.depth_scale, grid.depth_bias);
 
         if let Some(directional_light) = self.directional_light {
             memory_manager.set_directional_light_data(directional_light);
@@ -69,6 +140,15 @@ impl RendererState {
         } else {
             memory_manager.set_directional_light_count(0);
         }
+
+        // set by the ambient occlusion stage, if it runs this frame
+        memory_manager.set_ambient_occlusion_map(0);
+
+        if let Some(environment) = &self.environment {
+            environment.upload(memory_manager);
+        } else {
+            memory_manager.set_environment_maps(0, 0, 0, 0.0);
+        }
     }
 
     pub fn reset_lights(&mut self) {
@@ -77,6 +157,20 @@ impl RendererState {
         self.directional_light = None;
     }
 
+    /// The read back depth pyramid, if it is recent enough to occlusion test against. Only the primary view has
+    /// one
+    pub fn fresh_depth_pyramid(&self) -> Option<&DepthPyramid> {
+        self.depth_pyramid
+            .as_ref()
+            .filter(|pyramid| self.primary_view && pyramid.is_fresh(self.frame))
+    }
+
+    /// The Hi-Z pyramid, if it was built from the frame before this one. Only the primary view has one
+    pub fn previous_hiz(&self) -> Option<HiZ> {
+        self.hiz
+            .filter(|hiz| self.primary_view && hiz.is_previous_frame(self.frame))
+    }
+
     pub fn set_shader_program(
         &mut self,
         shader_program_id: ShaderProgramID,
@@ -0,0 +1,1728 @@
+mod tests {
+    #![allow(unused_imports)]
+    use super::*;
+    use crate::{
+        components::MeshInstance,
+        graphics::{
+            framebuffer::{FramebufferAttachmentConfig, FramebufferConfig, InternalFormat},
+            state::Comparison,
+            texture::{lut_size, lut_volume, TextureType},
+        },
+        math::*,
+        memory_manager::{
+            memory_manager::InstanceData,
+            uniform_layouts::{
+                CullBatch, CullHeader, DirectionalLight, Material, MaterialModel, PointLight,
+                ShadowFilter, ShadowSettings, SpotLight, CLUSTER_GRID_X, CLUSTER_GRID_Y,
+                CLUSTER_GRID_Z, MAX_CASCADES,
+            },
+        },
+        renderer::{
+            camera::Camera,
+            cascades::{calculate_cascades, split_distances, CascadeSettings},
+            clusters::{assign_lights, cluster_index, depth_slice, LightBounds, LightGrid},
+            command::{DrawCommands, SortKeyField, SortKeyLayout, SortKeyValues},
+            culling::{cull_renderables, Frustum},
+            environment::{equirect_face_size, prefiltered_roughness, PREFILTERED_LEVELS},
+            gpu_culling::frustum_planes,
+            graph::{PassBuilder, RenderGraph},
+            lod::{screen_size, select_lod, LodSelection, LodSettings, LodStats},
+            pipeline::RendererPipeline,
+            occlusion::{
+                mip_level_count, readback_level, DepthLevel, DepthPyramid, MAX_DEPTH_PYRAMID_AGE,
+                MAX_READBACK_SIZE,
+            },
+            pipeline_stages::{
+                anti_aliasing::{halton, taa_jitter},
+                ao::ao_pass,
+                bloom::{bloom_mip_count, MIN_BLOOM_SIZE},
+                msaa::{scene_targets, validate_samples, MsaaResolveStage},
+                post_process::{adaptation_rate, exposure_scale},
+                scene::{scene_pass, scene_rasteriser_state},
+                sky::SkyStage,
+                transparent::back_to_front,
+                PipelineStage, StageName, RESOURCE_AO, RESOURCE_DEPTH, RESOURCE_HDR, RESOURCE_MSAA,
+                RESOURCE_MSAA_DEPTH, RESOURCE_SHADOW_MAPS, RENDER_SCENE, RENDER_TRANSPARENT, STAGE_AO,
+                STAGE_DEPTH, STAGE_MSAA_RESOLVE, STAGE_SCENE, STAGE_SHADOW, STAGE_SKY,
+            },
+            post_effects::{PostEffect, PostEffectStack, PostParameter, PostPassKind, PostShader},
+            shadow_atlas::{
+                light_range, tile_size_for_coverage, AtlasTile, ShadowAtlasAllocator,
+                ShadowAtlasSettings,
+            },
+        },
+        resource_manager::{
+            model::{Aabb, LodChain, LodLevel, Mesh, Vertex, MAX_LOD_LEVELS},
+            prefabs::unit_cube_mesh,
+            resource_manager::{
+                FramebufferID, MaterialID, MeshID, MeshMut, ResourceIDTrait, ResourceManager,
+                ResourceManagerTrait, ShaderProgramID,
+            },
+        },
+    };
+
+    fn unit_aabb() -> Aabb {
+        Aabb::new(Vec3f::uniform(-0.5), Vec3f::uniform(0.5))
+    }
+
+    /// Camera at the origin, looking down -Z
+    fn camera_frustum() -> Frustum {
+        let projection = Mat4f::perspective(1.0, 90f32.to_radians(), 0.1, 100.0);
+        let view = Camera::look_at(
+            &Vec3f::new(0.0, 0.0, 0.0),
+            &Vec3f::new(0.0, 0.0, 1.0),
+            &Vec3f::new(0.0, 1.0, 0.0),
+        );
+        Frustum::from_matrix(&(projection * view))
+    }
+
+    fn renderable(mesh_id: MeshID, transform: Mat4f) -> MeshInstance {
+        MeshInstance {
+            mesh_id,
+            material_id: MaterialID::new(0),
+            shader_id: ShaderProgramID::new(0),
+            transform,
+            pipeline_stages: 0,
+            lod_fade: 0.0,
+        }
+    }
+
+    #[test]
+    fn aabb_from_vertices_test() {
+        let vertex = |x, y, z| Vertex {
+            position: Vec3f::new(x, y, z),
+            normal: Vec3f::uniform(0.0),
+            tangent: Vec3f::uniform(0.0),
+            colour: Vec4f::uniform(0.0),
+            tex_coord: Vec2f::default(),
+        };
+        let aabb = Aabb::from_vertices(&[
+            vertex(1.0, -2.0, 3.0),
+            vertex(-4.0, 5.0, 0.0),
+            vertex(0.0, 0.0, -6.0),
+        ]);
+
+        assert_eq!((-4.0, -2.0, -6.0), aabb.min.as_tuple());
+        assert_eq!((1.0, 5.0, 3.0), aabb.max.as_tuple());
+    }
+
+    #[test]
+    fn aabb_transform_test() {
+        let aabb = unit_aabb()
+            .transform(&(Mat4f::translate(10.0, 0.0, 0.0) * Mat4f::scale(2.0, 1.0, 1.0)));
+
+        assert_eq!((9.0, -0.5, -0.5), aabb.min.as_tuple());
+        assert_eq!((11.0, 0.5, 0.5), aabb.max.as_tuple());
+
+        // a 45 degree rotation grows the box to enclose the rotated corners
+        let aabb = unit_aabb().transform(&Mat4f::rotate_around_y(45f32.to_radians()));
+
+        assert!((aabb.max.x - 0.5 * 2f32.sqrt()).abs() < 1e-5);
+        assert!((aabb.max.y - 0.5).abs() < 1e-5);
+    }
+
+    #[test]
+    fn frustum_point_test() {
+        let frustum = camera_frustum();
+
+        assert!(frustum.contains_point(Vec3f::new(0.0, 0.0, -10.0)));
+        assert!(!frustum.contains_point(Vec3f::new(0.0, 0.0, 10.0)));
+        assert!(!frustum.contains_point(Vec3f::new(0.0, 0.0, -200.0)));
+        assert!(!frustum.contains_point(Vec3f::new(0.0, 0.0, -0.01)));
+        assert!(!frustum.contains_point(Vec3f::new(20.0, 0.0, -10.0)));
+        assert!(!frustum.contains_point(Vec3f::new(0.0, -20.0, -10.0)));
+    }
+
+    #[test]
+    fn frustum_aabb_test() {
+        let frustum = camera_frustum();
+
+        assert!(frustum.intersects_aabb(&unit_aabb().transform(&Mat4f::translate(0.0, 0.0, -5.0))));
+        assert!(!frustum.intersects_aabb(&unit_aabb().transform(&Mat4f::translate(0.0, 0.0, 5.0))));
+        // straddling the left plane, with the centre outside
+        assert!(frustum.intersects_aabb(&unit_aabb().transform(&Mat4f::translate(-5.3, 0.0, -5.0))));
+        assert!(!frustum.intersects_aabb(&unit_aabb().transform(&Mat4f::translate(-7.0, 0.0, -5.0))));
+
+        assert!(frustum.intersects_sphere(Vec3f::new(0.0, 0.0, 1.0), 1.5));
+        assert!(!frustum.intersects_sphere(Vec3f::new(0.0, 0.0, 1.0), 0.5));
+    }
+
+    #[test]
+    fn cull_renderables_test() {
+        let mut mesh_bounds: ResourceManager<Aabb, MeshID> = ResourceManager::new();
+        let mesh_id = mesh_bounds.load(unit_aabb());
+
+        let renderables = [
+            renderable(mesh_id, Mat4f::translate(0.0, 0.0, -5.0)),
+            renderable(mesh_id, Mat4f::translate(0.0, 0.0, 5.0)),
+            renderable(mesh_id, Mat4f::translate(50.0, 0.0, -5.0)),
+            renderable(mesh_id, Mat4f::translate(0.0, 0.0, -50.0)),
+        ];
+
+        let mut indices = vec![0, 1, 2, 3];
+        cull_renderables(&[camera_frustum()], &renderables, &mesh_bounds, &mut indices);
+        assert_eq!(vec![0, 3], indices);
+
+        // a second frustum, looking down +Z, keeps anything visible to either
+        let behind = Frustum::from_matrix(
+            &(Mat4f::perspective(1.0, 90f32.to_radians(), 0.1, 100.0)
+                * Camera::look_at(
+                    &Vec3f::new(0.0, 0.0, 0.0),
+                    &Vec3f::new(0.0, 0.0, -1.0),
+                    &Vec3f::new(0.0, 1.0, 0.0),
+                )),
+        );
+        let mut indices = vec![0, 1, 2, 3];
+        cull_renderables(
+            &[camera_frustum(), behind],
+            &renderables,
+            &mesh_bounds,
+            &mut indices,
+        );
+        assert_eq!(vec![0, 1, 3], indices);
+
+        let mut indices = vec![0, 1, 2, 3];
+        cull_renderables(&[], &renderables, &mesh_bounds, &mut indices);
+        assert!(indices.is_empty());
+    }
+
+    #[test]
+    fn mutated_mesh_bounds_test() {
+        let mut meshes: ResourceManager<Mesh, MeshID> = ResourceManager::new();
+        let mut mesh_bounds: ResourceManager<Aabb, MeshID> = ResourceManager::new();
+        let mesh = unit_cube_mesh(Vec4f::uniform(1.0));
+        mesh_bounds.load(Aabb::from_vertices(&mesh.vertices));
+        let mesh_id = meshes.load(mesh);
+
+        // the renderable is placed behind the camera, and its vertices are moved in front of it
+        let renderables = [renderable(mesh_id, Mat4f::translate(0.0, 0.0, 5.0))];
+        let mut indices = vec![0];
+        cull_renderables(&[camera_frustum()], &renderables, &mesh_bounds, &mut indices);
+        assert!(indices.is_empty());
+
+        {
+            let mut mesh = MeshMut::new(&mut meshes, &mut mesh_bounds, &mesh_id).unwrap();
+            for vertex in mesh.vertices.iter_mut() {
+                vertex.position.z -= 10.0;
+            }
+        }
+
+        let mut indices = vec![0];
+        cull_renderables(&[camera_frustum()], &renderables, &mesh_bounds, &mut indices);
+        assert_eq!(vec![0], indices);
+        assert_eq!(-10.5, mesh_bounds.borrow(&mesh_id).unwrap().min.z);
+    }
+
+    #[test]
+    fn cascade_split_test() {
+        let splits = split_distances(0.1, 100.0, 4, 0.75);
+
+        assert!(splits.windows(2).all(|pair| pair[0] < pair[1]));
+        assert_eq!(100.0, splits[3]);
+
+        // a lambda of zero gives evenly sized cascades
+        let splits = split_distances(10.0, 90.0, 4, 0.0);
+        assert_eq!([30.0, 50.0, 70.0, 90.0], splits);
+
+        // unused cascades end at the far plane
+        let splits = split_distances(0.1, 100.0, 2, 0.5);
+        assert_eq!([100.0, 100.0], [splits[2], splits[3]]);
+    }
+
+    #[test]
+    fn cascade_split_scheme_test() {
+        // a lambda of one gives logarithmic splits, where each cascade covers the same ratio of depths
+        let splits = split_distances(1.0, 1000.0, 3, 1.0);
+        for (split, expected) in splits.iter().zip([10.0, 100.0, 1000.0]) {
+            assert!((split - expected).abs() < 1e-3 * expected);
+        }
+
+        // raising lambda moves every split towards the camera, where shadow detail matters most
+        let uniform = split_distances(0.1, 100.0, 4, 0.0);
+        let practical = split_distances(0.1, 100.0, 4, 0.5);
+        let logarithmic = split_distances(0.1, 100.0, 4, 1.0);
+        for i in 0..3 {
+            assert!(logarithmic[i] < practical[i] && practical[i] < uniform[i]);
+            assert!((practical[i] - (uniform[i] + logarithmic[i]) / 2.0).abs() < 1e-4 * uniform[i]);
+        }
+        assert_eq!([100.0; 3], [uniform[3], practical[3], logarithmic[3]]);
+    }
+
+    #[test]
+    fn cascade_count_test() {
+        // counts outside of the supported range are clamped, so there is always at least one cascade
+        assert_eq!([50.0; MAX_CASCADES], split_distances(1.0, 50.0, 0, 0.75));
+        assert_eq!([50.0; MAX_CASCADES], split_distances(1.0, 50.0, 1, 0.75));
+        assert_eq!(
+            split_distances(1.0, 50.0, MAX_CASCADES, 0.75),
+            split_distances(1.0, 50.0, MAX_CASCADES + 3, 0.75)
+        );
+
+        let projection = Mat4f::perspective(16.0 / 9.0, 70f32.to_radians(), 0.1, 100.0);
+        let light_direction = Vec3f::new(0.3, 1.0, 0.5);
+        let cascades_with = |settings: CascadeSettings| {
+            calculate_cascades(
+                &projection,
+                &Mat4f::identity(),
+                light_direction,
+                1024,
+                &settings,
+            )
+        };
+
+        let cascades = cascades_with(CascadeSettings {
+            count: 0,
+            ..Default::default()
+        });
+        assert_eq!(1, cascades.count);
+        assert_eq!(100.0, cascades.splits[0]);
+
+        let cascades = cascades_with(CascadeSettings {
+            count: MAX_CASCADES as u32 + 1,
+            ..Default::default()
+        });
+        assert_eq!(MAX_CASCADES, cascades.count);
+    }
+
+    #[test]
+    fn cascade_order_test() {
+        let projection = Mat4f::perspective(16.0 / 9.0, 70f32.to_radians(), 0.1, 500.0);
+        let light_direction = Vec3f::new(0.3, 1.0, 0.5);
+        let settings = CascadeSettings {
+            count: 3,
+            ..Default::default()
+        };
+
+        let cascades = calculate_cascades(
+            &projection,
+            &Mat4f::identity(),
+            light_direction,
+            1024,
+            &settings,
+        );
+
+        // cascades are ordered from the camera outwards, ending at the maximum shadow distance rather than the far
+        // plane
+        assert_eq!(3, cascades.count);
+        assert!(cascades.splits[0] > 0.1);
+        assert!(
+            cascades.splits[..3]
+                .windows(2)
+                .all(|pair| pair[0] < pair[1])
+        );
+        assert!((cascades.splits[2] - settings.max_distance).abs() < 1e-3);
+
+        // each cascade covers a longer slice than the last, so its texels are larger
+        let texel_sizes: Vec<f32> = cascades.views[..3]
+            .iter()
+            .map(|view| {
+                1.0 / (*view)[(0, 0)]
+                    .hypot((*view)[(0, 1)])
+                    .hypot((*view)[(0, 2)])
+            })
+            .collect();
+        assert!(texel_sizes.windows(2).all(|pair| pair[0] < pair[1]));
+
+        // the maximum distance is clamped to the far plane, which is only recovered from the projection to within
+        // floating point precision
+        let cascades = calculate_cascades(
+            &projection,
+            &Mat4f::identity(),
+            light_direction,
+            1024,
+            &CascadeSettings {
+                max_distance: 1000.0,
+                ..settings
+            },
+        );
+        assert!((cascades.splits[2] - 500.0).abs() < 500.0 * 1e-3);
+    }
+
+    #[test]
+    fn cascade_fit_test() {
+        const RESOLUTION: u32 = 2048;
+        let projection = Mat4f::perspective(16.0 / 9.0, 70f32.to_radians(), 0.1, 100.0);
+        let light_direction = Vec3f::new(0.3, 1.0, 0.5);
+        let settings = CascadeSettings::default();
+
+        let camera_view = |position: Vec3f| {
+            Camera::look_at(
+                &position,
+                &Vec3f::new(0.2, 0.1, 1.0).normalise(),
+                &Vec3f::new(0.0, 1.0, 0.0),
+            )
+        };
+
+        let view = camera_view(Vec3f::new(0.0, 2.0, 0.0));
+        let cascades =
+            calculate_cascades(&projection, &view, light_direction, RESOLUTION, &settings);
+
+        // the corners of each slice of the camera frustum fall within its cascade
+        let inverse_projection = projection.inverse().unwrap();
+        let inverse_view = view.inverse().unwrap();
+        let mut slice_start = 0.1;
+        for i in 0..cascades.count {
+            for depth in [slice_start, cascades.splits[i]] {
+                let corner = inverse_projection * Vec4f::new(1.0, 1.0, -1.0, 1.0);
+                // scale the near plane corner out to the depth, in view space
+                let corner = Vec3f::from(corner) / corner.w;
+                let corner = corner * (depth / -corner.z);
+                let world = inverse_view * Vec4f::new(corner.x, corner.y, corner.z, 1.0);
+                let clip = cascades.views[i] * world;
+
+                assert!(clip.x.abs() <= 1.0 && clip.y.abs() <= 1.0 && clip.z.abs() <= 1.0);
+            }
+            slice_start = cascades.splits[i];
+        }
+
+        // moving the camera slightly moves the shadow map by whole texels, so a point stays on the same texel
+        // boundaries
+        let moved_view = camera_view(Vec3f::new(0.37, 2.21, -0.13));
+        let moved = calculate_cascades(
+            &projection,
+            &moved_view,
+            light_direction,
+            RESOLUTION,
+            &settings,
+        );
+        let point = Vec4f::new(3.0, 0.5, 7.0, 1.0);
+
+        for i in 0..cascades.count {
+            let texel_offset = ((moved.views[i] * point).x - (cascades.views[i] * point).x)
+                * RESOLUTION as f32
+                / 2.0;
+            assert!((texel_offset - texel_offset.round()).abs() < 1e-2);
+        }
+    }
+
+    fn pass(reads: &[&'static str], writes: &[&'static str]) -> PassBuilder {
+        let mut pass = PassBuilder::default();
+        for name in reads {
+            pass.read(name);
+        }
+        for name in writes {
+            pass.write(name);
+        }
+        pass
+    }
+
+    fn colour_config(internal_format: InternalFormat) -> FramebufferConfig {
+        FramebufferConfig {
+            colour: FramebufferAttachmentConfig::Texture {
+                target: TextureType::T2D,
+                internal_format,
+                layers: 1,
+                levels: 1,
+            },
+            width: 64,
+            height: 64,
+            ..Default::default()
+        }
+    }
+
+    fn graph_with_resources(names: &[&'static str]) -> RenderGraph {
+        let mut graph = RenderGraph::new();
+        for name in names {
+            graph.declare_external(name);
+        }
+        graph.mark_output("backbuffer");
+        graph
+    }
+
+    fn pass_names(graph: &RenderGraph, passes: &[usize]) -> Vec<&'static str> {
+        passes.iter().map(|i| graph.get_pass(*i).name).collect()
+    }
+
+    #[test]
+    fn graph_order_test() {
+        let mut graph = graph_with_resources(&["shadow_maps", "hdr", "bloom", "backbuffer"]);
+        graph.import_framebuffer("hdr", FramebufferID::new(0));
+
+        // added in reverse, the dependencies decide the order
+        graph.add_pass("post", pass(&["hdr", "bloom"], &["backbuffer"]));
+        graph.add_pass("bloom", pass(&["hdr"], &["bloom"]));
+        graph.add_pass("scene", pass(&["shadow_maps"], &["hdr"]));
+        graph.add_pass("shadow", pass(&[], &["shadow_maps"]));
+
+        let compiled = graph.compile().unwrap();
+
+        assert_eq!(
+            vec!["shadow", "scene", "bloom", "post"],
+            pass_names(&graph, &compiled.order)
+        );
+        assert!(compiled.culled.is_empty());
+
+        // writers of the same resource keep the order they were added in
+        let mut graph = graph_with_resources(&["hdr", "backbuffer"]);
+        graph.add_pass("post", pass(&["hdr"], &["backbuffer"]));
+        graph.add_pass("scene", pass(&[], &["hdr"]));
+        graph.add_pass("sky", pass(&["hdr"], &["hdr"]));
+
+        let compiled = graph.compile().unwrap();
+
+        assert_eq!(
+            vec!["scene", "sky", "post"],
+            pass_names(&graph, &compiled.order)
+        );
+    }
+
+    #[test]
+    fn graph_cull_test() {
+        let mut graph = graph_with_resources(&["hdr", "unused", "backbuffer"]);
+
+        graph.add_pass("scene", pass(&[], &["hdr"]));
+        graph.add_pass("unused", pass(&["hdr"], &["unused"]));
+        graph.add_pass("post", pass(&["hdr"], &["backbuffer"]));
+
+        let mut capture = pass(&["hdr"], &[]);
+        capture.side_effects();
+        graph.add_pass("capture", capture);
+
+        let compiled = graph.compile().unwrap();
+
+        assert_eq!(
+            vec!["scene", "post", "capture"],
+            pass_names(&graph, &compiled.order)
+        );
+        assert_eq!(vec!["unused"], pass_names(&graph, &compiled.culled));
+    }
+
+    #[test]
+    fn graph_error_test() {
+        let mut graph = graph_with_resources(&["a", "b", "backbuffer"]);
+
+        // reads of a resource written by nobody else do not create a dependency, so a cycle needs two writers
+        graph.add_pass("first", pass(&["b"], &["a"]));
+        graph.add_pass("second", pass(&["a"], &["b"]));
+        graph.add_pass("third", pass(&[], &["a", "b"]));
+        assert!(graph.compile().is_err());
+
+        let mut graph = graph_with_resources(&["backbuffer"]);
+        graph.add_pass("post", pass(&["missing"], &["backbuffer"]));
+        assert!(graph.compile().is_err());
+    }
+
+    #[test]
+    fn graph_transient_aliasing_test() {
+        let mut graph = graph_with_resources(&["backbuffer"]);
+
+        let mut first = PassBuilder::default();
+        first.create_framebuffer("first", colour_config(InternalFormat::RGBA16F), true);
+        graph.add_pass("first", first);
+
+        let mut second = pass(&["first"], &[]);
+        second.create_framebuffer("second", colour_config(InternalFormat::RGBA16F), true);
+        graph.add_pass("second", second);
+
+        // `first` is no longer used by now, so `third` can take its framebuffer
+        let mut third = pass(&["second"], &[]);
+        third.create_framebuffer("third", colour_config(InternalFormat::RGBA16F), true);
+        graph.add_pass("third", third);
+
+        // same lifetime, but a different format
+        let mut fourth = pass(&["third"], &[]);
+        fourth.create_framebuffer("fourth", colour_config(InternalFormat::RGBA8), true);
+        graph.add_pass("fourth", fourth);
+
+        graph.add_pass("post", pass(&["fourth"], &["backbuffer"]));
+
+        let compiled = graph.compile().unwrap();
+
+        assert_eq!((0, 1), compiled.lifetimes["first"]);
+        assert_eq!((1, 2), compiled.lifetimes["second"]);
+        assert_ne!(compiled.allocations["first"], compiled.allocations["second"]);
+        assert_eq!(compiled.allocations["first"], compiled.allocations["third"]);
+        assert_ne!(compiled.allocations["third"], compiled.allocations["fourth"]);
+        assert_eq!(3, compiled.slots.len());
+    }
+
+    #[test]
+    fn atlas_allocate_test() {
+        let mut allocator = ShadowAtlasAllocator::new(1024, 64);
+
+        let tiles = allocator.allocate_all(&[256, 512, 100, 256, 256, 64]);
+        let tiles: Vec<AtlasTile> = tiles.into_iter().map(|tile| tile.unwrap()).collect();
+
+        // rounded up to powers of two
+        let sizes: Vec<u32> = tiles.iter().map(|tile| tile.size).collect();
+        assert_eq!(vec![256, 512, 128, 256, 256, 64], sizes);
+
+        for (i, tile) in tiles.iter().enumerate() {
+            assert!(tile.x + tile.size <= 1024 && tile.y + tile.size <= 1024);
+            assert_eq!(0, tile.x % tile.size);
+            assert_eq!(0, tile.y % tile.size);
+
+            for other in tiles[i + 1..].iter() {
+                assert!(!tile.overlaps(other));
+            }
+        }
+
+        let uv_rect = tiles[1].uv_rect(1024);
+        assert_eq!(
+            (0.0, 0.0, 0.5, 0.5),
+            (uv_rect.x, uv_rect.y, uv_rect.z, uv_rect.w)
+        );
+    }
+
+    #[test]
+    fn atlas_full_test() {
+        let mut allocator = ShadowAtlasAllocator::new(1024, 256);
+        let sizes = |tiles: Vec<Option<AtlasTile>>| -> Vec<Option<u32>> {
+            tiles
+                .iter()
+                .map(|tile| tile.map(|tile| tile.size))
+                .collect()
+        };
+
+        // the largest request is halved so that the others still fit
+        let tiles = allocator.allocate_all(&[1024, 512, 256]);
+        assert_eq!(vec![Some(512), Some(512), Some(256)], sizes(tiles));
+
+        let tiles = allocator.allocate_all(&[1024, 1024, 1024]);
+        assert_eq!(vec![Some(512), Some(512), Some(512)], sizes(tiles));
+
+        // sixteen minimum size tiles fill the atlas, so the last request goes without
+        let tiles = allocator.allocate_all(&[256; 17]);
+        assert!(tiles[..16].iter().all(|tile| tile.is_some()));
+        assert_eq!(None, tiles[16]);
+    }
+
+    #[test]
+    fn atlas_tile_size_test() {
+        let settings = ShadowAtlasSettings {
+            max_tile_size: 1024,
+            min_tile_size: 128,
+            importance_scale: 1.0,
+        };
+
+        assert_eq!(1024, tile_size_for_coverage(1.0, &settings));
+        assert_eq!(512, tile_size_for_coverage(0.3, &settings));
+        assert_eq!(128, tile_size_for_coverage(0.0, &settings));
+
+        // 0.0 * d^2 + 1.0 * d + 1.0 reaches 256 at d = 255
+        assert_eq!(255.0, light_range(Vec3f::new(0.0, 1.0, 1.0)));
+        let range = light_range(Vec3f::new(1.0, 0.0, 0.0));
+        assert!((range - 16.0).abs() < 1e-4);
+    }
+
+    fn cluster_lights(grid: &LightGrid, x: usize, y: usize, z: usize) -> (Vec<u32>, Vec<u32>) {
+        let cluster = grid.clusters[cluster_index(x, y, z)];
+        let start = cluster.offset as usize;
+        let middle = start + cluster.point_light_count as usize;
+        let end = middle + cluster.spot_light_count as usize;
+
+        (
+            grid.light_indices[start..middle].to_vec(),
+            grid.light_indices[middle..end].to_vec(),
+        )
+    }
+
+    #[test]
+    fn cluster_depth_slice_test() {
+        let projection = Mat4f::perspective(1.0, 90f32.to_radians(), 0.1, 100.0);
+        let grid = assign_lights(&projection, &Mat4f::identity(), &[], &[]);
+
+        assert_eq!(0, depth_slice(0.1, grid.depth_scale, grid.depth_bias));
+        assert_eq!(0, depth_slice(0.01, grid.depth_scale, grid.depth_bias));
+        assert_eq!(
+            CLUSTER_GRID_Z - 1,
+            depth_slice(99.9, grid.depth_scale, grid.depth_bias)
+        );
+
+        // slices are spaced exponentially, so the geometric midpoint is the middle slice
+        let middle = (0.1f32 * 100.0).sqrt() * 1.01;
+        assert_eq!(
+            CLUSTER_GRID_Z / 2,
+            depth_slice(middle, grid.depth_scale, grid.depth_bias)
+        );
+
+        assert!(grid.light_indices.is_empty());
+    }
+
+    #[test]
+    fn cluster_assign_test() {
+        let projection = Mat4f::perspective(1.0, 90f32.to_radians(), 0.1, 100.0);
+        let view = Camera::look_at(
+            &Vec3f::new(0.0, 0.0, 0.0),
+            &Vec3f::new(0.0, 0.0, 1.0),
+            &Vec3f::new(0.0, 1.0, 0.0),
+        );
+
+        let point_lights = [
+            // in front of the camera, in the middle of the screen
+            LightBounds {
+                position: Vec3f::new(0.0, 0.0, -10.0),
+                radius: 1.0,
+            },
+            // behind the camera
+            LightBounds {
+                position: Vec3f::new(0.0, 0.0, 10.0),
+                radius: 1.0,
+            },
+            // surrounds the camera, so covers the whole screen
+            LightBounds {
+                position: Vec3f::new(0.0, 0.0, 0.0),
+                radius: 5.0,
+            },
+        ];
+        let spot_lights = [LightBounds {
+            position: Vec3f::new(0.0, 0.0, -10.0),
+            radius: 1.0,
+        }];
+
+        let grid = assign_lights(&projection, &view, &point_lights, &spot_lights);
+        let slice = depth_slice(10.0, grid.depth_scale, grid.depth_bias);
+        let (centre_x, centre_y) = (CLUSTER_GRID_X / 2, CLUSTER_GRID_Y / 2);
+
+        assert_eq!(
+            (vec![0], vec![0]),
+            cluster_lights(&grid, centre_x, centre_y, slice)
+        );
+        assert_eq!((vec![], vec![]), cluster_lights(&grid, 0, 0, slice));
+
+        let near_slice = depth_slice(1.0, grid.depth_scale, grid.depth_bias);
+        assert_eq!((vec![2], vec![]), cluster_lights(&grid, 0, 0, near_slice));
+        assert_eq!(
+            (vec![2], vec![]),
+            cluster_lights(&grid, CLUSTER_GRID_X - 1, CLUSTER_GRID_Y - 1, near_slice)
+        );
+
+        assert!(!grid.light_indices.contains(&1));
+    }
+
+    #[test]
+    fn material_layout_test() {
+        use memoffset::offset_of;
+
+        // must match the std140 layout of Material in defs/material.glsl
+        assert_eq!(4, offset_of!(Material, model));
+        assert_eq!(8, offset_of!(Material, diffuse_texture));
+        assert_eq!(48, offset_of!(Material, emissive_texture));
+        assert_eq!(64, offset_of!(Material, base_colour));
+        assert_eq!(80, offset_of!(Material, emissive));
+        assert_eq!(92, offset_of!(Material, metallic));
+        assert_eq!(100, offset_of!(Material, occlusion_strength));
+        // array elements are padded to a multiple of 16 bytes
+        assert_eq!(112, std::mem::size_of::<Material>());
+
+        assert_eq!(MaterialModel::Phong, Material::default().model);
+        assert_eq!(1, MaterialModel::Pbr as u32);
+    }
+
+    #[test]
+    fn cull_batch_layout_test() {
+        use memoffset::offset_of;
+
+        // must match the std430 layout of CullBatches in gpu_cull.glsl
+        assert_eq!(96, offset_of!(CullHeader, view_projection));
+        assert_eq!(160, offset_of!(CullHeader, hiz_texture));
+        assert_eq!(176, offset_of!(CullHeader, hiz_levels));
+        assert_eq!(180, offset_of!(CullHeader, batch_count));
+        assert_eq!(192, std::mem::size_of::<CullHeader>());
+        assert_eq!(32, offset_of!(CullBatch, index_count));
+        assert_eq!(60, offset_of!(CullBatch, command_base));
+        assert_eq!(64, std::mem::size_of::<CullBatch>());
+        // the shader reads instances as words
+        assert_eq!(18 * 4, std::mem::size_of::<InstanceData>());
+    }
+
+    #[test]
+    fn shadow_settings_layout_test() {
+        use memoffset::offset_of;
+
+        // must match ShadowSettings and the SHADOW_FILTER_ defines in defs/lights.glsl
+        assert_eq!(4, offset_of!(ShadowSettings, depth_bias));
+        assert_eq!(8, offset_of!(ShadowSettings, normal_bias));
+        assert_eq!(12, offset_of!(ShadowSettings, light_size));
+        assert_eq!(16, std::mem::size_of::<ShadowSettings>());
+        assert_eq!(0, ShadowFilter::HardwarePcf as u32);
+        assert_eq!(1, ShadowFilter::PoissonPcf as u32);
+        assert_eq!(2, ShadowFilter::Pcss as u32);
+
+        // the directional light is in a std140 block, where the settings start on a 16 byte boundary
+        assert_eq!(368, offset_of!(DirectionalLight, shadow));
+        assert_eq!(384, std::mem::size_of::<DirectionalLight>());
+        // point and spot lights are in a std430 buffer, where the settings follow the matrices directly
+        assert_eq!(464, offset_of!(PointLight, shadow));
+        assert_eq!(480, std::mem::size_of::<PointLight>());
+        assert_eq!(176, offset_of!(SpotLight, shadow));
+        assert_eq!(192, std::mem::size_of::<SpotLight>());
+    }
+
+    #[test]
+    fn shadow_settings_test() {
+        let settings = ShadowSettings::default();
+        assert_eq!(ShadowFilter::HardwarePcf as u32, settings.filter);
+        assert_eq!(0.0001, settings.depth_bias);
+        assert_eq!(1.0, settings.normal_bias);
+        assert_eq!(40.0, settings.light_size);
+
+        // choosing a filter keeps the default biases and light size
+        for filter in [ShadowFilter::PoissonPcf, ShadowFilter::Pcss] {
+            let settings = ShadowSettings::new(filter);
+            assert_eq!(filter as u32, settings.filter);
+            assert_eq!(0.0001, settings.depth_bias);
+            assert_eq!(1.0, settings.normal_bias);
+            assert_eq!(40.0, settings.light_size);
+        }
+
+        // every light starts with the same settings
+        assert_eq!(
+            ShadowFilter::HardwarePcf as u32,
+            DirectionalLight::default().shadow.filter
+        );
+        assert_eq!(
+            ShadowFilter::HardwarePcf as u32,
+            PointLight::default().shadow.filter
+        );
+        assert_eq!(
+            ShadowFilter::HardwarePcf as u32,
+            SpotLight::default().shadow.filter
+        );
+    }
+
+    #[test]
+    fn frustum_planes_test() {
+        let frustum = camera_frustum();
+        let planes = frustum_planes(&frustum);
+
+        for point in [
+            Vec3f::new(0.0, 0.0, -5.0),
+            Vec3f::new(0.0, 0.0, 5.0),
+            Vec3f::new(-7.0, 2.0, -5.0),
+        ] {
+            for (plane, coefficients) in frustum.planes.iter().zip(planes.iter()) {
+                let distance = coefficients.x * point.x
+                    + coefficients.y * point.y
+                    + coefficients.z * point.z
+                    + coefficients.w;
+                assert!((plane.signed_distance(point) - distance).abs() < 0.0001);
+            }
+        }
+    }
+
+    #[test]
+    fn depth_pyramid_downsample_test() {
+        // 5x3, where the odd column and row are taken by the last texel of each
+        let base = DepthLevel {
+            width: 5,
+            height: 3,
+            depths: vec![
+                0.1, 0.2, 0.3, 0.4, 0.9, //
+                0.1, 0.1, 0.1, 0.1, 0.1, //
+                0.5, 0.1, 0.1, 0.1, 0.1, //
+            ],
+        };
+        let pyramid = DepthPyramid::new(base, 5, 3, 0, Mat4f::identity(), 0);
+
+        assert_eq!(3, pyramid.levels.len());
+        assert_eq!((2, 1), (pyramid.levels[1].width, pyramid.levels[1].height));
+        assert_eq!(vec![0.5, 0.9], pyramid.levels[1].depths);
+        assert_eq!(vec![0.9], pyramid.levels[2].depths);
+
+        assert_eq!(11, mip_level_count(1920, 1080));
+        assert_eq!(1, mip_level_count(1, 1));
+        assert_eq!(3, readback_level(1920, 1080));
+        assert_eq!(0, readback_level(MAX_READBACK_SIZE, 100));
+    }
+
+    #[test]
+    fn depth_pyramid_occlusion_test() {
+        let projection = Mat4f::perspective(1.0, 90f32.to_radians(), 0.1, 100.0);
+        let view = Camera::look_at(
+            &Vec3f::new(0.0, 0.0, 0.0),
+            &Vec3f::new(0.0, 0.0, 1.0),
+            &Vec3f::new(0.0, 1.0, 0.0),
+        );
+        let view_projection = projection * view;
+
+        // a wall across the whole screen, five units in front of the camera
+        let wall = view_projection * Vec4f::new(0.0, 0.0, -5.0, 1.0);
+        let wall_depth = wall.z / wall.w * 0.5 + 0.5;
+        let base = DepthLevel {
+            width: 64,
+            height: 64,
+            depths: vec![wall_depth; 64 * 64],
+        };
+        let pyramid = DepthPyramid::new(base, 256, 256, 2, view_projection, 10);
+
+        let behind = Aabb::new(Vec3f::new(-1.0, -1.0, -12.0), Vec3f::new(1.0, 1.0, -10.0));
+        let in_front = Aabb::new(Vec3f::new(-1.0, -1.0, -4.0), Vec3f::new(1.0, 1.0, -2.0));
+        let through = Aabb::new(Vec3f::new(-1.0, -1.0, -8.0), Vec3f::new(1.0, 1.0, -4.0));
+        let around_camera = Aabb::new(Vec3f::new(-1.0, -1.0, -12.0), Vec3f::new(1.0, 1.0, 1.0));
+        let offscreen = Aabb::new(Vec3f::new(50.0, -1.0, -12.0), Vec3f::new(52.0, 1.0, -10.0));
+
+        assert!(pyramid.is_occluded(&behind));
+        assert!(!pyramid.is_occluded(&in_front));
+        assert!(!pyramid.is_occluded(&through));
+        assert!(!pyramid.is_occluded(&around_camera));
+        assert!(!pyramid.is_occluded(&offscreen));
+
+        // a hole in the wall reveals what is behind it
+        let mut base = DepthLevel {
+            width: 64,
+            height: 64,
+            depths: vec![wall_depth; 64 * 64],
+        };
+        base.depths[32 * 64 + 32] = 1.0;
+        let pyramid = DepthPyramid::new(base, 256, 256, 2, view_projection, 10);
+
+        assert!(!pyramid.is_occluded(&behind));
+        assert!(pyramid.is_fresh(10 + MAX_DEPTH_PYRAMID_AGE));
+        assert!(!pyramid.is_fresh(11 + MAX_DEPTH_PYRAMID_AGE));
+    }
+
+    #[test]
+    fn environment_face_size_test() {
+        // a face covers a quarter of the width of the equirectangular texture
+        assert_eq!(512, equirect_face_size(2048, 1024));
+        assert_eq!(1024, equirect_face_size(3000, 1500));
+        assert_eq!(64, equirect_face_size(16, 8));
+        assert_eq!(2048, equirect_face_size(16384, 8192));
+    }
+
+    #[test]
+    fn prefiltered_roughness_test() {
+        assert_eq!(0.0, prefiltered_roughness(0, PREFILTERED_LEVELS));
+        assert_eq!(1.0, prefiltered_roughness(PREFILTERED_LEVELS - 1, PREFILTERED_LEVELS));
+        assert_eq!(0.5, prefiltered_roughness(2, 5));
+        assert_eq!(1.0, prefiltered_roughness(10, 5));
+        assert_eq!(0.0, prefiltered_roughness(3, 1));
+    }
+
+    #[test]
+    fn back_to_front_test() {
+        // looking down the negative z axis, as the direction points behind the camera
+        let view = Camera::look_at(
+            &Vec3f::new(0.0, 0.0, 0.0),
+            &Vec3f::new(0.0, 0.0, 1.0),
+            &Vec3f::new(0.0, 1.0, 0.0),
+        );
+        let centres = [
+            Vec3f::new(0.0, 0.0, -2.0),
+            Vec3f::new(1.0, 0.0, -10.0),
+            Vec3f::new(0.0, 0.0, -5.0),
+            // behind the camera is nearest of all
+            Vec3f::new(0.0, 3.0, 1.0),
+        ];
+
+        let mut order = vec![7, 7];
+        back_to_front(&view, &centres, &mut order);
+        assert_eq!(vec![1, 2, 0, 3], order);
+
+        back_to_front(&view, &[], &mut order);
+        assert!(order.is_empty());
+    }
+
+    fn sort_test_renderable(shader: u32, mesh: u32, material: u32, z: f32) -> MeshInstance {
+        MeshInstance {
+            mesh_id: MeshID::new(mesh),
+            material_id: MaterialID::new(material),
+            shader_id: ShaderProgramID::new(shader),
+            transform: Mat4f::translate(0.0, 0.0, z),
+            pipeline_stages: RENDER_SCENE,
+            lod_fade: 0.0,
+        }
+    }
+
+    #[test]
+    fn sort_key_layout_test() {
+        assert!(
+            SortKeyLayout::new(&[(SortKeyField::Shader, 32), (SortKeyField::Mesh, 32)]).is_ok()
+        );
+        assert!(
+            SortKeyLayout::new(&[(SortKeyField::Shader, 32), (SortKeyField::Mesh, 33)]).is_err()
+        );
+        assert!(SortKeyLayout::new(&[(SortKeyField::Shader, 0)]).is_err());
+
+        let layout =
+            SortKeyLayout::new(&[(SortKeyField::Shader, 4), (SortKeyField::Mesh, 4)]).unwrap();
+        let key = |shader, mesh| {
+            layout.build(&SortKeyValues {
+                shader,
+                mesh,
+                ..Default::default()
+            })
+        };
+        assert_eq!(0x21, key(2, 1));
+        // too large for the field, so clamped rather than spilling into the shader
+        assert_eq!(0x2F, key(2, 300));
+        assert!(key(1, 15) < key(2, 0));
+
+        let layout = SortKeyLayout::new(&[(SortKeyField::Mesh, 64)]).unwrap();
+        assert_eq!(
+            u32::MAX as u64,
+            layout.build(&SortKeyValues {
+                mesh: u32::MAX,
+                ..Default::default()
+            })
+        );
+    }
+
+    #[test]
+    fn sort_key_batching_test() {
+        let view = Mat4f::identity();
+        let mut renderables = Vec::new();
+        // more meshes than fit in a byte, where meshes 256 apart used to share keys and interleave
+        for i in 0..1200 {
+            renderables.push(sort_test_renderable(
+                i % 3,
+                (i * 7) % 600,
+                i % 5,
+                -((i % 11) as f32),
+            ));
+        }
+        let indices: Vec<usize> = (0..renderables.len()).collect();
+
+        let mut commands = DrawCommands::new(SortKeyLayout::opaque());
+        commands.update_keys(&renderables, &indices, &view);
+        commands.sort_indices();
+
+        let sorted: Vec<&MeshInstance> = commands.indices.iter().map(|i| &renderables[*i]).collect();
+        let mut batches = Vec::new();
+        for (i, renderable) in sorted.iter().enumerate() {
+            if i == 0
+                || sorted[i - 1].shader_id != renderable.shader_id
+                || sorted[i - 1].mesh_id != renderable.mesh_id
+            {
+                batches.push((renderable.shader_id.index(), renderable.mesh_id.index()));
+            }
+        }
+
+        // each shader and mesh pair is contiguous, so is drawn by exactly one command
+        let mut unique = batches.clone();
+        unique.sort();
+        unique.dedup();
+        assert_eq!(unique.len(), batches.len());
+        assert_eq!(batches, unique);
+
+        // within a batch, nearer renderables come first
+        for pair in sorted.windows(2) {
+            if pair[0].shader_id == pair[1].shader_id
+                && pair[0].mesh_id == pair[1].mesh_id
+                && pair[0].material_id == pair[1].material_id
+            {
+                assert!(pair[0].transform[(2, 3)] >= pair[1].transform[(2, 3)]);
+            }
+        }
+    }
+
+    #[test]
+    fn sort_key_translucency_test() {
+        let view = Mat4f::identity();
+        let mut renderables = vec![
+            sort_test_renderable(0, 0, 0, -5.0),
+            sort_test_renderable(1, 1, 0, -2.0),
+            sort_test_renderable(0, 0, 0, -8.0),
+            sort_test_renderable(1, 0, 0, -1.0),
+        ];
+        renderables[1].pipeline_stages = RENDER_TRANSPARENT;
+        renderables[2].pipeline_stages = RENDER_TRANSPARENT;
+        let indices = [0, 1, 2, 3];
+
+        // opaque renderables first, then translucent ones, each from back to front whatever their shader
+        let mut commands = DrawCommands::new(SortKeyLayout::translucent());
+        commands.update_keys(&renderables, &indices, &view);
+        commands.sort_indices();
+        assert_eq!(vec![0, 3, 2, 1], commands.indices);
+
+        // an earlier pass comes before everything of a later one
+        let mut later = DrawCommands::new(SortKeyLayout::translucent());
+        later.pass = 1;
+        later.update_keys(&renderables, &indices, &view);
+        assert!(commands.renderable_keys.iter().max() < later.renderable_keys.iter().min());
+    }
+
+    fn lod_chain(screen_sizes: &[f32]) -> LodChain {
+        LodChain::new(
+            screen_sizes
+                .iter()
+                .enumerate()
+                .map(|(i, screen_size)| LodLevel {
+                    mesh_id: MeshID::new(i as u32),
+                    screen_size: *screen_size,
+                })
+                .collect(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn lod_chain_test() {
+        let level = |screen_size| LodLevel {
+            mesh_id: MeshID::new(0),
+            screen_size,
+        };
+
+        assert!(LodChain::new(Vec::new()).is_err());
+        assert!(LodChain::new(vec![level(0.5), level(0.5)]).is_err());
+        assert!(LodChain::new(vec![level(0.1), level(0.5)]).is_err());
+        assert!(LodChain::new(vec![level(0.0); MAX_LOD_LEVELS + 1]).is_err());
+        assert!(LodChain::new(vec![level(0.5), level(0.1), level(0.0)]).is_ok());
+        assert_eq!(1, LodChain::single(MeshID::new(0)).levels().len());
+    }
+
+    #[test]
+    fn lod_screen_size_test() {
+        let projection = Mat4f::perspective(1.0, std::f32::consts::FRAC_PI_2, 0.1, 100.0);
+        let view = Camera::look_at(
+            &Vec3f::new(0.0, 0.0, 0.0),
+            &Vec3f::new(0.0, 0.0, 1.0),
+            &Vec3f::new(0.0, 1.0, 0.0),
+        );
+        let radius = unit_aabb().extents().magnitude();
+
+        // with a 90 degree field of view, the screen's height at a depth is twice that depth
+        let size = screen_size(
+            &unit_aabb(),
+            &Mat4f::translate(0.0, 0.0, -10.0),
+            &view,
+            &projection,
+        );
+        assert!((size - radius / 10.0).abs() < 0.0001);
+
+        // halves as the distance doubles
+        let further = screen_size(
+            &unit_aabb(),
+            &Mat4f::translate(0.0, 0.0, -20.0),
+            &view,
+            &projection,
+        );
+        assert!((further * 2.0 - size).abs() < 0.0001);
+
+        // and grows with the renderable's scale
+        let scaled = screen_size(
+            &unit_aabb(),
+            &(Mat4f::translate(0.0, 0.0, -10.0) * Mat4f::scale(2.0, 2.0, 2.0)),
+            &view,
+            &projection,
+        );
+        assert!((scaled - size * 2.0).abs() < 0.0001);
+
+        // the camera is within the bounds
+        assert_eq!(
+            f32::MAX,
+            screen_size(&unit_aabb(), &Mat4f::identity(), &view, &projection)
+        );
+    }
+
+    #[test]
+    fn lod_selection_test() {
+        let chain = lod_chain(&[0.5, 0.2, 0.05]);
+        let settings = LodSettings {
+            cross_fade: false,
+            ..Default::default()
+        };
+        let level = |size, settings: &LodSettings| select_lod(&chain, size, settings).level;
+
+        assert_eq!(0, level(f32::MAX, &settings));
+        assert_eq!(0, level(0.5, &settings));
+        assert_eq!(1, level(0.4, &settings));
+        assert_eq!(2, level(0.1, &settings));
+        // the last level is drawn however small the renderable is
+        assert_eq!(2, level(0.0, &settings));
+
+        // each step of bias halves the size that the levels are selected from
+        let biased = LodSettings {
+            bias: 1.0,
+            ..settings
+        };
+        assert_eq!(1, level(0.8, &biased));
+        assert_eq!(0, level(1.0, &biased));
+        let sharpened = LodSettings {
+            bias: -1.0,
+            ..settings
+        };
+        assert_eq!(0, level(0.25, &sharpened));
+
+        let single = LodChain::single(MeshID::new(0));
+        assert_eq!(
+            LodSelection {
+                level: 0,
+                coverage: 1.0
+            },
+            select_lod(&single, 0.0, &LodSettings::default())
+        );
+    }
+
+    #[test]
+    fn lod_cross_fade_test() {
+        let chain = lod_chain(&[0.5, 0.2, 0.05]);
+        let settings = LodSettings {
+            bias: 0.0,
+            cross_fade: true,
+            fade_band: 0.2,
+        };
+
+        // solid above the band, and at the level's size
+        assert_eq!(1.0, select_lod(&chain, 0.7, &settings).coverage);
+        assert_eq!(1.0, select_lod(&chain, 0.5, &settings).coverage);
+
+        // fades out across the band above the level's size, as the next level fades in
+        let selection = select_lod(&chain, 0.55, &settings);
+        assert_eq!(0, selection.level);
+        assert!((selection.coverage - 0.5).abs() < 0.0001);
+        let nearer = select_lod(&chain, 0.58, &settings);
+        assert!(nearer.coverage > selection.coverage);
+
+        // the last level has nothing to fade into
+        assert_eq!(1.0, select_lod(&chain, 0.055, &settings).coverage);
+
+        let without = LodSettings {
+            cross_fade: false,
+            ..settings
+        };
+        assert_eq!(1.0, select_lod(&chain, 0.55, &without).coverage);
+    }
+
+    #[test]
+    fn lod_stats_test() {
+        let mut stats = LodStats::default();
+        stats.add(0, 100);
+        stats.add(0, 100);
+        stats.add(2, 10);
+
+        assert_eq!(2, stats.instances[0]);
+        assert_eq!(0, stats.instances[1]);
+        assert_eq!(3, stats.total_instances());
+        assert_eq!(210, stats.total_triangles());
+    }
+
+    #[test]
+    fn halton_test() {
+        assert_eq!(0.0, halton(0, 2));
+        assert_eq!(0.5, halton(1, 2));
+        assert_eq!(0.25, halton(2, 2));
+        assert_eq!(0.75, halton(3, 2));
+        assert!((halton(1, 3) - 1.0 / 3.0).abs() < 0.0001);
+        assert!((halton(2, 3) - 2.0 / 3.0).abs() < 0.0001);
+        assert!((halton(3, 3) - 1.0 / 9.0).abs() < 0.0001);
+    }
+
+    #[test]
+    fn taa_jitter_test() {
+        let jitters: Vec<Vec2f> = (0..8).map(|frame| taa_jitter(frame, 8)).collect();
+
+        for jitter in jitters.iter() {
+            assert!(jitter.x.abs() <= 0.5 && jitter.y.abs() <= 0.5);
+        }
+        // every frame of the sequence is sampled at a different offset
+        for i in 0..jitters.len() {
+            for j in (i + 1)..jitters.len() {
+                assert!(
+                    (jitters[i].x - jitters[j].x).abs() > 0.001
+                        || (jitters[i].y - jitters[j].y).abs() > 0.001
+                );
+            }
+        }
+
+        // which then repeats
+        assert_eq!(jitters[0].x, taa_jitter(8, 8).x);
+        assert_eq!(jitters[3].y, taa_jitter(11, 8).y);
+    }
+
+    #[test]
+    fn camera_jitter_test() {
+        let mut camera = Camera::new_perspective(70.0, 0.1, 100.0);
+        camera.update_projection(200.0, 100.0);
+        let point = Vec4f::new(1.0, 2.0, -5.0, 1.0);
+        let ndc = |projection: Mat4f| {
+            let clip = projection * point;
+            (clip.x / clip.w, clip.y / clip.w, clip.z / clip.w)
+        };
+        let unjittered = ndc(camera.projection);
+
+        // moves the point by the jitter in pixels, whatever its depth, without changing its depth
+        camera.set_jitter(Vec2f::new(0.5, -0.25));
+        let jittered = ndc(camera.projection);
+        assert!((jittered.0 - unjittered.0 - 0.5 * 2.0 / 200.0).abs() < 0.0001);
+        assert!((jittered.1 - unjittered.1 + 0.25 * 2.0 / 100.0).abs() < 0.0001);
+        assert!((jittered.2 - unjittered.2).abs() < 0.0001);
+        assert_eq!(unjittered.0, ndc(camera.unjittered_projection).0);
+
+        // and is kept when the viewport is resized
+        camera.update_projection(400.0, 200.0);
+        let resized = ndc(camera.projection);
+        let resized_unjittered = ndc(camera.unjittered_projection);
+        assert!((resized.0 - resized_unjittered.0 - 0.5 * 2.0 / 400.0).abs() < 0.0001);
+    }
+    #[test]
+    fn msaa_samples_test() {
+        assert_eq!(Ok(1), validate_samples(1, 8));
+        assert_eq!(Ok(8), validate_samples(8, 8));
+        assert!(validate_samples(0, 8).is_err());
+        assert!(validate_samples(3, 8).is_err());
+        assert!(validate_samples(16, 8).is_err());
+        // disabling MSAA is always possible, even where multisampling isn't
+        assert_eq!(Ok(1), validate_samples(1, 0));
+
+        assert_eq!(
+            (RESOURCE_HDR, RESOURCE_DEPTH),
+            scene_targets(&[STAGE_SCENE])
+        );
+        assert_eq!(
+            (RESOURCE_MSAA, RESOURCE_MSAA_DEPTH),
+            scene_targets(&[STAGE_SCENE, STAGE_MSAA_RESOLVE])
+        );
+    }
+
+    #[test]
+    fn msaa_graph_test() {
+        let enabled_stages = [STAGE_SCENE, STAGE_MSAA_RESOLVE];
+        let mut graph = graph_with_resources(&[
+            "hdr",
+            "depth",
+            "msaa",
+            "msaa_depth",
+            "ao",
+            "bloom",
+            "backbuffer",
+        ]);
+
+        // mirrors the forward path, where ambient occlusion samples the depth resolved by the pre-pass before the
+        // multisampled scene is drawn
+        graph.add_pass("depth", pass(&[], &["msaa_depth", "depth"]));
+        graph.add_pass("ao", pass(&["depth"], &["ao"]));
+        graph.add_pass("scene", pass(&["msaa_depth", "ao"], &["msaa"]));
+
+        let mut sky = PassBuilder::default();
+        SkyStage::new().setup(&mut sky, &enabled_stages);
+        graph.add_pass("sky", sky);
+
+        let mut resolve = PassBuilder::default();
+        MsaaResolveStage::new().setup(&mut resolve, &enabled_stages);
+        graph.add_pass("resolve", resolve);
+
+        graph.add_pass("transparent", pass(&["depth"], &["hdr"]));
+        graph.add_pass("bloom", pass(&["hdr"], &["bloom"]));
+        graph.add_pass("post", pass(&["hdr", "bloom"], &["backbuffer"]));
+
+        let compiled = graph.compile().unwrap();
+
+        assert_eq!(
+            vec![
+                "depth",
+                "ao",
+                "scene",
+                "sky",
+                "resolve",
+                "transparent",
+                "bloom",
+                "post"
+            ],
+            pass_names(&graph, &compiled.order)
+        );
+    }
+    #[test]
+    fn exposure_test() {
+        assert_eq!(1.0, exposure_scale(0.0));
+        assert_eq!(4.0, exposure_scale(2.0));
+        assert_eq!(0.5, exposure_scale(-1.0));
+
+        assert_eq!(0.0, adaptation_rate(2.0, 0.0));
+        assert_eq!(0.0, adaptation_rate(0.0, 1.0));
+        assert!(adaptation_rate(100.0, 1.0) > 0.999);
+
+        // adapts by the same amount whether over one long frame or many short ones
+        let remaining = (0..10).fold(1.0, |remaining, _| {
+            remaining * (1.0 - adaptation_rate(2.0, 0.1))
+        });
+        assert!((remaining - (1.0 - adaptation_rate(2.0, 1.0))).abs() < 0.0001);
+    }
+    #[test]
+    fn lut_test() {
+        assert_eq!(Ok(16), lut_size(256, 16));
+        assert!(lut_size(256, 15).is_err());
+        assert!(lut_size(1, 1).is_err());
+
+        // two slices of 2x2 side by side, where each byte is its index in the volume
+        let image = [0, 1, 4, 5, 2, 3, 6, 7];
+        assert_eq!((0..8).collect::<Vec<u8>>(), lut_volume(&image, 2, 1));
+    }
+
+    #[test]
+    fn post_effect_parameters_test() {
+        let mut vignette = PostEffect::vignette(0.5, 0.2);
+        assert!(matches!(vignette.parameter("intensity"), Some(PostParameter::Float(x)) if x == 0.5));
+        assert!(matches!(vignette.shader(), PostShader::BuiltIn(_)));
+
+        assert!(vignette.set_parameter("intensity", PostParameter::Float(0.8)).is_ok());
+        assert!(matches!(vignette.parameter("intensity"), Some(PostParameter::Float(x)) if x == 0.8));
+
+        // must be declared, and keep its type
+        assert!(vignette.set_parameter("radius", PostParameter::Float(1.0)).is_err());
+        assert!(vignette.set_parameter("intensity", PostParameter::Int(1)).is_err());
+
+        // declaring again replaces the value, rather than adding another
+        let posterise = PostEffect::fragment("posterise", ShaderProgramID::new(0))
+            .with_parameter("levels", PostParameter::Float(4.0))
+            .with_parameter("levels", PostParameter::Float(8.0));
+        assert_eq!(1, posterise.parameters().len());
+        assert!(matches!(
+            posterise.shader(),
+            PostShader::Custom {
+                kind: PostPassKind::Fragment,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn post_effect_stack_test() {
+        let mut stack = PostEffectStack::new();
+        stack.push(PostEffect::sharpen(0.2)).unwrap();
+        stack.push(PostEffect::vignette(0.5, 0.2)).unwrap();
+        stack.insert(1, PostEffect::film_grain(0.1)).unwrap();
+
+        // names are unique
+        assert!(stack.push(PostEffect::sharpen(0.5)).is_err());
+
+        let names = |stack: &PostEffectStack| {
+            stack
+                .enabled()
+                .map(|effect| effect.name)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(vec!["sharpen", "film_grain", "vignette"], names(&stack));
+
+        stack.get_mut("film_grain").unwrap().enabled = false;
+        assert_eq!(vec!["sharpen", "vignette"], names(&stack));
+        assert_eq!(3, stack.effects().len());
+
+        assert!(stack.remove("sharpen").is_some());
+        assert_eq!(Some(1), stack.position("vignette"));
+        assert!(stack.get("sharpen").is_none());
+    }
+    #[test]
+    fn bloom_mip_count_test() {
+        // halves from 1080 until it would fall below the minimum: 540, 270, 135, 67, 33, 16, 8
+        assert_eq!(7, bloom_mip_count(1920, 1080, None));
+        assert_eq!(6, bloom_mip_count(1280, 720, None));
+        assert!(720 >> bloom_mip_count(1280, 720, None) >= MIN_BLOOM_SIZE);
+
+        // an explicit count is kept within what the resolution allows
+        assert_eq!(4, bloom_mip_count(1920, 1080, Some(4)));
+        assert_eq!(7, bloom_mip_count(1920, 1080, Some(12)));
+        assert_eq!(1, bloom_mip_count(1920, 1080, Some(0)));
+
+        // always at least one level, however small the scene
+        assert_eq!(1, bloom_mip_count(4, 4, None));
+    }
+    #[test]
+    fn viewport_pixels_test() {
+        use crate::renderer::view::ViewportRect;
+
+        assert_eq!(ViewportRect::FULL.pixels(1280, 720), (0, 0, 1280, 720));
+        assert_eq!(
+            ViewportRect::new(0.5, 0.0, 0.5, 1.0).pixels(1280, 720),
+            (640, 0, 640, 720)
+        );
+        assert_eq!(
+            ViewportRect::new(0.75, 0.75, 0.5, 0.5).pixels(100, 100),
+            (75, 75, 25, 25)
+        );
+        assert_eq!(
+            ViewportRect::new(-0.5, 0.0, 1.0, 1.0).pixels(100, 100),
+            (0, 0, 50, 100)
+        );
+        assert_eq!(
+            ViewportRect::new(0.5, 0.5, 0.0, 0.0).pixels(100, 100),
+            (50, 50, 1, 1)
+        );
+        assert_eq!(
+            ViewportRect::new(1.0, 1.0, 0.5, 0.5).pixels(100, 100),
+            (99, 99, 1, 1)
+        );
+        assert_eq!(ViewportRect::FULL.pixels(0, 0), (0, 0, 0, 0));
+    }
+
+    #[test]
+    fn view_layers_test() {
+        use crate::renderer::view::{is_visible, LAYER_ALL, LAYER_DEFAULT};
+
+        assert!(is_visible(LAYER_DEFAULT, LAYER_ALL));
+        assert!(is_visible(0b110, 0b010));
+        assert!(!is_visible(0b100, 0b011));
+        assert!(!is_visible(0, LAYER_ALL));
+    }
+
+    #[test]
+    fn view_order_test() {
+        use crate::{
+            renderer::view::{draw_order, primary_view, RenderTarget, RenderTexture, ViewSettings},
+            resource_manager::resource_manager::TextureID,
+        };
+
+        let texture = RenderTarget::Texture(RenderTexture {
+            texture_id: TextureID::new(0),
+            framebuffer_id: FramebufferID::new(0),
+            width: 64,
+            height: 64,
+        });
+        let view = |target, priority| ViewSettings {
+            target,
+            priority,
+            ..Default::default()
+        };
+
+        let views = [
+            view(RenderTarget::Window, 0),
+            view(RenderTarget::Window, 1),
+            view(texture, -1),
+            view(RenderTarget::Window, 0),
+        ];
+        let order = draw_order(&views);
+        assert_eq!(order, vec![2, 0, 3, 1]);
+        assert_eq!(primary_view(&views, &order), Some(0));
+
+        let views = [view(texture, 0), view(texture, 1)];
+        let order = draw_order(&views);
+        assert_eq!(order, vec![0, 1]);
+        assert_eq!(primary_view(&views, &order), None);
+    }
+    #[test]
+    fn camera_view_matrix_test() {
+        let position = Vec3f::new(1.0, 2.0, 3.0);
+        let forward = Vec3f::new(-0.4, -0.3, -1.0);
+        let orientation = Quat::look_rotation(&forward, &Vec3f::new(0.0, 1.0, 0.0));
+
+        // agrees with look_at, which takes the camera's +Z axis
+        let view = Camera::view_matrix(&position, &orientation);
+        let expected =
+            Camera::look_at(&position, &-forward.normalise(), &Vec3f::new(0.0, 1.0, 0.0));
+        for (a, b) in view.as_slice().iter().zip(expected.as_slice()) {
+            assert!((a - b).abs() < 1e-5);
+        }
+
+        // and keeps working when looking straight down, where look_at can't
+        let mut camera = Camera::new_perspective(70.0, 0.1, 100.0);
+        camera.position = Vec3f::new(0.0, 10.0, 0.0);
+        camera.look_towards(&Vec3f::new(0.0, 0.0, 0.0));
+        camera.update_view();
+        assert!(camera.view.as_slice().iter().all(|x| x.is_finite()));
+        let origin = camera.view * Vec4f::new(0.0, 0.0, 0.0, 1.0);
+        assert!(origin.x.abs() < 1e-5 && origin.y.abs() < 1e-5);
+        assert!((origin.z + 10.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn camera_controller_test() {
+        use crate::input::camera_controller::{
+            yaw_pitch, CameraController, ControllerInput, FirstPerson, Follow, FreeFly, Orbit,
+        };
+
+        let close = |a: Vec3f, b: Vec3f| (a - b).magnitude() < 1e-3;
+        let still = ControllerInput::default();
+
+        let (yaw, pitch) = yaw_pitch(&Vec3f::new(0.0, 0.0, -1.0));
+        assert!(yaw.abs() < 1e-5 && pitch.abs() < 1e-5);
+        let (yaw, pitch) = yaw_pitch(&Vec3f::new(-1.0, 1.0, 0.0));
+        assert!((yaw - 90.0).abs() < 1e-3 && (pitch - 45.0).abs() < 1e-3);
+
+        // free-fly turns right and moves forward, and can pitch past straight up
+        let mut camera = Camera::new_perspective(70.0, 0.1, 100.0);
+        let mut controller = CameraController::FreeFly(FreeFly::default());
+        let turn = ControllerInput {
+            look: Vec2f::new(90.0, 0.0),
+            ..Default::default()
+        };
+        controller.update(&mut camera, &turn, 0.0);
+        assert!(close(
+            camera.orientation.forward(),
+            Vec3f::new(1.0, 0.0, 0.0)
+        ));
+        let walk = ControllerInput {
+            movement: Vec3f::new(0.0, 0.0, 2.0),
+            ..Default::default()
+        };
+        controller.update(&mut camera, &walk, 0.5);
+        assert!(close(camera.position, Vec3f::new(1.0, 0.0, -3.0)));
+        let look_up = ControllerInput {
+            look: Vec2f::new(0.0, -120.0),
+            ..Default::default()
+        };
+        controller.update(&mut camera, &look_up, 0.0);
+        assert!(camera.orientation.forward().x < 0.0);
+        assert!(camera.orientation.up().y < 0.0);
+
+        // first-person keeps its height and clamps its pitch
+        let mut camera = Camera::new_perspective(70.0, 0.1, 100.0);
+        let mut controller = CameraController::FirstPerson(FirstPerson::from_camera(&camera));
+        controller.update(&mut camera, &look_up, 0.0);
+        let CameraController::FirstPerson(first_person) = controller else {
+            unreachable!()
+        };
+        assert_eq!(first_person.max_pitch, first_person.pitch);
+        controller.update(&mut camera, &walk, 1.0);
+        assert!((camera.position.y).abs() < 1e-5);
+        assert!(close(camera.position, Vec3f::new(0.0, 0.0, -5.0)));
+
+        // orbit stays at its distance from the target, looking at it
+        let mut camera = Camera::new_perspective(70.0, 0.1, 100.0);
+        let target = Vec3f::new(0.0, 0.0, -10.0);
+        let mut controller = CameraController::Orbit(Orbit::from_camera(&camera, target));
+        controller.update(&mut camera, &turn, 0.0);
+        assert!(((camera.position - target).magnitude() - 7.0).abs() < 1e-3);
+        assert!(close(
+            camera.orientation.forward(),
+            (target - camera.position).normalise()
+        ));
+        let zoom = ControllerInput {
+            zoom: 1.0,
+            ..Default::default()
+        };
+        controller.update(&mut camera, &zoom, 0.5);
+        assert!(((camera.position - target).magnitude() - 3.5).abs() < 1e-3);
+
+        // follow eases towards the offset, at a rate independent of the frame rate
+        let follow = Follow {
+            target: Vec3f::new(0.0, 0.0, 0.0),
+            offset: Vec3f::new(0.0, 0.0, 10.0),
+            stiffness: 2.0,
+        };
+        let mut once = Camera::new_perspective(70.0, 0.1, 100.0);
+        CameraController::Follow(follow).update(&mut once, &still, 1.0);
+        let mut steps = Camera::new_perspective(70.0, 0.1, 100.0);
+        let mut controller = CameraController::Follow(follow);
+        for _ in 0..10 {
+            controller.update(&mut steps, &still, 0.1);
+        }
+        assert!(close(once.position, steps.position));
+        assert!(once.position.z > -3.0 && once.position.z < 10.0);
+        for _ in 0..200 {
+            controller.update(&mut steps, &still, 0.1);
+        }
+        assert!(close(steps.position, Vec3f::new(0.0, 0.0, 10.0)));
+        assert!(close(
+            steps.orientation.forward(),
+            Vec3f::new(0.0, 0.0, -1.0)
+        ));
+    }
+    #[test]
+    fn pipeline_stage_name_test() {
+        let mut pipeline = RendererPipeline::new();
+        pipeline.add_stage(SkyStage::new());
+        pipeline.add_stage(MsaaResolveStage::new());
+        assert!(pipeline.is_enabled(STAGE_SKY));
+        assert!(pipeline.is_enabled(STAGE_MSAA_RESOLVE));
+
+        pipeline.disable_stage(STAGE_MSAA_RESOLVE);
+        assert!(!pipeline.is_enabled(STAGE_MSAA_RESOLVE));
+        assert!(
+            pipeline
+                .get_stage_mut::<MsaaResolveStage>(STAGE_MSAA_RESOLVE)
+                .is_some()
+        );
+        // stages are found by name, and only then downcast
+        assert!(
+            pipeline
+                .get_stage_mut::<MsaaResolveStage>(STAGE_SKY)
+                .is_none()
+        );
+
+        // a stage of the same name replaces the old one, and is enabled
+        pipeline.add_stage(MsaaResolveStage::new());
+        assert!(pipeline.is_enabled(STAGE_MSAA_RESOLVE));
+
+        pipeline.remove_stage(STAGE_SKY);
+        assert!(!pipeline.is_enabled(STAGE_SKY));
+        assert!(pipeline.get_stage_mut::<SkyStage>(STAGE_SKY).is_none());
+    }
+    #[test]
+    fn scene_depth_prepass_test() {
+        // without the pre-pass, the scene fills the depth buffer itself
+        let mut scene = PassBuilder::default();
+        assert_eq!(RESOURCE_DEPTH, scene_pass(&mut scene, &[STAGE_SCENE]));
+        assert!(scene.writes.contains(&RESOURCE_DEPTH));
+        assert!(!scene.reads.contains(&RESOURCE_DEPTH));
+        assert_eq!(Some(RESOURCE_HDR), scene.render_target);
+
+        let state = scene_rasteriser_state(false);
+        assert!(state.depth_func == Comparison::Less);
+        assert!(state.depth_mask);
+        assert!(!state.blend);
+
+        // with it, the depth written by the depth stage is only read, so the scene is drawn after it
+        let enabled_stages = [STAGE_DEPTH, STAGE_SCENE];
+        let mut scene = PassBuilder::default();
+        assert_eq!(RESOURCE_DEPTH, scene_pass(&mut scene, &enabled_stages));
+        assert!(scene.reads.contains(&RESOURCE_DEPTH));
+        assert!(!scene.writes.contains(&RESOURCE_DEPTH));
+
+        let state = scene_rasteriser_state(true);
+        assert!(state.depth_func == Comparison::Equal);
+        assert!(!state.depth_mask);
+        assert!(!state.blend);
+
+        let mut graph = graph_with_resources(&["hdr", "depth", "backbuffer"]);
+        graph.add_pass("scene", scene);
+        graph.add_pass("depth", pass(&[], &["depth"]));
+        graph.add_pass("post", pass(&["hdr", "depth"], &["backbuffer"]));
+        let compiled = graph.compile().unwrap();
+        assert_eq!(
+            vec!["depth", "scene", "post"],
+            pass_names(&graph, &compiled.order)
+        );
+
+        // the multisampled depth is read instead while MSAA is enabled
+        let mut scene = PassBuilder::default();
+        let depth_target = scene_pass(&mut scene, &[STAGE_DEPTH, STAGE_SCENE, STAGE_MSAA_RESOLVE]);
+        assert_eq!(RESOURCE_MSAA_DEPTH, depth_target);
+        assert_eq!(vec![RESOURCE_MSAA_DEPTH], scene.reads);
+        assert_eq!(vec![RESOURCE_MSAA], scene.writes);
+    }
+    /// The scene pass is added third
+    fn ao_graph(enabled_stages: &[StageName]) -> RenderGraph {
+        let mut graph = graph_with_resources(&["hdr", "depth", "backbuffer"]);
+        graph.add_pass("depth", pass(&[], &["depth"]));
+
+        let mut ao = PassBuilder::default();
+        ao_pass(&mut ao);
+        graph.add_pass("ao", ao);
+
+        let mut scene = PassBuilder::default();
+        scene_pass(&mut scene, enabled_stages);
+        graph.add_pass("scene", scene);
+        graph.add_pass("post", pass(&["hdr"], &["backbuffer"]));
+        graph
+    }
+
+    #[test]
+    fn ao_graph_test() {
+        let mut ao = PassBuilder::default();
+        ao_pass(&mut ao);
+        assert_eq!(vec![RESOURCE_DEPTH], ao.reads);
+        assert!(ao.render_target.is_none());
+        // the occlusion and its blur are both transient, and follow the viewport's size
+        assert_eq!(2, ao.creates.len());
+        assert_eq!(RESOURCE_AO, ao.creates[0].0);
+        assert!(
+            ao.creates
+                .iter()
+                .all(|(_, framebuffer)| framebuffer.resize_with_viewport)
+        );
+        assert!(ao.creates.iter().all(|(name, _)| ao.writes.contains(name)));
+
+        // the scene samples the occlusion after the pre-pass, so it is calculated in between them
+        let graph = ao_graph(&[STAGE_DEPTH, STAGE_AO, STAGE_SCENE]);
+        assert!(graph.get_pass(2).builder.reads.contains(&RESOURCE_AO));
+        let compiled = graph.compile().unwrap();
+        assert_eq!(
+            vec!["depth", "ao", "scene", "post"],
+            pass_names(&graph, &compiled.order)
+        );
+
+        // while ambient occlusion is disabled, nothing reads it, so the pass is culled
+        let graph = ao_graph(&[STAGE_DEPTH, STAGE_SCENE]);
+        assert!(!graph.get_pass(2).builder.reads.contains(&RESOURCE_AO));
+        let compiled = graph.compile().unwrap();
+        assert_eq!(vec!["ao"], pass_names(&graph, &compiled.culled));
+
+        // and likewise without the pre-pass, which the scene relies on to sample it. The scene then fills the depth
+        // itself, so the depth pass is culled too
+        let graph = ao_graph(&[STAGE_AO, STAGE_SCENE]);
+        assert!(!graph.get_pass(2).builder.reads.contains(&RESOURCE_AO));
+        let compiled = graph.compile().unwrap();
+        assert_eq!(vec!["depth", "ao"], pass_names(&graph, &compiled.culled));
+    }
+}
\ No newline at end of file
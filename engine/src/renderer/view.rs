@@ -0,0 +1,120 @@
+use crate::resource_manager::resource_manager::{FramebufferID, TextureID};
+
+/// Layer that renderables are usually on
+pub const LAYER_DEFAULT: u32 = 1;
+/// Every layer, for cameras that see everything
+pub const LAYER_ALL: u32 = u32::MAX;
+
+/// Whether a renderable on `layers` is drawn by a camera that sees `mask`, which is when they share any layer
+pub fn is_visible(layers: u32, mask: u32) -> bool {
+    layers & mask != 0
+}
+
+/// Region of a target that a camera draws into, as fractions of the target's size from its bottom left corner
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ViewportRect {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+impl ViewportRect {
+    pub const FULL: ViewportRect = ViewportRect {
+        x: 0.0,
+        y: 0.0,
+        width: 1.0,
+        height: 1.0,
+    };
+
+    pub fn new(x: f32, y: f32, width: f32, height: f32) -> Self {
+        Self {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    /// The region as `(x, y, width, height)` in the pixels of a target of this size, which is kept within the
+    /// target and is at least a pixel across, unless the target is empty
+    pub fn pixels(&self, width: u32, height: u32) -> (u32, u32, u32, u32) {
+        let span = |start: f32, size: f32, pixels: u32| {
+            let first = ((start.clamp(0.0, 1.0) * pixels as f32).round() as u32)
+                .min(pixels.saturating_sub(1));
+            let end = ((start + size).clamp(0.0, 1.0) * pixels as f32).round() as u32;
+            (first, end.max(first + 1).min(pixels) - first.min(pixels))
+        };
+
+        let (x, width) = span(self.x, self.width, width);
+        let (y, height) = span(self.y, self.height, height);
+        (x, y, width, height)
+    }
+}
+
+/// A texture that a camera draws into, which materials can sample like any other once it has been drawn
+#[derive(Clone, Copy)]
+pub struct RenderTexture {
+    pub texture_id: TextureID,
+    /// Draws into the texture, but doesn't own it
+    pub framebuffer_id: FramebufferID,
+    pub width: u32,
+    pub height: u32,
+}
+
+#[derive(Clone, Copy)]
+pub enum RenderTarget {
+    Window,
+    Texture(RenderTexture),
+}
+
+/// Where and what a camera draws
+#[derive(Clone, Copy)]
+pub struct ViewSettings {
+    pub viewport: ViewportRect,
+    pub target: RenderTarget,
+    /// Render layers that the camera sees, see [`is_visible`]
+    pub layers: u32,
+    /// Cameras are drawn from the lowest priority to the highest, so a camera is drawn over those before it that
+    /// share its target, and a camera that draws into a texture should come before those that see the texture
+    pub priority: i32,
+}
+
+impl Default for ViewSettings {
+    fn default() -> Self {
+        Self {
+            viewport: ViewportRect::FULL,
+            target: RenderTarget::Window,
+            layers: LAYER_ALL,
+            priority: 0,
+        }
+    }
+}
+
+/// Indices of the views in the order they are drawn, where views of the same priority keep their order
+pub fn draw_order(views: &[ViewSettings]) -> Vec<usize> {
+    let mut order = (0..views.len()).collect::<Vec<_>>();
+    order.sort_by_key(|&i| views[i].priority);
+    order
+}
+
+/// The view, of those in draw order, that keeps the history of temporal effects and occlusion culling, which is
+/// the first to draw into the window. The others are drawn without them, as the history of one camera is of no
+/// use to another
+pub fn primary_view(views: &[ViewSettings], order: &[usize]) -> Option<usize> {
+    order
+        .iter()
+        .copied()
+        .find(|&i| matches!(views[i].target, RenderTarget::Window))
+}
+
+/// Where a view's final image is blitted, once it has been post processed
+#[derive(Clone, Copy)]
+pub struct ViewOutput {
+    /// The default framebuffer when `None`
+    pub framebuffer: Option<FramebufferID>,
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
@@ -1,9 +1,11 @@
 use bytemuck::{Pod, Zeroable};
 
-use super::resource_manager::TextureID;
+use super::resource_manager::{MeshID, TextureID};
 use crate::math::*;
 
 pub const VERTEX_SIZE: u32 = std::mem::size_of::<Vertex>() as u32;
+/// Levels that a `LodChain` may have, which is also how many levels `LodStats` counts
+pub const MAX_LOD_LEVELS: usize = 8;
 
 pub struct Model {
     pub meshes: Vec<Mesh>,
@@ -15,14 +17,180 @@ pub struct Mesh {
     pub indices: Vec<u32>,
 }
 
+/// Axis-aligned bounding box, in the local space of the mesh it was computed from
+#[derive(Debug, Clone, Copy)]
+pub struct Aabb {
+    pub min: Vec3f,
+    pub max: Vec3f,
+}
+
+impl Aabb {
+    pub fn new(min: Vec3f, max: Vec3f) -> Self {
+        Self { min, max }
+    }
+
+    pub fn from_vertices(vertices: &[Vertex]) -> Self {
+        if vertices.is_empty() {
+            return Self::new(Vec3f::uniform(0.0), Vec3f::uniform(0.0));
+        }
+
+        let mut min = Vec3f::uniform(f32::MAX);
+        let mut max = Vec3f::uniform(f32::MIN);
+
+        for vertex in vertices {
+            for i in 0..3 {
+                min[i] = min[i].min(vertex.position[i]);
+                max[i] = max[i].max(vertex.position[i]);
+            }
+        }
+
+        Self::new(min, max)
+    }
+
+    pub fn centre(&self) -> Vec3f {
+        (self.min + self.max) * 0.5
+    }
+
+    /// Half the size of the box along each axis
+    pub fn extents(&self) -> Vec3f {
+        (self.max - self.min) * 0.5
+    }
+
+    /// Returns the axis-aligned box that encloses this box after being transformed
+    pub fn transform(&self, transform: &Mat4f) -> Self {
+        let centre = self.centre();
+        let centre = Vec3f::from(*transform * Vec4f::new(centre.x, centre.y, centre.z, 1.0));
+        let extents = self.extents();
+        let mut new_extents = Vec3f::uniform(0.0);
+
+        for i in 0..3 {
+            for j in 0..3 {
+                new_extents[i] += transform[(i, j)].abs() * extents[j];
+            }
+        }
+
+        Self::new(centre - new_extents, centre + new_extents)
+    }
+}
+
 #[derive(Clone, Copy)]
-pub struct Material {
+pub struct LodLevel {
+    pub mesh_id: MeshID,
+    /// Fraction of the screen's height that the renderable's bounding sphere must cover, at least, for this level
+    /// to be drawn
+    pub screen_size: f32,
+}
+
+/// Meshes of the same object at decreasing detail, where the first level is the most detailed. <br>
+/// The bounds of the first level are used to select a level, so the others should fit within them.
+#[derive(Clone)]
+pub struct LodChain {
+    /// Private so that every chain has been validated by `new`, and has at least one level
+    levels: Vec<LodLevel>,
+}
+
+impl LodChain {
+    /// The levels must be ordered from the largest screen size to the smallest. The last level is drawn however
+    /// small the renderable is, so its screen size is only used for cross-fading into it
+    pub fn new(levels: Vec<LodLevel>) -> Result<Self, String> {
+        if levels.is_empty() {
+            return Err("LOD chain has no levels".to_string());
+        }
+        if levels.len() > MAX_LOD_LEVELS {
+            return Err(format!(
+                "LOD chain has {} levels, but at most {} are supported",
+                levels.len(),
+                MAX_LOD_LEVELS
+            ));
+        }
+        if levels
+            .windows(2)
+            .any(|pair| pair[1].screen_size >= pair[0].screen_size)
+        {
+            return Err("LOD chain screen sizes must decrease with each level".to_string());
+        }
+
+        Ok(Self { levels })
+    }
+
+    pub fn levels(&self) -> &[LodLevel] {
+        &self.levels
+    }
+
+    /// A chain of one mesh, which is always drawn
+    pub fn single(mesh_id: MeshID) -> Self {
+        Self {
+            levels: vec![LodLevel {
+                mesh_id,
+                screen_size: 0.0,
+            }],
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub enum Material {
+    Phong(PhongMaterial),
+    Pbr(PbrMaterial),
+}
+
+impl From<PhongMaterial> for Material {
+    fn from(material: PhongMaterial) -> Self {
+        Self::Phong(material)
+    }
+}
+
+impl From<PbrMaterial> for Material {
+    fn from(material: PbrMaterial) -> Self {
+        Self::Pbr(material)
+    }
+}
+
+/// Lit with the Blinn-Phong model. Missing textures are replaced with placeholders
+#[derive(Clone, Copy)]
+pub struct PhongMaterial {
     pub shininess: f32,
     pub diffuse_texture_id: Option<TextureID>,
     pub specular_texture_id: Option<TextureID>,
     pub normal_texture_id: Option<TextureID>,
 }
 
+/// Lit with the metallic/roughness model, using a Cook-Torrance BRDF. <br>
+/// Each factor is multiplied by its texture, where there is one, following the glTF conventions
+#[derive(Clone, Copy)]
+pub struct PbrMaterial {
+    pub base_colour: Vec4f,
+    pub metallic: f32,
+    pub roughness: f32,
+    /// How much the occlusion texture darkens ambient light, between 0.0 and 1.0
+    pub occlusion_strength: f32,
+    pub emissive: Vec3f,
+    pub base_colour_texture_id: Option<TextureID>,
+    /// Roughness in the green channel, and metallic in the blue channel
+    pub metallic_roughness_texture_id: Option<TextureID>,
+    pub normal_texture_id: Option<TextureID>,
+    /// Ambient occlusion in the red channel
+    pub occlusion_texture_id: Option<TextureID>,
+    pub emissive_texture_id: Option<TextureID>,
+}
+
+impl Default for PbrMaterial {
+    fn default() -> Self {
+        Self {
+            base_colour: Vec4f::uniform(1.0),
+            metallic: 0.0,
+            roughness: 0.5,
+            occlusion_strength: 1.0,
+            emissive: Vec3f::uniform(0.0),
+            base_colour_texture_id: None,
+            metallic_roughness_texture_id: None,
+            normal_texture_id: None,
+            occlusion_texture_id: None,
+            emissive_texture_id: None,
+        }
+    }
+}
+
 #[repr(C)]
 #[derive(Debug, Clone, Copy)]
 pub struct Vertex {
@@ -1,14 +1,23 @@
 use std::{collections::VecDeque, marker::PhantomData};
 
+use log::error;
+
 use super::model::*;
-use crate::graphics::{
-    framebuffer::{Framebuffer, FramebufferConfig},
-    shader::Program,
-    texture::{Image, Texture, TextureConfig, TextureFilter, TextureWrap},
+use crate::{
+    graphics::{
+        framebuffer::{Framebuffer, FramebufferConfig, InternalFormat},
+        shader::Program,
+        texture::{Image, Texture, TextureConfig, TextureFilter, TextureType, TextureWrap},
+    },
+    memory_manager::memory_manager::{MemoryManager, MeshAllocation},
 };
 
 pub struct ResourcesManager {
     pub mesh_manager: ResourceManager<Mesh, MeshID>,
+    pub mesh_bounds_manager: ResourceManager<Aabb, MeshID>,
+    /// Where the mesh lives in the geometry pool, or None for dynamic meshes that are uploaded every frame
+    pub mesh_allocation_manager: ResourceManager<Option<MeshAllocation>, MeshID>,
+    pub lod_chain_manager: ResourceManager<LodChain, LodChainID>,
     pub material_manager: ResourceManager<Material, MaterialID>,
     pub shader_program_manager: ResourceManager<Program, ShaderProgramID>,
     pub texture_manager: ResourceManager<Texture, TextureID>,
@@ -26,6 +35,9 @@ impl ResourcesManager {
     pub fn new() -> Self {
         let mut rm = ResourcesManager {
             mesh_manager: ResourceManager::new(),
+            mesh_bounds_manager: ResourceManager::new(),
+            mesh_allocation_manager: ResourceManager::new(),
+            lod_chain_manager: ResourceManager::new(),
             material_manager: ResourceManager::new(),
             shader_program_manager: ResourceManager::new(),
             texture_manager: ResourceManager::new(),
@@ -110,6 +122,17 @@ impl ResourcesManager {
         }
     }
 
+    /// Loads a colour grading LUT, from an image of its slices laid side by side
+    pub fn load_lut(
+        &mut self,
+        path: &'static str,
+        config: &TextureConfig,
+    ) -> Result<TextureID, String> {
+        let image = Image::from_path(path).map_err(|err| err.to_string())?;
+        let texture = Texture::new_lut(image, config)?;
+        Ok(self.texture_manager.load(texture))
+    }
+
     pub fn load_skybox_textures(
         &mut self,
         paths: [&'static str; 6],
@@ -152,7 +175,31 @@ impl ResourcesManager {
         // solution: remove shader bucket, but that will offset the index!!!
     }
 
-    pub fn load_mesh(&mut self, mesh: Mesh) -> MeshID {
+    /// Uploads the mesh into the geometry pool once, so it can be drawn without re-uploading. Changes made to
+    /// the mesh afterwards will not be seen by the GPU, use `load_dynamic_mesh` for meshes that change.
+    pub fn load_mesh(&mut self, mesh: Mesh, memory_manager: &mut MemoryManager) -> MeshID {
+        let allocation = match memory_manager.upload_static_mesh(&mesh.vertices, &mesh.indices) {
+            Ok(allocation) => Some(allocation),
+            Err(err) => {
+                error!("{}, it will be treated as dynamic", err);
+                None
+            }
+        };
+
+        self.insert_mesh(mesh, allocation)
+    }
+
+    /// The mesh is uploaded each frame that it is drawn, so it may be modified via `borrow_mut_mesh`, which keeps
+    /// its bounds up to date
+    pub fn load_dynamic_mesh(&mut self, mesh: Mesh) -> MeshID {
+        self.insert_mesh(mesh, None)
+    }
+
+    fn insert_mesh(&mut self, mesh: Mesh, allocation: Option<MeshAllocation>) -> MeshID {
+        // data is kept alongside the mesh, so all of these managers hand out the same ID
+        self.mesh_bounds_manager
+            .load(Aabb::from_vertices(&mesh.vertices));
+        self.mesh_allocation_manager.load(allocation);
         self.mesh_manager.load(mesh)
     }
 
@@ -164,8 +211,12 @@ impl ResourcesManager {
         // solution: remove mesh buckets, but that will offset the index!!!
     }
 
-    pub fn load_material(&mut self, material: Material) -> MaterialID {
-        self.material_manager.load(material)
+    pub fn load_lod_chain(&mut self, lod_chain: LodChain) -> LodChainID {
+        self.lod_chain_manager.load(lod_chain)
+    }
+
+    pub fn load_material(&mut self, material: impl Into<Material>) -> MaterialID {
+        self.material_manager.load(material.into())
     }
 
     fn remove_material(&mut self, material_id: MaterialID) {
@@ -181,6 +232,51 @@ impl ResourcesManager {
         // solution: we replace material id when rendering with id of the default material
     }
 
+    /// Creates a texture that can be drawn into, along with a framebuffer to draw into it through, which doesn't
+    /// resize with the window
+    pub fn load_render_texture(
+        &mut self,
+        width: u32,
+        height: u32,
+    ) -> Result<(TextureID, FramebufferID), String> {
+        if width == 0 || height == 0 {
+            return Err(format!(
+                "A render texture must have a size, but was {}x{}",
+                width, height
+            ));
+        }
+
+        // the final image is already gamma encoded, so it is stored as sRGB to be decoded when sampled
+        let texture = Texture::new_framebuffer_texture(
+            TextureType::T2D,
+            InternalFormat::SRGB8A8,
+            1,
+            1,
+            1,
+            width,
+            height,
+            &TextureConfig {
+                wrap: TextureWrap::ClampToEdge,
+                min_filter: TextureFilter::Linear,
+                mag_filter: TextureFilter::Linear,
+                mipmap: false,
+                srgb: true,
+            },
+        );
+
+        let framebuffer = Framebuffer::new(&FramebufferConfig {
+            width,
+            height,
+            ..Default::default()
+        });
+        framebuffer.attach_colour_texture(&texture);
+
+        Ok((
+            self.texture_manager.load(texture),
+            self.framebuffer_manager.load(framebuffer),
+        ))
+    }
+
     pub fn load_framebuffer(
         &mut self,
         config: &FramebufferConfig,
@@ -194,10 +290,34 @@ impl ResourcesManager {
         id
     }
 
+    /// Deletes the framebuffer's attachments, after which its ID is dead
+    pub fn remove_framebuffer(&mut self, framebuffer_id: FramebufferID) {
+        if let Some(framebuffer) = self.framebuffer_manager.borrow(&framebuffer_id) {
+            framebuffer.delete();
+        }
+        self.framebuffer_manager.remove(framebuffer_id);
+        self.resize_framebuffers.retain(|id| *id != framebuffer_id);
+    }
+
     pub fn borrow_mesh(&self, mesh_id: &MeshID) -> Option<&Mesh> {
         self.mesh_manager.borrow(mesh_id)
     }
 
+    pub fn borrow_mesh_bounds(&self, mesh_id: &MeshID) -> Option<&Aabb> {
+        self.mesh_bounds_manager.borrow(mesh_id)
+    }
+
+    /// Returns None if the mesh is dead, or dynamic
+    pub fn borrow_mesh_allocation(&self, mesh_id: &MeshID) -> Option<&MeshAllocation> {
+        self.mesh_allocation_manager
+            .borrow(mesh_id)
+            .and_then(|allocation| allocation.as_ref())
+    }
+
+    pub fn borrow_lod_chain(&self, lod_chain_id: &LodChainID) -> Option<&LodChain> {
+        self.lod_chain_manager.borrow(lod_chain_id)
+    }
+
     pub fn borrow_material(&self, material_id: &MaterialID) -> Option<&Material> {
         self.material_manager.borrow(material_id)
     }
@@ -214,8 +334,13 @@ impl ResourcesManager {
         self.framebuffer_manager.borrow(framebuffer_id)
     }
 
-    pub fn borrow_mut_mesh(&mut self, mesh_id: &MeshID) -> Option<&mut Mesh> {
-        self.mesh_manager.borrow_mut(mesh_id)
+    /// The mesh's bounds are recomputed once the returned guard is dropped
+    pub fn borrow_mut_mesh(&mut self, mesh_id: &MeshID) -> Option<MeshMut<'_>> {
+        MeshMut::new(
+            &mut self.mesh_manager,
+            &mut self.mesh_bounds_manager,
+            mesh_id,
+        )
     }
 
     pub fn borrow_mut_material(&mut self, material_id: &MaterialID) -> Option<&mut Material> {
@@ -260,6 +385,47 @@ pub trait ResourceManagerTrait {
     fn remove(&mut self, resource_id: Self::ResourceIDType);
 }
 
+/// Mutable access to a mesh, which recomputes the mesh's bounds when dropped so that culling sees the changes
+pub struct MeshMut<'a> {
+    mesh: &'a mut Mesh,
+    bounds: Option<&'a mut Aabb>,
+}
+
+impl<'a> MeshMut<'a> {
+    pub fn new(
+        mesh_manager: &'a mut ResourceManager<Mesh, MeshID>,
+        mesh_bounds_manager: &'a mut ResourceManager<Aabb, MeshID>,
+        mesh_id: &MeshID,
+    ) -> Option<Self> {
+        Some(Self {
+            mesh: mesh_manager.borrow_mut(mesh_id)?,
+            bounds: mesh_bounds_manager.borrow_mut(mesh_id),
+        })
+    }
+}
+
+impl std::ops::Deref for MeshMut<'_> {
+    type Target = Mesh;
+
+    fn deref(&self) -> &Self::Target {
+        self.mesh
+    }
+}
+
+impl std::ops::DerefMut for MeshMut<'_> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.mesh
+    }
+}
+
+impl Drop for MeshMut<'_> {
+    fn drop(&mut self) {
+        if let Some(bounds) = self.bounds.as_deref_mut() {
+            *bounds = Aabb::from_vertices(&self.mesh.vertices);
+        }
+    }
+}
+
 pub trait ResourceIDTrait {
     fn new(id: u32) -> Self
     where
@@ -363,6 +529,12 @@ pub struct MeshID {
     id: u32,
 }
 
+impl_resourceID!(LodChainID);
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct LodChainID {
+    id: u32,
+}
+
 impl_resourceID!(TextureID);
 #[derive(Clone, Copy, PartialEq, Eq)]
 pub struct TextureID {
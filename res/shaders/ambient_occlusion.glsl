@@ -0,0 +1,97 @@
+#shader compute
+#version 450 core
+#include "res/shaders/common/buffers/matricesBuffer.glsl"
+#include "res/shaders/common/buffers/generalBuffer.glsl"
+#include "res/shaders/common/util.glsl"
+
+#line 0 15
+
+#define IMAGE_SIZE          vector1.xy
+#define RADIUS              vector1.z
+#define INTENSITY           vector1.w
+#define BIAS                vector2.x
+#define SAMPLE_COUNT        int(index1)
+
+#define PI                  3.14159265
+
+#define GROUP_WIDTH         16
+
+layout (local_size_x = GROUP_WIDTH, local_size_y = GROUP_WIDTH, local_size_z = 1) in;
+layout(binding = 0) uniform sampler2D depth_texture;
+layout(r8, binding = 1) writeonly uniform image2D write_image;
+
+// reconstruct the view space position of a texel from the depth buffer, using the perspective projection
+vec3 viewPosition(ivec2 texelCoord) {
+    texelCoord = clamp(texelCoord, ivec2(0), ivec2(IMAGE_SIZE) - 1);
+
+    float depth = texelFetch(depth_texture, texelCoord, 0).r;
+    vec3 ndc = vec3((vec2(texelCoord) + 0.5) / IMAGE_SIZE, depth) * 2.0 - 1.0;
+
+    float viewZ = -projection[3][2] / (ndc.z + projection[2][2]);
+    vec2 viewXY = ndc.xy * -viewZ / vec2(projection[0][0], projection[1][1]);
+
+    return vec3(viewXY, viewZ);
+}
+
+// the normal is taken from the neighbours with the smallest difference in depth, to avoid smearing across edges
+vec3 viewNormal(ivec2 texelCoord, vec3 position) {
+    vec3 right = viewPosition(texelCoord + ivec2(1, 0)) - position;
+    vec3 left = position - viewPosition(texelCoord - ivec2(1, 0));
+    vec3 up = viewPosition(texelCoord + ivec2(0, 1)) - position;
+    vec3 down = position - viewPosition(texelCoord - ivec2(0, 1));
+
+    vec3 dx = abs(right.z) < abs(left.z) ? right : left;
+    vec3 dy = abs(up.z) < abs(down.z) ? up : down;
+
+    return normalize(cross(dx, dy));
+}
+
+void main() {
+    ivec2 texelCoord = ivec2(gl_GlobalInvocationID);
+
+    if (any(greaterThanEqual(texelCoord, ivec2(IMAGE_SIZE)))) {
+        return;
+    }
+
+    // nothing has been drawn here, so there is nothing to occlude
+    if (texelFetch(depth_texture, texelCoord, 0).r >= 1.0) {
+        imageStore(write_image, texelCoord, vec4(1.0));
+        return;
+    }
+
+    vec3 position = viewPosition(texelCoord);
+    vec3 normal = viewNormal(texelCoord, position);
+
+    // rotate the sample kernel per pixel, and let the blur pass remove the resulting noise
+    float phi = InterleavedGradientNoise(vec2(texelCoord)) * 2.0 * PI;
+    vec3 helper = abs(normal.z) < 0.999 ? vec3(0.0, 0.0, 1.0) : vec3(1.0, 0.0, 0.0);
+    vec3 tangent = normalize(cross(helper, normal));
+    vec3 bitangent = cross(normal, tangent);
+    mat3 tbn = mat3(tangent, bitangent, normal);
+
+    float occlusion = 0.0;
+
+    for (int i = 0; i < SAMPLE_COUNT; i++) {
+        // project a point on the disk up onto the hemisphere, giving a cosine weighted direction
+        vec2 disk = VogelDisk(i, SAMPLE_COUNT, phi);
+        vec3 direction = vec3(disk, sqrt(max(0.0, 1.0 - dot(disk, disk))));
+
+        // bias samples towards the centre, where occluders matter more
+        float scale = float(i + 1) / float(SAMPLE_COUNT);
+        scale = mix(0.1, 1.0, scale * scale);
+
+        vec3 samplePosition = position + tbn * direction * RADIUS * scale;
+
+        vec4 clip = projection * vec4(samplePosition, 1.0);
+        vec2 uv = clip.xy / clip.w * 0.5 + 0.5;
+        float sceneZ = viewPosition(ivec2(uv * IMAGE_SIZE)).z;
+
+        // ignore occluders that are far outside of the radius, such as a foreground object in front of a wall
+        float rangeCheck = smoothstep(0.0, 1.0, RADIUS / abs(position.z - sceneZ));
+        occlusion += (sceneZ >= samplePosition.z + BIAS ? 1.0 : 0.0) * rangeCheck;
+    }
+
+    float ao = pow(1.0 - occlusion / float(SAMPLE_COUNT), INTENSITY);
+
+    imageStore(write_image, texelCoord, vec4(ao));
+}
@@ -0,0 +1,53 @@
+#shader compute
+#version 450 core
+#include "res/shaders/common/buffers/matricesBuffer.glsl"
+#include "res/shaders/common/buffers/generalBuffer.glsl"
+
+#line 0 16
+
+#define IMAGE_SIZE          vector1.xy
+#define DIRECTION           ivec2(vector1.zw)
+#define BLUR_RADIUS         int(index1)
+
+// how quickly the weight of a sample falls off as its depth differs from the centre
+#define DEPTH_SHARPNESS     16.0
+
+#define GROUP_WIDTH         16
+
+layout (local_size_x = GROUP_WIDTH, local_size_y = GROUP_WIDTH, local_size_z = 1) in;
+layout(binding = 0) uniform sampler2D depth_texture;
+layout(binding = 1) uniform sampler2D read_image;
+layout(r8, binding = 2) writeonly uniform image2D write_image;
+
+float linearDepth(ivec2 texelCoord) {
+    float depth = texelFetch(depth_texture, texelCoord, 0).r;
+    return projection[3][2] / (depth * 2.0 - 1.0 + projection[2][2]);
+}
+
+// separable gaussian blur, that doesn't blur across edges in the depth buffer
+void main() {
+    ivec2 texelCoord = ivec2(gl_GlobalInvocationID);
+    ivec2 maxCoord = ivec2(IMAGE_SIZE) - 1;
+
+    if (any(greaterThan(texelCoord, maxCoord))) {
+        return;
+    }
+
+    float centreDepth = linearDepth(texelCoord);
+    float sigma = max(float(BLUR_RADIUS), 1.0) * 0.5;
+
+    float total = 0.0;
+    float totalWeight = 0.0;
+
+    for (int i = -BLUR_RADIUS; i <= BLUR_RADIUS; i++) {
+        ivec2 sampleCoord = clamp(texelCoord + DIRECTION * i, ivec2(0), maxCoord);
+
+        float depthDifference = abs(linearDepth(sampleCoord) - centreDepth) / max(abs(centreDepth), 0.0001);
+        float weight = exp(-float(i * i) / (2.0 * sigma * sigma)) * exp(-depthDifference * DEPTH_SHARPNESS);
+
+        total += texelFetch(read_image, sampleCoord, 0).r * weight;
+        totalWeight += weight;
+    }
+
+    imageStore(write_image, texelCoord, vec4(total / totalWeight));
+}
@@ -15,12 +15,12 @@ layout(location = 6) in mat4 a_transform;
 
 
 void main() {
-    if (index1 == 0) {
-        gl_Position = directionalLight.view * a_transform * vec4(a_position, 1.0);
-    } else if (index1 < MAX_SPOT_LIGHTS + 1) {
+    // spot lights come first, followed by each face of each point light
+    if (index1 < MAX_SPOT_LIGHT_SHADOWS) {
         gl_Position = spotLights[index1].view * a_transform * vec4(a_position, 1.0);
-    } else if (index1 < MAX_POINT_LIGHTS + MAX_SPOT_LIGHTS + 1) {
-        
+    } else {
+        uint face = index1 - MAX_SPOT_LIGHT_SHADOWS;
+        gl_Position = pointLights[face / 6].views[face % 6] * a_transform * vec4(a_position, 1.0);
     }
 }
 
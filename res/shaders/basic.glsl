@@ -11,17 +11,23 @@ layout(location = 4) in vec2 a_texCoord;
 
 layout(location = 5) in uint a_materialIndex;
 layout(location = 6) in mat4 a_transform;
+layout(location = 10) in float a_lodFade;
 
 
 out VS_OUT {
     vec2 texCoord;
     flat Material material;
+    flat float lodFade;
 } vs_out;
 
 
+// must match the depth pre-pass exactly, for the equal depth test
+invariant gl_Position;
+
 void main() {
     vs_out.texCoord = a_texCoord;
     vs_out.material = materials[a_materialIndex];
+    vs_out.lodFade = a_lodFade;
 
     gl_Position = projection * view * a_transform * vec4(a_position, 1.0);
 }
@@ -32,14 +38,20 @@ void main() {
 #version 450 core
 #extension GL_ARB_bindless_texture : require
 #include "res/shaders/common/defs/material.glsl"
+#include "res/shaders/common/util.glsl"
 
 in VS_OUT {
     vec2 texCoord;
     flat Material material;
+    flat float lodFade;
 } vs_in;
 
 out vec4 FragColor;
 
 void main() {
+    if (LodFadeDiscarded(gl_FragCoord.xy, vs_in.lodFade)) {
+        discard;
+    }
+
     FragColor = texture(sampler2D(vs_in.material.diffuseTexture), vs_in.texCoord);
 }
\ No newline at end of file
@@ -5,8 +5,6 @@
 #define READ_IMAGE_SIZE         vector1.xy
 #define READ_MIP_LEVEL          index1
 
-#define THRESHOLD               0.35
-
 #define GROUP_WIDTH             16
 #define GROUP_COUNT             (GROUP_WIDTH * GROUP_WIDTH)
 #define SAMPLE_RADIUS           2
@@ -17,11 +15,21 @@ layout (local_size_x = GROUP_WIDTH, local_size_y = GROUP_WIDTH, local_size_z = 1
 layout(binding = 0) uniform sampler2D read_image;
 layout(rgba16f, binding = 1) writeonly uniform image2D write_image;
 
+// only the first downsample, from the scene, is filtered by brightness
+uniform bool prefilter;
+uniform float threshold;
+// width of the curve below the threshold that the bloom fades in over
+uniform float knee;
+
 shared vec3 samples[GROUP_SAMPLES_COUNT];
 
+// quadratic curve from (threshold - knee) to (threshold + knee), which then becomes linear, so that the bloom fades in
+// rather than cutting off sharply at the threshold
 vec3 thresholdFilter(vec3 c) {
     float brightness = max(c.r, max(c.g, c.b));
-    float contribution = max(0, brightness - THRESHOLD);
+    float soft = clamp(brightness - threshold + knee, 0.0, 2.0 * knee);
+    soft = (soft * soft) / (4.0 * knee + 0.00001);
+    float contribution = max(soft, brightness - threshold);
     contribution /= max(brightness, 0.00001);
     return c * contribution;
 }
@@ -93,7 +101,7 @@ void main() {
     downsample += (b+d+f+h)*0.0625;
     downsample += (j+k+l+m)*0.125;
 
-    if (READ_MIP_LEVEL == 0) {
+    if (prefilter) {
         downsample = thresholdFilter(downsample);
     }
 
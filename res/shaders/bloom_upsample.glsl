@@ -5,8 +5,6 @@
 #define READ_IMAGE_SIZE         vector1.xy
 #define READ_MIP_LEVEL          index1
 
-#define BLOOM_INTENSITY         1.33
-
 #define GROUP_WIDTH             16
 #define GROUP_COUNT             (GROUP_WIDTH * GROUP_WIDTH)
 #define SAMPLE_RADIUS           2
@@ -16,6 +14,15 @@
 layout (local_size_x = GROUP_WIDTH, local_size_y = GROUP_WIDTH, local_size_z = 1) in;
 layout(binding = 0) uniform sampler2D read_image;
 layout(rgba16f, binding = 1) uniform image2D write_image;
+layout(binding = 2) uniform sampler2D dirt_texture;
+
+// the last upsample adds the bloom to the scene, rather than to the next level of the chain
+uniform bool composite;
+// weight of each wider level as it is added to the one above
+uniform float radius;
+uniform float intensity;
+uniform bool useDirt;
+uniform float dirtIntensity;
 
 shared vec3 samples[GROUP_SAMPLES_COUNT];
 
@@ -73,7 +80,18 @@ void main() {
     upsample += (b+d+f+h)*2.0;
     upsample += (a+c+g+i);
     upsample /= 16.0;
-    upsample *= BLOOM_INTENSITY;
+
+    if (composite) {
+        upsample *= intensity;
+
+        if (useDirt) {
+            vec2 uv = (vec2(texelCoord) + 0.5) / READ_IMAGE_SIZE;
+            upsample *= 1.0 + textureLod(dirt_texture, uv, 0.0).rgb * dirtIntensity;
+        }
+    } else {
+        upsample *= radius;
+    }
+
     vec3 before = imageLoad(write_image, texelCoord).rgb;
     vec3 result = before + upsample;
 
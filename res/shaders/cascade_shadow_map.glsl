@@ -20,20 +20,22 @@ void main() {
 #shader geometry
 #version 450 core
 #include "res/shaders/common/buffers/lightsBuffer.glsl"
-#include "res/shaders/common/buffers/generalBuffer.glsl"
 
-layout (triangles) in;
-layout (triangle_strip, max_vertices=18) out;
+// one invocation per cascade, each rendering to its own layer of the shadow map
+layout (triangles, invocations = MAX_CASCADES) in;
+layout (triangle_strip, max_vertices = 3) out;
 
 void main() {
-      for (int face = 0; face < 6; ++face) {
-        gl_Layer = face; // built-in variable that specifies to which face we render.
-        for (int i = 0; i < 3; ++i) {
-            gl_Position = pointLights[index1].views[face] * gl_in[i].gl_Position;
-            EmitVertex();
-        }    
-        EndPrimitive();
+    if (gl_InvocationID >= directionalLight.cascadeCount) {
+        return;
     }
+
+    gl_Layer = gl_InvocationID;
+    for (int i = 0; i < 3; ++i) {
+        gl_Position = directionalLight.cascadeViews[gl_InvocationID] * gl_in[i].gl_Position;
+        EmitVertex();
+    }
+    EndPrimitive();
 }
 
 
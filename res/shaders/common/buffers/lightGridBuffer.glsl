@@ -0,0 +1,24 @@
+#include "res/shaders/common/defs/lights.glsl"
+
+#define MAX_POINT_LIGHTS 1024
+#define MAX_SPOT_LIGHTS 1024
+
+#define CLUSTER_GRID_X 16
+#define CLUSTER_GRID_Y 9
+#define CLUSTER_GRID_Z 24
+#define MAX_CLUSTERS (CLUSTER_GRID_X * CLUSTER_GRID_Y * CLUSTER_GRID_Z)
+
+struct LightCluster {
+    // start of the cluster's point light indices, which are followed by its spot light indices
+    uint offset;
+    uint pointLightCount;
+    uint spotLightCount;
+    uint padding;
+};
+
+layout (std430, binding = 5) readonly buffer LightGrid {
+    PointLight pointLights[MAX_POINT_LIGHTS];
+    SpotLight spotLights[MAX_SPOT_LIGHTS];
+    LightCluster clusters[MAX_CLUSTERS];
+    uint clusterLightIndices[];
+};
\ No newline at end of file
@@ -1,11 +1,11 @@
 #include "res/shaders/common/defs/lights.glsl"
+#include "res/shaders/common/buffers/lightGridBuffer.glsl"
 
-#define MAX_POINT_LIGHTS 2
-#define MAX_SPOT_LIGHTS 4
+// only the first lights of each type are given a shadow map
+#define MAX_POINT_LIGHT_SHADOWS 2
+#define MAX_SPOT_LIGHT_SHADOWS 4
 
 layout (std140, binding = 2) uniform Lights {
-    PointLight pointLights[MAX_POINT_LIGHTS];
-    SpotLight spotLights[MAX_SPOT_LIGHTS];
     DirectionalLight directionalLight;
 
     int pointLightCount;
@@ -14,4 +14,18 @@ layout (std140, binding = 2) uniform Lights {
     
     vec3 cameraDir;
     vec3 cameraPos;
+
+    // zero when there is no ambient occlusion this frame
+    uvec2 ambientOcclusionMap;
+
+    // takes the log of the view depth to the cluster's depth slice
+    float clusterDepthScale;
+    float clusterDepthBias;
+
+    // image based lighting from the environment, each zero when there is no environment
+    uvec2 irradianceMap;
+    uvec2 prefilteredMap;
+    uvec2 brdfLut;
+    // the roughest level of the prefiltered map, which has a roughness of one
+    float prefilteredMaxLevel;
 };
\ No newline at end of file
@@ -1,7 +1,10 @@
 #include "res/shaders/common/buffers/lightsBuffer.glsl"
 
 layout (std140, binding = 1) uniform ShadowMaps {
-    uvec2 pointShadowMaps[MAX_POINT_LIGHTS];
-    uvec2 spotShadowMaps[MAX_SPOT_LIGHTS];
-    uvec2 directionalShadowMap;
+    // spot lights and each face of the point lights are drawn into tiles of the atlas
+    ShadowMap shadowAtlas;
+    ShadowMap directionalShadowMap;
+    // UV offset (xy) and scale (zw) of each tile within the atlas, where a scale of zero means there is no shadow
+    vec4 spotShadowTiles[MAX_SPOT_LIGHT_SHADOWS];
+    vec4 pointShadowTiles[MAX_POINT_LIGHT_SHADOWS][6];
 };
\ No newline at end of file
@@ -0,0 +1,17 @@
+#include "res/shaders/common/buffers/lightsBuffer.glsl"
+#include "res/shaders/common/buffers/matricesBuffer.glsl"
+
+#line 0 17
+
+// The cluster that a world space position falls into, which must match the binning in renderer/clusters.rs
+LightCluster getCluster(vec3 pos_ws) {
+    vec4 pos_vs = view * vec4(pos_ws, 1.0);
+    vec4 pos_cs = projection * pos_vs;
+    vec2 ndc = pos_cs.xy / pos_cs.w;
+
+    uvec2 tile = uvec2(clamp((ndc * 0.5 + 0.5) * vec2(CLUSTER_GRID_X, CLUSTER_GRID_Y), vec2(0.0),
+                             vec2(CLUSTER_GRID_X - 1, CLUSTER_GRID_Y - 1)));
+    uint slice = uint(clamp(log(-pos_vs.z) * clusterDepthScale + clusterDepthBias, 0.0, CLUSTER_GRID_Z - 1));
+
+    return clusters[tile.x + tile.y * CLUSTER_GRID_X + slice * CLUSTER_GRID_X * CLUSTER_GRID_Y];
+}
\ No newline at end of file
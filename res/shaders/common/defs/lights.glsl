@@ -1,3 +1,25 @@
+#define MAX_CASCADES 4
+
+#define SHADOW_FILTER_HARDWARE_PCF 0
+#define SHADOW_FILTER_POISSON_PCF 1
+#define SHADOW_FILTER_PCSS 2
+
+struct ShadowSettings {
+    int filter;
+    float depthBias;
+    // in shadow map texels
+    float normalBias;
+    // scales the penumbra of PCSS
+    float lightSize;
+};
+
+struct ShadowMap {
+    // samples with a depth comparison
+    uvec2 comparison;
+    // samples the stored depth
+    uvec2 depth;
+};
+
 struct PointLight {
     vec3 ambientCol;
     vec3 diffuseCol;
@@ -11,6 +33,8 @@ struct PointLight {
     vec3 position;
 
     mat4 views[6];
+
+    ShadowSettings shadow;
 };
 
 struct SpotLight {
@@ -30,6 +54,8 @@ struct SpotLight {
     vec3 direction;
 
     mat4 view;
+
+    ShadowSettings shadow;
 };
 
 struct DirectionalLight {
@@ -40,5 +66,13 @@ struct DirectionalLight {
     vec3 position;
     vec3 direction;
 
-    mat4 view;
+    mat4 cascadeViews[MAX_CASCADES];
+    // view space depth that each cascade ends at
+    vec4 cascadeSplits;
+    int cascadeCount;
+    // fraction of each cascade, at its far end, that is blended with the next
+    float cascadeBlend;
+    int cascadeDebug;
+
+    ShadowSettings shadow;
 };
\ No newline at end of file
@@ -1,6 +1,20 @@
+#define MATERIAL_MODEL_PHONG 0
+#define MATERIAL_MODEL_PBR 1
+
 struct Material {
     float shininess;
+    uint model;
+    // also the base colour texture of PBR materials
     uvec2 diffuseTexture;
     uvec2 specularTexture;
     uvec2 normalTexture;
+    // textures of PBR materials, which are zero when the material doesn't have them
+    uvec2 metallicRoughnessTexture;
+    uvec2 occlusionTexture;
+    uvec2 emissiveTexture;
+    vec4 baseColour;
+    vec3 emissive;
+    float metallic;
+    float roughness;
+    float occlusionStrength;
 };
\ No newline at end of file
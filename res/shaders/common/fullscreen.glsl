@@ -0,0 +1,10 @@
+#line 0 36
+
+out vec2 texCoord;
+
+// Covers the screen with a single triangle, made from the vertex index alone, so no vertex data is needed
+void main() {
+    vec2 position = vec2((gl_VertexID << 1) & 2, gl_VertexID & 2);
+    texCoord = position;
+    gl_Position = vec4(position * 2.0 - 1.0, 0.0, 1.0);
+}
\ No newline at end of file
@@ -0,0 +1,56 @@
+#line 0 22
+
+// Shared by the compute shaders that bake the image based lighting maps from an environment
+// https://learnopengl.com/PBR/IBL/Specular-IBL
+
+#ifndef PI
+#define PI  3.141592653
+#endif
+
+// World space direction through the centre of a texel of a cubemap face, following the layout of the cubemap
+// faces in the OpenGL specification
+vec3 cubemapDirection(ivec3 texelCoord, int faceSize) {
+    vec2 st = (vec2(texelCoord.xy) + 0.5) / float(faceSize) * 2.0 - 1.0;
+
+    vec3 direction;
+    switch (texelCoord.z) {
+        case 0: direction = vec3( 1.0,  -st.y, -st.x); break;
+        case 1: direction = vec3(-1.0,  -st.y,  st.x); break;
+        case 2: direction = vec3( st.x,  1.0,   st.y); break;
+        case 3: direction = vec3( st.x, -1.0,  -st.y); break;
+        case 4: direction = vec3( st.x, -st.y,  1.0);  break;
+        default: direction = vec3(-st.x, -st.y, -1.0); break;
+    }
+
+    return normalize(direction);
+}
+
+// Low discrepancy sequence, which covers the sample space more evenly than random numbers
+vec2 hammersley(uint i, uint count) {
+    uint bits = i;
+    bits = (bits << 16u) | (bits >> 16u);
+    bits = ((bits & 0x55555555u) << 1u) | ((bits & 0xAAAAAAAAu) >> 1u);
+    bits = ((bits & 0x33333333u) << 2u) | ((bits & 0xCCCCCCCCu) >> 2u);
+    bits = ((bits & 0x0F0F0F0Fu) << 4u) | ((bits & 0xF0F0F0F0u) >> 4u);
+    bits = ((bits & 0x00FF00FFu) << 8u) | ((bits & 0xFF00FF00u) >> 8u);
+    float radicalInverse = float(bits) * 2.3283064365386963e-10;
+
+    return vec2(float(i) / float(count), radicalInverse);
+}
+
+// Halfway vector around the normal, distributed by the GGX lobe of the given roughness, so that the samples are
+// concentrated where they contribute the most
+vec3 importanceSampleGGX(vec2 xi, vec3 normal, float roughness) {
+    float a = roughness * roughness;
+
+    float phi = 2.0 * PI * xi.x;
+    float cosTheta = sqrt((1.0 - xi.y) / (1.0 + (a * a - 1.0) * xi.y));
+    float sinTheta = sqrt(1.0 - cosTheta * cosTheta);
+    vec3 halfway = vec3(cos(phi) * sinTheta, sin(phi) * sinTheta, cosTheta);
+
+    vec3 up = abs(normal.z) < 0.999 ? vec3(0.0, 0.0, 1.0) : vec3(1.0, 0.0, 0.0);
+    vec3 tangent = normalize(cross(up, normal));
+    vec3 bitangent = cross(normal, tangent);
+
+    return normalize(tangent * halfway.x + bitangent * halfway.y + normal * halfway.z);
+}
\ No newline at end of file
@@ -0,0 +1,179 @@
+#include "res/shaders/common/defs/lights.glsl"
+#include "res/shaders/common/defs/material.glsl"
+#include "res/shaders/common/buffers/lightsBuffer.glsl"
+#include "res/shaders/common/shadow.glsl"
+
+#line 0 21
+
+// Metallic/roughness shading with a Cook-Torrance BRDF
+// https://learnopengl.com/PBR/Theory
+
+// dielectrics all reflect roughly 4% of light head on
+#define DIELECTRIC_F0 0.04
+
+// The material's parameters at a point on its surface, after its textures have been applied
+struct PbrSurface {
+    vec3 baseColour;
+    float metallic;
+    float roughness;
+    float occlusion;
+    vec3 emissive;
+    float alpha;
+};
+
+PbrSurface getPbrSurface(Material material, vec2 texCoord) {
+    PbrSurface surface;
+    surface.baseColour = material.baseColour.rgb;
+    surface.metallic = material.metallic;
+    surface.roughness = material.roughness;
+    surface.occlusion = 1.0;
+    surface.emissive = material.emissive;
+    surface.alpha = material.baseColour.a;
+
+    if (material.diffuseTexture != uvec2(0)) {
+        vec4 baseColour = texture(sampler2D(material.diffuseTexture), texCoord);
+        surface.baseColour *= baseColour.rgb;
+        surface.alpha *= baseColour.a;
+    }
+    if (material.metallicRoughnessTexture != uvec2(0)) {
+        vec3 metallicRoughness = texture(sampler2D(material.metallicRoughnessTexture), texCoord).rgb;
+        surface.roughness *= metallicRoughness.g;
+        surface.metallic *= metallicRoughness.b;
+    }
+    if (material.occlusionTexture != uvec2(0)) {
+        float occlusion = texture(sampler2D(material.occlusionTexture), texCoord).r;
+        surface.occlusion = mix(1.0, occlusion, material.occlusionStrength);
+    }
+    if (material.emissiveTexture != uvec2(0)) {
+        surface.emissive *= texture(sampler2D(material.emissiveTexture), texCoord).rgb;
+    }
+
+    // a perfectly smooth surface has an infinitely small highlight, which can't be sampled
+    surface.roughness = clamp(surface.roughness, 0.04, 1.0);
+
+    return surface;
+}
+
+// Trowbridge-Reitz GGX, the proportion of microfacets that are aligned with the halfway vector
+float distributionGGX(float NdotH, float roughness) {
+    float a = roughness * roughness;
+    float a2 = a * a;
+    float denominator = NdotH * NdotH * (a2 - 1.0) + 1.0;
+
+    return a2 / (PI * denominator * denominator);
+}
+
+// Schlick-GGX, the proportion of microfacets that are not shadowed by others in one direction
+float geometrySchlickGGX(float NdotX, float k) {
+    return NdotX / (NdotX * (1.0 - k) + k);
+}
+
+// Smith's method, which combines the shadowing towards the light with the masking towards the camera
+float geometrySmith(float NdotV, float NdotL, float roughness) {
+    float r = roughness + 1.0;
+    float k = (r * r) / 8.0;
+
+    return geometrySchlickGGX(NdotV, k) * geometrySchlickGGX(NdotL, k);
+}
+
+vec3 fresnelSchlick(float cosTheta, vec3 F0) {
+    return F0 + (1.0 - F0) * pow(clamp(1.0 - cosTheta, 0.0, 1.0), 5.0);
+}
+
+// Light reflected towards the camera from light of the given radiance arriving from the light direction.
+// The vectors can be in any space, as long as they are all in the same one
+vec3 cookTorrance(PbrSurface surface, vec3 normal, vec3 toLight, vec3 toCam, vec3 radiance) {
+    vec3 N = normalize(normal);
+    vec3 V = normalize(toCam);
+    vec3 L = normalize(toLight);
+    vec3 H = normalize(V + L);
+
+    float NdotL = max(dot(N, L), 0.0);
+    float NdotV = max(dot(N, V), 0.0001);
+    float NdotH = max(dot(N, H), 0.0);
+
+    vec3 F0 = mix(vec3(DIELECTRIC_F0), surface.baseColour, surface.metallic);
+    vec3 F = fresnelSchlick(max(dot(H, V), 0.0), F0);
+    float D = distributionGGX(NdotH, surface.roughness);
+    float G = geometrySmith(NdotV, NdotL, surface.roughness);
+
+    vec3 specular = D * G * F / (4.0 * NdotV * NdotL + 0.0001);
+
+    // whatever isn't reflected is refracted, and metals absorb all of the refracted light
+    vec3 kD = (1.0 - F) * (1.0 - surface.metallic);
+
+    return (kD * surface.baseColour / PI + specular) * radiance * NdotL;
+}
+
+// The same small, flat ambient contribution from each light as the Phong model, on top of any image based lighting
+vec3 calcPbrAmbient(PbrSurface surface, vec3 radiance, float ambientOcclusion) {
+    return 0.05 * radiance * surface.baseColour * surface.occlusion * ambientOcclusion;
+}
+
+// Light arriving from the environment, split into diffuse light from the irradiance map and specular light from the
+// prefiltered map, which is scaled by the BRDF integration. The vectors must be in world space, as the maps are.
+// Nothing is added when there is no environment
+vec3 calcPbrImageBasedLight(PbrSurface surface, vec3 normal_ws, vec3 toCam_ws, float ambientOcclusion) {
+    if (irradianceMap == uvec2(0)) {
+        return vec3(0.0);
+    }
+
+    vec3 N = normalize(normal_ws);
+    vec3 V = normalize(toCam_ws);
+    vec3 R = reflect(-V, N);
+    float NdotV = max(dot(N, V), 0.0001);
+
+    vec3 F0 = mix(vec3(DIELECTRIC_F0), surface.baseColour, surface.metallic);
+    // the Fresnel term takes the roughness into account, as there is no single halfway vector
+    vec3 F = F0 + (max(vec3(1.0 - surface.roughness), F0) - F0) * pow(clamp(1.0 - NdotV, 0.0, 1.0), 5.0);
+    vec3 kD = (1.0 - F) * (1.0 - surface.metallic);
+
+    vec3 irradiance = texture(samplerCube(irradianceMap), N).rgb;
+    vec3 diffuse = kD * irradiance * surface.baseColour;
+
+    vec3 prefiltered = textureLod(samplerCube(prefilteredMap), R, surface.roughness * prefilteredMaxLevel).rgb;
+    vec2 brdf = texture(sampler2D(brdfLut), vec2(NdotV, surface.roughness)).rg;
+    vec3 specular = prefiltered * (F * brdf.x + brdf.y);
+
+    return (diffuse + specular) * surface.occlusion * ambientOcclusion;
+}
+
+vec3 calcPbrPointLight(PointLight light, PbrSurface surface, vec3 toLight, vec3 toCam, vec3 pos_ws, vec3 normal_ws,
+                       ShadowMap shadowMap, vec4 shadowTiles[6], vec3 normal, float ambientOcclusion) {
+    float distance = length(toLight);
+    float lightAttenuation = 1.0 / (distance * distance * light.attenuation.x +
+                                    distance * light.attenuation.y +
+                                    light.attenuation.z);
+    vec3 radiance = light.diffuseCol * lightAttenuation;
+
+    float shadow = 1.0 - calcOmniShadow(light, shadowMap, shadowTiles, pos_ws, normal_ws);
+
+    return calcPbrAmbient(surface, radiance, ambientOcclusion) +
+           shadow * cookTorrance(surface, normal, toLight, toCam, radiance);
+}
+
+// light.direction is expected to be in the same space as the other vectors
+vec3 calcPbrSpotlight(SpotLight light, PbrSurface surface, vec3 toLight, vec3 toCam, vec3 pos_ws, vec3 normal_ws,
+                      ShadowMap shadowMap, vec4 shadowTile, vec3 normal, float ambientOcclusion) {
+    float theta = dot(normalize(toLight), normalize(light.direction));
+    float intensity = clamp((theta - light.outerCutoff) / (light.innerCutoff - light.outerCutoff), 0.0, 1.0);
+
+    float distance = length(toLight);
+    float lightAttenuation = 1.0 / (distance * distance * light.attenuation.x +
+                                    distance * light.attenuation.y +
+                                    light.attenuation.z);
+    vec3 radiance = light.diffuseCol * lightAttenuation;
+
+    float shadow = 1.0 - calcSpotShadow(light, shadowMap, shadowTile, pos_ws, normal_ws);
+
+    return calcPbrAmbient(surface, radiance, ambientOcclusion) +
+           shadow * intensity * cookTorrance(surface, normal, toLight, toCam, radiance);
+}
+
+vec3 calcPbrDirLight(DirectionalLight light, PbrSurface surface, vec3 toLight, vec3 toCam, vec3 pos_ws,
+                     vec3 normal_ws, float viewDepth, ShadowMap shadowMap, vec3 normal, float ambientOcclusion) {
+    float shadow = 1.0 - calcCascadeShadow(light, shadowMap, pos_ws, normal_ws, viewDepth);
+
+    return calcPbrAmbient(surface, light.diffuseCol, ambientOcclusion) +
+           shadow * cookTorrance(surface, normal, toLight, toCam, light.diffuseCol);
+}
\ No newline at end of file
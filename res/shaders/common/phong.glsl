@@ -32,8 +32,9 @@ float calcLightCos(vec3 toLight_ts, vec3 normal) {
     return diff;
 }
 
-float calcAmbientLight(float strength) {
-    return strength;
+// Ambient light is reduced by the screen space ambient occlusion, where 1.0 is unoccluded
+float calcAmbientLight(float strength, float ambientOcclusion) {
+    return strength * ambientOcclusion;
 }
 
 // Diffuse light is based on angle between light ray and surface normal
@@ -70,14 +71,15 @@ float calcBlinnSpecularLight(vec3 toCam_ts, vec3 toLight_ts, vec3 normal, float
 }
 
 
-vec3 calcBlinnPhongPointLight(PointLight light, Material material, vec3 toLight_ts, vec3 toCam_ts, vec3 lightToPos_ws,
-                            float lightToPosDepth_ws, samplerCubeShadow shadowMap, vec3 normal_ts, float gloss) {
+vec3 calcBlinnPhongPointLight(PointLight light, Material material, vec3 toLight_ts, vec3 toCam_ts, vec3 pos_ws,
+                            vec3 normal_ws, ShadowMap shadowMap, vec4 shadowTiles[6], vec3 normal_ts, float gloss,
+                            float ambientOcclusion) {
     float lightAttenuation = calcLightAttenuation(toLight_ts, light.attenuation);
 
-    float shadow = 1.0 - calcOmniShadow(shadowMap, lightToPos_ws, lightToPosDepth_ws);
+    float shadow = 1.0 - calcOmniShadow(light, shadowMap, shadowTiles, pos_ws, normal_ws);
     float lightCos = calcLightCos(toLight_ts, normal_ts);
 
-    float ambientLight = calcAmbientLight(0.05);
+    float ambientLight = calcAmbientLight(0.05, ambientOcclusion);
     float diffuseLight = shadow * calcDiffuseLight(lightCos);
     float specularLight = lightCos * shadow * calcBlinnSpecularLight(toCam_ts, toLight_ts, normal_ts, gloss);
 
@@ -85,16 +87,17 @@ vec3 calcBlinnPhongPointLight(PointLight light, Material material, vec3 toLight_
 }
 
 // Applies cut-off at radius of light's pointing direction
-vec3 calcBlinnPhongSpotlight(SpotLight light, Material material, vec3 toLight_ts, vec3 toCam_ts, vec3 spotLightToPos_ls, 
-                                sampler2D shadowMap, vec3 normal_ts, float gloss) {
+vec3 calcBlinnPhongSpotlight(SpotLight light, Material material, vec3 toLight_ts, vec3 toCam_ts, vec3 pos_ws, 
+                                vec3 normal_ws, ShadowMap shadowMap, vec4 shadowTile, vec3 normal_ts, float gloss,
+                                float ambientOcclusion) {
     // get the vector from the light position to the pixel
     vec3 lightRayDir = normalize(toLight_ts); 
 
     // use dot product to get cosine between direction that the actual light is pointing and
     // the vector of the light to the pixel.
     // if that angle is larger than our cutoff, we do not apply the cutoff. 
+    // light.direction is expected to be in tangent space, like the other vectors
     float theta = dot(lightRayDir, normalize(light.direction));
-    // FIXME: This light.direction above will not be in tangent space! do not forget
 
     // from 0-90 degrees, cosine values actually get smaller. So if the cosine value is larger, 
     // the angle is smaller. So we do inner - outer for this reason.
@@ -106,9 +109,9 @@ vec3 calcBlinnPhongSpotlight(SpotLight light, Material material, vec3 toLight_ts
     // of the cutoff
     float lightAttenuation = calcLightAttenuation(toLight_ts, light.attenuation);
     float lightCos = calcLightCos(toLight_ts, normal_ts);
-    float shadow = 1.0 - calcDirShadow(shadowMap, spotLightToPos_ls, lightCos);
+    float shadow = 1.0 - calcSpotShadow(light, shadowMap, shadowTile, pos_ws, normal_ws);
 
-    float ambientLight = calcAmbientLight(0.05);
+    float ambientLight = calcAmbientLight(0.05, ambientOcclusion);
     float diffuseLight = shadow * intensity * calcDiffuseLight(lightCos);
     float specularLight = lightCos * shadow * intensity * calcBlinnSpecularLight(toCam_ts, toLight_ts, normal_ts, gloss);
 
@@ -118,12 +121,13 @@ vec3 calcBlinnPhongSpotlight(SpotLight light, Material material, vec3 toLight_ts
 
 // Maintains a fixed light ray direction that is perpendicular to the actual light's
 // pointing direction during the diffuse light calculation
-vec3 calcBlinnPhongDirLight(DirectionalLight light, Material material, vec3 toLight_ts, vec3 toCam_ts, vec3 dirLightToPos_ls,
-                             sampler2D shadowMap, vec3 normal_ts, float gloss ) {
+vec3 calcBlinnPhongDirLight(DirectionalLight light, Material material, vec3 toLight_ts, vec3 toCam_ts, vec3 pos_ws,
+                             vec3 normal_ws, float viewDepth, ShadowMap shadowMap, vec3 normal_ts, float gloss,
+                             float ambientOcclusion) {
     float lightCos = calcLightCos(toLight_ts, normal_ts);
-    float shadow = 1.0 - calcDirShadow(shadowMap, dirLightToPos_ls, lightCos);
+    float shadow = 1.0 - calcCascadeShadow(light, shadowMap, pos_ws, normal_ws, viewDepth);
 
-    float ambientLight = calcAmbientLight(0.05);
+    float ambientLight = calcAmbientLight(0.05, ambientOcclusion);
     float diffuseLight = shadow * calcDiffuseLight(lightCos);
     float specularLight = lightCos * shadow * calcBlinnSpecularLight(toCam_ts, toLight_ts, normal_ts, gloss);
 
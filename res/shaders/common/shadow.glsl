@@ -1,3 +1,4 @@
+#include "res/shaders/common/defs/lights.glsl"
 #include "res/shaders/common/comparison.glsl"
 #include "res/shaders/common/util.glsl"
 
@@ -6,76 +7,229 @@
 #define PI  3.141592653
 #define TAU 6.283185307
 
+// Each of the shadow functions returns how much of the receiver is in shadow, where 1.0 is fully shadowed.
+// The comparison handles of the shadow maps return 1.0 when the reference depth is greater than the stored depth.
+
+#define POISSON_SAMPLES 16
+// radius of the Poisson PCF disk, in texels
+#define POISSON_RADIUS 2.5
+#define BLOCKER_SAMPLES 8
+// radius that PCSS searches for blockers within, and the widest its penumbra can be, in texels
+#define MAX_PENUMBRA 100.0
+
+const vec2 POISSON_DISK[POISSON_SAMPLES] = vec2[](
+    vec2(-0.94201624, -0.39906216),
+    vec2( 0.94558609, -0.76890725),
+    vec2(-0.09418410, -0.92938870),
+    vec2( 0.34495938,  0.29387760),
+    vec2(-0.91588581,  0.45771432),
+    vec2(-0.81544232, -0.87912464),
+    vec2(-0.38277543,  0.27676845),
+    vec2( 0.97484398,  0.75648379),
+    vec2( 0.44323325, -0.97511554),
+    vec2( 0.53742981, -0.47373420),
+    vec2(-0.26496911, -0.41893023),
+    vec2( 0.79197514,  0.19090188),
+    vec2(-0.24188840,  0.99706507),
+    vec2(-0.81409955,  0.91437590),
+    vec2( 0.19984126,  0.78641367),
+    vec2( 0.14383161, -0.14100790)
+);
+
+vec2 rotateDisk(vec2 disk, float rotation) {
+    float sine = sin(rotation);
+    float cosine = cos(rotation);
+
+    return vec2(disk.x * cosine - disk.y * sine, disk.x * sine + disk.y * cosine);
+}
+
+// The normal offset bias grows as the surface turns away from the light, where depth bias alone would need to
+// be large enough to cause peter panning
+float normalOffsetScale(vec3 normal_ws, vec3 toLight_ws) {
+    float lightCos = clamp(dot(normal_ws, normalize(toLight_ws)), 0.0, 1.0);
+    return sqrt(1.0 - lightCos * lightCos);
+}
+
 // http://maxest.gct-game.net/content/chss.pdf
-float penumbraWidth(sampler2D shadowMap, vec2 shadowMapUV, vec2 texelSize, float currentDepth, float diskRotation, int sampleCount) {
-    const float maxPenumbraFilterSize = 100.0;
+float penumbraWidth(float currentDepth, float blockersDepth, float lightSize) {
+    return min(lightSize * (currentDepth - blockersDepth) / blockersDepth, MAX_PENUMBRA);
+}
+
+
+// Shadow map arrays, used by directional light cascades, and the atlas that spot and point lights share
+// Each light's UVs are within its tile, which is the whole layer for the cascades
+///////////////////////////////////////////////////////////////////////////////////////
+
+// Moves a UV within a tile into the shadow map, keeping it half a texel inside the tile so that filtering never
+// reads from a neighbouring tile
+vec2 tileUV(vec2 uv, vec4 tile, vec2 texelSize) {
+    return tile.xy + clamp(uv, texelSize * 0.5, 1.0 - texelSize * 0.5) * tile.zw;
+}
+
+// Each comparison is bilinearly filtered, so four of them, half a texel apart, cover a 3x3 area of texels
+float hardwarePcf(sampler2DArrayShadow shadowMap, vec3 uvDepth, float layer, vec4 tile, vec2 texelSize) {
+    float shadow = 0.0;
+
+    for (int i = 0; i < 4; i++) {
+        vec2 offset = (vec2(i & 1, i >> 1) - 0.5) * texelSize;
+        shadow += texture(shadowMap, vec4(tileUV(uvDepth.xy + offset, tile, texelSize), layer, uvDepth.z));
+    }
+
+    return shadow * 0.25;
+}
+
+float poissonPcf(sampler2DArrayShadow shadowMap, vec3 uvDepth, float layer, vec4 tile, vec2 texelSize,
+                 vec2 radius, float rotation) {
+    float shadow = 0.0;
+
+    for (int i = 0; i < POISSON_SAMPLES; i++) {
+        vec2 offset = rotateDisk(POISSON_DISK[i], rotation) * radius;
+        shadow += texture(shadowMap, vec4(tileUV(uvDepth.xy + offset, tile, texelSize), layer, uvDepth.z));
+    }
+
+    return shadow / POISSON_SAMPLES;
+}
+
+float pcss(ShadowMap shadowMap, vec3 uvDepth, float layer, vec4 tile, vec2 texelSize, float rotation,
+           float lightSize) {
+    sampler2DArray depthMap = sampler2DArray(shadowMap.depth);
 
     float blockersDepth = 0.0;
     float blockersCount = 0.0;
 
-    for (int i = 0; i < sampleCount; i++) {
-        vec2 sampleUV = shadowMapUV + VogelDisk(i, sampleCount, diskRotation) * texelSize * maxPenumbraFilterSize;
-        float shadowMapDepth = texture(shadowMap, sampleUV).r;
+    for (int i = 0; i < BLOCKER_SAMPLES; i++) {
+        vec2 sampleUV = uvDepth.xy + VogelDisk(i, BLOCKER_SAMPLES, rotation) * texelSize * MAX_PENUMBRA;
+        float shadowMapDepth = texture(depthMap, vec3(tileUV(sampleUV, tile, texelSize), layer)).r;
 
-        if (shadowMapDepth < currentDepth) {
+        if (shadowMapDepth < uvDepth.z) {
             blockersDepth += shadowMapDepth;
             blockersCount++;
         }
     }
 
-    if (blockersCount > 0.0) {
-        blockersDepth /= blockersCount;
-        // TODO: make this variable via uniform
-        float lightSize = 40.0;
-
-        return lightSize * (currentDepth - blockersDepth) / blockersDepth;
-    }   
+    if (blockersCount == 0.0) {
+        return 0.0;
+    }
 
-    return 0.0;
+    float penumbra = penumbraWidth(uvDepth.z, blockersDepth / blockersCount, lightSize);
+    return poissonPcf(sampler2DArrayShadow(shadowMap.comparison), uvDepth, layer, tile, texelSize,
+                      texelSize * max(penumbra, 1.0), rotation);
 }
 
-float shadowWithPenumbra(sampler2D shadowMap, vec3 lightToPos_ls) {
-    const int SAMPLES = 8;
+// Looks up the receiver in a tile of a layer of the shadow map, after offsetting it by the light's biases
+float calcLayerShadow(ShadowMap shadowMap, ShadowSettings settings, mat4 lightView, float layer, vec4 tile,
+                      vec3 pos_ws, vec3 normal_ws, vec3 toLight_ws) {
+    // the light wasn't given a tile this frame
+    if (tile.z == 0.0) {
+        return 0.0;
+    }
+
+    sampler2DArrayShadow comparisonMap = sampler2DArrayShadow(shadowMap.comparison);
+    vec2 resolution = vec2(textureSize(comparisonMap, 0).xy) * tile.zw;
+
+    // world space size of a texel at the receiver, where w is 1.0 for orthographic projections
+    vec4 lightToPos_cs = lightView * vec4(pos_ws, 1.0);
+    float texelSize_ws = 2.0 * lightToPos_cs.w / (length(vec3(lightView[0][0], lightView[1][0], lightView[2][0])) * resolution.x);
+
+    float normalOffset = settings.normalBias * texelSize_ws * normalOffsetScale(normal_ws, toLight_ws);
+    lightToPos_cs = lightView * vec4(pos_ws + normal_ws * normalOffset, 1.0);
 
     // transform to [0,1] range
-    vec3 projCoords = clamp(lightToPos_ls * 0.5 + 0.5, 0.0, 1.0);
-    vec2 shadowMapUV = projCoords.xy;
-    float currentDepth = projCoords.z - 0.0001;
-    vec2 texelSize = 1.0 / textureSize(shadowMap, 0);
+    vec3 uvDepth = clamp(lightToPos_cs.xyz / lightToPos_cs.w * 0.5 + 0.5, 0.0, 1.0);
+    uvDepth.z -= settings.depthBias;
 
+    vec2 texelSize = 1.0 / resolution;
     float rotation = InterleavedGradientNoise(gl_FragCoord.xy) * TAU;
-    float penumbraWidth = penumbraWidth(shadowMap, shadowMapUV, texelSize, currentDepth, rotation, SAMPLES);
 
-    float shadow = 0.0;
+    switch (settings.filter) {
+        case SHADOW_FILTER_POISSON_PCF:
+            return poissonPcf(comparisonMap, uvDepth, layer, tile, texelSize, texelSize * POISSON_RADIUS, rotation);
+        case SHADOW_FILTER_PCSS:
+            return pcss(shadowMap, uvDepth, layer, tile, texelSize, rotation, settings.lightSize);
+        default:
+            return hardwarePcf(comparisonMap, uvDepth, layer, tile, texelSize);
+    }
+}
+
+float calcSpotShadow(SpotLight light, ShadowMap atlas, vec4 tile, vec3 pos_ws, vec3 normal_ws) {
+    return calcLayerShadow(atlas, light.shadow, light.view, 0.0, tile, pos_ws, normal_ws, light.position - pos_ws);
+}
 
-    for (int i = 0; i < SAMPLES; i++) {
-        vec2 sampleUV = shadowMapUV + (penumbraWidth * VogelDisk(i, SAMPLES, rotation) * texelSize);
-        float shadowMapDepth = texture(shadowMap, sampleUV).r;
-        shadow += step(shadowMapDepth, currentDepth);
+// Index of the cube face that the direction points through, in the same order as PointLight.views
+int cubeFace(vec3 direction) {
+    vec3 absDirection = abs(direction);
+
+    if (absDirection.x >= absDirection.y && absDirection.x >= absDirection.z) {
+        return direction.x > 0.0 ? 0 : 1;
+    } else if (absDirection.y >= absDirection.z) {
+        return direction.y > 0.0 ? 2 : 3;
     }
 
-    shadow /= SAMPLES;
+    return direction.z > 0.0 ? 4 : 5;
+}
 
-    return shadow;
+// Each face of a point light has its own tile, so only the face that the receiver is behind is looked up
+float calcOmniShadow(PointLight light, ShadowMap atlas, vec4 tiles[6], vec3 pos_ws, vec3 normal_ws) {
+    int face = cubeFace(pos_ws - light.position);
+
+    return calcLayerShadow(atlas, light.shadow, light.views[face], 0.0, tiles[face], pos_ws, normal_ws,
+                           light.position - pos_ws);
 }
 
-float hardShadow(sampler2D shadowMap, vec3 lightToPos_ls) {
-    // transform to [0,1] range
-    vec3 projCoords = clamp(lightToPos_ls * 0.5 + 0.5, 0.0, 1.0);
-    vec2 shadowMapUV = projCoords.xy;
-    float currentDepth = projCoords.z - 0.0001;
+// Index of the cascade covering the view space depth, or the cascade count when it is beyond all of them
+int calcCascadeIndex(DirectionalLight light, float viewDepth) {
+    for (int i = 0; i < light.cascadeCount; i++) {
+        if (viewDepth < light.cascadeSplits[i]) {
+            return i;
+        }
+    }
 
-    float shadowMapDepth = texture(shadowMap, shadowMapUV).r;
-    return step(shadowMapDepth, currentDepth);
+    return light.cascadeCount;
 }
 
-float calcDirShadow(sampler2D shadowMap, vec3 lightToPos_ls, float lightCos) {
-    return shadowWithPenumbra(shadowMap, lightToPos_ls);
-}  
+float calcCascadeLayerShadow(DirectionalLight light, ShadowMap shadowMap, int cascade, vec3 pos_ws, vec3 normal_ws) {
+    // each cascade fills its whole layer
+    vec4 tile = vec4(0.0, 0.0, 1.0, 1.0);
+
+    return calcLayerShadow(shadowMap, light.shadow, light.cascadeViews[cascade], float(cascade), tile, pos_ws,
+                           normal_ws, light.direction);
+}
 
+float calcCascadeShadow(DirectionalLight light, ShadowMap shadowMap, vec3 pos_ws, vec3 normal_ws, float viewDepth) {
+    int cascade = calcCascadeIndex(light, viewDepth);
+    if (cascade >= light.cascadeCount) {
+        return 0.0;
+    }
 
-float calcOmniShadow(samplerCubeShadow shadowMap, vec3 lightToPos_ws, float lightToPosDepth_ws) {
-    float shadow = texture(shadowMap, vec4(lightToPos_ws, lightToPosDepth_ws)).r;
+    float shadow = calcCascadeLayerShadow(light, shadowMap, cascade, pos_ws, normal_ws);
+
+    // blend into the next cascade over the far end of this one, so the seam between them isn't visible.
+    // the last cascade fades out instead
+    float cascadeStart = cascade == 0 ? 0.0 : light.cascadeSplits[cascade - 1];
+    float cascadeEnd = light.cascadeSplits[cascade];
+    float blendStart = cascadeEnd - (cascadeEnd - cascadeStart) * light.cascadeBlend;
+
+    if (viewDepth > blendStart) {
+        float nextShadow = 0.0;
+        if (cascade + 1 < light.cascadeCount) {
+            nextShadow = calcCascadeLayerShadow(light, shadowMap, cascade + 1, pos_ws, normal_ws);
+        }
 
-    return shadow * (1.0 - smoothstep(0.995, 1.0, lightToPosDepth_ws));
-}  
\ No newline at end of file
+        shadow = mix(shadow, nextShadow, smoothstep(blendStart, cascadeEnd, viewDepth));
+    }
+
+    return shadow;
+}
+
+vec3 cascadeDebugColour(DirectionalLight light, float viewDepth) {
+    const vec3 COLOURS[MAX_CASCADES + 1] = vec3[](
+        vec3(1.0, 0.3, 0.3),
+        vec3(0.3, 1.0, 0.3),
+        vec3(0.3, 0.3, 1.0),
+        vec3(1.0, 1.0, 0.3),
+        // beyond the last cascade
+        vec3(1.0)
+    );
+
+    return COLOURS[calcCascadeIndex(light, viewDepth)];
+}
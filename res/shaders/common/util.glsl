@@ -28,4 +28,38 @@ vec2 VogelDisk(int sampleIndex, int sampleCount, float phi) {
     float cosine = cos(theta);
 
     return r * vec2(cosine, sine);
+}
+
+// Packs a unit vector into two components, by folding the octahedron that it lies on into a square
+// https://knarkowicz.wordpress.com/2014/04/16/octahedron-normal-vector-encoding/
+vec2 OctahedralEncode(vec3 n) {
+    n /= abs(n.x) + abs(n.y) + abs(n.z);
+    vec2 folded = (1.0 - abs(n.yx)) * vec2(n.x >= 0.0 ? 1.0 : -1.0, n.y >= 0.0 ? 1.0 : -1.0);
+
+    return n.z >= 0.0 ? n.xy : folded;
+}
+
+vec3 OctahedralDecode(vec2 encoded) {
+    vec3 n = vec3(encoded, 1.0 - abs(encoded.x) - abs(encoded.y));
+    float t = clamp(-n.z, 0.0, 1.0);
+    n.x += n.x >= 0.0 ? -t : t;
+    n.y += n.y >= 0.0 ? -t : t;
+
+    return normalize(n);
+}
+
+// Weight of a translucent fragment for weighted blended order independent transparency, which favours fragments
+// that are nearer and more opaque, as they would be in front if the fragments were sorted
+// https://jcgt.org/published/0002/02/09/
+float WeightedBlendedWeight(float depth, float alpha) {
+    return clamp(alpha * max(0.01, 3000.0 * pow(1.0 - depth, 3.0)), 0.01, 3000.0);
+}
+
+// Whether a fragment of an instance that is cross-fading between levels of detail is left out. A positive fade is
+// the coverage of the level fading in, and the level fading out is given the same value negated, so that together
+// they cover each pixel exactly once. A fade of zero is never discarded
+bool LodFadeDiscarded(vec2 screenPosition, float fade) {
+    float threshold = InterleavedGradientNoise(floor(screenPosition));
+
+    return fade > 0.0 ? threshold >= fade : threshold < -fade;
 }
\ No newline at end of file
@@ -23,6 +23,9 @@ void main() {
 
 out vec4 FragColor;
 
+// renderables that were occlusion culled are drawn in red, through whatever hid them
+uniform bool occluded;
+
 void main() {
-    FragColor = vec4(1.0);
+    FragColor = occluded ? vec4(1.0, 0.0, 0.0, 1.0) : vec4(1.0);
 }
\ No newline at end of file
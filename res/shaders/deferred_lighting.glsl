@@ -0,0 +1,149 @@
+#shader compute
+#version 450 core
+#extension GL_ARB_bindless_texture : require
+#include "res/shaders/common/defs/lights.glsl"
+#include "res/shaders/common/defs/material.glsl"
+#include "res/shaders/common/buffers/lightsBuffer.glsl"
+#include "res/shaders/common/buffers/matricesBuffer.glsl"
+#include "res/shaders/common/buffers/shadowMapsBuffer.glsl"
+#include "res/shaders/common/clusters.glsl"
+#include "res/shaders/common/pbr.glsl"
+#include "res/shaders/common/phong.glsl"
+#include "res/shaders/common/util.glsl"
+
+#line 0 20
+
+#define GROUP_WIDTH     16
+
+layout (local_size_x = GROUP_WIDTH, local_size_y = GROUP_WIDTH, local_size_z = 1) in;
+layout(binding = 0) uniform sampler2D albedo_texture;
+layout(binding = 1) uniform sampler2D normal_texture;
+layout(binding = 2) uniform sampler2D material_texture;
+layout(binding = 3) uniform sampler2D emissive_texture;
+layout(binding = 4) uniform sampler2D depth_texture;
+layout(rgba16f, binding = 0) writeonly uniform image2D write_image;
+
+// reconstruct the view space position of a texel from the depth buffer, using the perspective projection
+vec3 viewPosition(ivec2 texelCoord, vec2 resolution) {
+    float depth = texelFetch(depth_texture, texelCoord, 0).r;
+    vec3 ndc = vec3((vec2(texelCoord) + 0.5) / resolution, depth) * 2.0 - 1.0;
+
+    float viewZ = -projection[3][2] / (ndc.z + projection[2][2]);
+    vec2 viewXY = ndc.xy * -viewZ / vec2(projection[0][0], projection[1][1]);
+
+    return vec3(viewXY, viewZ);
+}
+
+// The same lighting as the forward path in lighting.glsl, except that every vector is in world space rather than
+// tangent space, as the normal map has already been applied by the G-buffer pass
+void main() {
+    ivec2 texelCoord = ivec2(gl_GlobalInvocationID);
+    ivec2 resolution = textureSize(depth_texture, 0);
+
+    if (any(greaterThanEqual(texelCoord, resolution))) {
+        return;
+    }
+
+    // nothing has been drawn here, so it is left for the sky stage
+    if (texelFetch(depth_texture, texelCoord, 0).r >= 1.0) {
+        imageStore(write_image, texelCoord, vec4(0.0, 0.0, 0.0, 1.0));
+        return;
+    }
+
+    vec3 albedo = texelFetch(albedo_texture, texelCoord, 0).rgb;
+    vec4 normals = texelFetch(normal_texture, texelCoord, 0);
+    vec4 materialParams = texelFetch(material_texture, texelCoord, 0);
+
+    bool pbr = materialParams.a > 0.5;
+    float gloss = materialParams.r;
+
+    PbrSurface surface;
+    surface.baseColour = albedo;
+    surface.roughness = materialParams.r;
+    surface.metallic = materialParams.g;
+    surface.occlusion = materialParams.b;
+    surface.emissive = texelFetch(emissive_texture, texelCoord, 0).rgb;
+
+    vec3 normal = OctahedralDecode(normals.xy);
+    vec3 normal_ws = OctahedralDecode(normals.zw);
+
+    vec3 pos_vs = viewPosition(texelCoord, vec2(resolution));
+    // the view matrix has no scale, so its rotation is inverted by its transpose
+    vec3 pos_ws = cameraPos + transpose(mat3(view)) * pos_vs;
+    float viewDepth = -pos_vs.z;
+    vec3 toCam = cameraPos - pos_ws;
+
+    float ambientOcclusion = 1.0;
+    if (ambientOcclusionMap != uvec2(0)) {
+        ambientOcclusion = texelFetch(sampler2D(ambientOcclusionMap), texelCoord, 0).r;
+    }
+
+    // not used by the Blinn-Phong terms, which take their inputs from the G-buffer
+    Material material;
+
+    vec3 totalLight = vec3(0.0);
+
+    LightCluster cluster = getCluster(pos_ws);
+
+    for (uint i = 0; i < cluster.pointLightCount; i++) {
+        uint lightIndex = clusterLightIndices[cluster.offset + i];
+        PointLight light = pointLights[lightIndex];
+
+        vec4 shadowTiles[6] = vec4[6](vec4(0.0), vec4(0.0), vec4(0.0), vec4(0.0), vec4(0.0), vec4(0.0));
+        if (lightIndex < MAX_POINT_LIGHT_SHADOWS) {
+            shadowTiles = pointShadowTiles[lightIndex];
+        }
+
+        if (pbr) {
+            totalLight += calcPbrPointLight(light, surface, light.position - pos_ws, toCam, pos_ws, normal_ws,
+                                            shadowAtlas, shadowTiles, normal, ambientOcclusion);
+        } else {
+            totalLight += calcBlinnPhongPointLight(light, material, light.position - pos_ws, toCam, pos_ws,
+                                                   normal_ws, shadowAtlas, shadowTiles, normal, gloss,
+                                                   ambientOcclusion);
+        }
+    }
+
+    for (uint i = 0; i < cluster.spotLightCount; i++) {
+        uint lightIndex = clusterLightIndices[cluster.offset + cluster.pointLightCount + i];
+        SpotLight light = spotLights[lightIndex];
+
+        vec4 shadowTile = vec4(0.0);
+        if (lightIndex < MAX_SPOT_LIGHT_SHADOWS) {
+            shadowTile = spotShadowTiles[lightIndex];
+        }
+
+        if (pbr) {
+            totalLight += calcPbrSpotlight(light, surface, light.position - pos_ws, toCam, pos_ws, normal_ws,
+                                           shadowAtlas, shadowTile, normal, ambientOcclusion);
+        } else {
+            totalLight += calcBlinnPhongSpotlight(light, material, light.position - pos_ws, toCam, pos_ws,
+                                                  normal_ws, shadowAtlas, shadowTile, normal, gloss,
+                                                  ambientOcclusion);
+        }
+    }
+
+    if (directionalLightCount == 1) {
+        if (pbr) {
+            totalLight += calcPbrDirLight(directionalLight, surface, directionalLight.direction, toCam, pos_ws,
+                                          normal_ws, viewDepth, directionalShadowMap, normal, ambientOcclusion);
+        } else {
+            totalLight += calcBlinnPhongDirLight(directionalLight, material, directionalLight.direction, toCam,
+                                                 pos_ws, normal_ws, viewDepth, directionalShadowMap, normal, gloss,
+                                                 ambientOcclusion);
+        }
+    }
+
+    if (pbr) {
+        totalLight += calcPbrImageBasedLight(surface, normal, toCam, ambientOcclusion);
+    }
+
+    // the Blinn-Phong terms are the amount of light, whereas the PBR terms already include the base colour
+    vec3 colour = pbr ? totalLight + surface.emissive : albedo * totalLight;
+
+    if (directionalLightCount == 1 && directionalLight.cascadeDebug != 0) {
+        colour *= cascadeDebugColour(directionalLight, viewDepth);
+    }
+
+    imageStore(write_image, texelCoord, vec4(colour, 1.0));
+}
\ No newline at end of file
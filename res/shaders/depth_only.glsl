@@ -10,9 +10,16 @@ layout(location = 4) in vec2 a_texCoord;
 
 layout(location = 5) in uint a_materialIndex;
 layout(location = 6) in mat4 a_transform;
+layout(location = 10) in float a_lodFade;
 
+flat out float lodFade;
+
+// shared by the depth pre-pass and scene shaders, so their depth values match for the equal depth test
+invariant gl_Position;
 
 void main() {
+    lodFade = a_lodFade;
+
     gl_Position = projection * view * a_transform * vec4(a_position, 1.0);
 }
 
@@ -20,6 +27,12 @@ void main() {
 ///////////////////////////////////////////////////////////////////////////////////////
 #shader fragment
 #version 450 core
+#include "res/shaders/common/util.glsl"
+
+flat in float lodFade;
 
 void main() {
+    if (LodFadeDiscarded(gl_FragCoord.xy, lodFade)) {
+        discard;
+    }
 }
\ No newline at end of file
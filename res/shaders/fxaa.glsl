@@ -0,0 +1,76 @@
+#shader compute
+#version 450 core
+
+#line 0 32
+
+#define GROUP_WIDTH     16
+
+#define REDUCE_MIN      (1.0 / 128.0)
+#define REDUCE_MUL      (1.0 / 8.0)
+
+layout (local_size_x = GROUP_WIDTH, local_size_y = GROUP_WIDTH, local_size_z = 1) in;
+layout(binding = 0) uniform sampler2D colour_texture;
+layout(rgba16f, binding = 0) writeonly uniform image2D resolved_image;
+
+uniform float edgeThreshold;
+uniform float edgeThresholdMin;
+uniform float spanMax;
+
+// Luma of the colour once tone mapped, as edges are found in the HDR buffer, where the contrast of bright pixels
+// would otherwise be exaggerated
+float Luma(vec3 colour) {
+    float luma = dot(colour, vec3(0.299, 0.587, 0.114));
+    return luma / (1.0 + luma);
+}
+
+vec3 Sample(vec2 uv) {
+    return textureLod(colour_texture, uv, 0.0).rgb;
+}
+
+// Blurs along the direction of the edge that the pixel is on, if any
+// https://developer.download.nvidia.com/assets/gamedev/files/sdk/11/FXAA_WhitePaper.pdf
+void main() {
+    ivec2 texelCoord = ivec2(gl_GlobalInvocationID.xy);
+    ivec2 size = imageSize(resolved_image);
+
+    if (any(greaterThanEqual(texelCoord, size))) {
+        return;
+    }
+
+    vec2 texelSize = 1.0 / vec2(size);
+    vec2 uv = (vec2(texelCoord) + 0.5) * texelSize;
+
+    vec3 colour = Sample(uv);
+    float lumaM = Luma(colour);
+    float lumaNW = Luma(Sample(uv + vec2(-1.0, -1.0) * texelSize));
+    float lumaNE = Luma(Sample(uv + vec2(1.0, -1.0) * texelSize));
+    float lumaSW = Luma(Sample(uv + vec2(-1.0, 1.0) * texelSize));
+    float lumaSE = Luma(Sample(uv + vec2(1.0, 1.0) * texelSize));
+
+    float lumaMin = min(lumaM, min(min(lumaNW, lumaNE), min(lumaSW, lumaSE)));
+    float lumaMax = max(lumaM, max(max(lumaNW, lumaNE), max(lumaSW, lumaSE)));
+
+    if (lumaMax - lumaMin < max(edgeThresholdMin, lumaMax * edgeThreshold)) {
+        imageStore(resolved_image, texelCoord, vec4(colour, 1.0));
+        return;
+    }
+
+    // perpendicular to the gradient of the luma, so along the edge
+    vec2 direction = vec2(
+        (lumaSW + lumaSE) - (lumaNW + lumaNE),
+        (lumaNW + lumaSW) - (lumaNE + lumaSE)
+    );
+
+    float directionReduce = max((lumaNW + lumaNE + lumaSW + lumaSE) * 0.25 * REDUCE_MUL, REDUCE_MIN);
+    float inverseDirectionMin = 1.0 / (min(abs(direction.x), abs(direction.y)) + directionReduce);
+    direction = clamp(direction * inverseDirectionMin, vec2(-spanMax), vec2(spanMax)) * texelSize;
+
+    vec3 near = 0.5 * (Sample(uv + direction * (1.0 / 3.0 - 0.5)) + Sample(uv + direction * (2.0 / 3.0 - 0.5)));
+    vec3 far = near * 0.5 + 0.25 * (Sample(uv + direction * -0.5) + Sample(uv + direction * 0.5));
+
+    // the wider blur is only kept if it hasn't reached past the edge, into colours that weren't around the pixel
+    float lumaFar = Luma(far);
+    vec3 resolved = lumaFar < lumaMin || lumaFar > lumaMax ? near : far;
+
+    imageStore(resolved_image, texelCoord, vec4(resolved, 1.0));
+}
\ No newline at end of file
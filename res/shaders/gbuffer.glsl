@@ -0,0 +1,107 @@
+#shader vertex
+#version 450 core
+#include "res/shaders/common/buffers/materialsBuffer.glsl"
+#include "res/shaders/common/buffers/matricesBuffer.glsl"
+
+#line 0 18
+
+layout(location = 0) in vec3 a_position;
+layout(location = 1) in vec3 a_normal;
+layout(location = 2) in vec3 a_tangent;
+layout(location = 3) in vec4 a_colour;
+layout(location = 4) in vec2 a_texCoord;
+
+layout(location = 5) in uint a_materialIndex;
+layout(location = 6) in mat4 a_transform;
+layout(location = 10) in float a_lodFade;
+
+out OBJECT {
+    vec2 texCoord;
+    flat Material material;
+    flat float lodFade;
+} obj_out;
+
+out WORLD_SPACE {
+    // surface normal without the normal map, for the shadow normal offset
+    vec3 normal;
+    mat3 fromTangent;
+} ws_out;
+
+void main() {
+    vec3 T = normalize(vec3(a_transform * vec4(a_tangent, 0.0)));
+    vec3 N = normalize(vec3(a_transform * vec4(a_normal, 0.0)));
+    // re-orthogonalize T with respect to N
+    T = normalize(T - dot(T, N) * N);
+    vec3 B = cross(N, T);
+
+    obj_out.texCoord = a_texCoord;
+    obj_out.material = materials[a_materialIndex];
+    obj_out.lodFade = a_lodFade;
+
+    ws_out.normal = N;
+    ws_out.fromTangent = mat3(T, B, N);
+
+    gl_Position = projection * view * a_transform * vec4(a_position, 1.0);
+}
+
+
+///////////////////////////////////////////////////////////////////////////////////////
+#shader fragment
+#version 450 core
+#extension GL_ARB_bindless_texture : require
+#include "res/shaders/common/defs/material.glsl"
+#include "res/shaders/common/pbr.glsl"
+#include "res/shaders/common/util.glsl"
+
+#line 0 19
+
+in OBJECT {
+    vec2 texCoord;
+    flat Material material;
+    flat float lodFade;
+} obj_in;
+
+in WORLD_SPACE {
+    vec3 normal;
+    mat3 fromTangent;
+} ws_in;
+
+// must match the attachments of the G-buffer, see pipeline_stages/gbuffer.rs
+layout(location = 0) out vec4 Albedo;
+layout(location = 1) out vec4 Normal;
+// gloss or roughness, metallic, occlusion, and whether the material is PBR
+layout(location = 2) out vec4 MaterialParams;
+layout(location = 3) out vec4 Emissive;
+
+void main() {
+    if (LodFadeDiscarded(gl_FragCoord.xy, obj_in.lodFade)) {
+        discard;
+    }
+
+    Material material = obj_in.material;
+
+    // PBR materials don't need a normal texture, in which case the surface normal is used as it is
+    vec3 normalMap = vec3(0.0, 0.0, 1.0);
+    if (material.normalTexture != uvec2(0)) {
+        normalMap = texture(sampler2D(material.normalTexture), obj_in.texCoord).rgb;
+        normalMap = normalize(normalMap * 2.0 - 1.0);
+    }
+
+    Normal = vec4(OctahedralEncode(normalize(ws_in.fromTangent * normalMap)),
+                  OctahedralEncode(normalize(ws_in.normal)));
+
+    if (material.model == MATERIAL_MODEL_PBR) {
+        PbrSurface surface = getPbrSurface(material, obj_in.texCoord);
+
+        Albedo = vec4(surface.baseColour, 1.0);
+        MaterialParams = vec4(surface.roughness, surface.metallic, surface.occlusion, 1.0);
+        Emissive = vec4(surface.emissive, 1.0);
+    } else {
+        vec3 diffuseTexture = texture(sampler2D(material.diffuseTexture), obj_in.texCoord).rgb;
+        float gloss = texture(sampler2D(material.specularTexture), obj_in.texCoord).r;
+
+        Albedo = vec4(diffuseTexture, 1.0);
+        MaterialParams = vec4(gloss, 0.0, 1.0, 0.0);
+        Emissive = vec4(0.0);
+    }
+}
\ No newline at end of file
@@ -0,0 +1,184 @@
+#shader compute
+#version 450 core
+#extension GL_ARB_bindless_texture : require
+
+#line 0 28
+
+#define GROUP_WIDTH     64
+
+// words of each instance in the instance buffer, the material index followed by the transform and the LOD fade
+#define INSTANCE_WORDS  18
+#define COMMAND_WORDS   5
+
+layout (local_size_x = GROUP_WIDTH, local_size_y = 1, local_size_z = 1) in;
+
+struct CullBatch {
+    // w of the minimum is zero when the mesh has no bounds, so is always visible
+    vec4 boundsMin;
+    vec4 boundsMax;
+    uint indexCount;
+    uint firstIndex;
+    uint baseVertex;
+    uint inputBase;
+    uint instanceCount;
+    uint outputBase;
+    uint drawCountIndex;
+    uint commandBase;
+};
+
+layout (std430, binding = 6) readonly buffer CullBatches {
+    // world space, facing inwards
+    vec4 planes[6];
+    // of the frame the Hi-Z pyramid was built from
+    mat4 viewProjection;
+    uvec2 hizTexture;
+    uvec2 hizSize;
+    // zero when there is no pyramid, so instances are only frustum culled
+    uint hizLevels;
+    uint batchCount;
+    CullBatch batches[];
+};
+
+layout (std430, binding = 7) buffer Instances {
+    uint instances[];
+};
+
+layout (std430, binding = 8) writeonly buffer Commands {
+    uint commands[];
+};
+
+layout (std430, binding = 9) buffer DrawCounts {
+    uint drawCounts[];
+};
+
+shared uint visibleCount;
+
+mat4 loadTransform(uint instance) {
+    uint base = instance * INSTANCE_WORDS + 1;
+    mat4 transform;
+
+    for (int i = 0; i < 16; i++) {
+        transform[i / 4][i % 4] = uintBitsToFloat(instances[base + i]);
+    }
+
+    return transform;
+}
+
+// Conservative, in the same way as `Frustum::intersects_aabb`
+bool intersectsFrustum(vec3 centre, vec3 extents) {
+    for (int i = 0; i < 6; i++) {
+        float radius = dot(extents, abs(planes[i].xyz));
+
+        if (dot(planes[i].xyz, centre) + planes[i].w < -radius) {
+            return false;
+        }
+    }
+
+    return true;
+}
+
+// Conservative, in the same way as `DepthPyramid::is_occluded`
+bool isOccluded(vec3 centre, vec3 extents) {
+    vec2 uvMin = vec2(1.0e30);
+    vec2 uvMax = vec2(-1.0e30);
+    float nearest = 1.0e30;
+
+    for (int i = 0; i < 8; i++) {
+        vec3 corner = centre + extents * vec3(
+            (i & 1) == 0 ? -1.0 : 1.0,
+            (i & 2) == 0 ? -1.0 : 1.0,
+            (i & 4) == 0 ? -1.0 : 1.0
+        );
+        vec4 clip = viewProjection * vec4(corner, 1.0);
+
+        // crosses the camera's plane, so its projection is unbounded
+        if (clip.w <= 0.0) {
+            return false;
+        }
+
+        vec3 ndc = clip.xyz / clip.w * 0.5 + 0.5;
+        uvMin = min(uvMin, ndc.xy);
+        uvMax = max(uvMax, ndc.xy);
+        nearest = min(nearest, ndc.z);
+    }
+
+    if (any(lessThan(uvMax, vec2(0.0))) || any(greaterThan(uvMin, vec2(1.0)))) {
+        return false;
+    }
+
+    ivec2 size = ivec2(hizSize);
+    ivec2 first = min(ivec2(clamp(uvMin, 0.0, 1.0) * vec2(size)), size - 1);
+    ivec2 last = min(ivec2(clamp(uvMax, 0.0, 1.0) * vec2(size)), size - 1);
+
+    // the finest level where the bounds cover no more than two texels along each axis
+    int level = 0;
+    while (level + 1 < int(hizLevels) && any(greaterThan((last >> level) - (first >> level), ivec2(1)))) {
+        level++;
+    }
+
+    ivec2 levelSize = max(size >> level, ivec2(1));
+    ivec2 from = min(first >> level, levelSize - 1);
+    ivec2 to = min(last >> level, levelSize - 1);
+    float furthest = 0.0;
+
+    for (int y = from.y; y <= to.y; y++) {
+        for (int x = from.x; x <= to.x; x++) {
+            furthest = max(furthest, texelFetch(sampler2D(hizTexture), ivec2(x, y), level).r);
+        }
+    }
+
+    return nearest > furthest;
+}
+
+// Each work group culls the instances of one batch, compacting the visible ones to the batch's output, then writes
+// a single command that draws them
+void main() {
+    if (gl_WorkGroupID.x >= batchCount) {
+        return;
+    }
+
+    CullBatch batch = batches[gl_WorkGroupID.x];
+
+    if (gl_LocalInvocationIndex == 0) {
+        visibleCount = 0;
+    }
+    barrier();
+
+    vec3 localCentre = (batch.boundsMin.xyz + batch.boundsMax.xyz) * 0.5;
+    vec3 localExtents = (batch.boundsMax.xyz - batch.boundsMin.xyz) * 0.5;
+
+    for (uint i = gl_LocalInvocationIndex; i < batch.instanceCount; i += GROUP_WIDTH) {
+        uint instance = batch.inputBase + i;
+
+        if (batch.boundsMin.w != 0.0) {
+            mat4 transform = loadTransform(instance);
+            vec3 centre = (transform * vec4(localCentre, 1.0)).xyz;
+            vec3 extents = mat3(abs(transform[0].xyz), abs(transform[1].xyz), abs(transform[2].xyz)) * localExtents;
+
+            if (!intersectsFrustum(centre, extents)) {
+                continue;
+            }
+
+            if (hizLevels > 0 && isOccluded(centre, extents)) {
+                continue;
+            }
+        }
+
+        uint slot = batch.outputBase + atomicAdd(visibleCount, 1);
+        for (int word = 0; word < INSTANCE_WORDS; word++) {
+            instances[slot * INSTANCE_WORDS + word] = instances[instance * INSTANCE_WORDS + word];
+        }
+    }
+
+    barrier();
+
+    if (gl_LocalInvocationIndex == 0 && visibleCount > 0) {
+        uint command = (batch.commandBase + atomicAdd(drawCounts[batch.drawCountIndex], 1)) * COMMAND_WORDS;
+
+        commands[command] = batch.indexCount;
+        commands[command + 1] = visibleCount;
+        commands[command + 2] = batch.firstIndex;
+        commands[command + 3] = batch.baseVertex;
+        commands[command + 4] = batch.outputBase;
+    }
+}
\ No newline at end of file
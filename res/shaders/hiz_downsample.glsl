@@ -0,0 +1,46 @@
+#shader compute
+#version 450 core
+
+#line 0 29
+
+#define GROUP_WIDTH     16
+
+layout (local_size_x = GROUP_WIDTH, local_size_y = GROUP_WIDTH, local_size_z = 1) in;
+layout(binding = 0) uniform sampler2D depth_texture;
+layout(r32f, binding = 0) readonly uniform image2D read_image;
+layout(r32f, binding = 1) writeonly uniform image2D write_image;
+
+// level of the pyramid that is written, where the first is a copy of the depth buffer
+uniform int level;
+
+// Each texel keeps the furthest depth of the texels it covers in the level above, so anything behind it is
+// certainly hidden
+void main() {
+    ivec2 texelCoord = ivec2(gl_GlobalInvocationID.xy);
+    ivec2 writeSize = imageSize(write_image);
+
+    if (any(greaterThanEqual(texelCoord, writeSize))) {
+        return;
+    }
+
+    if (level == 0) {
+        imageStore(write_image, texelCoord, vec4(texelFetch(depth_texture, texelCoord, 0).r));
+        return;
+    }
+
+    ivec2 readSize = imageSize(read_image);
+    ivec2 first = texelCoord * 2;
+
+    // odd sizes leave a texel at the end of the row or column that no other would cover, so the last texel takes it
+    ivec2 last = first + 1 + ivec2(equal(texelCoord, writeSize - 1)) * (readSize & 1);
+    last = min(last, readSize - 1);
+
+    float depth = 0.0;
+    for (int y = first.y; y <= last.y; y++) {
+        for (int x = first.x; x <= last.x; x++) {
+            depth = max(depth, imageLoad(read_image, ivec2(x, y)).r);
+        }
+    }
+
+    imageStore(write_image, texelCoord, vec4(depth));
+}
\ No newline at end of file
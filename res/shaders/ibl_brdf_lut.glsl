@@ -0,0 +1,64 @@
+#shader compute
+#version 450 core
+#include "res/shaders/common/ibl.glsl"
+
+#line 0 26
+
+#define GROUP_WIDTH     16
+#define SAMPLE_COUNT    1024u
+
+layout (local_size_x = GROUP_WIDTH, local_size_y = GROUP_WIDTH, local_size_z = 1) in;
+layout(rg16f, binding = 0) writeonly uniform image2D write_image;
+
+float geometrySchlickGGX(float NdotX, float k) {
+    return NdotX / (NdotX * (1.0 - k) + k);
+}
+
+// unlike direct lighting, k is remapped from the roughness without the offset
+float geometrySmithIBL(float NdotV, float NdotL, float roughness) {
+    float k = (roughness * roughness) / 2.0;
+
+    return geometrySchlickGGX(NdotV, k) * geometrySchlickGGX(NdotL, k);
+}
+
+// Integrates the specular BRDF over the hemisphere for the angle to the camera along the x axis, and the roughness
+// along the y axis. The result is a scale and a bias to the Fresnel reflectance at normal incidence, which doesn't
+// depend on the environment, so is shared by all of them
+void main() {
+    ivec2 texelCoord = ivec2(gl_GlobalInvocationID);
+    ivec2 size = imageSize(write_image);
+
+    if (any(greaterThanEqual(texelCoord, size))) {
+        return;
+    }
+
+    vec2 uv = (vec2(texelCoord) + 0.5) / vec2(size);
+    float NdotV = uv.x;
+    float roughness = uv.y;
+
+    vec3 toCam = vec3(sqrt(1.0 - NdotV * NdotV), 0.0, NdotV);
+    vec3 normal = vec3(0.0, 0.0, 1.0);
+
+    float scale = 0.0;
+    float bias = 0.0;
+
+    for (uint i = 0u; i < SAMPLE_COUNT; i++) {
+        vec3 halfway = importanceSampleGGX(hammersley(i, SAMPLE_COUNT), normal, roughness);
+        vec3 toLight = normalize(2.0 * dot(toCam, halfway) * halfway - toCam);
+
+        float NdotL = max(toLight.z, 0.0);
+        float NdotH = max(halfway.z, 0.0);
+        float VdotH = max(dot(toCam, halfway), 0.0);
+
+        if (NdotL > 0.0) {
+            float G = geometrySmithIBL(NdotV, NdotL, roughness);
+            float visibility = (G * VdotH) / (NdotH * NdotV);
+            float fresnel = pow(1.0 - VdotH, 5.0);
+
+            scale += (1.0 - fresnel) * visibility;
+            bias += fresnel * visibility;
+        }
+    }
+
+    imageStore(write_image, texelCoord, vec4(scale, bias, 0.0, 0.0) / float(SAMPLE_COUNT));
+}
\ No newline at end of file
@@ -0,0 +1,29 @@
+#shader compute
+#version 450 core
+#include "res/shaders/common/ibl.glsl"
+
+#line 0 23
+
+#define GROUP_WIDTH     16
+
+layout (local_size_x = GROUP_WIDTH, local_size_y = GROUP_WIDTH, local_size_z = 1) in;
+layout(binding = 0) uniform sampler2D equirect_texture;
+layout(rgba16f, binding = 0) writeonly uniform imageCube write_image;
+
+// Projects an equirectangular environment onto each face of a cubemap, with one dispatch layer per face
+void main() {
+    ivec3 texelCoord = ivec3(gl_GlobalInvocationID);
+    int faceSize = imageSize(write_image).x;
+
+    if (any(greaterThanEqual(texelCoord.xy, ivec2(faceSize)))) {
+        return;
+    }
+
+    vec3 direction = cubemapDirection(texelCoord, faceSize);
+
+    // the first row of the image is the top of the environment
+    vec2 uv = vec2(atan(direction.z, direction.x) / (2.0 * PI) + 0.5, 0.5 - asin(direction.y) / PI);
+    vec3 colour = textureLod(equirect_texture, uv, 0).rgb;
+
+    imageStore(write_image, texelCoord, vec4(colour, 1.0));
+}
\ No newline at end of file
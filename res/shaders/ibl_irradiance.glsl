@@ -0,0 +1,52 @@
+#shader compute
+#version 450 core
+#include "res/shaders/common/ibl.glsl"
+
+#line 0 24
+
+#define GROUP_WIDTH     8
+// angle between samples around the hemisphere, in radians
+#define SAMPLE_DELTA    0.025
+
+layout (local_size_x = GROUP_WIDTH, local_size_y = GROUP_WIDTH, local_size_z = 1) in;
+layout(binding = 0) uniform samplerCube environment_texture;
+layout(rgba16f, binding = 0) writeonly uniform imageCube write_image;
+
+// Convolves the environment over the hemisphere around each direction, which is the diffuse light arriving at a
+// surface facing that direction
+void main() {
+    ivec3 texelCoord = ivec3(gl_GlobalInvocationID);
+    int faceSize = imageSize(write_image).x;
+
+    if (any(greaterThanEqual(texelCoord.xy, ivec2(faceSize)))) {
+        return;
+    }
+
+    vec3 normal = cubemapDirection(texelCoord, faceSize);
+    vec3 up = abs(normal.y) < 0.999 ? vec3(0.0, 1.0, 0.0) : vec3(0.0, 0.0, 1.0);
+    vec3 right = normalize(cross(up, normal));
+    up = cross(normal, right);
+
+    // the environment is far larger than the irradiance map, so a blurrier level keeps small bright spots from
+    // being missed between samples
+    float level = max(log2(float(textureSize(environment_texture, 0).x) / float(faceSize)) - 1.0, 0.0);
+
+    vec3 irradiance = vec3(0.0);
+    float sampleCount = 0.0;
+
+    for (float phi = 0.0; phi < 2.0 * PI; phi += SAMPLE_DELTA) {
+        for (float theta = 0.0; theta < 0.5 * PI; theta += SAMPLE_DELTA) {
+            vec3 tangentSample = vec3(sin(theta) * cos(phi), sin(theta) * sin(phi), cos(theta));
+            vec3 sampleDirection = tangentSample.x * right + tangentSample.y * up + tangentSample.z * normal;
+
+            // weighted by the cosine of the incoming light, and by the sine to account for the samples being
+            // closer together towards the top of the hemisphere
+            irradiance += textureLod(environment_texture, sampleDirection, level).rgb * cos(theta) * sin(theta);
+            sampleCount += 1.0;
+        }
+    }
+
+    irradiance = PI * irradiance / sampleCount;
+
+    imageStore(write_image, texelCoord, vec4(irradiance, 1.0));
+}
\ No newline at end of file
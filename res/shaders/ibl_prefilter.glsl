@@ -0,0 +1,69 @@
+#shader compute
+#version 450 core
+#include "res/shaders/common/ibl.glsl"
+
+#line 0 25
+
+#define GROUP_WIDTH     8
+#define SAMPLE_COUNT    1024u
+
+layout (local_size_x = GROUP_WIDTH, local_size_y = GROUP_WIDTH, local_size_z = 1) in;
+layout(binding = 0) uniform samplerCube environment_texture;
+layout(rgba16f, binding = 0) writeonly uniform imageCube write_image;
+
+// roughness of the level being written, which is dispatched once per level
+uniform float roughness;
+
+float distributionGGX(float NdotH, float roughness) {
+    float a = roughness * roughness;
+    float a2 = a * a;
+    float denominator = NdotH * NdotH * (a2 - 1.0) + 1.0;
+
+    return a2 / (PI * denominator * denominator);
+}
+
+// Convolves the environment with the GGX lobe of a roughness, assuming that the view direction is the same as the
+// normal and the reflected direction. Rougher levels are smaller, as they are blurrier
+void main() {
+    ivec3 texelCoord = ivec3(gl_GlobalInvocationID);
+    int faceSize = imageSize(write_image).x;
+
+    if (any(greaterThanEqual(texelCoord.xy, ivec2(faceSize)))) {
+        return;
+    }
+
+    vec3 normal = cubemapDirection(texelCoord, faceSize);
+
+    // the first level is a perfect mirror, so is a copy of the environment
+    if (roughness == 0.0) {
+        imageStore(write_image, texelCoord, vec4(textureLod(environment_texture, normal, 0).rgb, 1.0));
+        return;
+    }
+
+    float environmentSize = float(textureSize(environment_texture, 0).x);
+    float texelSolidAngle = 4.0 * PI / (6.0 * environmentSize * environmentSize);
+
+    vec3 prefiltered = vec3(0.0);
+    float totalWeight = 0.0;
+
+    for (uint i = 0u; i < SAMPLE_COUNT; i++) {
+        vec3 halfway = importanceSampleGGX(hammersley(i, SAMPLE_COUNT), normal, roughness);
+        vec3 toLight = normalize(2.0 * dot(normal, halfway) * halfway - normal);
+
+        float NdotL = dot(normal, toLight);
+        if (NdotL > 0.0) {
+            // samples that are less likely to be taken cover more of the environment, so are read from a blurrier
+            // level, which removes the bright dots that would otherwise appear
+            // https://developer.nvidia.com/gpugems/gpugems3/part-iii-rendering/chapter-20-gpu-based-importance-sampling
+            float NdotH = max(dot(normal, halfway), 0.0);
+            float pdf = distributionGGX(NdotH, roughness) / 4.0 + 0.0001;
+            float sampleSolidAngle = 1.0 / (float(SAMPLE_COUNT) * pdf + 0.0001);
+            float level = max(0.5 * log2(sampleSolidAngle / texelSolidAngle), 0.0);
+
+            prefiltered += textureLod(environment_texture, toLight, level).rgb * NdotL;
+            totalWeight += NdotL;
+        }
+    }
+
+    imageStore(write_image, texelCoord, vec4(prefiltered / totalWeight, 1.0));
+}
\ No newline at end of file
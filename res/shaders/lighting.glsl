@@ -15,22 +15,22 @@ layout(location = 4) in vec2 a_texCoord;
 
 layout(location = 5) in uint a_materialIndex;
 layout(location = 6) in mat4 a_transform;
+layout(location = 10) in float a_lodFade;
 
 out OBJECT {
     vec2 texCoord;
+    // only used when the renderable is drawn by the transparent stage
+    float alpha;
     flat Material material;
+    flat float lodFade;
 } obj_out;
 
-out LIGHT_SPACE {
-    // vec3 spotLightToPos[MAX_SPOT_LIGHTS];
-    vec3 dirLightToPos;
-} ls_out;
-
 out WORLD_SPACE {
     vec3 pos;
-
-    // vec3 pointLightToPos[MAX_POINT_LIGHTS];
-    // float pointLightToPosDepth[MAX_POINT_LIGHTS];
+    // surface normal without the normal map, for the shadow normal offset
+    vec3 normal;
+    // used to select the directional light's shadow cascade
+    float viewDepth;
 } ws_out;
 
 out TANGENT_SPACE {
@@ -38,13 +38,17 @@ out TANGENT_SPACE {
     vec3 normal;
 
     vec3 toCam;
-
-    // vec3 toPointLight[MAX_POINT_LIGHTS];
-    // vec3 toSpotLight[MAX_SPOT_LIGHTS];
     vec3 toDirLight;
+
+    // the point and spot lights that affect each fragment aren't known until its cluster is, so their directions
+    // are moved into tangent space in the fragment shader
+    mat3 fromWorld;
 } ts_out;
 
 
+// must match the depth pre-pass exactly, for the equal depth test
+invariant gl_Position;
+
 void main() {
     vec3 T = normalize(vec3(a_transform * vec4(a_tangent, 0.0)));
     vec3 N = normalize(vec3(a_transform * vec4(a_normal, 0.0)));
@@ -56,31 +60,20 @@ void main() {
     mat3 worldToTangentSpace = transpose(tangentToWorldSpace);
 
     obj_out.texCoord = a_texCoord;
+    obj_out.alpha = a_colour.a;
     obj_out.material = materials[a_materialIndex];
+    obj_out.lodFade = a_lodFade;
 
     ws_out.pos = vec3(a_transform * vec4(a_position, 1.0));
+    ws_out.normal = N;
+    ws_out.viewDepth = -(view * vec4(ws_out.pos, 1.0)).z;
 
     ts_out.pos = worldToTangentSpace * ws_out.pos;
     ts_out.normal = worldToTangentSpace * N;
     ts_out.toCam = worldToTangentSpace * (cameraPos - ws_out.pos);
-
-    // for (int i = 0; i < pointLightCount; i++) {
-    //     ws_out.pointLightToPos[i] = ws_out.pos - pointLights[i].position;
-    //     ws_out.pointLightToPosDepth[i] = VectorToDepthValue(ws_out.pointLightToPos[i]);
-    //     ts_out.toPointLight[i] = worldToTangentSpace * (pointLights[i].position - ws_out.pos);
-    // }
-
-    // for (int i = 0; i < spotLightCount; i++) {
-    //     vec4 spotLightToPos = spotLights[i].view * vec4(ws_out.pos, 1.0);
-    //     ls_out.spotLightToPos[i] = spotLightToPos.xyz / spotLightToPos.w;
-
-    //     ts_out.toSpotLight[i] = worldToTangentSpace * (spotLights[i].position - ws_out.pos);
-    // }
+    ts_out.fromWorld = worldToTangentSpace;
 
     if (directionalLightCount == 1) {
-        vec4 dirLightToPos = directionalLight.view * vec4(ws_out.pos, 1.0);
-        ls_out.dirLightToPos = dirLightToPos.xyz / dirLightToPos.w;
-
         ts_out.toDirLight = worldToTangentSpace * directionalLight.direction;
     }
  
@@ -97,25 +90,24 @@ void main() {
 #include "res/shaders/common/defs/material.glsl"
 #include "res/shaders/common/buffers/lightsBuffer.glsl"
 #include "res/shaders/common/buffers/shadowMapsBuffer.glsl"
+#include "res/shaders/common/clusters.glsl"
+#include "res/shaders/common/pbr.glsl"
 #include "res/shaders/common/phong.glsl"
+#include "res/shaders/common/util.glsl"
 
 #line 0 13
 
 in OBJECT {
     vec2 texCoord;
+    float alpha;
     flat Material material;
+    flat float lodFade;
 } obj_in;
 
-in LIGHT_SPACE {
-    // vec3 spotLightToPos[MAX_SPOT_LIGHTS];
-    vec3 dirLightToPos;
-} ls_in;
-
 in WORLD_SPACE {
     vec3 pos;
-
-    // vec3 pointLightToPos[MAX_POINT_LIGHTS];
-    // float pointLightToPosDepth[MAX_POINT_LIGHTS];
+    vec3 normal;
+    float viewDepth;
 } ws_in;
 
 in TANGENT_SPACE {
@@ -123,64 +115,166 @@ in TANGENT_SPACE {
     vec3 normal;
 
     vec3 toCam;
-
-    // vec3 toPointLight[MAX_POINT_LIGHTS];
-    // vec3 toSpotLight[MAX_SPOT_LIGHTS];
     vec3 toDirLight;
+
+    // the point and spot lights that affect each fragment aren't known until its cluster is, so their directions
+    // are moved into tangent space in the fragment shader
+    mat3 fromWorld;
 } ts_in;
 
-out vec4 FragColor;
+// set by the transparent stage when it uses weighted blended order independent transparency, in which case the
+// colour is accumulated with the fragment's weight, and the revealage is written alongside it
+uniform bool weightedBlended;
+
+layout(location = 0) out vec4 FragColor;
+layout(location = 1) out vec4 Revealage;
 
 void main() {
-    vec3 normalMap = texture(sampler2D(obj_in.material.normalTexture), obj_in.texCoord).rgb;
-    normalMap = normalize(normalMap * 2.0 - 1.0);   
+    if (LodFadeDiscarded(gl_FragCoord.xy, obj_in.lodFade)) {
+        discard;
+    }
+
+    Material material = obj_in.material;
+    bool pbr = material.model == MATERIAL_MODEL_PBR;
+
+    // PBR materials don't need a normal texture, in which case the surface normal is used as it is
+    vec3 normalMap = vec3(0.0, 0.0, 1.0);
+    if (material.normalTexture != uvec2(0)) {
+        normalMap = texture(sampler2D(material.normalTexture), obj_in.texCoord).rgb;
+        normalMap = normalize(normalMap * 2.0 - 1.0);
+    }
+
+    vec3 diffuseTexture = vec3(0.0);
+    float gloss = 0.0;
+    float alpha = obj_in.alpha;
+    PbrSurface surface;
+
+    if (pbr) {
+        surface = getPbrSurface(material, obj_in.texCoord);
+        alpha *= surface.alpha;
+    } else {
+        vec4 diffuse = texture(sampler2D(material.diffuseTexture), obj_in.texCoord);
+        diffuseTexture = diffuse.rgb;
+        alpha *= diffuse.a;
+        gloss = texture(sampler2D(material.specularTexture), obj_in.texCoord).r;
+    }
 
-    vec3 diffuseTexture = texture(sampler2D(obj_in.material.diffuseTexture), obj_in.texCoord).rgb;
-    float gloss = texture(sampler2D(obj_in.material.specularTexture), obj_in.texCoord).r; 
 
+    float ambientOcclusion = 1.0;
+    if (ambientOcclusionMap != uvec2(0)) {
+        ambientOcclusion = texelFetch(sampler2D(ambientOcclusionMap), ivec2(gl_FragCoord.xy), 0).r;
+    }
 
     vec3 totalLight = vec3(0.0); 
 
-    // for (int i = 0; i < pointLightCount; i++) {
-    //     totalLight += calcBlinnPhongPointLight(
-    //                         pointLights[i], 
-    //                         obj_in.material, 
-    //                         ts_in.toPointLight[i], 
-    //                         ts_in.toCam,
-    //                         ws_in.pointLightToPos[i],
-    //                         ws_in.pointLightToPosDepth[i],
-    //                         samplerCubeShadow(pointShadowMaps[i]), 
-    //                         normalMap, 
-    //                         gloss
-    //                     );
-    // }
-
-
-    // for (int i = 0; i < spotLightCount; i++) {
-    //     totalLight += calcBlinnPhongSpotlight(
-    //                         spotLights[i], 
-    //                         obj_in.material, 
-    //                         ts_in.toSpotLight[i], 
-    //                         ts_in.toCam, 
-    //                         ls_in.spotLightToPos[i],
-    //                         sampler2D(spotShadowMaps[i]), 
-    //                         normalMap, 
-    //                         gloss
-    //                     );
-    // }
+    // only the lights whose bounds overlap this fragment's cluster can affect it
+    LightCluster cluster = getCluster(ws_in.pos);
+    vec3 normal_ws = normalize(ws_in.normal);
+
+    for (uint i = 0; i < cluster.pointLightCount; i++) {
+        uint lightIndex = clusterLightIndices[cluster.offset + i];
+        PointLight light = pointLights[lightIndex];
+        vec3 toLight_ts = ts_in.fromWorld * (light.position - ws_in.pos);
+
+        vec4 shadowTiles[6] = vec4[6](vec4(0.0), vec4(0.0), vec4(0.0), vec4(0.0), vec4(0.0), vec4(0.0));
+        if (lightIndex < MAX_POINT_LIGHT_SHADOWS) {
+            shadowTiles = pointShadowTiles[lightIndex];
+        }
+
+        if (pbr) {
+            totalLight += calcPbrPointLight(light, surface, toLight_ts, ts_in.toCam, ws_in.pos, normal_ws,
+                                            shadowAtlas, shadowTiles, normalMap, ambientOcclusion);
+        } else {
+            totalLight += calcBlinnPhongPointLight(
+                                light,
+                                material,
+                                toLight_ts,
+                                ts_in.toCam,
+                                ws_in.pos,
+                                normal_ws,
+                                shadowAtlas,
+                                shadowTiles,
+                                normalMap,
+                                gloss,
+                                ambientOcclusion
+                            );
+        }
+    }
+
+    for (uint i = 0; i < cluster.spotLightCount; i++) {
+        uint lightIndex = clusterLightIndices[cluster.offset + cluster.pointLightCount + i];
+        SpotLight light = spotLights[lightIndex];
+        vec3 toLight_ts = ts_in.fromWorld * (light.position - ws_in.pos);
+        // compared against the tangent space direction to the light, for the cutoff
+        light.direction = ts_in.fromWorld * light.direction;
+
+        vec4 shadowTile = vec4(0.0);
+        if (lightIndex < MAX_SPOT_LIGHT_SHADOWS) {
+            shadowTile = spotShadowTiles[lightIndex];
+        }
+
+        if (pbr) {
+            totalLight += calcPbrSpotlight(light, surface, toLight_ts, ts_in.toCam, ws_in.pos, normal_ws,
+                                           shadowAtlas, shadowTile, normalMap, ambientOcclusion);
+        } else {
+            totalLight += calcBlinnPhongSpotlight(
+                                light,
+                                material,
+                                toLight_ts,
+                                ts_in.toCam,
+                                ws_in.pos,
+                                normal_ws,
+                                shadowAtlas,
+                                shadowTile,
+                                normalMap,
+                                gloss,
+                                ambientOcclusion
+                            );
+        }
+    }
 
     if (directionalLightCount == 1)  {
-        totalLight += calcBlinnPhongDirLight(
-                            directionalLight, 
-                            obj_in.material, 
-                            ts_in.toDirLight, 
-                            ts_in.toCam, 
-                            ls_in.dirLightToPos,
-                            sampler2D(directionalShadowMap), 
-                            normalMap, 
-                            gloss
-                         );
+        if (pbr) {
+            totalLight += calcPbrDirLight(directionalLight, surface, ts_in.toDirLight, ts_in.toCam, ws_in.pos,
+                                          normal_ws, ws_in.viewDepth, directionalShadowMap, normalMap,
+                                          ambientOcclusion);
+        } else {
+            totalLight += calcBlinnPhongDirLight(
+                                directionalLight,
+                                material,
+                                ts_in.toDirLight,
+                                ts_in.toCam,
+                                ws_in.pos,
+                                normal_ws,
+                                ws_in.viewDepth,
+                                directionalShadowMap,
+                                normalMap,
+                                gloss,
+                                ambientOcclusion
+                             );
+        }
+    }
+
+    // the Blinn-Phong terms are the amount of light, whereas the PBR terms already include the base colour
+    vec3 colour;
+    if (pbr) {
+        // the tangent space transform is a rotation, so is inverted by its transpose
+        vec3 mappedNormal_ws = transpose(ts_in.fromWorld) * normalMap;
+        totalLight += calcPbrImageBasedLight(surface, mappedNormal_ws, cameraPos - ws_in.pos, ambientOcclusion);
+        colour = totalLight + surface.emissive;
+    } else {
+        colour = diffuseTexture * totalLight;
+    }
+
+    if (directionalLightCount == 1 && directionalLight.cascadeDebug != 0) {
+        colour *= cascadeDebugColour(directionalLight, ws_in.viewDepth);
     }
 
-    FragColor = vec4(diffuseTexture * totalLight, 1.0);
+    // opaque geometry is drawn without blending, so the alpha only matters to the transparent stage
+    if (weightedBlended) {
+        FragColor = vec4(colour * alpha, alpha) * WeightedBlendedWeight(gl_FragCoord.z, alpha);
+        Revealage = vec4(alpha);
+    } else {
+        FragColor = vec4(colour, alpha);
+    }
 }
\ No newline at end of file
@@ -0,0 +1,59 @@
+#shader compute
+#version 450 core
+
+#line 0 34
+
+#define HISTOGRAM_BINS  256
+
+layout (local_size_x = HISTOGRAM_BINS, local_size_y = 1, local_size_z = 1) in;
+
+layout (std430, binding = 10) buffer Luminance {
+    uint histogram[HISTOGRAM_BINS];
+    float adaptedLuminance;
+};
+
+uniform float minLogLuminance;
+uniform float logLuminanceRange;
+uniform uint pixelCount;
+// fraction of the way to move towards the average luminance this frame, when it is brighter, then darker, than the
+// adapted luminance
+uniform vec2 adaptation;
+// whether there is no adapted luminance yet, so the average is used as it is
+uniform bool resetAdaptation;
+
+shared uint weightedCounts[HISTOGRAM_BINS];
+
+// Averages the histogram in log2 space, then moves the adapted luminance towards it. The histogram is cleared as it
+// is read, ready for the next frame
+void main() {
+    uint bin = gl_LocalInvocationIndex;
+    uint count = histogram[bin];
+
+    weightedCounts[bin] = count * bin;
+    histogram[bin] = 0;
+    barrier();
+
+    for (uint stride = HISTOGRAM_BINS / 2; stride > 0; stride >>= 1) {
+        if (bin < stride) {
+            weightedCounts[bin] += weightedCounts[bin + stride];
+        }
+        barrier();
+    }
+
+    if (bin != 0) {
+        return;
+    }
+
+    // the first bin holds the pixels that are too dark to count, and weighs nothing anyway
+    float litPixels = max(float(pixelCount) - float(count), 1.0);
+    float averageBin = max(float(weightedCounts[0]) / litPixels, 1.0);
+    float logLuminance = (averageBin - 1.0) / float(HISTOGRAM_BINS - 2) * logLuminanceRange + minLogLuminance;
+    float luminance = exp2(logLuminance);
+
+    if (resetAdaptation || isnan(adaptedLuminance) || adaptedLuminance <= 0.0) {
+        adaptedLuminance = luminance;
+    } else {
+        float rate = luminance > adaptedLuminance ? adaptation.x : adaptation.y;
+        adaptedLuminance += (luminance - adaptedLuminance) * rate;
+    }
+}
\ No newline at end of file
@@ -0,0 +1,51 @@
+#shader compute
+#version 450 core
+
+#line 0 33
+
+#define GROUP_WIDTH     16
+#define HISTOGRAM_BINS  256
+
+layout (local_size_x = GROUP_WIDTH, local_size_y = GROUP_WIDTH, local_size_z = 1) in;
+layout(rgba16f, binding = 0) readonly uniform image2D hdr_image;
+
+layout (std430, binding = 10) buffer Luminance {
+    uint histogram[HISTOGRAM_BINS];
+    float adaptedLuminance;
+};
+
+// log2 of the darkest luminance the histogram covers
+uniform float minLogLuminance;
+uniform float inverseLogLuminanceRange;
+
+shared uint groupHistogram[HISTOGRAM_BINS];
+
+// The first bin is kept for pixels too dark to register, such as the sky at night, so they can be left out of the
+// average. The rest cover the range evenly in log2 space, where anything brighter is clamped into the last bin
+uint LuminanceBin(vec3 colour) {
+    float luminance = dot(colour, vec3(0.2126, 0.7152, 0.0722));
+
+    if (luminance < 0.0001) {
+        return 0;
+    }
+
+    float logLuminance = clamp((log2(luminance) - minLogLuminance) * inverseLogLuminanceRange, 0.0, 1.0);
+    return uint(logLuminance * (HISTOGRAM_BINS - 2) + 1.0);
+}
+
+void main() {
+    groupHistogram[gl_LocalInvocationIndex] = 0;
+    barrier();
+
+    ivec2 texelCoord = ivec2(gl_GlobalInvocationID.xy);
+
+    if (all(lessThan(texelCoord, imageSize(hdr_image)))) {
+        uint bin = LuminanceBin(imageLoad(hdr_image, texelCoord).rgb);
+        atomicAdd(groupHistogram[bin], 1);
+    }
+
+    barrier();
+
+    // each group only adds to the shared histogram once per bin, rather than once per pixel
+    atomicAdd(histogram[gl_LocalInvocationIndex], groupHistogram[gl_LocalInvocationIndex]);
+}
\ No newline at end of file
@@ -0,0 +1,34 @@
+#shader compute
+#version 450 core
+
+#line 0 27
+
+#define GROUP_WIDTH     16
+
+layout (local_size_x = GROUP_WIDTH, local_size_y = GROUP_WIDTH, local_size_z = 1) in;
+layout(binding = 0) uniform sampler2D accumulation_texture;
+layout(binding = 1) uniform sampler2D revealage_texture;
+layout(rgba16f, binding = 0) uniform image2D hdr_image;
+
+// Blends the weighted average colour of the translucent fragments over the opaque scene, by how much of the scene
+// is still revealed through all of them
+void main() {
+    ivec2 texelCoord = ivec2(gl_GlobalInvocationID);
+
+    if (any(greaterThanEqual(texelCoord, imageSize(hdr_image)))) {
+        return;
+    }
+
+    float revealage = texelFetch(revealage_texture, texelCoord, 0).r;
+
+    // nothing translucent was drawn here
+    if (revealage >= 1.0) {
+        return;
+    }
+
+    vec4 accumulation = texelFetch(accumulation_texture, texelCoord, 0);
+    vec3 averageColour = accumulation.rgb / max(accumulation.a, 0.00001);
+
+    vec4 scene = imageLoad(hdr_image, texelCoord);
+    imageStore(hdr_image, texelCoord, vec4(mix(averageColour, scene.rgb, revealage), scene.a));
+}
\ No newline at end of file
@@ -0,0 +1,32 @@
+#shader compute
+#version 450 core
+
+#line 0 39
+
+#define GROUP_WIDTH     16
+
+layout (local_size_x = GROUP_WIDTH, local_size_y = GROUP_WIDTH, local_size_z = 1) in;
+layout(binding = 0) uniform sampler2D previous_texture;
+layout(rgba16f, binding = 0) writeonly uniform image2D output_image;
+
+// furthest the channels are offset, at the edges of the screen, as a fraction of its size
+uniform float intensity;
+
+void main() {
+    ivec2 texelCoord = ivec2(gl_GlobalInvocationID.xy);
+    ivec2 size = imageSize(output_image);
+
+    if (any(greaterThanEqual(texelCoord, size))) {
+        return;
+    }
+
+    // the offset grows towards the edges, as a lens would refract each wavelength differently
+    vec2 uv = (vec2(texelCoord) + 0.5) / vec2(size);
+    vec2 offset = (uv - 0.5) * 2.0 * intensity;
+
+    float r = textureLod(previous_texture, uv - offset, 0.0).r;
+    float g = texelFetch(previous_texture, texelCoord, 0).g;
+    float b = textureLod(previous_texture, uv + offset, 0.0).b;
+
+    imageStore(output_image, texelCoord, vec4(r, g, b, 1.0));
+}
\ No newline at end of file
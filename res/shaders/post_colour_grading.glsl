@@ -0,0 +1,31 @@
+#shader compute
+#version 450 core
+
+#line 0 37
+
+#define GROUP_WIDTH     16
+
+layout (local_size_x = GROUP_WIDTH, local_size_y = GROUP_WIDTH, local_size_z = 1) in;
+layout(binding = 0) uniform sampler2D previous_texture;
+layout(binding = 1) uniform sampler3D lut_texture;
+layout(rgba16f, binding = 0) writeonly uniform image2D output_image;
+
+// how much of the graded colour replaces the original
+uniform float strength;
+
+void main() {
+    ivec2 texelCoord = ivec2(gl_GlobalInvocationID.xy);
+
+    if (any(greaterThanEqual(texelCoord, imageSize(output_image)))) {
+        return;
+    }
+
+    vec3 colour = texelFetch(previous_texture, texelCoord, 0).rgb;
+
+    // the ends of the range are the centres of the first and last texels, rather than their edges
+    float size = float(textureSize(lut_texture, 0).x);
+    vec3 lutCoord = clamp(colour, 0.0, 1.0) * ((size - 1.0) / size) + 0.5 / size;
+    vec3 graded = textureLod(lut_texture, lutCoord, 0.0).rgb;
+
+    imageStore(output_image, texelCoord, vec4(mix(colour, graded, strength), 1.0));
+}
\ No newline at end of file
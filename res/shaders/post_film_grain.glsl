@@ -0,0 +1,39 @@
+#shader compute
+#version 450 core
+
+#line 0 40
+
+#define GROUP_WIDTH     16
+
+layout (local_size_x = GROUP_WIDTH, local_size_y = GROUP_WIDTH, local_size_z = 1) in;
+layout(binding = 0) uniform sampler2D previous_texture;
+layout(rgba16f, binding = 0) writeonly uniform image2D output_image;
+
+uniform float intensity;
+// changes the noise each frame
+uniform uint frame;
+
+// https://www.shadertoy.com/view/4djSRW
+float Hash(vec3 p) {
+    p = fract(p * 0.1031);
+    p += dot(p, p.zyx + 31.32);
+    return fract((p.x + p.y) * p.z);
+}
+
+void main() {
+    ivec2 texelCoord = ivec2(gl_GlobalInvocationID.xy);
+
+    if (any(greaterThanEqual(texelCoord, imageSize(output_image)))) {
+        return;
+    }
+
+    vec3 colour = texelFetch(previous_texture, texelCoord, 0).rgb;
+
+    float noise = Hash(vec3(texelCoord, float(frame % 1024u))) - 0.5;
+
+    // grain is most visible in the mid-tones, and fades out towards black and white
+    float luminance = clamp(dot(colour, vec3(0.2126, 0.7152, 0.0722)), 0.0, 1.0);
+    float response = 1.0 - pow(abs(luminance * 2.0 - 1.0), 2.0);
+
+    imageStore(output_image, texelCoord, vec4(max(colour + noise * intensity * response, 0.0), 1.0));
+}
\ No newline at end of file
@@ -0,0 +1,24 @@
+#shader vertex
+#version 450 core
+#include "res/shaders/common/fullscreen.glsl"
+
+
+#shader fragment
+#version 450 core
+
+#line 0 42
+
+layout(binding = 0) uniform sampler2D previous_texture;
+
+// number of steps that each channel is reduced to
+uniform float levels;
+
+in vec2 texCoord;
+
+out vec4 FragColor;
+
+// An example of a user supplied post effect, drawn as a fullscreen triangle
+void main() {
+    vec3 colour = textureLod(previous_texture, texCoord, 0.0).rgb;
+    FragColor = vec4(floor(colour * levels + 0.5) / levels, 1.0);
+}
\ No newline at end of file
@@ -1,22 +1,125 @@
 #shader compute
 #version 450 core
 
+#line 0 35
+
 #define GAMMA           2.2
-#define EXPOSURE        1.0
+#define MIDDLE_GREY     0.18
+#define HISTOGRAM_BINS  256
+
+#define TONE_MAPPING_REINHARD   0
+#define TONE_MAPPING_ACES       1
+#define TONE_MAPPING_AGX        2
+#define TONE_MAPPING_UNCHARTED2 3
 
 #define GROUP_WIDTH     16
 
 layout (local_size_x = GROUP_WIDTH, local_size_y = GROUP_WIDTH, local_size_z = 1) in;
 layout(rgba16f, binding = 0) uniform image2D write_image;
 
+layout (std430, binding = 10) readonly buffer Luminance {
+    uint histogram[HISTOGRAM_BINS];
+    float adaptedLuminance;
+};
+
+uniform int toneMapping;
+// scale from the exposure in EVs, which is a compensation on top of the adapted exposure when it is automatic
+uniform float exposure;
+// whether the scene is exposed so that the adapted luminance becomes middle grey
+uniform bool autoExposure;
+
+vec3 Reinhard(vec3 colour) {
+    return colour / (1.0 + colour);
+}
+
+// Narkowicz fit of the ACES filmic curve
+// https://knarkowicz.wordpress.com/2016/01/06/aces-filmic-tone-mapping-curve/
+vec3 Aces(vec3 colour) {
+    return clamp((colour * (2.51 * colour + 0.03)) / (colour * (2.43 * colour + 0.59) + 0.14), 0.0, 1.0);
+}
+
+// Polynomial fit of the AgX sigmoid, which desaturates bright colours towards white rather than skewing their hue
+// https://iolite-engine.com/blog_posts/minimal_agx_implementation
+vec3 AgX(vec3 colour) {
+    const mat3 inset = mat3(
+        0.842479062253094, 0.0423282422610123, 0.0423756549057051,
+        0.0784335999999992, 0.878468636469772, 0.0784336,
+        0.0792237451477643, 0.0791661274605434, 0.879142973793104
+    );
+    const mat3 outset = mat3(
+        1.19687900512017, -0.0528968517574562, -0.0529716355144438,
+        -0.0980208811401368, 1.15190312990417, -0.0980434501171241,
+        -0.0990297440797205, -0.0989611768448433, 1.15107367264116
+    );
+    const float minEv = -12.47393;
+    const float maxEv = 4.026069;
+
+    colour = inset * colour;
+    colour = clamp(log2(max(colour, 1e-10)), minEv, maxEv);
+    colour = (colour - minEv) / (maxEv - minEv);
+
+    vec3 x2 = colour * colour;
+    vec3 x4 = x2 * x2;
+    colour = 15.5 * x4 * x2 - 40.14 * x4 * colour + 31.96 * x4 - 6.868 * x2 * colour + 0.4298 * x2
+        + 0.1191 * colour - 0.00232;
+
+    // the curve outputs display encoded values, which are linearised as gamma correction follows
+    colour = outset * colour;
+    return pow(max(colour, 0.0), vec3(GAMMA));
+}
+
+vec3 Uncharted2Partial(vec3 x) {
+    const float A = 0.15;
+    const float B = 0.50;
+    const float C = 0.10;
+    const float D = 0.20;
+    const float E = 0.02;
+    const float F = 0.30;
+
+    return ((x * (A * x + C * B) + D * E) / (x * (A * x + B) + D * F)) - E / F;
+}
+
+// Hable filmic curve, scaled so that the white point maps to one
+// http://filmicworlds.com/blog/filmic-tonemapping-operators/
+vec3 Uncharted2(vec3 colour) {
+    const float exposureBias = 2.0;
+    const vec3 whitePoint = vec3(11.2);
+
+    return Uncharted2Partial(colour * exposureBias) / Uncharted2Partial(whitePoint);
+}
+
 void main() {
     ivec2 texelCoord = ivec2(gl_GlobalInvocationID);
 
+    if (any(greaterThanEqual(texelCoord, imageSize(write_image)))) {
+        return;
+    }
+
     vec3 hdrColor = imageLoad(write_image, texelCoord).rgb;
 
-    // exposure tone mapping
-    vec3 mapped = vec3(1.0) - exp(-hdrColor * EXPOSURE);
-    // gamma correction 
+    float exposureScale = exposure;
+    if (autoExposure) {
+        exposureScale *= MIDDLE_GREY / max(adaptedLuminance, 0.0001);
+    }
+    hdrColor *= exposureScale;
+
+    vec3 mapped;
+    switch (toneMapping) {
+        case TONE_MAPPING_REINHARD:
+            mapped = Reinhard(hdrColor);
+            break;
+        case TONE_MAPPING_AGX:
+            mapped = AgX(hdrColor);
+            break;
+        case TONE_MAPPING_UNCHARTED2:
+            mapped = Uncharted2(hdrColor);
+            break;
+        default:
+            mapped = Aces(hdrColor);
+            break;
+    }
+
+    // gamma correction
     mapped = pow(mapped, vec3(1.0 / GAMMA));
 
     imageStore(write_image, texelCoord, vec4(mapped, 1.0));
@@ -0,0 +1,34 @@
+#shader compute
+#version 450 core
+
+#line 0 41
+
+#define GROUP_WIDTH     16
+
+layout (local_size_x = GROUP_WIDTH, local_size_y = GROUP_WIDTH, local_size_z = 1) in;
+layout(binding = 0) uniform sampler2D previous_texture;
+layout(rgba16f, binding = 0) writeonly uniform image2D output_image;
+
+uniform float strength;
+
+vec3 Neighbour(ivec2 texelCoord, ivec2 offset, ivec2 size) {
+    return texelFetch(previous_texture, clamp(texelCoord + offset, ivec2(0), size - 1), 0).rgb;
+}
+
+void main() {
+    ivec2 texelCoord = ivec2(gl_GlobalInvocationID.xy);
+    ivec2 size = imageSize(output_image);
+
+    if (any(greaterThanEqual(texelCoord, size))) {
+        return;
+    }
+
+    vec3 colour = texelFetch(previous_texture, texelCoord, 0).rgb;
+    vec3 neighbours = Neighbour(texelCoord, ivec2(1, 0), size) + Neighbour(texelCoord, ivec2(-1, 0), size)
+        + Neighbour(texelCoord, ivec2(0, 1), size) + Neighbour(texelCoord, ivec2(0, -1), size);
+
+    // pushes the pixel away from the average of its neighbours
+    vec3 sharpened = colour + (colour * 4.0 - neighbours) * strength;
+
+    imageStore(output_image, texelCoord, vec4(max(sharpened, 0.0), 1.0));
+}
\ No newline at end of file
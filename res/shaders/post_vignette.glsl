@@ -0,0 +1,33 @@
+#shader compute
+#version 450 core
+
+#line 0 38
+
+#define GROUP_WIDTH     16
+
+layout (local_size_x = GROUP_WIDTH, local_size_y = GROUP_WIDTH, local_size_z = 1) in;
+layout(binding = 0) uniform sampler2D previous_texture;
+layout(rgba16f, binding = 0) writeonly uniform image2D output_image;
+
+// how dark the corners become
+uniform float intensity;
+// width of the falloff, as a fraction of the distance from the centre to the corners
+uniform float smoothness;
+
+void main() {
+    ivec2 texelCoord = ivec2(gl_GlobalInvocationID.xy);
+    ivec2 size = imageSize(output_image);
+
+    if (any(greaterThanEqual(texelCoord, size))) {
+        return;
+    }
+
+    vec3 colour = texelFetch(previous_texture, texelCoord, 0).rgb;
+
+    // zero at the centre and one in the corners
+    vec2 uv = (vec2(texelCoord) + 0.5) / vec2(size);
+    float distance = length(uv - 0.5) * sqrt(2.0);
+    float vignette = smoothstep(1.0 - smoothness, 1.0, distance);
+
+    imageStore(output_image, texelCoord, vec4(colour * (1.0 - vignette * intensity), 1.0));
+}
\ No newline at end of file
@@ -0,0 +1,35 @@
+#shader compute
+#version 450 core
+
+#line 0 30
+
+#define GROUP_WIDTH     16
+
+layout (local_size_x = GROUP_WIDTH, local_size_y = GROUP_WIDTH, local_size_z = 1) in;
+layout(binding = 0) uniform sampler2D depth_texture;
+layout(rg16f, binding = 0) writeonly uniform image2D motion_image;
+
+// takes a position in this frame's NDC to the last frame's clip space, without its jitter
+uniform mat4 reprojection;
+// offset of this frame's projection, in UV
+uniform vec2 jitter;
+
+// How far each pixel has moved across the screen since the last frame, in UV. This comes only from the depth of
+// the pixel and how the camera has moved, so it is wrong for renderables that have moved themselves
+void main() {
+    ivec2 texelCoord = ivec2(gl_GlobalInvocationID.xy);
+    ivec2 size = imageSize(motion_image);
+
+    if (any(greaterThanEqual(texelCoord, size))) {
+        return;
+    }
+
+    vec2 uv = (vec2(texelCoord) + 0.5) / vec2(size);
+    float depth = texelFetch(depth_texture, texelCoord, 0).r;
+
+    vec4 previous = reprojection * vec4(vec3(uv, depth) * 2.0 - 1.0, 1.0);
+    vec2 previousUV = previous.xy / previous.w * 0.5 + 0.5;
+
+    // the jitter is removed from the current position, so a still camera has no motion
+    imageStore(motion_image, texelCoord, vec4(uv - jitter - previousUV, 0.0, 0.0));
+}
\ No newline at end of file
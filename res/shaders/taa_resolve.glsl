@@ -0,0 +1,63 @@
+#shader compute
+#version 450 core
+
+#line 0 31
+
+#define GROUP_WIDTH     16
+
+layout (local_size_x = GROUP_WIDTH, local_size_y = GROUP_WIDTH, local_size_z = 1) in;
+layout(binding = 0) uniform sampler2D history_texture;
+layout(rgba16f, binding = 0) readonly uniform image2D current_image;
+layout(rg16f, binding = 1) readonly uniform image2D motion_image;
+layout(rgba16f, binding = 2) writeonly uniform image2D resolved_image;
+
+// weight of the current frame, where the rest comes from the history
+uniform float blend;
+// whether the history holds the last frame, otherwise the current frame is used as it is
+uniform bool historyValid;
+
+// Colours are blended after being tone mapped, so that a few very bright pixels don't dominate their neighbours
+// https://graphicrants.blogspot.com/2013/12/tone-mapping.html
+vec3 Tonemap(vec3 colour) {
+    return colour / (1.0 + max(colour.r, max(colour.g, colour.b)));
+}
+
+vec3 InverseTonemap(vec3 colour) {
+    return colour / max(1.0 - max(colour.r, max(colour.g, colour.b)), 0.0001);
+}
+
+void main() {
+    ivec2 texelCoord = ivec2(gl_GlobalInvocationID.xy);
+    ivec2 size = imageSize(resolved_image);
+
+    if (any(greaterThanEqual(texelCoord, size))) {
+        return;
+    }
+
+    vec3 current = Tonemap(imageLoad(current_image, texelCoord).rgb);
+
+    // the range of colours around the pixel, which the history is clamped to, so history that no longer matches
+    // what is under the pixel, such as after being disoccluded, is rejected rather than smeared
+    vec3 minimum = current;
+    vec3 maximum = current;
+    for (int y = -1; y <= 1; y++) {
+        for (int x = -1; x <= 1; x++) {
+            ivec2 neighbour = clamp(texelCoord + ivec2(x, y), ivec2(0), size - 1);
+            vec3 colour = Tonemap(imageLoad(current_image, neighbour).rgb);
+            minimum = min(minimum, colour);
+            maximum = max(maximum, colour);
+        }
+    }
+
+    vec2 uv = (vec2(texelCoord) + 0.5) / vec2(size);
+    vec2 historyUV = uv - imageLoad(motion_image, texelCoord).xy;
+
+    vec3 resolved = current;
+    if (historyValid && all(greaterThanEqual(historyUV, vec2(0.0))) && all(lessThanEqual(historyUV, vec2(1.0)))) {
+        vec3 history = Tonemap(textureLod(history_texture, historyUV, 0.0).rgb);
+        history = clamp(history, minimum, maximum);
+        resolved = mix(history, current, blend);
+    }
+
+    imageStore(resolved_image, texelCoord, vec4(InverseTonemap(resolved), 1.0));
+}
\ No newline at end of file